@@ -1,11 +1,13 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use grep_regex::RegexMatcherBuilder;
 use hgrep::chunk::File;
 use hgrep::printer::Printer;
 use hgrep::ripgrep;
 use hgrep::Result;
 use hgrep_bench::*;
+use std::fs;
 use std::iter;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Default)]
 struct DummyPrinter;
@@ -22,6 +24,42 @@ fn run_ripgrep(pat: &str, path: &Path) -> bool {
     ripgrep::grep(DummyPrinter, pat, Some(iter::once(path)), config).unwrap()
 }
 
+fn ignore_case_fixed_strings_config() -> ripgrep::Config<'static> {
+    let mut config = ripgrep::Config::new(3, 6);
+    config
+        .no_ignore(true)
+        .fixed_strings(true)
+        .case_insensitive(true);
+    config
+}
+
+// Copy all files in testdata/ many times to a temporary directory to search a large tree
+fn scaled_testdata() -> PathBuf {
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let (path, kind) = (entry.path(), entry.file_type().unwrap());
+            let dest = to.join(entry.file_name());
+            if kind.is_dir() {
+                copy_dir(&path, &dest);
+            } else if kind.is_file() {
+                fs::copy(&path, &dest).unwrap();
+            }
+            // Symbolic links are skipped since testdata has a cycle of them
+        }
+    }
+
+    let dir = std::env::temp_dir().join("hgrep-bench-scaled-testdata");
+    if !dir.exists() {
+        let testdata = Path::new("..").join("testdata");
+        for i in 0..50 {
+            copy_dir(&testdata, &dir.join(i.to_string()));
+        }
+    }
+    dir
+}
+
 fn bench(c: &mut Criterion) {
     let dir = Path::new("..").join("testdata").join("chunk");
     c.bench_function("ripgrep::testdata", |b| {
@@ -45,5 +83,33 @@ fn bench(c: &mut Criterion) {
     });
 }
 
-criterion_group!(ripgrep, bench);
+// Compare the fast path for `-i -F` with the case-insensitive regex matcher which was used before
+fn bench_ignore_case_fixed_strings(c: &mut Criterion) {
+    let dir = scaled_testdata();
+    let pat = "fn main() {";
+
+    c.bench_function("ripgrep::ignore_case_fixed_strings::fast_path", |b| {
+        b.iter(|| {
+            let config = ignore_case_fixed_strings_config();
+            assert!(
+                ripgrep::grep(DummyPrinter, pat, Some(iter::once(dir.as_path())), config).unwrap()
+            );
+        })
+    });
+
+    c.bench_function("ripgrep::ignore_case_fixed_strings::regex", |b| {
+        b.iter(|| {
+            let matcher = RegexMatcherBuilder::new()
+                .case_insensitive(true)
+                .line_terminator(Some(b'\n'))
+                .build(r"fn main\(\) \{")
+                .unwrap();
+            let config = ignore_case_fixed_strings_config();
+            let paths = Some(iter::once(dir.as_path()));
+            assert!(ripgrep::grep_with_matcher(DummyPrinter, matcher, paths, config).unwrap());
+        })
+    });
+}
+
+criterion_group!(ripgrep, bench, bench_ignore_case_fixed_strings);
 criterion_main!(ripgrep);
@@ -0,0 +1,71 @@
+// Search files with a custom matcher and print the results with hgrep's syntect printer.
+//
+//   cargo run --example custom_matcher -- WORD [PATH...]
+//
+// The matcher finds WORD only when it appears as a whole word, without building any regex.
+
+use anyhow::Result;
+use grep_matcher::{Match, Matcher, NoCaptures, NoError};
+use hgrep::printer::PrinterOptions;
+use hgrep::ripgrep::{self, Config};
+use hgrep::syntect::SyntectPrinter;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+struct WordMatcher(Vec<u8>);
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+impl Matcher for WordMatcher {
+    type Captures = NoCaptures;
+    type Error = NoError;
+
+    fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, NoError> {
+        let word = &self.0;
+        if word.is_empty() || haystack.len() < word.len() {
+            return Ok(None);
+        }
+        for start in at..=haystack.len() - word.len() {
+            let end = start + word.len();
+            if &haystack[start..end] != word.as_slice() {
+                continue;
+            }
+            let before = start > 0 && is_word_byte(haystack[start - 1]);
+            let after = end < haystack.len() && is_word_byte(haystack[end]);
+            if !before && !after {
+                return Ok(Some(Match::new(start, end)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn new_captures(&self) -> Result<NoCaptures, NoError> {
+        Ok(NoCaptures::new())
+    }
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let Some(word) = args.next() else {
+        eprintln!("Usage: custom_matcher WORD [PATH...]");
+        process::exit(2);
+    };
+    let paths: Vec<PathBuf> = args.map(PathBuf::from).collect();
+    let paths = (!paths.is_empty()).then(|| paths.iter().map(PathBuf::as_path));
+
+    let printer = SyntectPrinter::with_stdout(PrinterOptions::default())?;
+    let matcher = WordMatcher(word.into_bytes());
+    let found = ripgrep::grep_with_matcher(printer, matcher, paths, Config::new(3, 6))?;
+
+    process::exit(if found { 0 } else { 1 });
+}
+
+// `ripgrep::search_paths` can be used instead when the results should be processed without a printer:
+//
+//   for file in ripgrep::search_paths(matcher, paths, Config::new(3, 6))? {
+//       let file = file?;
+//       println!("{:?}: {} matches", file.path, file.line_matches.len());
+//   }
@@ -0,0 +1,71 @@
+#![no_main]
+
+use hgrep::chunk::{Files, OverlapPolicy};
+use hgrep::grep::BufReadExt;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    grep_output: &'a [u8],
+    contents: String,
+    min_context: u8,
+    max_context: u8,
+    max_bytes: Option<u16>,
+    overlap: u8,
+    suppress_empty_context: bool,
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let Input {
+        grep_output,
+        contents,
+        min_context,
+        max_context,
+        max_bytes,
+        overlap,
+        suppress_empty_context,
+    } = input;
+
+    // Only matches in the first file are read. Matches in other files would read arbitrary paths on the file system
+    let mut first_path = None;
+    let matches = grep_output.grep_lines().take_while(|m| match m {
+        Ok(m) => *first_path.get_or_insert_with(|| m.path.clone()) == m.path,
+        Err(_) => true,
+    });
+
+    // hgrep ensures that the max context is not smaller than the min context
+    let (min, max) = (min_context as u64, max_context.max(min_context) as u64);
+    let mut files = Files::new(matches, min, max, None).unwrap();
+    files
+        .contents(contents)
+        .suppress_empty_context(suppress_empty_context)
+        .overlap_policy(match overlap % 3 {
+            0 => OverlapPolicy::Merge,
+            1 => OverlapPolicy::Separate,
+            _ => OverlapPolicy::Clip,
+        });
+    if let Some(bytes) = max_bytes {
+        files.context_max_bytes(bytes as usize);
+    }
+
+    for file in files {
+        let Ok(file) = file else {
+            break;
+        };
+        // Chunks and matches are always in the file
+        let last_lnum = file.lines().last().map_or(0, |(_, n)| n);
+        for &(start, end) in file.chunks.iter() {
+            assert!(
+                1 <= start && start <= end && end <= last_lnum,
+                "{:?}",
+                file.chunks
+            );
+        }
+        for m in file.line_matches.iter() {
+            let lnum = m.line_number;
+            let in_chunk = file.chunks.iter().any(|&(s, e)| s <= lnum && lnum <= e);
+            assert!(in_chunk, "{} is not in {:?}", lnum, file.chunks);
+        }
+    }
+});
@@ -0,0 +1,144 @@
+#![no_main]
+
+use hgrep::chunk::{File, LineMatch};
+use hgrep::printer::{Printer, PrinterOptions, TermColorSupport, TextWrapMode};
+use hgrep::syntect::{SyntectAssets, SyntectPrinter, WriteOnLocked};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use std::cell::{RefCell, RefMut};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    contents: String,
+    term_width: u8,
+    tab_width: u8,
+    wrap: bool,
+    grid: bool,
+    first_line: u32,
+    matched: Vec<(u16, usize, usize)>,
+}
+
+#[derive(Default)]
+struct Output(RefCell<Vec<u8>>);
+
+struct OutputLock<'a>(RefMut<'a, Vec<u8>>);
+
+impl<'a> Write for OutputLock<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteOnLocked for Output {
+    type Locked<'a> = OutputLock<'a>;
+    fn lock(&self) -> Self::Locked<'_> {
+        OutputLock(self.0.borrow_mut())
+    }
+}
+
+fn assets() -> SyntectAssets {
+    static ASSETS: OnceLock<SyntectAssets> = OnceLock::new();
+    ASSETS
+        .get_or_init(|| SyntectAssets::load(None).unwrap())
+        .clone()
+}
+
+// Width of the printed line in the same way as the printer counts it. Box drawing characters of borders are one column
+fn text_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut saw_zwj = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Skip escape sequences like "\x1b[38;5;1m". ESC in the file contents is written as-is
+            '\x1b' if chars.next_if_eq(&'[').is_some() => {
+                while chars.next_if(|c| !('@'..='~').contains(c)).is_some() {}
+                chars.next();
+            }
+            '\u{200d}' => saw_zwj = true,
+            _ if saw_zwj => saw_zwj = false,
+            '\u{2500}'..='\u{257f}' => width += 1,
+            c => width += c.width_cjk().unwrap_or(0),
+        }
+    }
+    width
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        contents,
+        term_width,
+        tab_width,
+        wrap,
+        grid,
+        first_line,
+        matched,
+    } = input;
+
+    // hgrep rejects terminal width smaller than 10
+    let term_width = term_width.max(10) as u16;
+    let num_lines = contents.lines().count() as u64;
+    if num_lines == 0 {
+        return;
+    }
+    let mut lmats: Vec<LineMatch> = matched
+        .into_iter()
+        .map(|(lnum, start, end)| {
+            let lnum = lnum as u64 % num_lines + 1;
+            LineMatch::new(lnum, vec![(start.min(end), start.max(end))])
+        })
+        .collect();
+    if lmats.is_empty() {
+        lmats.push(LineMatch::lnum(1));
+    }
+    lmats.sort_by_key(|m| m.line_number);
+    lmats.dedup_by_key(|m| m.line_number);
+
+    // Line numbers are offset to make the gutter wide
+    let offset = first_line as u64;
+    let contents = "\n".repeat(offset as usize % 100_000) + &contents;
+    let offset = offset % 100_000;
+    for m in lmats.iter_mut() {
+        m.line_number += offset;
+    }
+    let chunks = vec![(offset + 1, offset + num_lines)];
+
+    let opts = PrinterOptions {
+        term_width,
+        tab_width: tab_width as usize % 16,
+        text_wrap: if wrap {
+            TextWrapMode::Char
+        } else {
+            TextWrapMode::Never
+        },
+        grid,
+        theme: Some("ansi"),
+        color_support: TermColorSupport::Ansi16,
+        ..Default::default()
+    };
+    let file = File::new(PathBuf::from("fuzz.txt"), lmats, chunks, contents);
+    let mut printer = SyntectPrinter::with_assets(assets(), Output::default(), opts);
+    printer.print(file).unwrap();
+
+    // Lines cannot fit in the terminal when the gutter leaves no room for a wide character
+    let gutter_width = (offset + num_lines).to_string().len() + if grid { 4 } else { 2 };
+    if !wrap || gutter_width + 2 > term_width as usize {
+        return;
+    }
+    let printed = printer.writer_mut().0.get_mut();
+    let printed = String::from_utf8_lossy(printed);
+    for line in printed.lines() {
+        let width = text_width(line);
+        assert!(
+            width <= term_width as usize,
+            "{width} > {term_width}: {line:?}"
+        );
+    }
+});
@@ -1,5 +1,7 @@
 use crate::chunk::File;
-use crate::printer::{Printer, PrinterOptions, TermColorSupport, TextWrapMode};
+use crate::printer::{
+    header_title, Layout, Printer, PrinterOptions, TermColorSupport, TextWrapMode,
+};
 use anyhow::Result;
 use bat::assets::HighlightingAssets;
 use bat::config::{Config, VisibleLines};
@@ -9,6 +11,7 @@ use bat::line_range::{HighlightedLineRanges, LineRange, LineRanges};
 use bat::style::{StyleComponent, StyleComponents};
 use bat::WrappingMode;
 use std::env;
+use std::fmt;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
@@ -39,7 +42,11 @@ pub struct BatPrinter<'main> {
 
 impl<'main> BatPrinter<'main> {
     pub fn new(opts: PrinterOptions<'main>) -> Self {
-        let styles = if opts.grid {
+        // bat always draws grid and snip lines with Unicode box-drawing characters. Omit them on terminals which are
+        // not capable of rendering the characters
+        let styles = if opts.ascii_lines {
+            &[StyleComponent::LineNumbers, StyleComponent::HeaderFilename][..]
+        } else if opts.grid {
             &[
                 StyleComponent::LineNumbers,
                 StyleComponent::Snip,
@@ -66,6 +73,7 @@ impl<'main> BatPrinter<'main> {
             tab_width: opts.tab_width,
             true_color: opts.color_support == TermColorSupport::True,
             wrapping_mode,
+            language: opts.syntax_extension,
             ..Default::default()
         };
 
@@ -108,9 +116,17 @@ impl<'main> BatPrinter<'main> {
     }
 
     pub fn print(&self, file: File) -> Result<()> {
+        self.print_to(file, None)
+    }
+
+    // When `output` is `None`, bat writes the output to stdout directly
+    pub fn print_to(&self, mut file: File, mut output: Option<&mut dyn fmt::Write>) -> Result<()> {
         if file.chunks.is_empty() || file.line_matches.is_empty() {
             return Ok(()); // Ensure to print some match
         }
+        if self.opts.expand_tabs {
+            file.expand_tabs(self.opts.tab_width);
+        }
 
         // XXX: We don't use `bat::PrettyPrinter`.
         //
@@ -142,8 +158,19 @@ impl<'main> BatPrinter<'main> {
         };
         config.visible_lines = VisibleLines::Ranges(LineRanges::from(ranges));
 
-        let input =
+        let mut input =
             Input::from_reader(Box::new(file.contents.as_bytes())).with_name(Some(&file.path));
+        let description = input.description_mut();
+        // The name is kept as the original path since bat detects the syntax from it
+        let title = header_title(
+            &file.path,
+            file.annotation.as_deref(),
+            self.opts.path_separator,
+        );
+        description.set_title(Some(title));
+        if self.opts.layout == Layout::Unified {
+            description.set_kind(None); // Omit "File: " label before the title
+        }
 
         let ranges = file
             .line_matches
@@ -157,14 +184,18 @@ impl<'main> BatPrinter<'main> {
         config.highlighted_lines = HighlightedLineRanges(LineRanges::from(ranges));
 
         if !self.opts.grid {
-            print!("\n\n"); // Empty lines as files separator
+            let sep = self.opts.layout.files_separator();
+            match &mut output {
+                Some(out) => out.write_str(sep)?,
+                None => print!("{}", sep),
+            }
         }
 
         let controller = Controller::new(&config, &self.assets);
 
         // Note: controller.run() returns true when no error
         // Note: `Controller::run_with_error_handler` because it requires `Fn` (not `FnMut`) for the handler type.
-        if controller.run(vec![input], None)? {
+        if controller.run(vec![input], output)? {
             Ok(())
         } else {
             anyhow::bail!("Could not print file {:?} by bat printer", file.path)
@@ -174,7 +205,11 @@ impl<'main> BatPrinter<'main> {
 
 impl<'main> Printer for Mutex<BatPrinter<'main>> {
     fn print(&self, file: File) -> Result<()> {
-        self.lock().unwrap().print(file)
+        // The lock is poisoned when printing other file panicked. The printer is still usable since it has no state
+        // across files
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .print(file)
     }
 }
 
@@ -212,6 +247,92 @@ mod tests {
         p.print(f).unwrap();
     }
 
+    #[test]
+    fn test_print_with_ascii_lines() {
+        let opts = PrinterOptions {
+            ascii_lines: true,
+            ..Default::default()
+        };
+        let p = BatPrinter::new(opts);
+        assert!(!p.config.style_components.grid());
+        assert!(!p.config.style_components.snip());
+        p.print(sample_file()).unwrap();
+    }
+
+    #[test]
+    fn test_print_with_annotation() {
+        let p = BatPrinter::new(PrinterOptions::default());
+        let mut f = sample_file();
+        f.annotation = Some("untracked".to_string());
+        p.print(f).unwrap();
+    }
+
+    fn print_to_string(opts: PrinterOptions<'_>, file: File) -> Vec<String> {
+        let p = BatPrinter::new(opts);
+        let mut out = String::new();
+        p.print_to(file, Some(&mut out)).unwrap();
+
+        // Remove escape sequences to count the visible characters
+        let mut stripped = String::new();
+        let mut in_escape = false;
+        for c in out.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if in_escape => {}
+                c => stripped.push(c),
+            }
+        }
+        stripped.lines().map(String::from).collect()
+    }
+
+    fn long_line_file() -> File {
+        let path = PathBuf::from("test.rs");
+        let lmats = vec![LineMatch::lnum(1)];
+        let chunks = vec![(1, 1)];
+        let contents = format!("// {}\n", "foo ".repeat(30));
+        File::new(path, lmats, chunks, contents)
+    }
+
+    #[test]
+    fn test_print_with_term_width() {
+        for width in [40, 80, 120] {
+            let opts = PrinterOptions {
+                term_width: width,
+                ..Default::default()
+            };
+            let lines = print_to_string(opts, sample_file());
+            let max = lines.iter().map(|l| l.chars().count()).max().unwrap();
+            assert_eq!(max, width as usize, "lines: {lines:#?}");
+        }
+    }
+
+    #[test]
+    fn test_print_with_wrap_mode() {
+        let opts = PrinterOptions {
+            term_width: 40,
+            text_wrap: TextWrapMode::Char,
+            ..Default::default()
+        };
+        let wrapped = print_to_string(opts, long_line_file());
+        assert!(
+            wrapped.iter().all(|l| l.chars().count() <= 40),
+            "lines: {wrapped:#?}",
+        );
+
+        let opts = PrinterOptions {
+            term_width: 40,
+            text_wrap: TextWrapMode::Never,
+            ..Default::default()
+        };
+        let unwrapped = print_to_string(opts, long_line_file());
+        assert!(
+            unwrapped.iter().any(|l| l.chars().count() > 40),
+            "lines: {unwrapped:#?}",
+        );
+        assert!(wrapped.len() > unwrapped.len());
+    }
+
     #[test]
     fn test_print_nothing() {
         let p = BatPrinter::new(PrinterOptions::default());
@@ -1,35 +1,98 @@
 use crate::grep::GrepMatch;
 use anyhow::{Context as _, Result};
-use encoding_rs::{Encoding, UTF_8};
+use encoding_rs::{DecoderResult, Encoding, UTF_8};
 use memchr::{memchr2, memchr_iter, Memchr};
 use pathdiff::diff_paths;
 use std::cmp;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::iter::Peekable;
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+// How to handle byte sequences which are malformed for the encoding of the file
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum EncodingErrors {
+    // Replace the malformed sequences with U+FFFD
+    #[default]
+    Replace,
+    // Remove the malformed sequences from the text
+    Skip,
+    // Fail to read the file
+    Strict,
+}
 
-fn decode_text(mut bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> String {
-    if let Some(encoding) = encoding {
-        return encoding.decode_with_bom_removal(&bytes).0.into_owned();
-    }
+pub(crate) fn encoding_for_label(label: Option<&str>) -> Result<Option<&'static Encoding>> {
+    let Some(label) = label else {
+        return Ok(None);
+    };
+    let encoding = Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| anyhow::anyhow!("Unknown encoding name: {label:?}"))?;
+    Ok(Some(encoding))
+}
 
-    if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
-        if encoding == UTF_8 {
-            bytes.drain(..bom_len); // Strip UTF-8 BOM from file (#20)
-        } else {
-            return encoding
-                .decode_without_bom_handling(&bytes[bom_len..])
-                .0
-                .into_owned();
+fn decode_malformed(
+    encoding: &'static Encoding,
+    bytes: &[u8],
+    errors: EncodingErrors,
+) -> Result<String> {
+    match errors {
+        EncodingErrors::Replace if encoding == UTF_8 => {
+            Ok(String::from_utf8_lossy(bytes).into_owned())
         }
+        EncodingErrors::Replace => Ok(encoding.decode_without_bom_handling(bytes).0.into_owned()),
+        EncodingErrors::Strict => encoding
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|text| text.into_owned())
+            .ok_or_else(|| {
+                anyhow::anyhow!("Malformed byte sequence for encoding {}", encoding.name())
+            }),
+        EncodingErrors::Skip => {
+            let mut decoder = encoding.new_decoder_without_bom_handling();
+            let mut text = String::new();
+            let mut bytes = bytes;
+            loop {
+                if let Some(len) = decoder.max_utf8_buffer_length_without_replacement(bytes.len()) {
+                    text.reserve(len);
+                }
+                let (result, read) =
+                    decoder.decode_to_string_without_replacement(bytes, &mut text, true);
+                bytes = &bytes[read..];
+                match result {
+                    DecoderResult::InputEmpty => return Ok(text),
+                    DecoderResult::OutputFull | DecoderResult::Malformed(..) => {}
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn decode_text(
+    mut bytes: Vec<u8>,
+    encoding: Option<&'static Encoding>,
+    errors: EncodingErrors,
+) -> Result<String> {
+    // The BOM is removed only when it is for the specified encoding
+    let (encoding, bom_len) = match (encoding, Encoding::for_bom(&bytes)) {
+        (Some(encoding), Some((bom, len))) if encoding == bom => (encoding, len),
+        (Some(encoding), _) => (encoding, 0),
+        (None, Some((bom, len))) => (bom, len),
+        (None, None) => (UTF_8, 0),
+    };
+
+    if encoding != UTF_8 {
+        return decode_malformed(encoding, &bytes[bom_len..], errors);
     }
 
-    String::from_utf8(bytes)
-        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+    bytes.drain(..bom_len); // Strip UTF-8 BOM from file (#20)
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(err) => decode_malformed(UTF_8, err.as_bytes(), errors),
+    }
 }
 
-#[cfg_attr(test, derive(Debug, PartialEq))]
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 #[derive(Clone)] // Implement Clone for benchmark
 pub struct LineMatch {
     pub line_number: u64,
@@ -53,13 +116,21 @@ impl LineMatch {
     }
 }
 
-#[cfg_attr(test, derive(Debug, PartialEq))]
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 #[derive(Clone)] // Implement Clone for benchmark
 pub struct File {
     pub path: PathBuf,
     pub line_matches: Box<[LineMatch]>,
     pub chunks: Box<[(u64, u64)]>, // Start/End line number of the chunk
     pub contents: Box<str>,
+    // Occurrences of the pattern in context lines. This is empty unless --highlight-surrounding-text is enabled
+    pub context_matches: Box<[LineMatch]>,
+    // Optional text which printers show next to the path in the header. Search frontends can set this to tell something
+    // about the file like "modified" or "untracked". Nothing is shown when this is `None`
+    pub annotation: Option<String>,
+    // Number of lines in the file before the first line of `contents`. This is non-zero when only a window of the file is
+    // loaded by the streaming search. Line numbers in matches and chunks are always relative to the start of the file
+    pub line_offset: u64,
 }
 
 impl File {
@@ -74,6 +145,9 @@ impl File {
             line_matches: lm.into_boxed_slice(),
             chunks: chunks.into_boxed_slice(),
             contents: contents.into_boxed_str(),
+            context_matches: Box::new([]),
+            annotation: None,
+            line_offset: 0,
         }
     }
 
@@ -107,8 +181,235 @@ fn print_sqrt<S: AsRef<str>>(input: S) {
         }
         line
     }
+
+    // Lines of `contents` with their line numbers in the file
+    pub fn lines(&self) -> LinesInclusive<'_> {
+        LinesInclusive::starting_at(&self.contents, self.line_offset + 1)
+    }
+
+    // Replace each tab with spaces up to the next tab stop. Columns are counted in characters. Byte offsets of matches
+    // are shifted so that they still point to the same text after the expansion
+    pub fn expand_tabs(&mut self, tab_width: usize) {
+        if tab_width == 0 || !self.contents.contains('\t') {
+            return;
+        }
+
+        let mut expanded = String::with_capacity(self.contents.len());
+        let mut line_matches = self.line_matches.iter_mut().peekable();
+        let mut context_matches = self.context_matches.iter_mut().peekable();
+        let mut tabs = vec![]; // Pairs of the byte offset of a tab and the number of bytes added by expanding it
+        for (line, lnum) in LinesInclusive::starting_at(&self.contents, self.line_offset + 1) {
+            tabs.clear();
+            let mut col = 0;
+            for (idx, c) in line.char_indices() {
+                if c == '\t' {
+                    let spaces = tab_width - col % tab_width;
+                    expanded.extend(std::iter::repeat_n(' ', spaces));
+                    tabs.push((idx, spaces - 1));
+                    col += spaces;
+                } else {
+                    expanded.push(c);
+                    col += 1;
+                }
+            }
+
+            if tabs.is_empty() {
+                continue;
+            }
+            let shift = |offset: usize| {
+                let added: usize = tabs
+                    .iter()
+                    .take_while(|(i, _)| *i < offset)
+                    .map(|(_, n)| n)
+                    .sum();
+                offset + added
+            };
+            for matches in [&mut line_matches, &mut context_matches] {
+                while let Some(lm) = matches.next_if(|lm| lm.line_number <= lnum) {
+                    if lm.line_number == lnum {
+                        for (start, end) in lm.ranges.iter_mut() {
+                            *start = shift(*start);
+                            *end = shift(*end);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.contents = expanded.into_boxed_str();
+    }
+
+    // Replace matched text which is equal to `text` ignoring case with `text` so that the matches are shown in the case
+    // of the pattern. Matched text whose length in bytes is different from `text` is not replaced since the byte offsets
+    // of the matches would be broken
+    pub fn normalize_match_case(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let folded = text.to_lowercase();
+
+        let line_starts: Vec<_> = LinesInclusive::new(&self.contents)
+            .scan(0, |offset, (line, _)| {
+                let start = *offset;
+                *offset += line.len();
+                Some(start)
+            })
+            .collect();
+        let mut contents = mem::take(&mut self.contents).into_string();
+        for lm in self.line_matches.iter().chain(self.context_matches.iter()) {
+            let Some(idx) = lm.line_number.checked_sub(self.line_offset + 1) else {
+                continue;
+            };
+            let Some(&line_start) = line_starts.get(idx as usize) else {
+                continue;
+            };
+            for &(start, end) in &lm.ranges {
+                let range = line_start + start..line_start + end;
+                let Some(matched) = contents.get(range.clone()) else {
+                    continue; // Offsets may not be at char boundaries when the file has invalid UTF-8 sequences
+                };
+                if matched.len() == text.len()
+                    && matched != text
+                    && matched.to_lowercase() == folded
+                {
+                    contents.replace_range(range, text);
+                }
+            }
+        }
+        self.contents = contents.into_boxed_str();
+    }
+
+    // Drop context lines of the chunks whose context lines are all blank since they add no information. Such chunk is
+    // split into the runs of consecutive matched lines
+    pub fn suppress_empty_context(&mut self) {
+        let matched: Vec<_> = self.line_matches.iter().map(|m| m.line_number).collect();
+        let mut lines = self.lines();
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        for &(start, end) in self.chunks.iter() {
+            let mut runs: Vec<(u64, u64)> = vec![];
+            let mut blank = true;
+            for (line, lnum) in lines.by_ref() {
+                if lnum < start {
+                    continue;
+                }
+                if matched.binary_search(&lnum).is_ok() {
+                    match runs.last_mut() {
+                        Some(run) if run.1 + 1 == lnum => run.1 = lnum,
+                        _ => runs.push((lnum, lnum)),
+                    }
+                } else if !line.trim().is_empty() {
+                    blank = false;
+                }
+                if lnum >= end {
+                    break;
+                }
+            }
+            if blank && !runs.is_empty() {
+                chunks.extend(runs);
+            } else {
+                chunks.push((start, end));
+            }
+        }
+        self.chunks = chunks.into_boxed_slice();
+    }
+}
+
+// How the context windows of matches are laid out when they abut or overlap
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OverlapPolicy {
+    // Merge the windows into one chunk
+    #[default]
+    Merge,
+    // Make a chunk per match. The later chunk starts after the end of the previous one
+    Separate,
+    // Make a chunk per match. The overlapped lines are split between the two matches following `ContextBias`
+    Clip,
+}
+
+// Which side of matches keeps context lines when the context of a chunk must be truncated
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ContextBias {
+    // Keep context lines before matches. This is useful for code to see the signature of the function
+    Before,
+    // Keep context lines after matches. This is useful for logs to see what happened next
+    After,
+    // Keep context lines on both sides evenly
+    #[default]
+    Balanced,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+// Files which have the same number of matched lines are sorted by their paths so that the order is stable even if the
+// files were searched in parallel
+pub fn sort_by_match_count(files: &mut [File], order: SortOrder) {
+    files.sort_by(|l, r| {
+        let ord = l.line_matches.len().cmp(&r.line_matches.len());
+        let ord = match order {
+            SortOrder::Ascending => ord,
+            SortOrder::Descending => ord.reverse(),
+        };
+        ord.then_with(|| l.path.cmp(&r.path))
+    });
+}
+
+// Group files by distinct matched texts across them. Line matches of the files in each group are narrowed down to the
+// ranges of the text and chunks which no longer contain any match are removed. Groups are sorted by the texts and files
+// in each group are sorted by their paths
+pub fn group_by_match(files: Vec<File>) -> Vec<(String, Vec<File>)> {
+    let mut groups: BTreeMap<String, Vec<File>> = BTreeMap::new();
+    for file in files {
+        let mut texts: BTreeMap<&str, Vec<LineMatch>> = BTreeMap::new();
+        let mut lmats = file.line_matches.iter().peekable();
+        for (line, lnum) in file.lines() {
+            let Some(lmat) = lmats.next_if(|m| m.line_number == lnum) else {
+                if lmats.peek().is_none() {
+                    break;
+                }
+                continue;
+            };
+            for &(start, end) in &lmat.ranges {
+                let Some(text) = line.get(start..end).filter(|t| !t.is_empty()) else {
+                    continue;
+                };
+                let lmats = texts.entry(text).or_default();
+                match lmats.last_mut() {
+                    Some(m) if m.line_number == lnum => m.ranges.push((start, end)),
+                    _ => lmats.push(LineMatch::new(lnum, vec![(start, end)])),
+                }
+            }
+        }
+
+        for (text, lmats) in texts {
+            let chunks = file
+                .chunks
+                .iter()
+                .copied()
+                .filter(|&(s, e)| {
+                    lmats
+                        .iter()
+                        .any(|m| s <= m.line_number && m.line_number <= e)
+                })
+                .collect();
+            let mut grouped =
+                File::new(file.path.clone(), lmats, chunks, file.contents.to_string());
+            grouped.line_offset = file.line_offset;
+            groups.entry(text.to_string()).or_default().push(grouped);
+        }
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    for (_, files) in groups.iter_mut() {
+        files.sort_by(|l, r| l.path.cmp(&r.path));
+    }
+    groups
 }
 
+#[derive(Clone)]
 pub struct LinesInclusive<'a> {
     lnum: u64,
     prev: usize,
@@ -118,8 +419,13 @@ pub struct LinesInclusive<'a> {
 
 impl<'a> LinesInclusive<'a> {
     pub fn new(buf: &'a str) -> Self {
+        Self::starting_at(buf, 1)
+    }
+
+    // The first line of `buf` is numbered `lnum`
+    pub fn starting_at(buf: &'a str, lnum: u64) -> Self {
         Self {
-            lnum: 1,
+            lnum,
             prev: 0,
             buf,
             iter: memchr_iter(b'\n', buf.as_bytes()),
@@ -148,6 +454,7 @@ impl<'a> Iterator for LinesInclusive<'a> {
 }
 
 // Optimized version of str::Lines with line numbers
+#[derive(Clone)]
 struct Lines<'a>(LinesInclusive<'a>);
 
 impl<'a> Lines<'a> {
@@ -167,13 +474,40 @@ impl<'a> Iterator for Lines<'a> {
     }
 }
 
+// Make the path of a matched file relative to the base directory, or to the current working directory when it is absolute
+pub fn relative_path(path: PathBuf, base_dir: Option<&Path>, cwd: Option<&Path>) -> PathBuf {
+    if let Some(base) = base_dir {
+        let diff = match cwd {
+            Some(cwd) if path.is_relative() => diff_paths(cwd.join(&path), base),
+            _ => diff_paths(&path, base),
+        };
+        return diff.unwrap_or(path);
+    }
+    if !path.is_relative() {
+        if let Some(cwd) = cwd {
+            if let Some(diff) = diff_paths(&path, cwd) {
+                return diff;
+            }
+        }
+    }
+    path
+}
+
 pub struct Files<I: Iterator> {
     iter: Peekable<I>,
     min_context: u64,
     max_context: u64,
     saw_error: bool,
     cwd: Option<PathBuf>,
+    base_dir: Option<PathBuf>,
+    max_bytes: Option<usize>,
     encoding: Option<&'static Encoding>,
+    encoding_errors: EncodingErrors,
+    overlap_policy: OverlapPolicy,
+    context_bias: ContextBias,
+    suppress_empty_context: bool,
+    peeked: Option<Option<Result<File>>>,
+    contents: Option<String>,
 }
 
 impl<I: Iterator> Files<I> {
@@ -183,13 +517,7 @@ impl<I: Iterator> Files<I> {
         max_context: u64,
         encoding: Option<&str>,
     ) -> Result<Self> {
-        let encoding = if let Some(label) = encoding {
-            let encoding = Encoding::for_label(label.as_bytes())
-                .ok_or_else(|| anyhow::anyhow!("Unknown encoding name: {label:?}"))?;
-            Some(encoding)
-        } else {
-            None
-        };
+        let encoding = encoding_for_label(encoding)?;
 
         Ok(Self {
             iter: iter.peekable(),
@@ -197,9 +525,59 @@ impl<I: Iterator> Files<I> {
             max_context,
             saw_error: false,
             cwd: env::current_dir().ok(),
+            base_dir: None,
+            max_bytes: None,
             encoding,
+            encoding_errors: EncodingErrors::Replace,
+            overlap_policy: OverlapPolicy::Merge,
+            context_bias: ContextBias::Balanced,
+            suppress_empty_context: false,
+            peeked: None,
+            contents: None,
         })
     }
+
+    // Paths of files are displayed relative to this directory instead of the current working directory
+    pub fn base_dir(&mut self, dir: PathBuf) -> &mut Self {
+        self.base_dir = Some(dir);
+        self
+    }
+
+    // Total bytes of lines in a chunk are limited to this size by removing context lines before the first match and after
+    // the last match of the chunk
+    pub fn context_max_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    pub fn overlap_policy(&mut self, policy: OverlapPolicy) -> &mut Self {
+        self.overlap_policy = policy;
+        self
+    }
+
+    pub fn encoding_errors(&mut self, errors: EncodingErrors) -> &mut Self {
+        self.encoding_errors = errors;
+        self
+    }
+
+    // Which side of matches keeps context lines when they are removed by `context_max_bytes` or by clipping overlapped
+    // chunks
+    pub fn context_bias(&mut self, bias: ContextBias) -> &mut Self {
+        self.context_bias = bias;
+        self
+    }
+
+    pub fn suppress_empty_context(&mut self, yes: bool) -> &mut Self {
+        self.suppress_empty_context = yes;
+        self
+    }
+
+    // Use the contents instead of reading the matched file. This is for searching a buffer in memory. All matches must be
+    // in the same buffer
+    pub fn contents(&mut self, contents: String) -> &mut Self {
+        self.contents = Some(contents);
+        self
+    }
 }
 
 impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
@@ -211,16 +589,20 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
     ) -> (u64, u64) {
         let before_start = cmp::max(match_start.saturating_sub(self.max_context), 1);
         let before_end = cmp::max(match_start.saturating_sub(self.min_context), 1);
-        let after_start = match_end + self.min_context;
-        let after_end = match_end + self.max_context;
+        let after_start = match_end.saturating_add(self.min_context);
+        let after_end = match_end.saturating_add(self.max_context);
 
         let mut range_start = before_start;
         let mut range_end = after_end;
         let mut last_lnum = None;
+        let mut line_lens = vec![];
 
         for (line, lnum) in lines {
             last_lnum = Some(lnum);
             assert!(lnum <= after_end, "line {} > chunk {}", lnum, after_end);
+            if self.max_bytes.is_some() && before_start <= lnum {
+                line_lens.push((lnum, line.len()));
+            }
 
             let in_before = before_start <= lnum && lnum < before_end;
             let in_after = after_start < lnum && lnum <= after_end;
@@ -242,18 +624,65 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
             range_end = cmp::min(range_end, n); // Make end of chunk fit to end of file
         }
 
+        if let Some(max_bytes) = self.max_bytes {
+            let line_len = |lnum| {
+                line_lens
+                    .iter()
+                    .find_map(|&(n, len)| (n == lnum).then_some(len))
+                    .unwrap_or(0)
+            };
+            let mut total: usize = (range_start..=range_end).map(line_len).sum();
+            // Remove context lines from the side which the bias does not favor (or from the longer side when balanced) until
+            // the chunk fits in the limit
+            while total > max_bytes {
+                let before = match_start.saturating_sub(range_start);
+                let after = range_end.saturating_sub(match_end);
+                if before == 0 && after == 0 {
+                    break;
+                }
+                let remove_before = match self.context_bias {
+                    ContextBias::Before => after == 0,
+                    ContextBias::After => before > 0,
+                    ContextBias::Balanced => before >= after,
+                };
+                if remove_before {
+                    total -= line_len(range_start);
+                    range_start += 1;
+                } else {
+                    total -= line_len(range_end);
+                    range_end -= 1;
+                }
+            }
+        }
+
         (range_start, range_end)
     }
 
-    fn relative_path(&self, path: PathBuf) -> PathBuf {
-        if !path.is_relative() {
-            if let Some(cwd) = &self.cwd {
-                if let Some(diff) = diff_paths(&path, cwd) {
-                    return diff;
-                }
+    // Make a chunk per match and resolve overlaps between adjacent chunks following the policy
+    fn split_chunks(&self, contents: &str, lmats: &[LineMatch]) -> Vec<(u64, u64)> {
+        let mut lines = Lines::new(contents).peekable();
+        let mut chunks: Vec<(u64, u64)> = Vec::with_capacity(lmats.len());
+        let mut prev_match = None;
+        for lmat in lmats {
+            let lnum = lmat.line_number;
+            let before_start = cmp::max(lnum.saturating_sub(self.max_context), 1);
+            while lines.next_if(|&(_, n)| n < before_start).is_some() {}
+            let (mut start, end) = self.calculate_chunk_range(lnum, lnum, lines.clone());
+
+            if let (Some(prev), Some(last)) = (prev_match, chunks.last_mut()) {
+                let boundary = match (self.overlap_policy, self.context_bias) {
+                    (OverlapPolicy::Clip, ContextBias::Before) => prev,
+                    (OverlapPolicy::Clip, ContextBias::Balanced) => (prev + lnum) / 2,
+                    _ => lnum - 1,
+                };
+                last.1 = cmp::min(last.1, boundary);
+                start = cmp::max(start, last.1 + 1);
             }
+
+            chunks.push((start, end));
+            prev_match = Some(lnum);
         }
-        path
+        chunks
     }
 
     fn error_item(&mut self, e: anyhow::Error) -> Option<Result<File>> {
@@ -262,107 +691,182 @@ impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
     }
 }
 
-impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
-    type Item = Result<File>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
+    fn next_file(&mut self) -> Option<Result<File>> {
         if self.saw_error {
             return None;
         }
 
-        let GrepMatch {
-            path,
-            mut line_number,
-            ranges,
-        } = match self.iter.next()? {
-            Ok(m) => m,
-            Err(e) => return self.error_item(e),
-        };
-        let contents = match fs::read(&path)
-            .with_context(|| format!("Could not open the matched file {:?}", path))
-        {
-            Ok(vec) => decode_text(vec, self.encoding),
-            Err(err) => return self.error_item(err),
-        };
-        // Assumes that matched lines are sorted by source location
-        let mut lines = Lines::new(&contents);
-        let mut lmats = vec![LineMatch {
-            line_number,
-            ranges,
-        }];
-        let mut chunks = Vec::new();
+        // Files whose matches are all dropped are skipped. This is a loop instead of recursion not to overflow the stack
+        // when many files are skipped in a row
+        let (path, lmats, mut chunks, contents) = loop {
+            let GrepMatch {
+                path,
+                mut line_number,
+                ranges,
+            } = loop {
+                match self.iter.next()? {
+                    Ok(m) if m.line_number == 0 => continue, // Line numbers start from 1 so line 0 is never matched
+                    Ok(m) => break m,
+                    Err(e) => return self.error_item(e),
+                }
+            };
+            let contents = if let Some(contents) = self.contents.take() {
+                contents
+            } else {
+                let decoded = fs::read(&path)
+                    .with_context(|| format!("Could not open the matched file {:?}", path))
+                    .and_then(|vec| {
+                        decode_text(vec, self.encoding, self.encoding_errors).with_context(|| {
+                            format!("Could not decode the matched file {:?}", path)
+                        })
+                    });
+                match decoded {
+                    Ok(text) => text,
+                    Err(err) => return self.error_item(err),
+                }
+            };
+            // Assumes that matched lines are sorted by source location
+            let mut lines = Lines::new(&contents);
+            let mut lmats = vec![LineMatch {
+                line_number,
+                ranges,
+            }];
+            let mut chunks = Vec::new();
 
-        'chunks: loop {
-            let first_match_line = line_number;
+            'chunks: loop {
+                let first_match_line = line_number;
 
-            enum State {
-                NextMatch,
-                EndOfFile,
-                EndOfChunk,
-                Error,
-            }
+                enum State {
+                    NextMatch,
+                    EndOfFile,
+                    EndOfChunk,
+                    Error,
+                }
 
-            loop {
-                let peeked = match self.iter.peek() {
-                    None => State::EndOfFile,
-                    Some(Err(_)) => State::Error,
-                    Some(Ok(m)) if m.path != path => State::EndOfFile,
-                    Some(Ok(m)) if m.line_number <= line_number => {
-                        // When the same line number is reported multiple times, ignore the grep line.
-                        // This happens when reading output from `rg --vimgrep` (#13)
-                        self.iter.next();
-                        continue;
-                    }
-                    Some(Ok(m)) if m.line_number - line_number >= self.max_context * 2 => {
-                        State::EndOfChunk
+                loop {
+                    let peeked = match self.iter.peek() {
+                        None => State::EndOfFile,
+                        Some(Err(_)) => State::Error,
+                        Some(Ok(m)) if m.path != path => State::EndOfFile,
+                        Some(Ok(m)) if m.line_number <= line_number => {
+                            // When the same line number is reported multiple times, merge the grep line into the previous
+                            // one. This happens when reading output from `rg --vimgrep` (#13) or `grep -o`
+                            let m = self.iter.next().unwrap().unwrap();
+                            if m.line_number == line_number {
+                                let last = lmats.last_mut().unwrap(); // OK since the first match was pushed
+                                for range in m.ranges {
+                                    if !last.ranges.contains(&range) {
+                                        last.ranges.push(range);
+                                    }
+                                }
+                                last.ranges.sort_unstable();
+                            }
+                            continue;
+                        }
+                        // Consecutive matched lines are always in the same chunk even if there is no context line
+                        Some(Ok(m))
+                            if self.overlap_policy == OverlapPolicy::Merge
+                                && m.line_number - line_number
+                                    >= cmp::max(self.max_context * 2, 2) =>
+                        {
+                            State::EndOfChunk
+                        }
+                        Some(Ok(_)) => State::NextMatch,
+                    };
+
+                    // Actions for each states
+                    match peeked {
+                        State::EndOfFile | State::EndOfChunk => chunks.push(
+                            self.calculate_chunk_range(first_match_line, line_number, &mut lines),
+                        ),
+                        State::Error => {
+                            let err = self.iter.next().unwrap().unwrap_err();
+                            return self.error_item(err);
+                        }
+                        State::NextMatch => {
+                            // Next match
+                            let m = self.iter.next().unwrap().unwrap();
+                            line_number = m.line_number;
+                            lmats.push(LineMatch::new(line_number, m.ranges));
+                        }
                     }
-                    Some(Ok(_)) => State::NextMatch,
-                };
 
-                // Actions for each states
-                match peeked {
-                    State::EndOfFile | State::EndOfChunk => chunks.push(
-                        self.calculate_chunk_range(first_match_line, line_number, &mut lines),
-                    ),
-                    State::Error => {
-                        let err = self.iter.next().unwrap().unwrap_err();
-                        return self.error_item(err);
-                    }
-                    State::NextMatch => {
-                        // Next match
-                        let m = self.iter.next().unwrap().unwrap();
-                        line_number = m.line_number;
-                        lmats.push(LineMatch::new(line_number, m.ranges));
+                    // Transition of each states
+                    match peeked {
+                        State::EndOfFile | State::Error => break 'chunks,
+                        State::EndOfChunk => break,
+                        State::NextMatch => continue,
                     }
                 }
 
-                // Transition of each states
-                match peeked {
-                    State::EndOfFile | State::Error => break 'chunks,
-                    State::EndOfChunk => break,
-                    State::NextMatch => continue,
+                // Go to next chunk
+                let m = self.iter.next().unwrap().unwrap();
+                line_number = m.line_number;
+                // First match line of next chunk
+                lmats.push(LineMatch::new(line_number, m.ranges));
+            }
+
+            // Matches may be beyond the end of the file when it was modified after grep ran. They are dropped not to print
+            // empty snippets
+            if lines.next().is_none() {
+                let last_lnum = Lines::new(&contents).last().map(|(_, n)| n).unwrap_or(0);
+                if lmats.last().is_some_and(|m| m.line_number > last_lnum) {
+                    lmats.retain(|m| m.line_number <= last_lnum);
+                    for chunk in chunks.iter_mut() {
+                        chunk.1 = cmp::min(chunk.1, last_lnum);
+                    }
+                    chunks.retain(|&(s, e)| {
+                        lmats
+                            .iter()
+                            .any(|m| s <= m.line_number && m.line_number <= e)
+                    });
                 }
             }
+            if chunks.is_empty() {
+                continue;
+            }
+            break (path, lmats, chunks, contents);
+        };
+        if self.overlap_policy != OverlapPolicy::Merge {
+            chunks = self.split_chunks(&contents, &lmats);
+        }
 
-            // Go to next chunk
-            let m = self.iter.next().unwrap().unwrap();
-            line_number = m.line_number;
-            // First match line of next chunk
-            lmats.push(LineMatch::new(line_number, m.ranges));
+        let path = relative_path(path, self.base_dir.as_deref(), self.cwd.as_deref());
+        let mut file = File::new(path, lmats, chunks, contents);
+        if self.suppress_empty_context {
+            file.suppress_empty_context();
         }
+        Some(Ok(file))
+    }
+}
 
-        if chunks.is_empty() {
-            return None;
+impl<I: Iterator<Item = Result<GrepMatch>>> Files<I> {
+    // Look ahead at the next file without consuming it. Consumers can decide how to print the current file (e.g. whether
+    // to emit a separator) by checking the next one
+    pub fn peek(&mut self) -> Option<&Result<File>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next_file());
         }
+        self.peeked.as_ref().unwrap().as_ref() // OK since `self.peeked` was set above
+    }
+}
+
+impl<I: Iterator<Item = Result<GrepMatch>>> Iterator for Files<I> {
+    type Item = Result<File>;
 
-        let path = self.relative_path(path);
-        Some(Ok(File::new(path, lmats, chunks, contents)))
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.next_file(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::grep::BufReadExt;
     use crate::test;
     use anyhow::Error;
     use encoding_rs::{SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8};
@@ -455,6 +959,9 @@ mod tests {
             chunks: vec![(5, 11)].into_boxed_slice(),
             contents: fs::read_to_string(&path).unwrap().into_boxed_str(),
             path,
+            context_matches: Box::new([]),
+            annotation: None,
+            line_offset: 0,
         };
 
         assert_eq!(got.len(), 1);
@@ -476,12 +983,124 @@ mod tests {
             chunks: vec![(8, 8)].into_boxed_slice(),
             contents: fs::read_to_string(&path).unwrap().into_boxed_str(),
             path,
+            context_matches: Box::new([]),
+            annotation: None,
+            line_offset: 0,
         };
 
         assert_eq!(got.len(), 1);
         assert_eq!(got[0], expected);
     }
 
+    #[test]
+    fn test_peek() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = ["before", "after", "single_max"];
+        let matches = test::read_all_matches(&dir, &inputs);
+        let expected = test::read_all_expected_chunks(&dir, &inputs);
+        let mut files = Files::new(matches.into_iter(), 3, 6, None).unwrap();
+
+        for want in expected {
+            let peeked = files.peek().unwrap().as_ref().unwrap().clone();
+            assert_eq!(peeked, want);
+            assert_eq!(files.peek().unwrap().as_ref().unwrap(), &want); // Peeking again returns the same file
+            assert_eq!(files.next().unwrap().unwrap(), want);
+        }
+        assert!(files.peek().is_none());
+        assert!(files.next().is_none());
+    }
+
+    #[test]
+    fn test_peek_error() {
+        let err = || Err(anyhow::anyhow!("dummy error"));
+        let mut files = Files::new(vec![err(), err()].into_iter(), 3, 6, None).unwrap();
+        assert!(files.peek().unwrap().is_err());
+        assert!(files.next().unwrap().is_err());
+        assert!(files.peek().is_none());
+        assert!(files.next().is_none());
+    }
+
+    #[test]
+    fn test_zero_context_multiple_matches() {
+        let path = Path::new("testdata").join("chunk").join("overlap.txt");
+        let matches = [1, 3, 4, 5, 8, 10, 20].map(|line_number| {
+            Result::Ok(GrepMatch {
+                path: path.clone(),
+                line_number,
+                ranges: vec![],
+            })
+        });
+        let files: Vec<_> = Files::new(matches.into_iter(), 0, 0, None)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        let want = [(1, 1), (3, 5), (8, 8), (10, 10), (20, 20)];
+        assert_eq!(files[0].chunks.as_ref(), &want);
+        let lnums: Vec<_> = files[0]
+            .line_matches
+            .iter()
+            .map(|m| m.line_number)
+            .collect();
+        assert_eq!(lnums, vec![1, 3, 4, 5, 8, 10, 20]);
+    }
+
+    #[test]
+    fn test_context_max_bytes() {
+        let dir = Path::new("testdata").join("chunk");
+        let path = dir.join("single_max.in");
+        let contents = fs::read_to_string(&path).unwrap();
+
+        // Each line in the chunk (2, 14) is 2 or 3 bytes like "7|" or "10|"
+        for (max_bytes, chunk) in [
+            (100, (2, 14)),
+            (31, (2, 14)),
+            (30, (3, 14)),
+            (11, (6, 10)),
+            (10, (7, 10)),
+            (2, (8, 8)),
+            (0, (8, 8)),
+        ] {
+            let matches = test::read_matches(&dir, "single_max");
+            let mut files = Files::new(matches.into_iter(), 3, 6, None).unwrap();
+            files.context_max_bytes(max_bytes);
+            let got: Vec<_> = files.collect::<Result<_>>().unwrap();
+
+            let expected = File {
+                line_matches: vec![LineMatch::lnum(8)].into_boxed_slice(),
+                chunks: vec![chunk].into_boxed_slice(),
+                contents: contents.clone().into_boxed_str(),
+                path: path.clone(),
+                context_matches: Box::new([]),
+                annotation: None,
+                line_offset: 0,
+            };
+            assert_eq!(got, [expected], "max bytes: {max_bytes}");
+        }
+    }
+
+    #[test]
+    fn test_context_max_bytes_keeps_lines_between_matches() {
+        let dir = Path::new("testdata").join("chunk");
+        let path = dir.join("three_chunks_joint_all.in");
+        let matches = test::read_matches(&dir, "three_chunks_joint_all");
+        let mut files = Files::new(matches.into_iter(), 3, 6, None).unwrap();
+        files.context_max_bytes(0);
+        let got: Vec<_> = files.collect::<Result<_>>().unwrap();
+
+        let expected = File {
+            line_matches: vec![LineMatch::lnum(8), LineMatch::lnum(13), LineMatch::lnum(18)]
+                .into_boxed_slice(),
+            chunks: vec![(8, 18)].into_boxed_slice(),
+            contents: fs::read_to_string(&path).unwrap().into_boxed_str(),
+            path,
+            context_matches: Box::new([]),
+            annotation: None,
+            line_offset: 0,
+        };
+        assert_eq!(got, [expected]);
+    }
+
     #[test]
     fn test_same_line_occurs_repeatedly() {
         // Same line may be reported multiple times when reading output from `rg --vimgrep` (regression test for #17)
@@ -505,10 +1124,426 @@ mod tests {
 
         let want = vec![LineMatch::lnum(1), LineMatch::lnum(2)].into_boxed_slice();
         assert_eq!(line_matches, want);
-        let want = vec![(1, 1), (2, 2)].into_boxed_slice();
+        let want = vec![(1, 2)].into_boxed_slice();
         assert_eq!(chunks, want);
     }
 
+    #[test]
+    fn test_matches_out_of_file() {
+        // Found by fuzzing. Line 0 and lines after the end of the file were reported as empty or inverted chunks
+        let mat = |path: &str, lnum| {
+            Result::Ok(GrepMatch {
+                path: path.into(),
+                line_number: lnum,
+                ranges: vec![],
+            })
+        };
+        let contents = "a\nb\nc\n".to_string();
+
+        let matches = [mat("a.txt", 0), mat("a.txt", 2), mat("a.txt", 9)];
+        let mut files = Files::new(matches.into_iter(), 1, 3, None).unwrap();
+        files.contents(contents.clone());
+        let file = files.next().unwrap().unwrap();
+        assert!(files.next().is_none());
+        assert_eq!(file.line_matches.as_ref(), &[LineMatch::lnum(2)]);
+        assert_eq!(file.chunks.as_ref(), &[(1, 3)]);
+
+        let matches = [
+            mat("a.txt", 9),
+            mat("a.txt", u64::MAX),
+            mat("Cargo.toml", 1),
+        ];
+        let mut files = Files::new(matches.into_iter(), 1, 3, None).unwrap();
+        files.contents(contents);
+        let file = files.next().unwrap().unwrap();
+        assert!(files.next().is_none());
+        assert_eq!(file.path, Path::new("Cargo.toml"));
+    }
+
+    #[test]
+    fn test_many_files_with_matches_out_of_file() {
+        // Files whose matches are all beyond the end of the file were skipped by recursion and overflowed the stack
+        let paths = ["testdata/chunk/after.in", "testdata/chunk/before.in"];
+        let matches = (0..30000)
+            .map(|i| {
+                Ok(GrepMatch {
+                    path: paths[i % 2].into(),
+                    line_number: u64::MAX,
+                    ranges: vec![],
+                })
+            })
+            .chain(iter::once(Ok(GrepMatch {
+                path: "Cargo.toml".into(),
+                line_number: 1,
+                ranges: vec![],
+            })));
+        let mut files = Files::new(matches, 1, 3, None).unwrap();
+        let file = files.next().unwrap().unwrap();
+        assert_eq!(file.path, Path::new("Cargo.toml"));
+        assert!(files.next().is_none());
+    }
+
+    #[test]
+    fn test_sort_by_match_count() {
+        let file = |path: &str, lnums: &[u64]| {
+            let lmats = lnums.iter().copied().map(LineMatch::lnum).collect();
+            File::new(PathBuf::from(path), lmats, vec![], String::new())
+        };
+        let mut files = vec![
+            file("b.txt", &[1]),
+            file("c.txt", &[1, 2, 3]),
+            file("a.txt", &[1]),
+            file("d.txt", &[1, 2]),
+        ];
+
+        sort_by_match_count(&mut files, SortOrder::Descending);
+        let paths: Vec<_> = files.iter().map(|f| f.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["c.txt", "d.txt", "a.txt", "b.txt"]);
+
+        sort_by_match_count(&mut files, SortOrder::Ascending);
+        let paths: Vec<_> = files.iter().map(|f| f.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["a.txt", "b.txt", "d.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn test_duplicate_lines_from_grep_only_matching() {
+        // Output of `grep -onH hit testdata/chunk/only_matching.in` reports the same line once per match
+        let dir = Path::new("testdata").join("chunk");
+        let input = fs::read(dir.join("only_matching.grep")).unwrap();
+        let files: Vec<_> = Files::new(input.grep_lines(), 3, 6, None)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let expected = test::read_expected_chunks(&dir, "only_matching").unwrap();
+        assert_eq!(files, vec![expected]);
+    }
+
+    #[test]
+    fn test_merge_ranges_of_duplicate_lines() {
+        let path = Path::new("testdata").join("chunk").join("only_matching.in");
+        let mat = |line_number, ranges| {
+            Result::Ok(GrepMatch {
+                path: path.clone(),
+                line_number,
+                ranges,
+            })
+        };
+        let matches = vec![
+            mat(5, vec![(6, 9)]),
+            mat(5, vec![(2, 5)]),
+            mat(5, vec![(10, 13), (6, 9)]),
+            mat(9, vec![(2, 5)]),
+        ];
+
+        let files: Vec<_> = Files::new(matches.into_iter(), 3, 6, None)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        let expected = [
+            LineMatch::new(5, vec![(2, 5), (6, 9), (10, 13)]),
+            LineMatch::new(9, vec![(2, 5)]),
+        ];
+        assert_eq!(files[0].line_matches.as_ref(), &expected);
+    }
+
+    #[test]
+    fn test_overlap_policy() {
+        let path = Path::new("testdata").join("chunk").join("overlap.txt");
+        let mat = |line_number| {
+            Result::Ok(GrepMatch {
+                path: path.clone(),
+                line_number,
+                ranges: vec![],
+            })
+        };
+
+        for (policy, want) in [
+            (OverlapPolicy::Merge, vec![(2, 11), (13, 19)]),
+            (OverlapPolicy::Separate, vec![(2, 7), (8, 11), (13, 19)]),
+            (OverlapPolicy::Clip, vec![(2, 6), (7, 11), (13, 19)]),
+        ] {
+            let matches = vec![mat(5), mat(8), mat(16)];
+            let mut files = Files::new(matches.into_iter(), 1, 3, None).unwrap();
+            files.overlap_policy(policy);
+            let files: Vec<_> = files.collect::<Result<_>>().unwrap();
+            assert_eq!(files.len(), 1, "{policy:?}");
+            assert_eq!(files[0].chunks.as_ref(), &want, "{policy:?}");
+            let lnums: Vec<_> = files[0]
+                .line_matches
+                .iter()
+                .map(|m| m.line_number)
+                .collect();
+            assert_eq!(lnums, vec![5, 8, 16], "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_context_bias() {
+        let dir = Path::new("testdata").join("chunk");
+
+        // Lines are removed from the side which the bias does not favor. Each line in the chunk (2, 14) is 2 or 3 bytes
+        for (bias, want) in [
+            (ContextBias::Balanced, (6, 10)),
+            (ContextBias::Before, (4, 8)),
+            (ContextBias::After, (8, 11)),
+        ] {
+            let matches = test::read_matches(&dir, "single_max");
+            let mut files = Files::new(matches.into_iter(), 3, 6, None).unwrap();
+            files.context_max_bytes(11).context_bias(bias);
+            let files: Vec<_> = files.collect::<Result<_>>().unwrap();
+            assert_eq!(files[0].chunks.as_ref(), &[want], "{bias:?}");
+        }
+
+        // Overlapped lines between matches at line 5 and 8 go to the chunk which the bias favors
+        let path = dir.join("overlap.txt");
+        for (bias, want) in [
+            (ContextBias::Balanced, vec![(2, 6), (7, 11)]),
+            (ContextBias::Before, vec![(2, 5), (6, 11)]),
+            (ContextBias::After, vec![(2, 7), (8, 11)]),
+        ] {
+            let matches = [5, 8].map(|line_number| {
+                Result::Ok(GrepMatch {
+                    path: path.clone(),
+                    line_number,
+                    ranges: vec![],
+                })
+            });
+            let mut files = Files::new(matches.into_iter(), 1, 3, None).unwrap();
+            files.overlap_policy(OverlapPolicy::Clip).context_bias(bias);
+            let files: Vec<_> = files.collect::<Result<_>>().unwrap();
+            assert_eq!(files[0].chunks.as_ref(), &want, "{bias:?}");
+        }
+    }
+
+    #[test]
+    fn test_separate_adjacent_matches() {
+        let path = Path::new("testdata").join("chunk").join("overlap.txt");
+        for (policy, want) in [
+            (OverlapPolicy::Separate, vec![(1, 3), (4, 5), (6, 9)]),
+            (OverlapPolicy::Clip, vec![(1, 3), (4, 5), (6, 9)]),
+        ] {
+            let matches = [3, 4, 6].map(|line_number| {
+                Result::Ok(GrepMatch {
+                    path: path.clone(),
+                    line_number,
+                    ranges: vec![],
+                })
+            });
+            let mut files = Files::new(matches.into_iter(), 1, 3, None).unwrap();
+            files.overlap_policy(policy);
+            let files: Vec<_> = files.collect::<Result<_>>().unwrap();
+            assert_eq!(files[0].chunks.as_ref(), &want, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        let contents = "a\tfoo\n\tbar\tfoo\nab\t\tfoo\r\nfoo\n";
+        let mut file = File::new(
+            PathBuf::from("test.txt"),
+            vec![
+                LineMatch::new(1, vec![(2, 5)]),
+                LineMatch::new(2, vec![(0, 1), (5, 8)]),
+                LineMatch::new(3, vec![(4, 7)]),
+            ],
+            vec![(1, 4)],
+            contents.to_string(),
+        );
+        file.context_matches = vec![LineMatch::new(4, vec![(0, 3)])].into_boxed_slice();
+        file.expand_tabs(4);
+
+        assert_eq!(
+            file.contents.as_ref(),
+            "a   foo\n    bar foo\nab      foo\r\nfoo\n",
+        );
+        let expected = [
+            LineMatch::new(1, vec![(4, 7)]),
+            LineMatch::new(2, vec![(0, 4), (8, 11)]),
+            LineMatch::new(3, vec![(8, 11)]),
+        ];
+        assert_eq!(file.line_matches.as_ref(), &expected);
+        assert_eq!(
+            file.context_matches.as_ref(),
+            &[LineMatch::new(4, vec![(0, 3)])],
+        );
+        for lm in file.line_matches.iter() {
+            let (line, _) = LinesInclusive::new(&file.contents)
+                .find(|(_, n)| *n == lm.line_number)
+                .unwrap();
+            let (s, e) = *lm.ranges.last().unwrap();
+            assert_eq!(&line[s..e], "foo");
+        }
+    }
+
+    #[test]
+    fn test_normalize_match_case() {
+        let contents = "Foo foo FOO\nfOo\nfoobar FOOBAR\nStraße\n";
+        let mut file = File::new(
+            PathBuf::from("test.txt"),
+            vec![
+                LineMatch::new(1, vec![(0, 3), (8, 11)]),
+                LineMatch::new(3, vec![(7, 10)]),
+                LineMatch::new(4, vec![(0, 7)]),
+            ],
+            vec![(1, 4)],
+            contents.to_string(),
+        );
+        file.context_matches = vec![LineMatch::new(2, vec![(0, 3)])].into_boxed_slice();
+        file.normalize_match_case("foo");
+        // Only matched regions are replaced. Text which was not matched keeps its case
+        assert_eq!(
+            file.contents.as_ref(),
+            "foo foo foo\nfoo\nfoobar fooBAR\nStraße\n",
+        );
+
+        // 'ß' is not equal to 'ss' ignoring case
+        file.normalize_match_case("STRASSE");
+        assert_eq!(
+            file.contents.as_ref(),
+            "foo foo foo\nfoo\nfoobar fooBAR\nStraße\n",
+        );
+        file.normalize_match_case("straße");
+        assert!(file.contents.ends_with("\nstraße\n"), "{:?}", file.contents);
+    }
+
+    #[test]
+    fn test_normalize_match_case_with_line_offset() {
+        let mut file = File::new(
+            PathBuf::from("test.txt"),
+            vec![LineMatch::new(11, vec![(4, 7)])],
+            vec![(10, 11)],
+            "FOO\nbar FOO\n".to_string(),
+        );
+        file.line_offset = 9;
+        file.normalize_match_case("Foo");
+        assert_eq!(file.contents.as_ref(), "FOO\nbar Foo\n");
+    }
+
+    #[test]
+    fn test_suppress_empty_context() {
+        let contents = "\nfoo\n  \n\nfoo\nfoo\n\t\n\nbar\nfoo\n\nfoo\n";
+        for (lnums, chunks, want) in [
+            // All context lines are blank
+            (&[2][..], &[(1, 3)][..], &[(2, 2)][..]),
+            (&[2, 5, 6], &[(1, 8)], &[(2, 2), (5, 6)]),
+            // Context line 'bar' is not blank
+            (&[10, 12], &[(8, 12)], &[(8, 12)]),
+            (&[2, 10, 12], &[(1, 3), (8, 12)], &[(2, 2), (8, 12)]),
+            // No context line
+            (&[5, 6], &[(5, 6)], &[(5, 6)]),
+        ] {
+            let lmats = lnums.iter().map(|&n| LineMatch::lnum(n)).collect();
+            let path = PathBuf::from("test.txt");
+            let mut file = File::new(path, lmats, chunks.to_vec(), contents.to_string());
+            file.suppress_empty_context();
+            assert_eq!(file.chunks.as_ref(), want, "{lnums:?} {chunks:?}");
+        }
+    }
+
+    #[test]
+    fn test_files_suppress_empty_context() {
+        let dir = Path::new("testdata").join("chunk");
+        let matches = test::read_matches(&dir, "blank_context");
+        let mut files = Files::new(matches.into_iter(), 3, 6, None).unwrap();
+        files.suppress_empty_context(true);
+        let got: Vec<_> = files.collect::<Result<_>>().unwrap();
+
+        // Only the chunk whose context lines are all blank loses its context lines
+        let mut expected = test::read_expected_chunks(&dir, "blank_context").unwrap();
+        assert_eq!(expected.chunks.as_ref(), &[(2, 8), (20, 25)]);
+        expected.chunks = vec![(5, 5), (20, 25)].into_boxed_slice();
+        assert_eq!(got, [expected]);
+    }
+
+    #[test]
+    fn test_expand_tabs_with_zero_width() {
+        let mut file = File::new(
+            PathBuf::from("test.txt"),
+            vec![LineMatch::new(1, vec![(1, 4)])],
+            vec![(1, 1)],
+            "\tfoo\n".to_string(),
+        );
+        file.expand_tabs(0);
+        assert_eq!(file.contents.as_ref(), "\tfoo\n");
+        assert_eq!(file.line_matches[0].ranges, vec![(1, 4)]);
+    }
+
+    #[test]
+    fn test_group_by_match() {
+        let contents = "fooBar foo_bar\nnothing\nfoo_bar\nFOO_BAR fooBar\n";
+        let file = |path: &str| {
+            let lmats = vec![
+                LineMatch::new(1, vec![(0, 6), (7, 14)]),
+                LineMatch::new(3, vec![(0, 7)]),
+                LineMatch::new(4, vec![(0, 7), (8, 14)]),
+            ];
+            File::new(
+                PathBuf::from(path),
+                lmats,
+                vec![(1, 1), (3, 4)],
+                contents.to_string(),
+            )
+        };
+
+        let groups = group_by_match(vec![file("b.txt"), file("a.txt")]);
+        let texts: Vec<_> = groups.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(texts, ["FOO_BAR", "fooBar", "foo_bar"]);
+
+        for (text, files) in &groups {
+            let paths: Vec<_> = files.iter().map(|f| f.path.to_str().unwrap()).collect();
+            assert_eq!(paths, ["a.txt", "b.txt"], "text={:?}", text);
+            for f in files {
+                let lines: Vec<_> = f.contents.lines().collect();
+                for m in f.line_matches.iter() {
+                    for &(s, e) in &m.ranges {
+                        let line = lines[m.line_number as usize - 1];
+                        assert_eq!(&line[s..e], text);
+                    }
+                }
+            }
+        }
+
+        let (_, files) = &groups[0];
+        assert_eq!(
+            files[0].line_matches.as_ref(),
+            &[LineMatch::new(4, vec![(0, 7)])]
+        );
+        assert_eq!(files[0].chunks.as_ref(), &[(3, 4)]);
+        let (_, files) = &groups[1];
+        let expected = [
+            LineMatch::new(1, vec![(0, 6)]),
+            LineMatch::new(4, vec![(8, 14)]),
+        ];
+        assert_eq!(files[0].line_matches.as_ref(), &expected);
+        assert_eq!(files[0].chunks.as_ref(), &[(1, 1), (3, 4)]);
+    }
+
+    #[test]
+    fn test_relative_to_base_dir() {
+        let mat = |path: PathBuf| {
+            Result::Ok(GrepMatch {
+                path,
+                line_number: 8,
+                ranges: vec![],
+            })
+        };
+        let cwd = env::current_dir().unwrap();
+        let relative = Path::new("testdata").join("chunk").join("single_max.in");
+        let absolute = cwd.join(&relative);
+
+        for path in [relative, absolute] {
+            let mut files = Files::new(iter::once(mat(path.clone())), 3, 6, None).unwrap();
+            files.base_dir(cwd.join("testdata"));
+            let file = files.next().unwrap().unwrap();
+            assert_eq!(
+                file.path,
+                Path::new("chunk").join("single_max.in"),
+                "input path: {path:?}",
+            );
+        }
+    }
+
     #[test]
     fn test_error_while_matching() {
         #[derive(Debug)]
@@ -592,6 +1627,9 @@ mod tests {
 
             let expected = [File {
                 path,
+                context_matches: Box::new([]),
+                annotation: None,
+                line_offset: 0,
                 line_matches: vec![LineMatch {
                     line_number: 4,
                     ranges,
@@ -623,6 +1661,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_file_equality() {
+        fn assert_eq_impl<T: Eq>() {}
+        assert_eq_impl::<File>();
+        assert_eq_impl::<LineMatch>();
+        assert_eq_impl::<GrepMatch>();
+
+        let file = File::sample_file();
+        assert_eq!(file, file.clone());
+
+        // Every field is compared. Files which differ only in one field are not equal
+        let changes: [fn(&mut File); 8] = [
+            |f| f.path.push("foo"),
+            |f| f.line_matches[0].line_number += 1,
+            |f| f.line_matches[0].ranges.push((0, 1)),
+            |f| f.chunks[0].1 += 1,
+            |f| f.contents = format!("{}\n", f.contents).into_boxed_str(),
+            |f| f.context_matches = vec![LineMatch::lnum(1)].into_boxed_slice(),
+            |f| f.annotation = Some("modified".to_string()),
+            |f| f.line_offset += 1,
+        ];
+        for (i, change) in changes.iter().enumerate() {
+            let mut other = file.clone();
+            change(&mut other);
+            assert_ne!(file, other, "change #{i}");
+        }
+
+        let lmat = LineMatch::new(1, vec![(0, 1), (2, 3)]);
+        assert_eq!(lmat, LineMatch::new(1, vec![(0, 1), (2, 3)]));
+        assert_ne!(lmat, LineMatch::new(1, vec![(2, 3), (0, 1)]));
+        assert_ne!(lmat, LineMatch::new(2, vec![(0, 1), (2, 3)]));
+    }
+
     #[test]
     fn test_file_get_first_line() {
         let tests = [
@@ -669,7 +1740,8 @@ mod tests {
         ];
 
         for (encoding, contents) in tests {
-            let text = decode_text(contents.to_vec(), Some(encoding));
+            let text =
+                decode_text(contents.to_vec(), Some(encoding), EncodingErrors::Replace).unwrap();
             assert_eq!(text, "こんにちは\r\n", "encoding={encoding:?}");
         }
     }
@@ -678,14 +1750,73 @@ mod tests {
     fn test_decode_content_with_encoding_detected_from_bom() {
         let tests = [HELLO_UTF_16BE_BOM, HELLO_UTF_16LE_BOM, HELLO_UTF_8_BOM];
         for contents in tests {
-            let text = decode_text(contents.to_vec(), None);
+            let text = decode_text(contents.to_vec(), None, EncodingErrors::Replace).unwrap();
             assert_eq!(text, "こんにちは\r\n", "input={contents:?}");
         }
     }
 
     #[test]
     fn test_decode_with_replacement_char_for_malformed_utf8_file() {
-        let text = decode_text(vec![0xff], Some(UTF_8));
+        let text = decode_text(vec![0xff], Some(UTF_8), EncodingErrors::Replace).unwrap();
         assert_eq!(text, "\u{fffd}");
     }
+
+    #[test]
+    fn test_decode_with_encoding_errors() {
+        let dir = Path::new("testdata").join("chunk").join("encoding");
+        let tests = [
+            ("invalid_utf8.txt", None),
+            ("invalid_sjis.txt", Some(SHIFT_JIS)),
+        ];
+        for (file, encoding) in tests {
+            let bytes = fs::read(dir.join(file)).unwrap();
+            let text = decode_text(bytes.clone(), encoding, EncodingErrors::Replace).unwrap();
+            assert_eq!(text, "こんにちは\u{fffd}\nworld\n", "{file:?}");
+            let text = decode_text(bytes.clone(), encoding, EncodingErrors::Skip).unwrap();
+            assert_eq!(text, "こんにちは\nworld\n", "{file:?}");
+            let err = decode_text(bytes, encoding, EncodingErrors::Strict).unwrap_err();
+            let msg = format!("{err}");
+            assert!(
+                msg.contains("Malformed byte sequence for encoding"),
+                "{file:?} {msg:?}"
+            );
+        }
+
+        // Valid text is not changed by the modes
+        for errors in [
+            EncodingErrors::Replace,
+            EncodingErrors::Skip,
+            EncodingErrors::Strict,
+        ] {
+            let text = decode_text(HELLO_UTF_16LE_BOM.to_vec(), None, errors).unwrap();
+            assert_eq!(text, "こんにちは\r\n", "{errors:?}");
+        }
+    }
+
+    #[test]
+    fn test_files_encoding_errors() {
+        let path = Path::new("testdata")
+            .join("chunk")
+            .join("encoding")
+            .join("invalid_sjis.txt");
+        let matches = || {
+            iter::once(Ok(GrepMatch {
+                path: path.clone(),
+                line_number: 2,
+                ranges: vec![(0, 5)],
+            }))
+        };
+
+        let mut files = Files::new(matches(), 0, 0, Some("sjis")).unwrap();
+        files.encoding_errors(EncodingErrors::Skip);
+        let file = files.next().unwrap().unwrap();
+        assert_eq!(file.contents.as_ref(), "こんにちは\nworld\n");
+
+        let mut files = Files::new(matches(), 0, 0, Some("sjis")).unwrap();
+        files.encoding_errors(EncodingErrors::Strict);
+        let err = files.next().unwrap().unwrap_err();
+        let msg = format!("{err}");
+        assert!(msg.contains("Could not decode the matched file"), "{msg:?}");
+        assert!(files.next().is_none());
+    }
 }
@@ -0,0 +1,314 @@
+use crate::chunk::{File, LineMatch};
+use crate::printer::Printer;
+use anyhow::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+// Number of distinct chunks remembered by `DedupChunks` by default. About 100 bytes are used per chunk
+pub const DEFAULT_CAPACITY: usize = 10000;
+
+// Chunks which were already printed. The least recently seen chunk is forgotten when the number of chunks exceeds the
+// capacity so that memory usage is bounded on huge trees
+struct SeenChunks {
+    capacity: usize,
+    tick: u64,
+    origins: HashMap<u64, (String, u64)>, // Hash of chunk -> (Location of the first occurrence, Last seen tick)
+    order: BTreeMap<u64, u64>,            // Last seen tick -> Hash of chunk
+}
+
+impl SeenChunks {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            origins: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    // Return the location of the first occurrence when the chunk was already seen. Otherwise remember the chunk
+    fn first_occurrence(&mut self, hash: u64, location: impl FnOnce() -> String) -> Option<String> {
+        self.tick += 1;
+        if let Some((origin, seen)) = self.origins.get_mut(&hash) {
+            self.order.remove(seen);
+            *seen = self.tick;
+            self.order.insert(self.tick, hash);
+            return Some(origin.clone());
+        }
+
+        if self.origins.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.origins.remove(&oldest);
+            }
+        }
+        self.origins.insert(hash, (location(), self.tick));
+        self.order.insert(self.tick, hash);
+        None
+    }
+}
+
+fn hash_matches<'a>(
+    hasher: &mut DefaultHasher,
+    matches: impl Iterator<Item = &'a LineMatch>,
+    start: u64,
+) {
+    for m in matches {
+        (m.line_number - start).hash(hasher);
+        m.ranges.hash(hasher);
+    }
+}
+
+// Hash the lines and the matches in each chunk. Matches are hashed with line numbers relative to the start of the chunk
+// so that the same snippet at different lines is regarded as identical
+fn chunk_hashes(file: &File) -> Vec<u64> {
+    let mut hashers = vec![DefaultHasher::new(); file.chunks.len()];
+    let mut first = 0;
+    for (line, lnum) in file.lines() {
+        while first < file.chunks.len() && file.chunks[first].1 < lnum {
+            first += 1;
+        }
+        for (i, &(start, end)) in file.chunks.iter().enumerate().skip(first) {
+            if lnum < start {
+                break;
+            }
+            if lnum <= end {
+                line.hash(&mut hashers[i]);
+            }
+        }
+    }
+
+    let in_chunk = |m: &&LineMatch, (start, end)| start <= m.line_number && m.line_number <= end;
+    file.chunks
+        .iter()
+        .zip(hashers)
+        .map(|(&chunk, mut hasher)| {
+            let lmats = file.line_matches.iter().filter(|m| in_chunk(m, chunk));
+            hash_matches(&mut hasher, lmats, chunk.0);
+            let cmats = file.context_matches.iter().filter(|m| in_chunk(m, chunk));
+            hash_matches(&mut hasher, cmats, chunk.0);
+            hasher.finish()
+        })
+        .collect()
+}
+
+fn remove_chunks(mut file: File, removed: &[usize]) -> File {
+    let chunks: Vec<_> = file
+        .chunks
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !removed.contains(i))
+        .map(|(_, &chunk)| chunk)
+        .collect();
+    let kept = |m: &LineMatch| {
+        chunks
+            .iter()
+            .any(|&(s, e)| s <= m.line_number && m.line_number <= e)
+    };
+    file.line_matches = file
+        .line_matches
+        .into_vec()
+        .into_iter()
+        .filter(kept)
+        .collect();
+    file.context_matches = file
+        .context_matches
+        .into_vec()
+        .into_iter()
+        .filter(kept)
+        .collect();
+    file.chunks = chunks.into_boxed_slice();
+    file
+}
+
+// Printer which prints a chunk identical to an already printed one as a one-line reference like
+// "vendor/foo.rs:120-140: same as src/foo.rs:120-140" instead of the snippet. This is useful when the same files are
+// vendored in multiple places. Chunks are compared by their contents before highlighting. When the capacity is not
+// given, this printer prints files as-is
+pub struct DedupChunks<P> {
+    printer: P,
+    seen: Option<Mutex<SeenChunks>>,
+}
+
+impl<P> DedupChunks<P> {
+    pub fn new(printer: P, capacity: Option<usize>) -> Self {
+        Self {
+            printer,
+            seen: capacity.map(|c| Mutex::new(SeenChunks::new(c))),
+        }
+    }
+}
+
+impl<P: Printer> Printer for DedupChunks<P> {
+    fn print(&self, file: File) -> Result<()> {
+        let Some(seen) = &self.seen else {
+            return self.printer.print(file);
+        };
+
+        let hashes = chunk_hashes(&file);
+        let duplicates: Vec<_> = {
+            let mut seen = seen.lock().unwrap();
+            let path = file.path.display();
+            hashes
+                .into_iter()
+                .zip(file.chunks.iter())
+                .enumerate()
+                .filter_map(|(i, (hash, &(start, end)))| {
+                    let origin = seen.first_occurrence(hash, || format!("{path}:{start}-{end}"))?;
+                    Some((i, format!("{path}:{start}-{end}: same as {origin}")))
+                })
+                .collect()
+        };
+        if duplicates.is_empty() {
+            return self.printer.print(file);
+        }
+
+        let removed: Vec<_> = duplicates.iter().map(|(i, _)| *i).collect();
+        let file = remove_chunks(file, &removed);
+        if !file.chunks.is_empty() {
+            self.printer.print(file)?;
+        }
+        for (_, reference) in duplicates {
+            self.printer.print_header(&reference)?;
+        }
+        Ok(())
+    }
+
+    fn print_header(&self, header: &str) -> Result<()> {
+        self.printer.print_header(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[derive(Default)]
+    struct DummyPrinter(Mutex<Vec<String>>);
+
+    impl Printer for &DummyPrinter {
+        fn print(&self, file: File) -> Result<()> {
+            let chunks: Vec<_> = file
+                .chunks
+                .iter()
+                .map(|(s, e)| format!("{s}-{e}"))
+                .collect();
+            let lnums: Vec<_> = file
+                .line_matches
+                .iter()
+                .map(|m| m.line_number.to_string())
+                .collect();
+            let printed = format!(
+                "{}:{} matches:{}",
+                file.path.display(),
+                chunks.join(","),
+                lnums.join(",")
+            );
+            self.0.lock().unwrap().push(printed);
+            Ok(())
+        }
+
+        fn print_header(&self, header: &str) -> Result<()> {
+            self.0.lock().unwrap().push(format!("header: {header}"));
+            Ok(())
+        }
+    }
+
+    fn file(path: &str, contents: &str, lnums: &[u64], chunks: &[(u64, u64)]) -> File {
+        let lmats = lnums.iter().copied().map(LineMatch::lnum).collect();
+        File::new(
+            PathBuf::from(path),
+            lmats,
+            chunks.to_vec(),
+            contents.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_dedup_identical_chunks() {
+        let printer = DummyPrinter::default();
+        let dedup = DedupChunks::new(&printer, Some(DEFAULT_CAPACITY));
+        dedup
+            .print(file("a.rs", "x\nfoo\nx\n", &[2], &[(1, 3)]))
+            .unwrap();
+        // The same snippet at different lines is a duplicate
+        dedup
+            .print(file("b.rs", "y\ny\nx\nfoo\nx\n", &[4], &[(3, 5)]))
+            .unwrap();
+        // Only the second chunk is a duplicate
+        dedup
+            .print(file(
+                "c.rs",
+                "z\nfoo\nz\nx\nfoo\nx\n",
+                &[2, 5],
+                &[(1, 3), (4, 6)],
+            ))
+            .unwrap();
+        // Same lines with different matches are not duplicates
+        dedup
+            .print(file("d.rs", "x\nfoo\nx\n", &[1], &[(1, 3)]))
+            .unwrap();
+        drop(dedup);
+
+        let printed = printer.0.into_inner().unwrap();
+        assert_eq!(
+            printed,
+            [
+                "a.rs:1-3 matches:2",
+                "header: b.rs:3-5: same as a.rs:1-3",
+                "c.rs:1-3 matches:2",
+                "header: c.rs:4-6: same as a.rs:1-3",
+                "d.rs:1-3 matches:1",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_forget_least_recently_seen_chunk() {
+        let printer = DummyPrinter::default();
+        let dedup = DedupChunks::new(&printer, Some(2));
+        for (path, contents) in [
+            ("a1", "a\n"),
+            ("b1", "b\n"),
+            ("a2", "a\n"), // 'a' is seen more recently than 'b'
+            ("c1", "c\n"), // 'b' is forgotten
+            ("b2", "b\n"),
+            ("a3", "a\n"), // 'a' was forgotten by 'b'
+        ] {
+            dedup.print(file(path, contents, &[1], &[(1, 1)])).unwrap();
+        }
+        drop(dedup);
+
+        let printed = printer.0.into_inner().unwrap();
+        assert_eq!(
+            printed,
+            [
+                "a1:1-1 matches:1",
+                "b1:1-1 matches:1",
+                "header: a2:1-1: same as a1:1-1",
+                "c1:1-1 matches:1",
+                "b2:1-1 matches:1",
+                "a3:1-1 matches:1",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_no_dedup() {
+        let printer = DummyPrinter::default();
+        let dedup = DedupChunks::new(&printer, None);
+        for path in ["a", "b"] {
+            dedup.print(file(path, "x\n", &[1], &[(1, 1)])).unwrap();
+        }
+        dedup.print_header("group").unwrap();
+        drop(dedup);
+
+        let printed = printer.0.into_inner().unwrap();
+        assert_eq!(
+            printed,
+            ["a:1-1 matches:1", "b:1-1 matches:1", "header: group"]
+        );
+    }
+}
@@ -1,10 +1,33 @@
+//! Parser of grep results read from stdin.
+//!
+//! Each line must be in the format of `grep -nH` output:
+//!
+//! ```text
+//! LINE   := PATH ':' LNUM ':' TEXT
+//! PATH   := one or more bytes except for ':'
+//! LNUM   := SPACE* DIGIT+ SPACE*
+//! DIGIT  := '0'..'9'
+//! SPACE  := ASCII whitespace
+//! ```
+//!
+//! When a label is set with `GrepLines::label`, each line must be in the format of `grep -n` output without file name.
+//! All matches are in the file at the path of the label:
+//!
+//! ```text
+//! LINE   := LNUM ':' TEXT
+//! ```
+//!
+//! Some tools pad line numbers with spaces (` 42`) or zeros (`0042`) or put a space after the colon (`foo.rs: 42: text`)
+//! so the whitespace around the line number and leading zeros are accepted. A line number which is empty, contains
+//! other characters like signs, or overflows `u64` is an error.
+
 use crate::chunk::Files;
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
 use std::borrow::Cow;
 use std::ffi::OsString;
 use std::fmt;
 use std::io::BufRead;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str;
 
 #[cfg(target_os = "windows")]
@@ -61,9 +84,47 @@ pub struct GrepMatch {
 
 pub struct GrepLines<R: BufRead> {
     reader: R,
+    strip_ansi: bool,
+    label: Option<PathBuf>,
+    lsp: bool,
+    references: Option<std::vec::IntoIter<Result<GrepMatch>>>,
 }
 
 impl<R: BufRead> GrepLines<R> {
+    // Remove ANSI escape sequences from each line before parsing it. This is for reading colored output of grep tools
+    pub fn strip_ansi(mut self, yes: bool) -> Self {
+        self.strip_ansi = yes;
+        self
+    }
+
+    // Parse lines in the format of `grep -n` output which has no file name. All matches are in the file at the path
+    pub fn label(mut self, path: Option<PathBuf>) -> Self {
+        self.label = path;
+        self
+    }
+
+    // Read the whole input as a response of LSP `textDocument/references` request instead of grep output. See `crate::lsp`
+    pub fn lsp_references(mut self, yes: bool) -> Self {
+        self.lsp = yes;
+        self
+    }
+
+    fn next_reference(&mut self) -> Option<Result<GrepMatch>> {
+        let references = self.references.get_or_insert_with(|| {
+            let mut input = String::new();
+            let parsed = self
+                .reader
+                .read_to_string(&mut input)
+                .context("Could not read LSP response from stdin")
+                .and_then(|_| crate::lsp::parse_references(&input));
+            match parsed {
+                Ok(matches) => matches.into_iter().map(Ok).collect::<Vec<_>>().into_iter(),
+                Err(err) => vec![Err(err)].into_iter(),
+            }
+        });
+        references.next()
+    }
+
     pub fn chunks_per_file(
         self,
         min: u64,
@@ -74,6 +135,47 @@ impl<R: BufRead> GrepLines<R> {
     }
 }
 
+// Remove escape sequences like "\x1b[31m" (CSI) and "\x1b]8;;url\x1b\\" (OSC). Other sequences are assumed to have one
+// byte after ESC
+pub fn strip_ansi_escapes(line: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(line.len());
+    let mut bytes = line.iter().copied();
+    while let Some(b) = bytes.next() {
+        if b != b'\x1b' {
+            stripped.push(b);
+            continue;
+        }
+        match bytes.next() {
+            // Parameter and intermediate bytes continue until the final byte
+            Some(b'[') => {
+                for b in bytes.by_ref() {
+                    if (0x40..=0x7e).contains(&b) {
+                        break;
+                    }
+                }
+            }
+            // Terminated by BEL or ST ("\x1b\\")
+            Some(b']') => {
+                while let Some(b) = bytes.next() {
+                    if b == b'\x07' || b == b'\x1b' && bytes.next() == Some(b'\\') {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+// `str::parse` is not used directly since it also accepts a leading '+'
+fn parse_line_number(digits: &[u8]) -> Option<u64> {
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    str::from_utf8(digits).ok()?.parse().ok()
+}
+
 fn parse_line(line: Vec<u8>) -> Result<GrepMatch> {
     // {path}:{lnum}:{line}...
     let mut split = line.splitn(3, |&b| b == b':');
@@ -84,7 +186,11 @@ fn parse_line(line: Vec<u8>) -> Result<GrepMatch> {
         (Some(p), Some(l), Some(_)) => (p, l),
         _ => return ParseError::err(line, "Path or line number is missing"),
     };
-    match str::from_utf8(lnum).ok().and_then(|s| s.parse().ok()) {
+    let lnum = lnum.trim_ascii();
+    if lnum.is_empty() {
+        return ParseError::err(line, "Path or line number is empty");
+    }
+    match parse_line_number(lnum) {
         Some(lnum) => Ok(GrepMatch {
             path: PathBuf::from(bytes_to_os_string(path)),
             line_number: lnum,
@@ -94,16 +200,46 @@ fn parse_line(line: Vec<u8>) -> Result<GrepMatch> {
     }
 }
 
+fn parse_line_without_path(line: Vec<u8>, path: &Path) -> Result<GrepMatch> {
+    // {lnum}:{line}...
+    let mut split = line.splitn(2, |&b| b == b':');
+    let lnum = match (split.next(), split.next()) {
+        (Some(l), Some(_)) => l,
+        _ => return ParseError::err(line, "Line number is missing"),
+    };
+    let lnum = lnum.trim_ascii();
+    if lnum.is_empty() {
+        return ParseError::err(line, "Line number is empty");
+    }
+    match parse_line_number(lnum) {
+        Some(lnum) => Ok(GrepMatch {
+            path: path.to_path_buf(),
+            line_number: lnum,
+            ranges: vec![],
+        }),
+        None => ParseError::err(line, "Could not parse line number as unsigned integer"),
+    }
+}
+
 impl<R: BufRead> Iterator for GrepLines<R> {
     type Item = Result<GrepMatch>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.lsp {
+            return self.next_reference();
+        }
         let mut buf = Vec::new();
         self.reader.read_until(b'\n', &mut buf).unwrap();
         if buf.is_empty() {
             return None;
         }
-        Some(parse_line(buf))
+        if self.strip_ansi && buf.contains(&b'\x1b') {
+            buf = strip_ansi_escapes(&buf);
+        }
+        match &self.label {
+            Some(path) => Some(parse_line_without_path(buf, path)),
+            None => Some(parse_line(buf)),
+        }
     }
 }
 
@@ -113,7 +249,13 @@ pub trait BufReadExt: BufRead + Sized {
 
 impl<R: BufRead> BufReadExt for R {
     fn grep_lines(self) -> GrepLines<Self> {
-        GrepLines { reader: self }
+        GrepLines {
+            reader: self,
+            strip_ansi: false,
+            label: None,
+            lsp: false,
+            references: None,
+        }
     }
 }
 
@@ -123,6 +265,9 @@ fn test_read_ok() {
         "/path/to/foo.txt:1:    hello",
         "/path/to/bar.txt:100:    bye",
         "/path/to/bar.txt:110:    this : line : include : colon",
+        "/path/to/foo.txt: 42:    padded",
+        "/path/to/foo.txt:0042:    zero padded",
+        "/path/to/foo.txt:\t7 :    tab",
     ]
     .join("\n")
     .into_bytes();
@@ -145,6 +290,21 @@ fn test_read_ok() {
             line_number: 110,
             ranges: vec![],
         },
+        GrepMatch {
+            path: PathBuf::from("/path/to/foo.txt"),
+            line_number: 42,
+            ranges: vec![],
+        },
+        GrepMatch {
+            path: PathBuf::from("/path/to/foo.txt"),
+            line_number: 42,
+            ranges: vec![],
+        },
+        GrepMatch {
+            path: PathBuf::from("/path/to/foo.txt"),
+            line_number: 7,
+            ranges: vec![],
+        },
     ];
 
     assert_eq!(&output, expected);
@@ -159,6 +319,10 @@ fn test_read_error() {
         "/path/to/foo.txt:   hello : world",
         ":",
         "::",
+        "/path/to/foo.txt:  :   blank",
+        "/path/to/foo.txt:+42:   sign",
+        "/path/to/foo.txt:4 2:   space",
+        "/path/to/foo.txt:18446744073709551616:   overflow",
     ]
     .join("\n")
     .into_bytes();
@@ -175,6 +339,10 @@ fn test_read_error() {
         "Could not parse line number as unsigned integer:",
         "Path or line number is missing:",
         "Path or line number is empty:",
+        "Path or line number is empty:",
+        "Could not parse line number as unsigned integer:",
+        "Could not parse line number as unsigned integer:",
+        "Could not parse line number as unsigned integer:",
     ];
 
     assert_eq!(msgs.len(), expected.len());
@@ -188,3 +356,180 @@ fn test_read_error() {
         );
     }
 }
+
+#[test]
+fn test_read_without_filename() {
+    let input = [
+        "1:    hello",
+        " 42 :    padded",
+        "110:    this : line : include : colon",
+        "/path/to/foo.txt:1:    with path",
+        "",
+        ":   empty",
+        "no colon",
+    ]
+    .join("\n")
+    .into_bytes();
+
+    let got: Vec<_> = input
+        .grep_lines()
+        .label(Some(PathBuf::from("foo.txt")))
+        .map(|r| r.map_err(|e| format!("{}", e)))
+        .collect();
+
+    let ok = |lnum| {
+        Ok(GrepMatch {
+            path: PathBuf::from("foo.txt"),
+            line_number: lnum,
+            ranges: vec![],
+        })
+    };
+    assert_eq!(got[..3], [ok(1), ok(42), ok(110)]);
+
+    let expected = [
+        "Could not parse line number as unsigned integer:",
+        "Line number is missing:",
+        "Line number is empty:",
+        "Line number is missing:",
+    ];
+    assert_eq!(got.len(), 3 + expected.len());
+    for (got, expected) in got[3..].iter().zip(expected.iter()) {
+        let got = got.as_ref().unwrap_err();
+        assert!(
+            got.contains(expected),
+            "expected {:?} is included in {:?}",
+            expected,
+            got
+        );
+    }
+}
+
+#[test]
+fn test_parse_padded_line_number() {
+    fn prop(lnum: u64, zeros: u8, before: Vec<bool>, after: Vec<bool>) -> bool {
+        let pad = |p: &[bool]| -> String {
+            p.iter()
+                .take(4)
+                .map(|&tab| if tab { '\t' } else { ' ' })
+                .collect()
+        };
+        let zeros = "0".repeat(zeros as usize % 8);
+        let line = format!(
+            "foo.txt:{}{}{}{}: text\n",
+            pad(&before),
+            zeros,
+            lnum,
+            pad(&after),
+        );
+        match parse_line(line.into_bytes()) {
+            Ok(m) => m.line_number == lnum && m.path.as_os_str() == "foo.txt",
+            Err(_) => false,
+        }
+    }
+    quickcheck::quickcheck(prop as fn(u64, u8, Vec<bool>, Vec<bool>) -> bool);
+}
+
+#[test]
+fn test_parse_overflowed_line_number() {
+    fn prop(delta: u64) -> bool {
+        let lnum = u64::MAX as u128 + 1 + delta as u128;
+        parse_line(format!("foo.txt:{}: text\n", lnum).into_bytes()).is_err()
+    }
+    quickcheck::quickcheck(prop as fn(u64) -> bool);
+}
+
+#[test]
+fn test_parse_random_line_number() {
+    // Build the line number field from the characters which appear around line numbers so that both valid and invalid
+    // fields are generated frequently
+    fn prop(indices: Vec<u8>) -> bool {
+        const CHARS: &[u8] = b"0123456789 \t+-x";
+        let field: String = indices
+            .iter()
+            .map(|&i| CHARS[i as usize % CHARS.len()] as char)
+            .collect();
+
+        let digits = field.trim_matches(|c: char| c.is_ascii_whitespace());
+        let valid = !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit())
+            && digits.parse::<u128>().is_ok_and(|n| n <= u64::MAX as u128);
+
+        let line = format!("foo.txt:{}: text\n", field);
+        match parse_line(line.into_bytes()) {
+            Ok(m) => valid && m.line_number as u128 == digits.parse::<u128>().unwrap(),
+            Err(_) => !valid,
+        }
+    }
+    quickcheck::quickcheck(prop as fn(Vec<u8>) -> bool);
+}
+
+#[test]
+fn test_strip_ansi() {
+    // Output of `grep --color=always -nH` and `rg --color=always -n --hyperlink-format=default`
+    let input = [
+        "\x1b[35m\x1b[Kfoo.rs\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\x1b[32m\x1b[K12\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[Kfn \x1b[01;31m\x1b[Kmain\x1b[m\x1b[K()",
+        "\x1b]8;;file:///bar.txt\x1b\\\x1b[0m\x1b[35mbar.txt\x1b[0m\x1b]8;;\x1b\\:\x1b[0m\x1b[32m3\x1b[0m:  \x1b[0m\x1b[1m\x1b[31mhi\x1b[0m",
+        "baz.txt:\x1b]8;;file:///baz.txt\x07\x1b[1m7\x1b[0m\x1b]8;;\x07:text",
+    ]
+    .join("\n")
+    .into_bytes();
+
+    let got: Vec<_> = input
+        .as_slice()
+        .grep_lines()
+        .strip_ansi(true)
+        .collect::<Result<_>>()
+        .unwrap();
+    let expected = [("foo.rs", 12), ("bar.txt", 3), ("baz.txt", 7)].map(|(path, lnum)| GrepMatch {
+        path: PathBuf::from(path),
+        line_number: lnum,
+        ranges: vec![],
+    });
+    assert_eq!(got, expected);
+
+    assert_eq!(strip_ansi_escapes(b"a\x1b[1;31mb\x1b[0mc"), b"abc");
+    assert_eq!(
+        strip_ansi_escapes(b"\x1b[38;2;1;2;3mfoo \x1b[48;5;10mbar"),
+        b"foo bar"
+    );
+    // Unterminated sequences are removed until the end of line
+    assert_eq!(strip_ansi_escapes(b"abc\x1b[1;3"), b"abc");
+    assert_eq!(strip_ansi_escapes(b"abc\x1b"), b"abc");
+
+    // Colored lines are not parsed without stripping
+    assert!(input.as_slice().grep_lines().all(|r| r.is_err()));
+}
+
+#[test]
+fn test_read_lsp_references() {
+    let input = r#"{"jsonrpc":"2.0","id":1,"result":[
+        {"uri":"file:///path/to/foo.txt","range":{"start":{"line":4,"character":0},"end":{"line":4,"character":3}}},
+        {"uri":"file:///path/to/bar.txt","range":{"start":{"line":0,"character":0},"end":{"line":0,"character":3}}}
+    ]}"#
+    .as_bytes();
+
+    let output: Vec<_> = input
+        .grep_lines()
+        .lsp_references(true)
+        .map(|r| r.map(|m| (m.path, m.line_number)))
+        .collect::<Result<_>>()
+        .unwrap();
+
+    let expected = &[
+        (PathBuf::from("/path/to/bar.txt"), 1),
+        (PathBuf::from("/path/to/foo.txt"), 5),
+    ];
+    assert_eq!(&output, expected);
+
+    // Grep output is not accepted as LSP response. The error is reported once
+    let mut lines = "/path/to/foo.txt:1:    hello\n"
+        .as_bytes()
+        .grep_lines()
+        .lsp_references(true);
+    let err = lines.next().unwrap().unwrap_err();
+    assert!(
+        format!("{}", err).contains("Could not parse LSP response as JSON"),
+        "{err}"
+    );
+    assert!(lines.next().is_none());
+}
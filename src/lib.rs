@@ -4,7 +4,11 @@
 compile_error!("Either feature \"bat-printer\" or \"syntect-printer\" must be enabled");
 
 pub mod chunk;
+pub mod dedup;
 pub mod grep;
+pub mod lsp;
+pub mod paginate;
+pub mod porcelain;
 pub mod printer;
 
 mod broken_pipe;
@@ -20,3 +24,5 @@ pub mod syntect;
 mod test;
 
 pub use anyhow::{Error, Result};
+#[cfg(feature = "ripgrep")]
+pub use ripgrep::search_str;
@@ -0,0 +1,290 @@
+// Parser of LSP `textDocument/references` responses read from stdin. This is experimental.
+//
+// The input is either a JSON-RPC response message or only its `result` value, which is an array of `Location` objects:
+//
+//   {"jsonrpc": "2.0", "id": 1, "result": [
+//     {"uri": "file:///path/to/foo.rs", "range": {"start": {"line": 9, "character": 4}, "end": {"line": 9, "character": 7}}}
+//   ]}
+//
+// Line numbers in LSP are 0-based. Each line covered by the range of a location is converted to a match. Character
+// offsets in the ranges are not used since they are counted in UTF-16 code units by default, so the whole lines are
+// highlighted as matches like grep results read from stdin. Only `file://` URIs are supported.
+
+use crate::grep::GrepMatch;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+fn hex_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hi = hex_digit(bytes.next()?)?;
+            let lo = hex_digit(bytes.next()?)?;
+            decoded.push(hi << 4 | lo);
+        } else {
+            decoded.push(b);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(target_os = "windows")]
+fn path_from_uri_path(decoded: Vec<u8>) -> Option<PathBuf> {
+    // file:///C:/path/to/file -> C:/path/to/file
+    let s = String::from_utf8(decoded).ok()?;
+    let s = match s.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &s[1..],
+        _ => &s,
+    };
+    Some(PathBuf::from(OsString::from(s)))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn path_from_uri_path(decoded: Vec<u8>) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(OsString::from_vec(decoded)))
+}
+
+fn path_from_uri(uri: &str) -> Result<PathBuf> {
+    let Some(rest) = uri.strip_prefix("file://") else {
+        anyhow::bail!("Only file:// URI is supported but got {:?}", uri);
+    };
+    // Authority part like 'localhost' in file://localhost/path/to/file is ignored
+    let path = &rest[rest.find('/').unwrap_or(rest.len())..];
+    if path.is_empty() {
+        anyhow::bail!("Path is empty in URI {:?}", uri);
+    }
+    percent_decode(path)
+        .and_then(path_from_uri_path)
+        .with_context(|| format!("Could not decode path in URI {:?}", uri))
+}
+
+// Ranges spanning more lines than this are rejected since references are usually symbols in a single line
+const MAX_RANGE_LINES: u64 = 10000;
+
+fn position_of(range: &Value, pos: &str) -> Option<(u64, u64)> {
+    let pos = range.get(pos)?;
+    Some((pos.get("line")?.as_u64()?, pos.get("character")?.as_u64()?))
+}
+
+fn parse_location(loc: &Value) -> Result<(PathBuf, u64, u64)> {
+    let uri = loc
+        .get("uri")
+        .and_then(Value::as_str)
+        .with_context(|| format!("Location has no \"uri\" string: {}", loc))?;
+    let path = path_from_uri(uri)?;
+    let range = loc
+        .get("range")
+        .with_context(|| format!("Location has no \"range\" object: {}", loc))?;
+    let (Some((start, _)), Some((mut end, end_char))) =
+        (position_of(range, "start"), position_of(range, "end"))
+    else {
+        anyhow::bail!(
+            "Range must have \"line\" and \"character\" numbers at \"start\" and \"end\": {}",
+            range
+        );
+    };
+    if start > end {
+        anyhow::bail!(
+            "Start line {} is after end line {} in range: {}",
+            start,
+            end,
+            range
+        );
+    }
+    // The end position is exclusive. When it is at the head of a line, the line is not covered by the range
+    if end_char == 0 && end > start {
+        end -= 1;
+    }
+    if end - start >= MAX_RANGE_LINES {
+        anyhow::bail!("Range spans more than {} lines: {}", MAX_RANGE_LINES, range);
+    }
+    Ok((path, start, end))
+}
+
+// Parse the response of `textDocument/references` request and convert the locations into matches. The matches are sorted
+// by their paths and line numbers since `Files` assumes matches in the same file are consecutive and sorted
+pub fn parse_references(input: &str) -> Result<Vec<GrepMatch>> {
+    let json: Value =
+        serde_json::from_str(input).context("Could not parse LSP response as JSON")?;
+    let result = match json {
+        Value::Object(mut msg) => {
+            if let Some(err) = msg.get("error") {
+                anyhow::bail!("LSP response is an error: {}", err);
+            }
+            msg.remove("result")
+                .context("LSP response has neither \"result\" nor \"error\"")?
+        }
+        json => json,
+    };
+    let locations = match result {
+        Value::Array(locations) => locations,
+        Value::Null => vec![], // The result is null when no reference was found
+        result => anyhow::bail!(
+            "Result of textDocument/references must be an array of locations but got {}",
+            result
+        ),
+    };
+
+    let mut matches = vec![];
+    for loc in &locations {
+        let (path, start, end) = parse_location(loc)?;
+        for lnum in start..=end {
+            // 0-based to 1-based
+            let line_number = lnum
+                .checked_add(1)
+                .with_context(|| format!("Line number {} is too large: {}", lnum, loc))?;
+            matches.push(GrepMatch {
+                path: path.clone(),
+                line_number,
+                ranges: vec![],
+            });
+        }
+    }
+    matches.sort_by(|a, b| (&a.path, a.line_number).cmp(&(&b.path, b.line_number)));
+    matches.dedup();
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(uri: &str, start: u64, end: u64) -> String {
+        format!(
+            r#"{{"uri":"{uri}","range":{{"start":{{"line":{start},"character":4}},"end":{{"line":{end},"character":7}}}}}}"#
+        )
+    }
+
+    fn lines(matches: &[GrepMatch]) -> Vec<(PathBuf, u64)> {
+        matches
+            .iter()
+            .map(|m| (m.path.clone(), m.line_number))
+            .collect()
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_parse_references() {
+        let locs = [
+            location("file:///path/to/b.rs", 3, 3),
+            location("file:///path/to/a.rs", 9, 9),
+            location("file:///path/to/a.rs", 0, 1),
+            location("file:///path/to/a.rs", 9, 9),
+            location("file:///path/with%20space/c.rs", 41, 41),
+            location("file://localhost/path/to/d.rs", 0, 0),
+        ]
+        .join(",");
+        let expected = vec![
+            (PathBuf::from("/path/to/a.rs"), 1),
+            (PathBuf::from("/path/to/a.rs"), 2),
+            (PathBuf::from("/path/to/a.rs"), 10),
+            (PathBuf::from("/path/to/b.rs"), 4),
+            (PathBuf::from("/path/to/d.rs"), 1),
+            (PathBuf::from("/path/with space/c.rs"), 42),
+        ];
+
+        let response = format!(r#"{{"jsonrpc":"2.0","id":1,"result":[{locs}]}}"#);
+        assert_eq!(lines(&parse_references(&response).unwrap()), expected);
+        let result = format!("[{locs}]");
+        assert_eq!(lines(&parse_references(&result).unwrap()), expected);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_parse_references_end_at_line_head() {
+        let range = |start, end, end_char| {
+            format!(
+                r#"[{{"uri":"file:///a.rs","range":{{"start":{{"line":{start},"character":0}},"end":{{"line":{end},"character":{end_char}}}}}}}]"#
+            )
+        };
+        let a = || PathBuf::from("/a.rs");
+        let tests = [
+            (range(2, 4, 0), vec![(a(), 3), (a(), 4)]),
+            (range(2, 4, 1), vec![(a(), 3), (a(), 4), (a(), 5)]),
+            (range(2, 2, 0), vec![(a(), 3)]),
+            (range(2, 10002, 0), (3..=10002).map(|l| (a(), l)).collect()),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                lines(&parse_references(&input).unwrap()),
+                expected,
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_no_references() {
+        for input in [
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#,
+            r#"{"jsonrpc":"2.0","id":1,"result":[]}"#,
+            "[]",
+            "null",
+        ] {
+            assert_eq!(parse_references(input).unwrap(), vec![], "{input:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_references_error() {
+        let location = |uri, start, end| format!("[{}]", location(uri, start, end));
+        let tests = [
+            (String::new(), "Could not parse LSP response as JSON"),
+            (
+                r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"not supported"}}"#
+                    .into(),
+                "LSP response is an error",
+            ),
+            (
+                r#"{"jsonrpc":"2.0","id":1}"#.into(),
+                r#"has neither "result" nor "error""#,
+            ),
+            ("42".into(), "must be an array of locations"),
+            (
+                r#"[{"range":{}}]"#.into(),
+                r#"Location has no "uri" string"#,
+            ),
+            (
+                r#"[{"uri":"file:///a.rs"}]"#.into(),
+                r#"Location has no "range" object"#,
+            ),
+            (
+                r#"[{"uri":"file:///a.rs","range":{"start":{"line":1}}}]"#.into(),
+                r#"must have "line" and "character" numbers"#,
+            ),
+            (
+                location("file:///a.rs", 0, 10000),
+                "Range spans more than 10000 lines",
+            ),
+            (
+                location("file:///a.rs", 0, u64::MAX),
+                "Range spans more than 10000 lines",
+            ),
+            (
+                location("file:///a.rs", u64::MAX, u64::MAX),
+                "Line number 18446744073709551615 is too large",
+            ),
+            (location("file:///a.rs", 3, 2), "is after end line"),
+            (location("untitled:Untitled-1", 0, 0), "Only file:// URI"),
+            (location("file://", 0, 0), "Path is empty"),
+            (location("file:///a%2.rs", 0, 0), "Could not decode path"),
+        ];
+        for (input, expected) in tests {
+            let msg = format!("{:#}", parse_references(&input).unwrap_err());
+            assert!(msg.contains(expected), "{expected:?} in {msg:?}");
+        }
+    }
+}
@@ -1,14 +1,29 @@
 #![deny(clippy::dbg_macro)]
 
 use anyhow::{Context, Result};
+use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, ArgMatches, Command};
-use hgrep::grep::BufReadExt;
-use hgrep::printer::{PrinterOptions, TextWrapMode};
+use hgrep::chunk::{
+    sort_by_match_count, ContextBias, EncodingErrors, File, Files, OverlapPolicy, SortOrder,
+};
+use hgrep::dedup::{self, DedupChunks};
+use hgrep::grep::{strip_ansi_escapes, BufReadExt, GrepLines};
+use hgrep::paginate::{Paginator, Quit};
+use hgrep::porcelain::PorcelainPrinter;
+use hgrep::printer::{
+    print_grouped_by_match, CatchPanic, Layout, PathSeparator, Printer, PrinterOptions,
+    TextWrapMode,
+};
 use std::cmp;
 use std::env;
 use std::ffi::OsString;
 use std::io;
+use std::path::PathBuf;
 use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "syntect-printer")]
+use std::sync::MutexGuard;
+use std::sync::{Arc, Mutex};
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -24,6 +39,7 @@ use hgrep::syntect::SyntectPrinter;
 
 const COMPLETION_SHELLS: [&str; 6] = ["bash", "zsh", "powershell", "fish", "elvish", "nushell"];
 const OPTS_ENV_VAR: &str = "HGREP_DEFAULT_OPTS";
+const PRINTER_ENV_VAR: &str = "HGREP_PRINTER";
 
 #[derive(Debug)]
 struct Args {
@@ -66,6 +82,292 @@ impl Iterator for Args {
     }
 }
 
+// Last indices of the arguments which came from ripgrep's config file and HGREP_DEFAULT_OPTS. Arguments are parsed in the
+// order of the config file, the environment variable, and the command line. The indices are assigned by clap
+#[derive(Clone, Copy, Default, Debug)]
+struct ArgOrigins {
+    config_file: usize,
+    env: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ArgSource {
+    Default,
+    Env,
+    ConfigFile,
+    Flag,
+}
+
+impl ArgSource {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Env => "env",
+            Self::ConfigFile => "config-file",
+            Self::Flag => "flag",
+        }
+    }
+}
+
+// Number of indices which clap assigns to the arguments. This is not the number of the arguments since an argument like
+// '--theme=Nord' or '-c2' has two indices for the option and its value, and '-iS' has two indices for the two flags. The
+// number is calculated from the definitions of the options so that the arguments don't need to be parsed again
+fn num_indices(cmd: &Command, args: &[OsString]) -> usize {
+    // Each value split by the delimiter like '--themes=Nord,Zenburn' has its own index
+    let value_indices = |opt: &Arg, value: &str| match opt.get_value_delimiter() {
+        Some(delim) => value.split(delim).count(),
+        None => 1,
+    };
+    // Indices of an option without its value in the same argument, and the number of values the option takes from the
+    // following arguments. An option whose value is optional (e.g. --debug-config) has an index for its default missing
+    // value
+    let option_indices = |opt: &Arg| {
+        let min_values = opt.get_num_args().map(|r| r.min_values()).unwrap_or(0);
+        if !opt.get_action().takes_values() {
+            (1, 0)
+        } else if opt.is_require_equals_set() || min_values == 0 {
+            (2, 0)
+        } else {
+            (1, min_values)
+        }
+    };
+    let find = |pred: &dyn Fn(&Arg) -> bool| cmd.get_arguments().find(|a| pred(a));
+
+    let mut count = 0;
+    let mut pending = None; // The option which takes the following arguments as its values
+    let mut positional_only = false;
+    for arg in args {
+        let arg = arg.to_string_lossy();
+        if let Some((opt, num_values)) = pending {
+            count += value_indices(opt, &arg);
+            pending = (num_values > 1).then_some((opt, num_values - 1));
+        } else if positional_only {
+            count += 1;
+        } else if arg == "--" {
+            positional_only = true;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let Some(opt) = find(&|a| a.get_long() == Some(name)) else {
+                count += 1;
+                continue;
+            };
+            if let Some(value) = value {
+                count += 1 + value_indices(opt, value);
+                continue;
+            }
+            let (indices, num_values) = option_indices(opt);
+            count += indices;
+            pending = (num_values > 0).then_some((opt, num_values));
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            for (i, c) in shorts.char_indices() {
+                let Some(opt) = find(&|a| a.get_short() == Some(c)) else {
+                    count += 1;
+                    continue;
+                };
+                let rest = &shorts[i + c.len_utf8()..];
+                if opt.get_action().takes_values() && !rest.is_empty() {
+                    // The rest is the value like '-c2'
+                    count += 1 + value_indices(opt, rest.strip_prefix('=').unwrap_or(rest));
+                    break;
+                }
+                let (indices, num_values) = option_indices(opt);
+                count += indices;
+                pending = (num_values > 0).then_some((opt, num_values));
+            }
+        } else {
+            count += 1;
+        }
+    }
+    count
+}
+
+impl ArgOrigins {
+    // The indices are calculated only for --debug-config since they are not used otherwise
+    fn new(
+        matches: &ArgMatches,
+        args: &[OsString],
+        num_config_file: usize,
+        num_env: usize,
+    ) -> Self {
+        if matches.value_source("debug-config").is_none() {
+            return Self::default();
+        }
+        let mut cmd = command();
+        cmd.build();
+        Self {
+            config_file: num_indices(&cmd, &args[..num_config_file]),
+            env: num_indices(&cmd, &args[..num_config_file + num_env]),
+        }
+    }
+
+    // Where the last value of the argument came from. `None` when the argument was not given and has no default value
+    fn source(&self, matches: &ArgMatches, id: &str) -> Option<ArgSource> {
+        if matches.value_source(id)? == ValueSource::DefaultValue {
+            return Some(ArgSource::Default);
+        }
+        let index = matches.indices_of(id)?.max()?;
+        Some(if index <= self.config_file {
+            ArgSource::ConfigFile
+        } else if index <= self.env {
+            ArgSource::Env
+        } else {
+            ArgSource::Flag
+        })
+    }
+}
+
+#[cfg(feature = "ripgrep")]
+const RIPGREP_CONFIG_ENV_VAR: &str = "RIPGREP_CONFIG_PATH";
+
+// Flags in ripgrep's config file which are mapped to hgrep's options with --use-ripgrep-config. Each entry is (long name
+// in ripgrep, short name in ripgrep, long names of hgrep's options, whether the flag takes a value). The value is given
+// to all the options. -C of ripgrep shows exactly NUM context lines so it is mapped to both --min-context and
+// --max-context
+#[cfg(feature = "ripgrep")]
+const RIPGREP_CONFIG_FLAGS: &[(&str, Option<char>, &[&str], bool)] = &[
+    ("context", Some('C'), &["min-context", "max-context"], true),
+    ("crlf", None, &["crlf"], false),
+    ("dfa-size-limit", None, &["dfa-size-limit"], true),
+    ("encoding", Some('E'), &["encoding"], true),
+    ("fixed-strings", Some('F'), &["fixed-strings"], false),
+    ("follow", Some('L'), &["follow"], false),
+    ("glob", Some('g'), &["glob"], true),
+    (
+        "glob-case-insensitive",
+        None,
+        &["glob-case-insensitive"],
+        false,
+    ),
+    ("hidden", Some('.'), &["hidden"], false),
+    ("ignore-case", Some('i'), &["ignore-case"], false),
+    ("invert-match", Some('v'), &["invert-match"], false),
+    ("line-regexp", Some('x'), &["line-regexp"], false),
+    ("max-count", Some('m'), &["max-count"], true),
+    ("max-depth", Some('d'), &["max-depth"], true),
+    ("max-filesize", None, &["max-filesize"], true),
+    ("mmap", None, &["mmap"], false),
+    ("multiline", Some('U'), &["multiline"], false),
+    ("multiline-dotall", None, &["multiline-dotall"], false),
+    ("no-ignore", None, &["no-ignore"], false),
+    ("no-unicode", None, &["no-unicode"], false),
+    ("one-file-system", None, &["one-file-system"], false),
+    ("pcre2", Some('P'), &["pcre2"], false),
+    ("regex-size-limit", None, &["regex-size-limit"], true),
+    ("smart-case", Some('S'), &["smart-case"], false),
+    ("type", Some('t'), &["type"], true),
+    ("type-not", Some('T'), &["type-not"], true),
+    ("unrestricted", Some('u'), &["unrestricted"], false),
+    ("word-regexp", Some('w'), &["word-regexp"], false),
+];
+
+// Translate the content of ripgrep's config file into hgrep's command line arguments. Like ripgrep, each line is one
+// argument and lines starting with '#' are ignored. A flag's value is given as `--flag=value` or on the next line.
+// Flags which hgrep does not support are reported to `warnings` and ignored
+#[cfg(feature = "ripgrep")]
+fn ripgrep_config_args(content: &str, warnings: &mut Vec<String>) -> Vec<String> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .peekable();
+    let mut args = vec![];
+
+    while let Some(arg) = lines.next() {
+        let (flag, value) = if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            (RIPGREP_CONFIG_FLAGS.iter().find(|f| f.0 == name), value)
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            let mut chars = short.chars();
+            let c = chars.next();
+            let rest = chars.as_str();
+            let value = (!rest.is_empty()).then(|| rest.strip_prefix('=').unwrap_or(rest));
+            (RIPGREP_CONFIG_FLAGS.iter().find(|f| f.1 == c), value)
+        } else {
+            warnings.push(format!("Argument {arg:?} is not a flag"));
+            continue;
+        };
+
+        let Some(&(_, _, opts, takes_value)) = flag else {
+            warnings.push(format!("Flag {arg:?} is not supported by hgrep"));
+            // Skip the value of the unknown flag on the next line
+            if !arg.contains('=') && lines.peek().is_some_and(|l| !l.starts_with('-')) {
+                lines.next();
+            }
+            continue;
+        };
+
+        if !takes_value {
+            if value.is_some() {
+                warnings.push(format!("Flag {arg:?} does not take a value"));
+                continue;
+            }
+            args.extend(opts.iter().map(|opt| format!("--{opt}")));
+            continue;
+        }
+
+        let Some(value) = value.or_else(|| lines.next()) else {
+            warnings.push(format!("Flag {arg:?} requires a value"));
+            continue;
+        };
+        for opt in opts {
+            args.push(format!("--{opt}"));
+            args.push(value.to_string());
+        }
+    }
+
+    args
+}
+
+// Arguments translated from the ripgrep's config file at the path in RIPGREP_CONFIG_PATH. Like ripgrep, no argument is
+// returned when the variable is not set or empty
+#[cfg(feature = "ripgrep")]
+fn read_ripgrep_config() -> Result<Vec<String>> {
+    let Some(path) = env::var_os(RIPGREP_CONFIG_ENV_VAR).filter(|p| !p.is_empty()) else {
+        return Ok(vec![]);
+    };
+    let content = std::fs::read_to_string(&path).with_context(|| {
+        format!("Could not read ripgrep config file {path:?} given with `{RIPGREP_CONFIG_ENV_VAR}` environment variable")
+    })?;
+    let mut warnings = vec![];
+    let args = ripgrep_config_args(&content, &mut warnings);
+    for warning in warnings {
+        eprintln!("\x1b[1;93mwarning:\x1b[0m {warning} in ripgrep config file {path:?}. Ignored");
+    }
+    Ok(args)
+}
+
+// With --use-ripgrep-config, the arguments are parsed again with the flags in ripgrep's config file prepended so that
+// options given to hgrep take precedence over them
+fn parse_args(args: Args) -> Result<(ArgMatches, ArgOrigins)> {
+    let num_env = args.env.len();
+    let args: Vec<_> = args.collect();
+    let matches = command().get_matches_from(&args);
+
+    #[cfg(feature = "ripgrep")]
+    if matches.get_flag("use-ripgrep-config") {
+        let mut prepended: Vec<_> = read_ripgrep_config()?
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        if !prepended.is_empty() {
+            let num_config_file = prepended.len();
+            prepended.extend(args);
+            let matches = command().get_matches_from(&prepended);
+            let origins = ArgOrigins::new(&matches, &prepended, num_config_file, num_env);
+            return Ok((matches, origins));
+        }
+    }
+
+    let origins = ArgOrigins::new(&matches, &args, 0, num_env);
+    Ok((matches, origins))
+}
+
 fn command() -> Command {
     #[cfg(feature = "syntect-printer")]
     const DEFAULT_PRINTER: &str = "syntect";
@@ -114,18 +416,69 @@ fn command() -> Command {
                 .default_value("6")
                 .help("Maximum lines of leading and trailing context surrounding each match"),
         )
+        .arg(
+            Arg::new("no-context")
+                .long("no-context")
+                .action(ArgAction::SetTrue)
+                .help("Show only matched lines in snippets without any context lines. This is the same as `--min-context 0 --max-context 0` and takes precedence over them"),
+        )
+        .arg(
+            Arg::new("context-max-bytes")
+                .long("context-max-bytes")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Maximum total bytes of lines in each snippet. Context lines are removed until the snippet fits in this size. Useful for files which have very long lines like minified JavaScript"),
+        )
+        .arg(
+            Arg::new("context-overlap-policy")
+                .long("context-overlap-policy")
+                .num_args(1)
+                .value_name("POLICY")
+                .value_parser(["merge", "separate", "clip"])
+                .default_value("merge")
+                .ignore_case(true)
+                .help("How to lay out snippets when the contexts of matches overlap. 'merge' merges them into one snippet. 'separate' makes a snippet per match and the later snippet starts after the previous one. 'clip' makes a snippet per match and splits the overlapped lines between the matches following --context-bias"),
+        )
+        .arg(
+            Arg::new("context-bias")
+                .long("context-bias")
+                .num_args(1)
+                .value_name("BIAS")
+                .value_parser(["before", "after", "balanced"])
+                .default_value("balanced")
+                .ignore_case(true)
+                .help("Which side of matches keeps context lines when the context must be truncated by --context-max-bytes or --context-overlap-policy clip. 'before' keeps lines before matches (e.g. function signatures in code). 'after' keeps lines after matches (e.g. what happened next in logs). 'balanced' keeps both sides evenly"),
+        )
+        .arg(
+            Arg::new("suppress-empty-context")
+                .long("suppress-empty-context")
+                .action(ArgAction::SetTrue)
+                .help("Show no context lines in snippets where all context lines are empty or whitespace-only"),
+        )
         .arg(
             Arg::new("no-grid")
                 .short('G')
                 .long("no-grid")
                 .action(ArgAction::SetTrue)
-                .help("Remove borderlines for more compact output"),
+                .help("Remove borderlines for more compact output. When both --grid and --no-grid are given, the last one takes effect"),
         )
         .arg(
             Arg::new("grid")
                 .long("grid")
                 .action(ArgAction::SetTrue)
-                .help("Add borderlines to output. This flag is an opposite of --no-grid"),
+                .help("Add borderlines to output. This flag is an opposite of --no-grid. When both --grid and --no-grid are given, the last one takes effect"),
+        )
+        .arg(
+            Arg::new("no-ascii-lines")
+                .long("no-ascii-lines")
+                .action(ArgAction::SetTrue)
+                .help("Use Unicode characters for drawing border lines even if the terminal does not seem to support them. ASCII characters are used by default when $TERM is 'dumb' or 'linux' or when the locale is not UTF-8. This flag is an opposite of --ascii-lines"),
+        )
+        .arg(
+            Arg::new("no-console-adjust")
+                .long("no-console-adjust")
+                .action(ArgAction::SetTrue)
+                .help("Don't adjust the console whose output code page is not UTF-8 on Windows. By default, the code page is switched to UTF-8 while hgrep is running. When it cannot be switched, ASCII characters are used for border lines and non-ASCII characters in file paths are escaped. This flag has no effect on other platforms"),
         )
         .arg(
             Arg::new("tab")
@@ -135,6 +488,12 @@ fn command() -> Command {
                 .default_value("4")
                 .help("Number of spaces for tab character. Set 0 to pass tabs through directly"),
         )
+        .arg(
+            Arg::new("expand-tabs")
+                .long("expand-tabs")
+                .action(ArgAction::SetTrue)
+                .help("Replace tabs with spaces up to the next tab stop before printing so that text after tabs is aligned by columns. The width of tab stops is set by --tab"),
+        )
         .arg(
             Arg::new("theme")
                 .long("theme")
@@ -148,6 +507,28 @@ fn command() -> Command {
                 .action(ArgAction::SetTrue)
                 .help("List all available theme names and their samples. Samples show the output where 'let' is searched. The names can be used at --theme option"),
         )
+        .arg(
+            Arg::new("themes")
+                .long("themes")
+                .num_args(1)
+                .value_name("NAMES")
+                .value_delimiter(',')
+                .help("Comma-separated theme names listed by --list-themes flag instead of all themes"),
+        )
+        .arg(
+            Arg::new("sample-file")
+                .long("sample-file")
+                .num_args(1)
+                .value_name("FILE[:LINE]")
+                .value_hint(clap::ValueHint::FilePath)
+                .help("Use the content of FILE as the sample of --list-themes flag and --render-sample flag. When ':LINE' is appended, the line is highlighted as matched"),
+        )
+        .arg(
+            Arg::new("render-sample")
+                .long("render-sample")
+                .action(ArgAction::SetTrue)
+                .help("Print only the sample highlighted with the theme specified by --theme option and exit. The output is useful to take a screenshot of the theme"),
+        )
         .arg(
             Arg::new("printer")
                 .short('p')
@@ -160,14 +541,14 @@ fn command() -> Command {
                     #[cfg(feature = "bat-printer")]
                     "bat",
                 ])
-                .help("Printer to print the match results"),
+                .help("Printer to print the match results. The default printer can be changed with HGREP_PRINTER environment variable"),
         )
         .arg(
             Arg::new("term-width")
                 .long("term-width")
                 .num_args(1)
                 .value_name("NUM")
-                .help("Width (number of characters) of terminal window"),
+                .help("Width (number of characters) of terminal window. When omitted, $COLUMNS environment variable or the size of the terminal window is used"),
         ).arg(
             Arg::new("wrap")
                 .long("wrap")
@@ -177,12 +558,59 @@ fn command() -> Command {
                 .value_parser(["char", "never"])
                 .ignore_case(true)
                 .help("Text-wrapping mode. 'char' enables character-wise text-wrapping. 'never' disables text-wrapping")
+        ).arg(
+            Arg::new("layout")
+                .long("layout")
+                .num_args(1)
+                .value_name("LAYOUT")
+                .default_value("native")
+                .value_parser(["native", "unified"])
+                .ignore_case(true)
+                .help("Layout of headers and separators of snippets. 'native' is the layout of each printer. 'unified' draws the same layout with both bat and syntect printers so that only highlighting differs")
+        ).arg(
+            Arg::new("path-separator")
+                .long("path-separator")
+                .num_args(1)
+                .value_name("SEP")
+                .default_value("auto")
+                .help("Separator of paths in output. SEP is a single character such as '/' or 'auto' which uses the separator of the platform. Both '/' and '\\' in paths are replaced with SEP. 'slash' and 'backslash' are aliases of '/' and '\\'. Files are still opened with the original paths")
         ).arg(
             Arg::new("first-only")
                 .short('f')
                 .long("first-only")
                 .action(ArgAction::SetTrue)
                 .help("Show only the first code snippet per file")
+        ).arg(
+            Arg::new("stdin-ext")
+                .long("stdin-ext")
+                .num_args(1)
+                .value_name("EXT")
+                .help("Highlight all files in grep results read from stdin with the syntax for the file extension EXT like 'rs' instead of detecting the syntax from their paths. This is useful when the paths don't tell the format of the files")
+        ).arg(
+            Arg::new("strip-ansi")
+                .long("strip-ansi")
+                .action(ArgAction::SetTrue)
+                .help("Remove ANSI escape sequences from grep results read from stdin before parsing them. This allows piping colored output like 'grep --color=always -nH' to hgrep")
+        ).arg(
+            Arg::new("input-format")
+                .long("input-format")
+                .num_args(1)
+                .value_name("FORMAT")
+                .value_parser(["nH", "n"])
+                .help("Format of grep results read from stdin. 'nH' is the output of 'grep -nH' which has file names (default). 'n' is the output of 'grep -n' without file names. With 'n', the file of the matched lines is given by --label option")
+        ).arg(
+            Arg::new("label")
+                .long("label")
+                .num_args(1)
+                .value_name("PATH")
+                .value_hint(clap::ValueHint::FilePath)
+                .help("Path of the file which grep results read from stdin are matched in. This option is required by --input-format n")
+        ).arg(
+            Arg::new("experimental-lsp")
+                .long("experimental-lsp")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["input-format", "label", "strip-ansi"])
+                .help("Read a response of LSP 'textDocument/references' request from stdin instead of grep results and print the referenced locations as code snippets. Both a JSON-RPC response message and only its 'result' array of locations are accepted. Only file:// URIs are supported. This flag is experimental")
         ).arg(
             Arg::new("encoding")
                 .short('E')
@@ -190,6 +618,78 @@ fn command() -> Command {
                 .num_args(1)
                 .value_name("ENCODING")
                 .help("Specify the text encoding that hgrep will use on all files printed like 'sjis'")
+        ).arg(
+            Arg::new("encoding-errors")
+                .long("encoding-errors")
+                .visible_alias("encoding-error-mode")
+                .num_args(1)
+                .value_name("MODE")
+                .value_parser(["replace", "skip", "strict"])
+                .default_value("replace")
+                .ignore_case(true)
+                .help("How to handle byte sequences which are malformed for the encoding of files both on searching and printing. 'replace' replaces them with U+FFFD. 'skip' removes them. 'strict' fails with an error")
+        ).arg(
+            Arg::new("with-git-root")
+                .long("with-git-root")
+                .action(ArgAction::SetTrue)
+                .help("Display paths relative to the root directory of the git repository instead of the current working directory")
+        ).arg(
+            Arg::new("group-by-match")
+                .long("group-by-match")
+                .action(ArgAction::SetTrue)
+                .help("Group the results by distinct matched texts across files. A header is printed per matched text followed by the code snippets which contain it. Results are not printed until all files are searched")
+        ).arg(
+            Arg::new("dedup-chunks")
+                .long("dedup-chunks")
+                .action(ArgAction::SetTrue)
+                .help("Print a code snippet identical to an already printed one as a one-line reference like 'vendor/foo.rs:120-140: same as src/foo.rs:120-140'. This is useful when the same files are vendored in multiple places. Output of --porcelain is not deduplicated")
+        ).arg(
+            Arg::new("sort-by-match-count")
+                .long("sort-by-match-count")
+                .num_args(1)
+                .value_name("ORDER")
+                .value_parser(["desc", "asc"])
+                .ignore_case(true)
+                .help("Sort files by the number of matched lines. 'desc' prints files with the most matches first. Results are not printed until all files are searched")
+        ).arg(
+            Arg::new("no-match-message")
+                .long("no-match-message")
+                .num_args(1)
+                .value_name("TEXT")
+                .overrides_with("quiet-no-match")
+                .help("Print TEXT to stderr when nothing matches. By default nothing is printed")
+        ).arg(
+            Arg::new("quiet-no-match")
+                .long("quiet-no-match")
+                .action(ArgAction::SetTrue)
+                .overrides_with("no-match-message")
+                .help("Print nothing when nothing matches. This is the default and cancels --no-match-message")
+        ).arg(
+            Arg::new("no-match-exit")
+                .long("no-match-exit")
+                .num_args(1)
+                .value_name("CODE")
+                .default_value("1")
+                .help("Exit status when nothing matches. This is useful in CI where no match means success. Exit status 2 is still used for errors")
+        ).arg(
+            Arg::new("report-errors-as-json")
+                .long("report-errors-as-json")
+                .action(ArgAction::SetTrue)
+                .help("Write an error to stderr as one line of JSON like {\"error\":\"message\",\"kind\":\"InvalidPattern\"} instead of the human-readable message. The kind is one of 'InvalidPattern', 'FileNotFound', 'PermissionDenied', 'Io', or 'Other'")
+        ).arg(
+            Arg::new("paginate")
+                .long("paginate")
+                .visible_alias("results-per-page")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Pause after printing NUM snippets and wait for a key. Press Enter or Space to show the next page, or q to quit. Snippets of one file are not split into different pages. This option is ignored when stdout is not a terminal")
+        )
+        .arg(
+            Arg::new("porcelain")
+                .long("porcelain")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("paginate")
+                .help("Print stable line-oriented output for scripts instead of code snippets. The first line is 'porcelain=1' and each chunk is printed as 'PATH<TAB>START<TAB>END' followed by 'match<TAB>LINE' per matched line. No color and no wrapping. The format is kept compatible until its version is bumped. --printer option is ignored")
         )
         .arg(
             Arg::new("generate-completion-script")
@@ -205,6 +705,29 @@ fn command() -> Command {
                 .long("generate-man-page")
                 .action(ArgAction::SetTrue)
                 .help("Print man page to stdout"),
+        )
+        .arg(
+            Arg::new("print-default-config")
+                .long("print-default-config")
+                .action(ArgAction::SetTrue)
+                .help("Print a sample of HGREP_DEFAULT_OPTS with all options and their default values to stdout. The options are commented out. Uncomment options to customize them"),
+        )
+        .arg(
+            Arg::new("debug-config")
+                .long("debug-config")
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("FORMAT")
+                .default_missing_value("text")
+                .value_parser(["text", "json"])
+                .help("Print the resolved configuration and exit. It shows the printer, options with where their values came from ('default', 'env' for HGREP_DEFAULT_OPTS or HGREP_PRINTER, 'config-file' for ripgrep's config file with --use-ripgrep-config, or 'flag' for command line arguments), all fields of the printer options, and all fields of the ripgrep config when PATTERN is given. FORMAT is 'text' (default) or 'json'"),
+        )
+        .arg(
+            Arg::new("self-test")
+                .long("self-test")
+                .visible_alias("test")
+                .action(ArgAction::SetTrue)
+                .help("Run the built-in self-test which searches a temporary file and checks the snippets printed by the printer, then exit. This is useful for verifying hgrep works correctly after installation"),
         );
 
     #[cfg(feature = "bat-printer")]
@@ -221,15 +744,159 @@ fn command() -> Command {
             Arg::new("background")
                 .long("background")
                 .action(ArgAction::SetTrue)
-                .help("Paint background colors. This flag is only for syntect printer"),
+                .help("Paint background colors"),
         )
         .arg(
             Arg::new("ascii-lines")
                 .long("ascii-lines")
                 .action(ArgAction::SetTrue)
                 .help(
-                    "Use ASCII characters for drawing border lines instead of Unicode characters",
+                    "Use ASCII characters for drawing border lines instead of Unicode characters. This flag is an opposite of --no-ascii-lines",
                 ),
+        )
+        .arg(
+            Arg::new("squeeze-blank")
+                .long("squeeze-blank")
+                .action(ArgAction::SetTrue)
+                .help("Collapse consecutive blank lines in snippets into one line like `cat -s`. Matched lines and their neighbors are not collapsed"),
+        )
+        .arg(
+            Arg::new("strip-trailing-whitespace")
+                .long("strip-trailing-whitespace")
+                .action(ArgAction::SetTrue)
+                .help("Remove trailing whitespaces of each printed line such as padding to fill the background color. Colors are kept as-is. This is useful to paste the output into tools which complain about trailing whitespaces"),
+        )
+        .arg(
+            Arg::new("with-character-position")
+                .long("with-character-position")
+                .action(ArgAction::SetTrue)
+                .help("Show the position of the first match in each matched line after its line number like 'LINE:POS'. The position is counted in Unicode characters rather than bytes"),
+        )
+        .arg(
+            Arg::new("ruler")
+                .long("ruler")
+                .action(ArgAction::SetTrue)
+                .help("Print a ruler of column numbers at the top of each snippet. This is useful for pinpointing columns in fixed-width records"),
+        )
+        .arg(
+            Arg::new("with-diagnostics")
+                .long("with-diagnostics")
+                .action(ArgAction::SetTrue)
+                .help("Print a warning to stderr before the snippets of each file which fell back to Plain Text due to unknown syntax or which has byte sequences invalid for its encoding. This helps to find files which are not highlighted or not decoded correctly"),
+        )
+        .arg(
+            Arg::new("explain-highlight")
+                .long("explain-highlight")
+                .action(ArgAction::SetTrue)
+                .help("Instead of printing snippets, print which syntax and theme are used for highlighting each matched file and why the syntax was selected (--stdin-ext, hgrep's mapping, file extension, shebang, first line like modeline, or fallback to Plain Text). This helps to diagnose wrong highlighting"),
+        )
+        .arg(
+            Arg::new("compact")
+                .long("compact")
+                .action(ArgAction::SetTrue)
+                .help("Print one highlighted line per match prefixed with 'PATH:LINE:' instead of snippets. This is denser than snippets while keeping syntax highlighting. Context lines, borders, and the gutter are not printed and long lines are not wrapped"),
+        )
+        .arg(
+            Arg::new("only-matching-with-context")
+                .long("only-matching-with-context")
+                .visible_alias("oc")
+                .num_args(1)
+                .value_name("CHARS")
+                .conflicts_with("compact")
+                .help("Print only each matched part with CHARS characters around it on the same line like '...fn foo(BAR)...', prefixed with 'PATH:LINE:'. This is useful for searching files with very long lines such as minified files"),
+        )
+        .arg(
+            Arg::new("chunk-range-header")
+                .long("chunk-range-header")
+                .action(ArgAction::SetTrue)
+                .help("Print the range of line numbers like 'lines 10–24' above each snippet"),
+        )
+        .arg(
+            Arg::new("context-color")
+                .long("context-color")
+                .num_args(1)
+                .value_name("HEX")
+                .help("Paint the background of context lines with the color instead of the theme's background color. The color is hex RGB value like '#1a1a2e'"),
+        )
+        .arg(
+            Arg::new("match-color")
+                .long("match-color")
+                .num_args(1)
+                .value_name("HEX")
+                .help("Paint the background of matched lines with the color instead of the theme's line highlight color. The color is hex RGB value like '#3a3a5c'"),
+        )
+        .arg(
+            Arg::new("color-scale")
+                .long("color-scale")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Multiply each RGB component of all theme colors by NUM. NUM less than 1.0 like 0.8 makes the output darker and NUM greater than 1.0 makes it brighter. This is useful to adapt themes to the background of the terminal"),
+        )
+        .arg(
+            Arg::new("gutter-links")
+                .long("gutter-links")
+                .action(ArgAction::SetTrue)
+                .help("Make line numbers in the gutter OSC 8 hyperlinks like 'file:///path/to/file#L42' so that terminals can open the file at the line. This also works with grep results read from stdin since their paths and line numbers are used for the links. This flag is ignored when stdout is not a terminal"),
+        )
+        .arg(
+            Arg::new("region-merge-gap")
+                .long("region-merge-gap")
+                .num_args(1)
+                .value_name("NUM")
+                .default_value("2")
+                .help("Highlight matched regions in a line as one region when they are separated by fewer than NUM characters. Set 0 to never merge regions. This option has no effect on bat printer"),
+        )
+        .arg(
+            Arg::new("max-regions-per-line")
+                .long("max-regions-per-line")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Highlight the whole line instead of each matched region when more than NUM regions remain in the line after merging"),
+        )
+        .arg(
+            Arg::new("matches-per-line")
+                .long("matches-per-line")
+                .visible_alias("match-per-line")
+                .num_args(1)
+                .value_name("NUM")
+                .help("Highlight only the first NUM matches in each line. This reduces visual noise of patterns which match many times in a line like '[a-z]'"),
+        )
+        .arg(
+            Arg::new("gutter-separator")
+                .long("gutter-separator")
+                .num_args(1)
+                .value_name("GLYPH")
+                .help("Character drawn between line numbers and code such as '│', '┃', '|', or ' '. The character must be 1 column wide. This option has no effect with --no-grid"),
+        )
+        .arg(
+            Arg::new("header-marker")
+                .long("header-marker")
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_name("MARKER")
+                .help("Put MARKER before the path in each file header so that the headers can be searched in a pager. For example, 'hgrep --header-marker pat | less -R +/⏺' allows jumping between files with 'n' and 'N'. When MARKER is omitted, '⏺' is used ('==>' with --ascii-lines). The marker is not printed with --compact, --only-matching-with-context, and --porcelain"),
+        )
+        .arg(
+            Arg::new("no-context-colors")
+                .long("no-context-colors")
+                .action(ArgAction::SetTrue)
+                .help("Draw context lines in the theme's default foreground color without syntax highlighting so that matched lines stand out"),
+        )
+        .arg(
+            Arg::new("preview-theme")
+                .long("preview-theme")
+                .num_args(2)
+                .value_names(["THEME", "FILE"])
+                .value_hint(clap::ValueHint::FilePath)
+                .help("Print the entire content of FILE highlighted with THEME and exit. This is useful to compare themes on your own code"),
+        )
+        .arg(
+            Arg::new("cat")
+                .long("cat")
+                .num_args(1)
+                .value_name("FILE[:LINE]")
+                .value_hint(clap::ValueHint::FilePath)
+                .help("Print the entire content of FILE with the same theme and layout as search results and exit. When ':LINE' is appended, the line is highlighted as matched"),
         );
 
     #[cfg(feature = "ripgrep")]
@@ -245,16 +912,14 @@ fn command() -> Command {
                     .short('i')
                     .long("ignore-case")
                     .action(ArgAction::SetTrue)
-                    .overrides_with("smart-case")
-                    .help("When this flag is provided, the given pattern will be searched case insensitively. This flag overrides --smart-case"),
+                    .help("When this flag is provided, the given pattern will be searched case insensitively. When both --ignore-case and --smart-case are given, the last one takes effect"),
             )
             .arg(
                 Arg::new("smart-case")
                     .short('S')
                     .long("smart-case")
                     .action(ArgAction::SetTrue)
-                    .overrides_with("ignore-case")
-                    .help("Search case insensitively if the pattern is all lowercase. Search case sensitively otherwise. This flag overrides --ignore-case"),
+                    .help("Search case insensitively if the pattern is all lowercase. Search case sensitively otherwise. When both --ignore-case and --smart-case are given, the last one takes effect"),
             )
             .arg(
                 Arg::new("hidden")
@@ -271,7 +936,15 @@ fn command() -> Command {
                     .num_args(1)
                     .value_name("GLOB")
                     .allow_hyphen_values(true)
-                    .help("Include or exclude files and directories for searching that match the given glob"),
+                    .help("Include or exclude files and directories for searching that match the given glob. Globs starting with '!' exclude matched paths. When multiple globs match a path, the last one takes precedence like ripgrep. Globs in HGREP_DEFAULT_OPTS precede ones in command line arguments"),
+            )
+            .arg(
+                Arg::new("prune")
+                    .long("prune")
+                    .action(ArgAction::Append)
+                    .num_args(1)
+                    .value_name("DIR")
+                    .help("Do not descend into directories named DIR at any depth. Unlike excluding them with --glob, the directories are not traversed at all. This option can be specified multiple times"),
             )
             .arg(
                 Arg::new("glob-case-insensitive")
@@ -291,15 +964,14 @@ fn command() -> Command {
                     .short('w')
                     .long("word-regexp")
                     .action(ArgAction::SetTrue)
-                    .overrides_with("line-regexp")
-                    .help("Only show matches surrounded by word boundaries. This flag overrides --line-regexp"),
+                    .help("Only show matches surrounded by word boundaries. When both --word-regexp and --line-regexp are given, the last one takes effect"),
             )
             .arg(
                 Arg::new("follow-symlink")
                     .short('L')
                     .long("follow")
                     .action(ArgAction::SetTrue)
-                    .help("When this flag is enabled, hgrep will follow symbolic links while traversing directories"),
+                    .help("When this flag is enabled, hgrep will follow symbolic links while traversing directories. Symbolic links which make a cycle are skipped with a warning and files reachable via multiple links are searched only once"),
             )
             .arg(
                 Arg::new("multiline")
@@ -311,6 +983,7 @@ fn command() -> Command {
             .arg(
                 Arg::new("multiline-dotall")
                     .long("multiline-dotall")
+                    .visible_aliases(["dot-matches-newline", "dotall"])
                     .action(ArgAction::SetTrue)
                     .help("Enable \"dot all\" in your regex pattern, which causes '.' to match newlines when multiline searching is enabled"),
             )
@@ -326,6 +999,13 @@ fn command() -> Command {
                     .action(ArgAction::SetTrue)
                     .help("Search using memory maps when possible. mmap is disabled by default unlike ripgrep"),
             )
+            .arg(
+                Arg::new("mmap-threshold")
+                    .long("mmap-threshold")
+                    .num_args(1)
+                    .value_name("NUM+SUFFIX?")
+                    .help("Search files larger than NUM in size using memory maps and read smaller files normally. This takes precedence over --mmap. For the size suffixes, see --max-filesize"),
+            )
             .arg(
                 Arg::new("max-count")
                     .short('m')
@@ -339,15 +1019,28 @@ fn command() -> Command {
                     .long("max-depth")
                     .num_args(1)
                     .value_name("NUM")
-                    .help("Limit the depth of directory traversal to NUM levels beyond the paths given"),
+                    .help("Limit the depth of directory traversal to NUM levels beyond the paths given. 0 means that only the given paths are searched and directories are not descended. 1 means that only immediate children of the given directories are searched"),
+            )
+            .arg(
+                Arg::new("max-files")
+                    .long("max-files")
+                    .num_args(1)
+                    .value_name("NUM")
+                    .help("Stop searching after NUM files are searched. This is useful to preview results on a huge directory tree without scanning everything. With --sort-by-match-count, all files are searched and the first NUM files in the sorted order are printed"),
+            )
+            .arg(
+                Arg::new("search-cwd")
+                    .long("search-cwd")
+                    .visible_alias("include-cwd")
+                    .action(ArgAction::SetTrue)
+                    .help("Search the current directory in addition to the paths given. This flag does nothing when no path is given since the current directory is searched by default. --max-depth counts the depth from the current directory as well as from the other paths, so the current directory is not descended with --max-depth 0. Files in the given paths under the current directory are searched only once"),
             )
             .arg(
                 Arg::new("line-regexp")
                     .short('x')
                     .long("line-regexp")
                     .action(ArgAction::SetTrue)
-                    .overrides_with("word-regexp")
-                    .help("Only show matches surrounded by line boundaries. This is equivalent to putting ^...$ around the search pattern. This flag overrides --word-regexp"),
+                    .help("Only show matches surrounded by line boundaries. This is equivalent to putting ^...$ around the search pattern. When both --word-regexp and --line-regexp are given, the last one takes effect"),
             )
             .arg(
                 Arg::new("pcre2")
@@ -357,35 +1050,132 @@ fn command() -> Command {
                     .help("When this flag is present, hgrep will use the PCRE2 regex engine instead of its default regex engine"),
             )
             .arg(
-                Arg::new("type")
-                    .short('t')
-                    .long("type")
+                Arg::new("pcre2-jit-stack")
+                    .long("pcre2-jit-stack")
                     .num_args(1)
-                    .value_name("TYPE")
-                    .action(clap::ArgAction::Append)
-                    .help("Only search files matching TYPE. This option is repeatable. --type-list can print the list of types"),
+                    .value_name("MB")
+                    .help("The max size of the JIT stack of PCRE2 in megabytes. Increase this when complex patterns fail to match on large inputs. The default size is 10"),
             )
             .arg(
-                Arg::new("type-not")
-                    .short('T')
-                    .long("type-not")
-                    .num_args(1)
-                    .value_name("TYPE")
-                    .action(clap::ArgAction::Append)
-                    .help("Do not search files matching TYPE. Inverse of --type. This option is repeatable. --type-list can print the list of types"),
+                Arg::new("no-pcre2-jit")
+                    .long("no-pcre2-jit")
+                    .action(ArgAction::SetTrue)
+                    .help("Disable JIT compilation of PCRE2 regex. This flag overrides --pcre2-jit-stack"),
             )
             .arg(
-                Arg::new("type-list")
-                    .long("type-list")
+                Arg::new("pcre2-ucp")
+                    .long("pcre2-ucp")
                     .action(ArgAction::SetTrue)
-                    .help("Show all supported file types and their corresponding globs"),
+                    .help(r"Make \b, \w, \d, \s, and POSIX character classes in PCRE2 regex Unicode-aware even if --no-unicode is given. This is the default unless --no-unicode is given"),
             )
             .arg(
-                Arg::new("max-filesize")
-                    .long("max-filesize")
-                    .num_args(1)
-                    .value_name("NUM+SUFFIX?")
-                    .help("Ignore files larger than NUM in size. This does not apply to directories.The input format accepts suffixes of K, M or G which correspond to kilobytes, megabytes and gigabytes, respectively. If no suffix is provided the input is treated as bytes"),
+                Arg::new("no-pcre2-ucp")
+                    .long("no-pcre2-ucp")
+                    .action(ArgAction::SetTrue)
+                    .help(r"Make \b, \w, \d, \s, and POSIX character classes in PCRE2 regex match only ASCII characters. Unlike --no-unicode, the pattern and the text are still handled as UTF-8"),
+            )
+            .arg(
+                Arg::new("report-skipped")
+                    .long("report-skipped")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .value_name("MODE")
+                    .default_missing_value("count")
+                    .value_parser(["count", "list"])
+                    .ignore_case(true)
+                    .help("Report files which were not searched due to binary data, --max-filesize, --type/--type-not, or --max-line-length to stderr after the results. 'count' only shows the numbers of skipped files. 'list' also shows their paths. When MODE is omitted, 'count' is used"),
+            )
+            .arg(
+                Arg::new("no-hints")
+                    .long("no-hints")
+                    .action(ArgAction::SetTrue)
+                    .help("Do not print hints to stderr after the results, such as the number of files skipped by --max-filesize when nothing matched"),
+            )
+            .arg(
+                Arg::new("highlight-surrounding-text")
+                    .long("highlight-surrounding-text")
+                    .action(ArgAction::SetTrue)
+                    .help("Also highlight occurrences of the pattern in context lines with a softer style than matched regions"),
+            )
+            .arg(
+                Arg::new("case-normalize-display")
+                    .long("case-normalize-display")
+                    .action(ArgAction::SetTrue)
+                    .help("Show matched text in the case of PATTERN when it is equal to PATTERN ignoring case. This is useful with --ignore-case or --smart-case to see how matches relate to the search term. Matches of regex patterns which are not equal to PATTERN are shown as-is"),
+            )
+            .arg(
+                Arg::new("stream")
+                    .long("stream")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all(["sort-by-match-count", "group-by-match"])
+                    .help("Print matches in each file while searching it instead of collecting all matches first. Memory usage is bounded by the window of lines around matches so that huge files with many matches can be searched. Files are searched one by one and syntax highlighting starts at each window. --context-max-bytes, --context-overlap-policy and --context-bias are not available with this flag"),
+            )
+            .arg(
+                Arg::new("type")
+                    .short('t')
+                    .long("type")
+                    .num_args(1)
+                    .value_name("TYPE")
+                    .action(clap::ArgAction::Append)
+                    .help("Only search files matching TYPE. This option is repeatable. --type-list can print the list of types"),
+            )
+            .arg(
+                Arg::new("type-not")
+                    .short('T')
+                    .long("type-not")
+                    .num_args(1)
+                    .value_name("TYPE")
+                    .action(clap::ArgAction::Append)
+                    .help("Do not search files matching TYPE. Inverse of --type. This option is repeatable. --type-list can print the list of types"),
+            )
+            .arg(
+                Arg::new("type-list")
+                    .long("type-list")
+                    .action(ArgAction::SetTrue)
+                    .help("Show all supported file types and their corresponding globs"),
+            )
+            .arg(
+                Arg::new("max-filesize")
+                    .long("max-filesize")
+                    .num_args(1)
+                    .value_name("NUM+SUFFIX?")
+                    .help("Ignore files larger than NUM in size. This does not apply to directories.The input format accepts suffixes of K, M or G which correspond to kilobytes, megabytes and gigabytes, respectively. If no suffix is provided the input is treated as bytes"),
+            )
+            .arg(
+                Arg::new("min-filesize")
+                    .long("min-filesize")
+                    .visible_alias("min-file-size")
+                    .num_args(1)
+                    .value_name("NUM+SUFFIX?")
+                    .help("Ignore files smaller than NUM in size such as near-empty placeholder files. This also applies to files given explicitly. For the size suffixes, see --max-filesize"),
+            )
+            .arg(
+                Arg::new("max-line-length")
+                    .long("max-line-length")
+                    .num_args(1)
+                    .value_name("NUM")
+                    .help("Skip files which have lines longer than NUM bytes like minified or generated files. Only the first 8KB of each file is checked"),
+            )
+            .arg(
+                Arg::new("heap-limit")
+                    .long("heap-limit")
+                    .num_args(1)
+                    .value_name("NUM+SUFFIX?")
+                    .help("Approximate upper limit of the memory to buffer lines while searching each file. Files which have a line longer than the limit are skipped with a warning instead of buffering the entire line. They are counted as 'long lines' with --report-skipped. Files searched with memory maps are not limited. For the size suffixes, see --max-filesize"),
+            )
+            .arg(
+                Arg::new("changed-within")
+                    .long("changed-within")
+                    .num_args(1)
+                    .value_name("DURATION")
+                    .help("Only search files modified within DURATION like '2h' or '3d'. The input format accepts suffixes of s, m, h, d or w which correspond to seconds, minutes, hours, days and weeks, respectively. If no suffix is provided the input is treated as seconds"),
+            )
+            .arg(
+                Arg::new("changed-before")
+                    .long("changed-before")
+                    .num_args(1)
+                    .value_name("DURATION")
+                    .help("Only search files last modified before DURATION ago. For the duration suffixes, see --changed-within"),
             )
             .arg(
                 Arg::new("invert-match")
@@ -411,14 +1201,14 @@ fn command() -> Command {
                     .long("regex-size-limit")
                     .num_args(1)
                     .value_name("NUM+SUFFIX?")
-                    .help("The upper size limit of the compiled regex. The default limit is 10M. For the size suffixes, see --max-filesize"),
+                    .help("The upper size limit of the compiled regex. Raise this when a pattern with many alternations fails to compile. The default limit is 100M. For the size suffixes, see --max-filesize"),
             )
             .arg(
                 Arg::new("dfa-size-limit")
                     .long("dfa-size-limit")
                     .num_args(1)
                     .value_name("NUM+SUFFIX?")
-                    .help("The upper size limit of the regex DFA. The default limit is 10M. For the size suffixes, see --max-filesize"),
+                    .help("The upper size limit of the regex DFA. The default limit is 1000M. For the size suffixes, see --max-filesize"),
             )
             .arg(
                 Arg::new("unrestricted")
@@ -427,9 +1217,50 @@ fn command() -> Command {
                     .action(ArgAction::Count)
                     .help(r#"Reduce the level of "smart" filtering by repeated uses (up to 2). A single flag is equivalent to --no-ignore. Two flags are equivalent to --no-ignore --hidden. Unlike ripgrep, three flags are not supported since hgrep doesn't support --binary flag"#)
             )
+            .arg(
+                Arg::new("no-ripgrep-defaults")
+                    .long("no-ripgrep-defaults")
+                    .action(ArgAction::SetTrue)
+                    .help("Search files as traditional grep does. Hidden files are searched and no ignore file (.gitignore, .ignore, .rgignore, etc.) is respected. This is equivalent to --no-ignore --hidden"),
+            )
+            .arg(
+                Arg::new("count-only")
+                    .long("count-only")
+                    .visible_alias("benchmark")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all(["porcelain", "paginate"])
+                    .help("Only print the total number of matched lines without printing any code snippet. Chunks are not built and no file is highlighted so this is the fastest way to search files. This is useful to compare the search throughput with ripgrep (e.g. `rg -c`). This flag is not available when reading grep results from stdin"),
+            )
+            .arg(
+                Arg::new("passthrough-matches")
+                    .long("passthrough-matches")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all(["porcelain", "paginate", "count-only"])
+                    .help("Print matched lines in grep's 'PATH:LINE:TEXT' format without printing code snippets. Files are filtered as usual (globs, types, ignore files, ...) so this is useful to feed the search results to other tools. Lines of different files are not interleaved but the order of files is not stable. This flag is not available when reading grep results from stdin"),
+            )
+            .arg(
+                Arg::new("use-ripgrep-config")
+                    .long("use-ripgrep-config")
+                    .action(ArgAction::SetTrue)
+                    .help("Read flags from ripgrep's config file at the path in RIPGREP_CONFIG_PATH environment variable for migrating from `rg | bat` pipelines. Flags supported by hgrep (e.g. --smart-case, --glob, --hidden, --context) are applied and other flags are ignored with warnings. Options given to hgrep take precedence over the flags in the file"),
+            )
+            .arg(
+                Arg::new("stdin")
+                    .long("stdin")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all(["PATTERN", "pattern-env"])
+                    .help("Read grep results from stdin even if it is not obvious. Without this flag, input piped to stdin is ignored with a warning when PATTERN is given"),
+            )
+            .arg(
+                Arg::new("pattern-env")
+                    .long("pattern-env")
+                    .num_args(1)
+                    .value_name("VAR")
+                    .help("Read the pattern to search from the environment variable VAR instead of the PATTERN argument so that the pattern does not appear in process listings or shell history. All positional arguments are regarded as paths to search. It is an error when the variable is not set or empty"),
+            )
             .arg(
                 Arg::new("PATTERN")
-                    .help("Pattern to search. Regular expression is available"),
+                    .help("Pattern to search. Regular expression is available. An empty pattern matches every line so that `hgrep '' PATH...` shows whole files"),
             )
             .arg(
                 Arg::new("PATH")
@@ -437,11 +1268,122 @@ fn command() -> Command {
                     .num_args(0..)
                     .value_hint(clap::ValueHint::AnyPath)
                     .value_parser(clap::builder::ValueParser::path_buf()),
-            );
+            )
+            // --stdin-ext, --strip-ansi, --input-format, --label, and --experimental-lsp are only for grep results read from
+            // stdin
+            .mut_arg("stdin-ext", |arg| arg.conflicts_with_all(["PATTERN", "pattern-env"]))
+            .mut_arg("strip-ansi", |arg| arg.conflicts_with_all(["PATTERN", "pattern-env"]))
+            .mut_arg("input-format", |arg| arg.conflicts_with_all(["PATTERN", "pattern-env"]))
+            .mut_arg("label", |arg| arg.conflicts_with_all(["PATTERN", "pattern-env"]))
+            .mut_arg("experimental-lsp", |arg| arg.conflicts_with_all(["PATTERN", "pattern-env"]));
+
+    mark_syntect_only(override_opposing_flags(cmd))
+}
+
+// Pairs of flags which have opposite effects
+const OPPOSING_FLAGS: &[(&str, &str)] = &[
+    ("grid", "no-grid"),
+    ("ascii-lines", "no-ascii-lines"),
+    ("ignore-case", "smart-case"),
+    ("line-regexp", "word-regexp"),
+    ("pcre2-ucp", "no-pcre2-ucp"),
+];
+
+// When both flags in a pair are given, the last one takes effect and the other one is ignored. This is consistent with
+// ripgrep and allows to override flags in HGREP_DEFAULT_OPTS with command line arguments.
+fn override_opposing_flags(mut cmd: Command) -> Command {
+    for &(a, b) in OPPOSING_FLAGS {
+        let defined = |id| cmd.get_arguments().any(|arg| arg.get_id() == id);
+        if defined(a) && defined(b) {
+            cmd = cmd
+                .mut_arg(a, |arg| arg.overrides_with(b))
+                .mut_arg(b, |arg| arg.overrides_with(a));
+        }
+    }
+    cmd
+}
+
+// Options which only syntect printer supports. Using them with bat printer is an error. Each entry is (id, reason why
+// bat printer cannot support the option). The reason is shown in the error message when it is known
+const SYNTECT_ONLY_OPTIONS: &[(&str, Option<&str>)] = &[
+    ("themes", None),
+    ("sample-file", None),
+    ("render-sample", None),
+    (
+        "background",
+        Some("bat does not support painting background colors"),
+    ),
+    ("ascii-lines", None),
+    ("squeeze-blank", None),
+    ("strip-trailing-whitespace", None),
+    ("with-character-position", None),
+    ("ruler", None),
+    ("with-diagnostics", None),
+    ("explain-highlight", None),
+    ("compact", None),
+    ("only-matching-with-context", None),
+    ("chunk-range-header", None),
+    ("context-color", None),
+    ("match-color", None),
+    ("color-scale", None),
+    ("gutter-links", None),
+    ("max-regions-per-line", None),
+    ("matches-per-line", None),
+    ("gutter-separator", None),
+    ("header-marker", None),
+    ("no-context-colors", None),
+    ("preview-theme", None),
+    ("cat", None),
+    ("highlight-surrounding-text", None),
+    (
+        "stream",
+        Some("bat cannot print a part of file with correct line numbers"),
+    ),
+];
+
+fn arg_kind(arg: &Arg) -> &'static str {
+    if arg.get_action().takes_values() {
+        "option"
+    } else {
+        "flag"
+    }
+}
 
+// Mention that the option is only for syntect printer at the end of its help text
+fn mark_syntect_only(mut cmd: Command) -> Command {
+    for &(id, _) in SYNTECT_ONLY_OPTIONS {
+        // Some options are not defined depending on the enabled features
+        if !cmd.get_arguments().any(|arg| arg.get_id() == id) {
+            continue;
+        }
+        cmd = cmd.mut_arg(id, |arg| {
+            let help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+            let help = format!(
+                "{}. This {} is only for syntect printer",
+                help,
+                arg_kind(&arg)
+            );
+            arg.help(help)
+        });
+    }
     cmd
 }
 
+// The first option given in the arguments which only syntect printer supports, and the reason why bat printer does not
+// support it
+#[cfg(feature = "bat-printer")]
+fn syntect_only_arg(matches: &ArgMatches) -> Option<(Arg, Option<&'static str>)> {
+    let &(id, reason) = SYNTECT_ONLY_OPTIONS.iter().find(|&&(id, _)| {
+        matches.try_get_raw(id).is_ok_and(|v| v.is_some())
+            && matches.value_source(id) != Some(ValueSource::DefaultValue)
+    })?;
+    let arg = command()
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .cloned()?;
+    Some((arg, reason))
+}
+
 fn generate_completion_script<W: io::Write>(shell: &str, out: &mut W) {
     use clap_complete::generate;
     use clap_complete::shells::*;
@@ -465,12 +1407,363 @@ fn generate_completion_script<W: io::Write>(shell: &str, out: &mut W) {
     }
 }
 
+// Options which print something and exit. They are not useful as default options
+const INFORMATIONAL_OPTIONS: &[&str] = &[
+    "help",
+    "version",
+    "list-themes",
+    "render-sample",
+    "preview-theme",
+    "cat",
+    "type-list",
+    "generate-completion-script",
+    "generate-man-page",
+    "print-default-config",
+    "debug-config",
+    "self-test",
+];
+
+// Comments are ignored when parsing HGREP_DEFAULT_OPTS so the output can be set to the environment variable as-is
+fn print_default_config<W: io::Write>(out: &mut W) -> io::Result<()> {
+    writeln!(
+        out,
+        "# Default options for hgrep\n\
+        #\n\
+        # Save this to a file, uncomment options to customize them, and set the content to {OPTS_ENV_VAR} environment\n\
+        # variable in your shell's configuration file. Lines starting with '#' are ignored.\n\
+        #\n\
+        #   export {OPTS_ENV_VAR}=\"$(cat /path/to/this/file)\"\n\
+        #\n\
+        # Options given via command line arguments take precedence over the options in the environment variable.",
+    )?;
+
+    let cmd = command();
+    for arg in cmd.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue; // Positional arguments
+        };
+        if arg.is_hide_set() || INFORMATIONAL_OPTIONS.contains(&long) {
+            continue;
+        }
+
+        writeln!(out)?;
+        if let Some(help) = arg.get_help() {
+            writeln!(out, "# {help}")?;
+        }
+        write!(out, "# --{long}")?;
+        if arg.get_action().takes_values() {
+            let defaults = arg.get_default_values();
+            if let Some(value) = defaults.first() {
+                write!(out, " {}", value.to_string_lossy())?;
+            } else if let Some(names) = arg.get_value_names() {
+                write!(out, " {}", names[0])?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// Options given to hgrep with their values and where they came from. Flags which are not set are omitted
+fn resolved_options(
+    matches: &ArgMatches,
+    origins: ArgOrigins,
+) -> Vec<(String, Vec<String>, ArgSource)> {
+    let cmd = command();
+    let mut options = vec![];
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        if id == "debug-config" {
+            continue;
+        }
+        let Some(source) = origins.source(matches, id) else {
+            continue;
+        };
+        let values: Vec<_> = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|v| v.to_string_lossy().into_owned())
+            .collect();
+        if source == ArgSource::Default && values == ["false"] {
+            continue;
+        }
+        let name = match arg.get_long() {
+            Some(long) => format!("--{long}"),
+            None => id.to_string(), // Positional arguments
+        };
+        options.push((name, values, source));
+    }
+    options
+}
+
+// Configuration printed by --debug-config
+struct ResolvedConfig<'a> {
+    printer: (&'a str, ArgSource),
+    options: Vec<(String, Vec<String>, ArgSource)>,
+    printer_opts: Vec<(&'static str, String)>,
+    ripgrep_config: Option<Vec<(&'static str, String)>>,
+}
+
+impl ResolvedConfig<'_> {
+    fn write_text<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        let (printer, source) = self.printer;
+        writeln!(out, "printer: {printer} ({})", source.name())?;
+
+        writeln!(out, "\noptions:")?;
+        for (name, values, source) in &self.options {
+            write!(out, "  {name}")?;
+            if values != &["true"] {
+                for value in values {
+                    write!(out, " {value:?}")?;
+                }
+            }
+            writeln!(out, " ({})", source.name())?;
+        }
+
+        writeln!(out, "\nprinter options:")?;
+        for (name, value) in &self.printer_opts {
+            writeln!(out, "  {name}: {value}")?;
+        }
+
+        writeln!(out, "\nripgrep config:")?;
+        match &self.ripgrep_config {
+            Some(config) => {
+                for (name, value) in config {
+                    writeln!(out, "  {name}: {value}")?;
+                }
+            }
+            None => writeln!(out, "  (not used since grep results are read from stdin)")?,
+        }
+        Ok(())
+    }
+
+    fn write_json<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        use serde_json::{json, Map, Value};
+
+        let fields = |fields: &[(&str, String)]| {
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), Value::String(value.clone())))
+                .collect::<Map<_, _>>()
+        };
+        let (printer, source) = self.printer;
+        let options: Vec<_> = self
+            .options
+            .iter()
+            .map(|(name, values, source)| {
+                json!({ "name": name, "values": values, "source": source.name() })
+            })
+            .collect();
+        let json = json!({
+            "printer": { "name": printer, "source": source.name() },
+            "options": options,
+            "printer_options": fields(&self.printer_opts),
+            "ripgrep_config": self.ripgrep_config.as_deref().map(fields),
+        });
+        serde_json::to_writer_pretty(&mut *out, &json)?;
+        writeln!(out)
+    }
+}
+
+fn context_max_bytes(matches: &ArgMatches) -> Result<Option<usize>> {
+    let Some(bytes) = matches.get_one::<String>("context-max-bytes") else {
+        return Ok(None);
+    };
+    let bytes = bytes
+        .parse()
+        .context("Could not parse --context-max-bytes option value as unsigned integer")?;
+    Ok(Some(bytes))
+}
+
+fn overlap_policy(matches: &ArgMatches) -> OverlapPolicy {
+    let policy = matches.get_one::<String>("context-overlap-policy").unwrap(); // OK since the default value is set
+    if policy.eq_ignore_ascii_case("merge") {
+        OverlapPolicy::Merge
+    } else if policy.eq_ignore_ascii_case("separate") {
+        OverlapPolicy::Separate
+    } else if policy.eq_ignore_ascii_case("clip") {
+        OverlapPolicy::Clip
+    } else {
+        unreachable!(); // Option value was validated by clap
+    }
+}
+
+fn context_bias(matches: &ArgMatches) -> ContextBias {
+    let bias = matches.get_one::<String>("context-bias").unwrap(); // OK since the default value is set
+    if bias.eq_ignore_ascii_case("before") {
+        ContextBias::Before
+    } else if bias.eq_ignore_ascii_case("after") {
+        ContextBias::After
+    } else if bias.eq_ignore_ascii_case("balanced") {
+        ContextBias::Balanced
+    } else {
+        unreachable!(); // Option value was validated by clap
+    }
+}
+
+fn encoding_errors(matches: &ArgMatches) -> EncodingErrors {
+    let mode = matches.get_one::<String>("encoding-errors").unwrap(); // OK since the default value is set
+    if mode.eq_ignore_ascii_case("replace") {
+        EncodingErrors::Replace
+    } else if mode.eq_ignore_ascii_case("skip") {
+        EncodingErrors::Skip
+    } else if mode.eq_ignore_ascii_case("strict") {
+        EncodingErrors::Strict
+    } else {
+        unreachable!(); // Option value was validated by clap
+    }
+}
+
+fn sort_order(matches: &ArgMatches) -> Option<SortOrder> {
+    let order = matches.get_one::<String>("sort-by-match-count")?;
+    if order.eq_ignore_ascii_case("desc") {
+        Some(SortOrder::Descending)
+    } else if order.eq_ignore_ascii_case("asc") {
+        Some(SortOrder::Ascending)
+    } else {
+        unreachable!(); // Option value was validated by clap
+    }
+}
+
 #[cfg(feature = "ripgrep")]
-fn build_ripgrep_config(
+fn report_skipped(matches: &ArgMatches, skipped: Mutex<ripgrep::SkippedFiles>, found: bool) {
+    let skipped = skipped.into_inner().unwrap();
+    let too_large = skipped.too_large.len();
+
+    if let Some(mode) = matches.get_one::<String>("report-skipped") {
+        let kinds = [
+            ("binary", skipped.binary),
+            ("too large", skipped.too_large),
+            ("filtered by type", skipped.filtered_by_type),
+            ("long lines", skipped.long_lines),
+        ];
+
+        if mode.eq_ignore_ascii_case("list") {
+            for (kind, paths) in &kinds {
+                let mut paths: Vec<_> = paths.iter().collect();
+                paths.sort();
+                for path in paths {
+                    eprintln!("Skipped ({}): {}", kind, path.display());
+                }
+            }
+        }
+
+        let counts: Vec<_> = kinds
+            .iter()
+            .map(|(kind, paths)| format!("{} {}", paths.len(), kind))
+            .collect();
+        eprintln!("Skipped files: {}", counts.join(", "));
+    }
+
+    // The file which was skipped due to its size may contain what the user is looking for
+    if !found && too_large > 0 && !matches.get_flag("no-hints") {
+        eprintln!(
+            "\x1b[1;96mhint:\x1b[0m {} file(s) exceeded --max-filesize; re-run with a larger limit?",
+            too_large,
+        );
+    }
+}
+
+// Path of the file for grep results without file names read from stdin
+fn input_label(matches: &ArgMatches) -> Result<Option<PathBuf>> {
+    let label = matches.get_one::<String>("label");
+    if matches
+        .get_one::<String>("input-format")
+        .map(String::as_str)
+        == Some("n")
+    {
+        let Some(label) = label else {
+            anyhow::bail!(
+                "--input-format n requires --label option to know the file of the matched lines"
+            );
+        };
+        return Ok(Some(label.into()));
+    }
+    if label.is_some() {
+        anyhow::bail!("--label option is only available with --input-format n");
+    }
+    Ok(None)
+}
+
+// Files of the grep results read from stdin configured with the options
+fn stdin_files(
+    matches: &ArgMatches,
     min_context: u64,
     max_context: u64,
-    matches: &ArgMatches,
-) -> Result<ripgrep::Config<'_>> {
+    git_root: Option<PathBuf>,
+) -> Result<Files<GrepLines<io::BufReader<io::Stdin>>>> {
+    let encoding = matches.get_one::<String>("encoding").map(String::as_str);
+    let mut files = io::BufReader::new(io::stdin())
+        .grep_lines()
+        .strip_ansi(matches.get_flag("strip-ansi"))
+        .label(input_label(matches)?)
+        .lsp_references(matches.get_flag("experimental-lsp"))
+        .chunks_per_file(min_context, max_context, encoding)?;
+    if let Some(dir) = git_root {
+        files.base_dir(dir);
+    }
+    if let Some(bytes) = context_max_bytes(matches)? {
+        files.context_max_bytes(bytes);
+    }
+    files
+        .encoding_errors(encoding_errors(matches))
+        .overlap_policy(overlap_policy(matches))
+        .context_bias(context_bias(matches))
+        .suppress_empty_context(matches.get_flag("suppress-empty-context"));
+    Ok(files)
+}
+
+// Print the files of grep results read from stdin. With `parallel`, files are printed in parallel so the order of them
+// is not stable. Only syntect printer prints files in parallel
+#[cfg_attr(
+    any(not(feature = "syntect-printer"), feature = "single-thread"),
+    allow(unused_variables)
+)]
+fn print_files<P, I>(printer: &P, files: I, matches: &ArgMatches, parallel: bool) -> Result<bool>
+where
+    P: Printer + Sync,
+    I: Iterator<Item = Result<File>> + Send,
+{
+    if matches.get_flag("group-by-match") {
+        let files = files.collect::<Result<Vec<_>>>()?;
+        return print_grouped_by_match(printer, files, sort_order(matches));
+    }
+    if let Some(order) = sort_order(matches) {
+        let mut files = files.collect::<Result<Vec<_>>>()?;
+        sort_by_match_count(&mut files, order);
+        let found = !files.is_empty();
+        for file in files {
+            printer.print(file)?;
+        }
+        return Ok(found);
+    }
+    #[cfg(all(feature = "syntect-printer", not(feature = "single-thread")))]
+    if parallel {
+        use rayon::prelude::*;
+        return files
+            .par_bridge()
+            .map(|file| {
+                printer.print(file?)?;
+                Ok(true)
+            })
+            .try_reduce(|| false, |a, b| Ok(a || b));
+    }
+    let mut found = false;
+    for file in files {
+        printer.print(file?)?;
+        found = true;
+    }
+    Ok(found)
+}
+
+#[cfg(feature = "ripgrep")]
+fn build_ripgrep_config<'a>(
+    min_context: u64,
+    max_context: u64,
+    pattern: Option<&'a str>,
+    matches: &'a ArgMatches,
+) -> Result<ripgrep::Config<'a>> {
     let mut config = ripgrep::Config::default();
     config
         .min_context(min_context)
@@ -489,14 +1782,34 @@ fn build_ripgrep_config(
         .multiline_dotall(matches.get_flag("multiline-dotall"))
         .mmap(matches.get_flag("mmap"))
         .line_regexp(matches.get_flag("line-regexp"))
+        .highlight_surrounding_text(matches.get_flag("highlight-surrounding-text"))
+        .group_by_match(matches.get_flag("group-by-match"))
+        .stream(matches.get_flag("stream"))
         .invert_match(matches.get_flag("invert-match"))
         .one_file_system(matches.get_flag("one-file-system"))
-        .no_unicode(matches.get_flag("no-unicode"));
+        .no_unicode(matches.get_flag("no-unicode"))
+        .no_pcre2_jit(matches.get_flag("no-pcre2-jit"));
 
     if let Some(globs) = matches.get_many::<String>("glob") {
         config.globs(globs.map(String::as_str));
     }
 
+    if let Some(dirs) = matches.get_many::<String>("prune") {
+        config.prune(dirs.map(String::as_str));
+    }
+
+    if matches.get_flag("case-normalize-display") {
+        if let Some(pattern) = pattern {
+            config.case_normalize_display(pattern);
+        }
+    }
+
+    if matches.get_flag("pcre2-ucp") {
+        config.pcre2_ucp(true);
+    } else if matches.get_flag("no-pcre2-ucp") {
+        config.pcre2_ucp(false);
+    }
+
     if let Some(num) = matches.get_one::<String>("max-count") {
         let num = num
             .parse()
@@ -504,6 +1817,19 @@ fn build_ripgrep_config(
         config.max_count(num);
     }
 
+    if let Some(len) = matches.get_one::<String>("max-line-length") {
+        let len = len
+            .parse()
+            .context("Could not parse --max-line-length option value as unsigned integer")?;
+        config.max_line_length(len);
+    }
+
+    if let Some(limit) = matches.get_one::<String>("heap-limit") {
+        config
+            .heap_limit(limit)
+            .context("Invalid --heap-limit option value")?;
+    }
+
     if let Some(num) = matches.get_one::<String>("max-depth") {
         let num = num
             .parse()
@@ -511,12 +1837,63 @@ fn build_ripgrep_config(
         config.max_depth(num);
     }
 
+    if let Some(num) = matches.get_one::<String>("max-files") {
+        let num = num
+            .parse()
+            .context("Could not parse --max-files option value as unsigned integer")?;
+        config.max_files(num);
+    }
+
+    if let Some(order) = sort_order(matches) {
+        config.sort_by_match_count(order);
+    }
+
+    if let Some(bytes) = context_max_bytes(matches)? {
+        config.context_max_bytes(bytes);
+    }
+    config
+        .encoding_errors(encoding_errors(matches))
+        .overlap_policy(overlap_policy(matches))
+        .context_bias(context_bias(matches))
+        .suppress_empty_context(matches.get_flag("suppress-empty-context"));
+
+    if let Some(size) = matches.get_one::<String>("pcre2-jit-stack") {
+        let size = size
+            .parse()
+            .context("Could not parse --pcre2-jit-stack option value as unsigned integer")?;
+        config.pcre2_jit_stack(size)?;
+    }
+
+    if let Some(duration) = matches.get_one::<String>("changed-within") {
+        config
+            .changed_within(duration)
+            .context("Could not parse --changed-within option value as duration string")?;
+    }
+
+    if let Some(duration) = matches.get_one::<String>("changed-before") {
+        config
+            .changed_before(duration)
+            .context("Could not parse --changed-before option value as duration string")?;
+    }
+
     if let Some(size) = matches.get_one::<String>("max-filesize") {
         config
             .max_filesize(size)
             .context("Could not parse --max-filesize option value as file size string")?;
     }
 
+    if let Some(size) = matches.get_one::<String>("min-filesize") {
+        config
+            .min_filesize(size)
+            .context("Could not parse --min-filesize option value as file size string")?;
+    }
+
+    if let Some(size) = matches.get_one::<String>("mmap-threshold") {
+        config
+            .mmap_threshold(size)
+            .context("Could not parse --mmap-threshold option value as size string")?;
+    }
+
     if let Some(limit) = matches.get_one::<String>("regex-size-limit") {
         config
             .regex_size_limit(limit)
@@ -550,6 +1927,10 @@ fn build_ripgrep_config(
         _ => anyhow::bail!("-u or --unrestricted cannot be repeated more than twice. Try -uu to search every text file"),
     }
 
+    if matches.get_flag("no-ripgrep-defaults") {
+        config.no_ignore(true).hidden(true);
+    }
+
     if let Some(encoding) = matches.get_one::<String>("encoding") {
         config.encoding(encoding);
     }
@@ -557,6 +1938,25 @@ fn build_ripgrep_config(
     Ok(config)
 }
 
+// Root directory of the git repository which contains the directory `cwd`
+fn git_root(cwd: &std::path::Path) -> Result<PathBuf> {
+    let output = process::Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(cwd)
+        .output()
+        .context("Could not run `git rev-parse --show-toplevel` for --with-git-root")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "Could not find the root directory of git repository for --with-git-root: {}",
+            stderr.trim(),
+        );
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .context("Output of `git rev-parse --show-toplevel` is not a valid UTF-8 sequence")?;
+    Ok(PathBuf::from(stdout.trim_end_matches(['\n', '\r'])))
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum PrinterKind {
     #[cfg(feature = "bat-printer")]
@@ -565,22 +1965,440 @@ enum PrinterKind {
     Syntect,
 }
 
-fn run(matches: ArgMatches) -> Result<bool> {
-    if let Some(shell) = matches.get_one::<String>("generate-completion-script") {
-        let stdout = io::stdout();
-        generate_completion_script(shell, &mut stdout.lock());
-        return Ok(true);
+// `HGREP_PRINTER` environment variable selects the printer when --printer option is not given explicitly
+fn printer_name(matches: &ArgMatches) -> Result<String> {
+    let name = matches.get_one::<String>("printer").unwrap();
+    if matches.value_source("printer") != Some(ValueSource::DefaultValue) {
+        return Ok(name.clone());
+    }
+    match env::var(PRINTER_ENV_VAR) {
+        Ok(var) if var == "syntect" || var == "bat" => Ok(var),
+        Ok(var) if var.is_empty() => Ok(name.clone()),
+        Ok(var) => anyhow::bail!("Unknown printer {var:?} in `{PRINTER_ENV_VAR}` environment variable. The value must be \"syntect\" or \"bat\""),
+        Err(env::VarError::NotPresent) => Ok(name.clone()),
+        Err(err) => Err(err).with_context(|| {
+            format!("Could not read `{PRINTER_ENV_VAR}` environment variable")
+        }),
     }
+}
 
-    if matches.get_flag("generate-man-page") {
+// Pattern and paths to search with the builtin ripgrep. With --pattern-env, the PATTERN argument is the first path
+#[cfg(feature = "ripgrep")]
+fn pattern_and_paths<'a>(
+    matches: &'a ArgMatches,
+    env_pattern: Option<&'a str>,
+) -> Option<(&'a str, Vec<&'a std::path::Path>)> {
+    use std::path::Path;
+
+    let arg = matches.get_one::<String>("PATTERN").map(String::as_str);
+    let (pattern, mut paths) = match env_pattern {
+        Some(pattern) => (pattern, arg.map(Path::new).into_iter().collect()),
+        None => (arg?, vec![]),
+    };
+    if let Some(p) = matches.get_many::<PathBuf>("PATH") {
+        paths.extend(p.map(PathBuf::as_path));
+    }
+    // The current directory is searched by default when no path is given
+    let cwd = Path::new(".");
+    if matches.get_flag("search-cwd") && !paths.is_empty() && !paths.contains(&cwd) {
+        paths.push(cwd);
+    }
+    Some((pattern, paths))
+}
+
+// Pattern read from the environment variable given with --pattern-env
+#[cfg(feature = "ripgrep")]
+fn env_pattern(matches: &ArgMatches) -> Result<Option<String>> {
+    let Some(var) = matches.get_one::<String>("pattern-env") else {
+        return Ok(None);
+    };
+    match env::var(var) {
+        Ok(pattern) if pattern.is_empty() => {
+            anyhow::bail!(
+                "Environment variable {:?} given with --pattern-env is empty",
+                var
+            )
+        }
+        Ok(pattern) => Ok(Some(pattern)),
+        Err(err) => Err(err).with_context(|| {
+            format!("Could not read environment variable {var:?} given with --pattern-env")
+        }),
+    }
+}
+
+// Returns true when some input is piped or redirected to stdin. Character devices like /dev/null are not counted since
+// they are often connected to stdin in non-interactive environments
+#[cfg(feature = "ripgrep")]
+fn stdin_is_piped() -> bool {
+    use std::io::IsTerminal;
+
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return false;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsFd;
+        use std::os::unix::fs::FileTypeExt;
+
+        let Ok(fd) = stdin.as_fd().try_clone_to_owned() else {
+            return false;
+        };
+        let Ok(meta) = std::fs::File::from(fd).metadata() else {
+            return false;
+        };
+        let ty = meta.file_type();
+        ty.is_fifo() || ty.is_file()
+    }
+
+    #[cfg(not(unix))]
+    true
+}
+
+// Split the value of --cat or --sample-file into the file path and the optional line number. The suffix after the last ':' is not
+// regarded as a line number when the entire value is a path of an existing file
+#[cfg(feature = "syntect-printer")]
+fn parse_cat_arg<'a>(arg: &'a str, opt: &str) -> Result<(&'a std::path::Path, Option<u64>)> {
+    use std::path::Path;
+
+    if let Some((path, line)) = arg.rsplit_once(':') {
+        let is_number = !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit());
+        if is_number && !path.is_empty() && !Path::new(arg).exists() {
+            let line = line.parse().with_context(|| {
+                format!(
+                    "Could not parse line number in --{} option value {:?}",
+                    opt, arg
+                )
+            })?;
+            return Ok((Path::new(path), Some(line)));
+        }
+    }
+    Ok((Path::new(arg), None))
+}
+
+const SELF_TEST_MARKER: &str = "hgrep self-test marker";
+const SELF_TEST_SOURCE: &str = "\
+fn main() {
+    let message = \"hello\";
+    // hgrep self-test marker
+    println!(\"{}\", message);
+}
+";
+// The matched line and its context lines which must be in the snippet
+const SELF_TEST_EXPECTED_LINES: &[&str] = &[
+    "let message = \"hello\";",
+    "// hgrep self-test marker",
+    "println!(\"{}\", message);",
+];
+
+// Output of the printer captured in memory for --self-test
+#[derive(Clone, Default)]
+struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedOutput {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "syntect-printer")]
+struct CapturedOutputLock<'a>(MutexGuard<'a, Vec<u8>>);
+
+#[cfg(feature = "syntect-printer")]
+impl io::Write for CapturedOutputLock<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "syntect-printer")]
+impl hgrep::syntect::WriteOnLocked for CapturedOutput {
+    type Locked<'a> = CapturedOutputLock<'a>;
+    fn lock(&self) -> Self::Locked<'_> {
+        CapturedOutputLock(self.0.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "bat-printer")]
+struct CapturingBatPrinter<'main> {
+    printer: Mutex<BatPrinter<'main>>,
+    out: CapturedOutput,
+}
+
+#[cfg(feature = "bat-printer")]
+impl Printer for CapturingBatPrinter<'_> {
+    fn print(&self, file: File) -> Result<()> {
+        let mut output = String::new();
+        self.printer
+            .lock()
+            .unwrap()
+            .print_to(file, Some(&mut output))?;
+        self.out
+            .0
+            .lock()
+            .unwrap()
+            .extend_from_slice(output.as_bytes());
+        Ok(())
+    }
+}
+
+fn check_self_test_output(found: bool, output: &[u8]) -> Result<(), String> {
+    if !found {
+        return Err("No match was found".to_string());
+    }
+    let output = String::from_utf8_lossy(&strip_ansi_escapes(output)).into_owned();
+    if !output.contains("sample.rs") {
+        return Err(format!("File name is not in the output:\n{output}"));
+    }
+    for line in SELF_TEST_EXPECTED_LINES {
+        if !output.contains(line) {
+            return Err(format!("Line {line:?} is not in the output:\n{output}"));
+        }
+    }
+    Ok(())
+}
+
+// Run both the search with the builtin ripgrep and reading grep results from stdin with the printer created by
+// `new_printer`. The result of each case is reported to stdout. Returns whether all the cases passed
+fn self_test<P, F>(new_printer: F) -> Result<bool>
+where
+    P: hgrep::printer::Printer + Sync,
+    F: Fn(CapturedOutput) -> Result<P>,
+{
+    let dir = env::temp_dir().join(format!("hgrep-self-test-{}", process::id()));
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Could not create directory {:?} for self-test", dir))?;
+    let result = run_self_test_cases(&dir, new_printer);
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+fn run_self_test_cases<P, F>(dir: &std::path::Path, new_printer: F) -> Result<bool>
+where
+    P: hgrep::printer::Printer + Sync,
+    F: Fn(CapturedOutput) -> Result<P>,
+{
+    let path = dir.join("sample.rs");
+    std::fs::write(&path, SELF_TEST_SOURCE)
+        .with_context(|| format!("Could not create file {:?} for self-test", path))?;
+
+    let mut stdout = io::stdout().lock();
+    let mut passed = true;
+    let mut report = |name: &str, result: Result<(), String>| -> io::Result<()> {
+        use io::Write as _;
+        match result {
+            Ok(()) => writeln!(stdout, "{name} ... \x1b[92mok\x1b[0m"),
+            Err(reason) => {
+                passed = false;
+                writeln!(stdout, "{name} ... \x1b[91mFAILED\x1b[0m")?;
+                for line in reason.lines() {
+                    writeln!(stdout, "    {line}")?;
+                }
+                Ok(())
+            }
+        }
+    };
+
+    {
+        let out = CapturedOutput::default();
+        let printer = new_printer(out.clone())?;
+        let input = format!("{}:3:    // {}\n", path.display(), SELF_TEST_MARKER);
+        let mut found = false;
+        for file in input.as_bytes().grep_lines().chunks_per_file(1, 2, None)? {
+            printer.print(file?)?;
+            found = true;
+        }
+        let result = check_self_test_output(found, &out.take());
+        report("read grep results from stdin", result)?;
+    }
+
+    #[cfg(feature = "ripgrep")]
+    {
+        let out = CapturedOutput::default();
+        let printer = new_printer(out.clone())?;
+        let paths = std::iter::once(dir);
+        let config = ripgrep::Config::new(1, 2);
+        let found = ripgrep::grep(printer, SELF_TEST_MARKER, Some(paths), config)?;
+        let result = check_self_test_output(found, &out.take());
+        report("search files with builtin ripgrep", result)?;
+    }
+
+    Ok(passed)
+}
+
+// How to report that nothing matches. This is separate from `run` because the exit status is decided in `main`
+#[derive(Debug, PartialEq, Eq)]
+struct NoMatch {
+    message: Option<String>,
+    exit_status: i32,
+}
+
+impl Default for NoMatch {
+    fn default() -> Self {
+        Self {
+            message: None,
+            exit_status: 1,
+        }
+    }
+}
+
+impl NoMatch {
+    fn new(matches: &ArgMatches) -> Result<Self> {
+        // --self-test returns false when some test case failed. It is not the case where nothing matches
+        if matches.get_flag("self-test") {
+            return Ok(Self::default());
+        }
+
+        let message = matches.get_one::<String>("no-match-message").cloned();
+        let exit_status = match matches.get_one::<String>("no-match-exit") {
+            Some(code) => code.parse::<u8>().with_context(|| {
+                format!(
+                    "Could not parse {:?} for --no-match-exit option. It must be an integer from 0 to 255",
+                    code,
+                )
+            })?,
+            None => 1,
+        };
+        Ok(Self {
+            message,
+            exit_status: exit_status.into(),
+        })
+    }
+
+    fn report(self) -> i32 {
+        if let Some(msg) = self.message {
+            eprintln!("{}", msg);
+        }
+        self.exit_status
+    }
+}
+
+// Number of files which could not be printed due to panics in printers. Each panic was already reported to stderr
+static PRINT_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+fn exit_status(matches: ArgMatches, origins: ArgOrigins) -> Result<i32> {
+    let no_match = NoMatch::new(&matches)?;
+    let found = match run(matches, origins) {
+        Ok(found) => found,
+        // Quitting at the prompt of --paginate is not an error. Something was already printed
+        Err(err) if err.is::<Quit>() => true,
+        Err(err) => return Err(err),
+    };
+    let failures = PRINT_FAILURES.load(Ordering::Relaxed);
+    if failures > 0 {
+        anyhow::bail!("{failures} file(s) could not be printed due to the panics reported above");
+    }
+    Ok(if found { 0 } else { no_match.report() })
+}
+
+// Number of snippets per page for --paginate. Pagination is disabled when stdout is not a terminal since nobody can
+// answer the prompt
+fn snippets_per_page(matches: &ArgMatches) -> Result<Option<usize>> {
+    use std::io::IsTerminal;
+
+    let Some(num) = matches.get_one::<String>("paginate") else {
+        return Ok(None);
+    };
+    let num: usize = num
+        .parse()
+        .context("Could not parse --paginate option value as unsigned integer")?;
+    if num == 0 {
+        anyhow::bail!("--paginate option value must be greater than 0");
+    }
+    Ok(io::stdout().is_terminal().then_some(num))
+}
+
+// Legacy Windows consoles print box-drawing characters and non-ASCII text as '?' when their output code page is not
+// UTF-8. `code_page` is `None` when stdout is not a console
+#[cfg(any(windows, test))]
+const UTF8_CODE_PAGE: u32 = 65001;
+
+#[cfg(any(windows, test))]
+#[derive(Debug, PartialEq, Eq)]
+enum ConsoleAdjustment {
+    Keep,
+    Switched { restore: u32 },
+    AsciiOnly,
+}
+
+#[cfg(any(windows, test))]
+fn adjust_console(
+    code_page: Option<u32>,
+    set_code_page: impl FnOnce(u32) -> bool,
+) -> ConsoleAdjustment {
+    match code_page {
+        None | Some(UTF8_CODE_PAGE) => ConsoleAdjustment::Keep,
+        Some(code_page) if set_code_page(UTF8_CODE_PAGE) => {
+            ConsoleAdjustment::Switched { restore: code_page }
+        }
+        Some(_) => ConsoleAdjustment::AsciiOnly,
+    }
+}
+
+#[cfg(windows)]
+mod console {
+    use super::{adjust_console, ConsoleAdjustment};
+    use std::io::{self, IsTerminal};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleOutputCP() -> u32;
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+    }
+
+    pub struct CodePageGuard(Option<u32>);
+
+    impl Drop for CodePageGuard {
+        fn drop(&mut self) {
+            if let Some(code_page) = self.0 {
+                unsafe { SetConsoleOutputCP(code_page) };
+            }
+        }
+    }
+
+    // Returns the guard to restore the code page and whether only ASCII characters should be printed
+    pub fn adjust() -> (CodePageGuard, bool) {
+        let code_page = if io::stdout().is_terminal() {
+            Some(unsafe { GetConsoleOutputCP() }).filter(|&cp| cp != 0) // 0 means failure
+        } else {
+            None
+        };
+        match adjust_console(code_page, |cp| unsafe { SetConsoleOutputCP(cp) } != 0) {
+            ConsoleAdjustment::Keep => (CodePageGuard(None), false),
+            ConsoleAdjustment::Switched { restore } => (CodePageGuard(Some(restore)), false),
+            ConsoleAdjustment::AsciiOnly => (CodePageGuard(None), true),
+        }
+    }
+}
+
+fn run(matches: ArgMatches, origins: ArgOrigins) -> Result<bool> {
+    if let Some(shell) = matches.get_one::<String>("generate-completion-script") {
+        let stdout = io::stdout();
+        generate_completion_script(shell, &mut stdout.lock());
+        return Ok(true);
+    }
+
+    if matches.get_flag("generate-man-page") {
         let man = clap_mangen::Man::new(command());
         let stdout = io::stdout();
         man.render(&mut stdout.lock())?;
         return Ok(true);
     }
 
+    if matches.get_flag("print-default-config") {
+        let stdout = io::stdout();
+        print_default_config(&mut stdout.lock())?;
+        return Ok(true);
+    }
+
     #[allow(unused_variables)] // printer_kind is unused when syntect-printer is disabled for now
-    let printer_kind = match matches.get_one::<String>("printer").unwrap().as_str() {
+    let printer_kind = match printer_name(&matches)?.as_str() {
         #[cfg(feature = "bat-printer")]
         "bat" => PrinterKind::Bat,
         #[cfg(not(feature = "bat-printer"))]
@@ -592,6 +2410,18 @@ fn run(matches: ArgMatches) -> Result<bool> {
         p => unreachable!(), // Argument paraser already checked this case
     };
 
+    #[cfg(feature = "bat-printer")]
+    if printer_kind == PrinterKind::Bat {
+        if let Some((arg, reason)) = syntect_only_arg(&matches) {
+            anyhow::bail!(
+                "--{} {} is only available for syntect printer since {}",
+                arg.get_long().unwrap(), // OK since all the options have long names
+                arg_kind(&arg),
+                reason.unwrap_or("bat does not support this feature"),
+            );
+        }
+    }
+
     let min_context = matches
         .get_one::<String>("min-context")
         .unwrap()
@@ -603,6 +2433,11 @@ fn run(matches: ArgMatches) -> Result<bool> {
         .parse()
         .context("Could not parse \"max-context\" option value as unsigned integer")?;
     let max_context = cmp::max(min_context, max_context);
+    let (min_context, max_context) = if matches.get_flag("no-context") {
+        (0, 0)
+    } else {
+        (min_context, max_context)
+    };
 
     let mut printer_opts = PrinterOptions::default();
     if let Some(width) = matches.get_one::<String>("tab") {
@@ -610,6 +2445,7 @@ fn run(matches: ArgMatches) -> Result<bool> {
             .parse()
             .context("Could not parse \"tab\" option value as unsigned integer")?;
     }
+    printer_opts.expand_tabs = matches.get_flag("expand-tabs");
 
     #[cfg(feature = "bat-printer")]
     let theme_env = env::var("BAT_THEME").ok();
@@ -623,20 +2459,34 @@ fn run(matches: ArgMatches) -> Result<bool> {
         printer_opts.theme = Some(theme);
     }
 
-    let is_grid = matches.get_flag("grid");
     #[cfg(feature = "bat-printer")]
     if printer_kind == PrinterKind::Bat {
         if let Ok("plain" | "header" | "numbers") =
             env::var("BAT_STYLE").as_ref().map(String::as_str)
         {
-            if !is_grid {
+            if !matches.get_flag("grid") {
                 printer_opts.grid = false;
             }
         }
     }
-    if matches.get_flag("no-grid") && !is_grid {
+    if matches.get_flag("no-grid") {
         printer_opts.grid = false;
     }
+    // The guard restores the original code page when returning from this function
+    #[cfg(windows)]
+    let _console = if matches.get_flag("no-console-adjust") {
+        None
+    } else {
+        let (guard, ascii_only) = console::adjust();
+        if ascii_only {
+            printer_opts.ascii_lines = true;
+            printer_opts.escape_non_ascii_paths = true;
+        }
+        Some(guard)
+    };
+    if matches.get_flag("no-ascii-lines") {
+        printer_opts.ascii_lines = false;
+    }
 
     if let Some(width) = matches.get_one::<String>("term-width") {
         let width = width
@@ -658,26 +2508,166 @@ fn run(matches: ArgMatches) -> Result<bool> {
         }
     }
 
+    if let Some(layout) = matches.get_one::<String>("layout") {
+        if layout.eq_ignore_ascii_case("unified") {
+            printer_opts.layout = Layout::Unified;
+        } else if layout.eq_ignore_ascii_case("native") {
+            printer_opts.layout = Layout::Native;
+        } else {
+            unreachable!(); // Option value was validated by clap
+        }
+    }
+
+    if let Some(sep) = matches.get_one::<String>("path-separator") {
+        printer_opts.path_separator = if sep.eq_ignore_ascii_case("auto") {
+            PathSeparator::Auto
+        } else if sep.eq_ignore_ascii_case("slash") {
+            PathSeparator::Char('/')
+        } else if sep.eq_ignore_ascii_case("backslash") {
+            PathSeparator::Char('\\')
+        } else {
+            let mut chars = sep.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c != '\n' => PathSeparator::Char(c),
+                _ => anyhow::bail!("--path-separator option value must be a single character, 'auto', 'slash', or 'backslash' but got {:?}", sep),
+            }
+        };
+    }
+
     if matches.get_flag("first-only") {
         printer_opts.first_only = true;
     }
 
+    if let Some(ext) = matches.get_one::<String>("stdin-ext") {
+        printer_opts.syntax_extension = Some(ext.trim_start_matches('.'));
+    }
+
     #[cfg(feature = "syntect-printer")]
     {
         if matches.get_flag("background") {
             printer_opts.background_color = true;
-            #[cfg(feature = "bat-printer")]
-            if printer_kind == PrinterKind::Bat {
-                anyhow::bail!("--background flag is only available for syntect printer since bat does not support painting background colors");
-            }
         }
 
         if matches.get_flag("ascii-lines") {
             printer_opts.ascii_lines = true;
-            #[cfg(feature = "bat-printer")]
-            if printer_kind == PrinterKind::Bat {
-                anyhow::bail!("--ascii-lines flag is only available for syntect printer since bat does not support this feature");
+        }
+
+        if matches.get_flag("squeeze-blank") {
+            printer_opts.squeeze_blank = true;
+        }
+
+        if matches.get_flag("strip-trailing-whitespace") {
+            printer_opts.strip_trailing_whitespace = true;
+        }
+
+        if matches.get_flag("with-character-position") {
+            printer_opts.character_position = true;
+        }
+
+        if matches.get_flag("ruler") {
+            printer_opts.ruler = true;
+        }
+
+        if matches.get_flag("with-diagnostics") {
+            printer_opts.diagnostics = true;
+        }
+
+        if matches.get_flag("explain-highlight") {
+            printer_opts.explain_highlight = true;
+        }
+
+        if matches.get_flag("compact") {
+            printer_opts.compact = true;
+        }
+
+        if let Some(chars) = matches.get_one::<String>("only-matching-with-context") {
+            printer_opts.match_context_chars = Some(chars.parse().context(
+                "Could not parse --only-matching-with-context option value as unsigned integer",
+            )?);
+        }
+
+        if matches.get_flag("chunk-range-header") {
+            printer_opts.chunk_range_header = true;
+        }
+
+        if matches.get_flag("gutter-links") {
+            use std::io::IsTerminal;
+            // Escape sequences for hyperlinks should not be written to files or pipes
+            printer_opts.gutter_links = io::stdout().is_terminal();
+        }
+
+        if matches.get_flag("no-context-colors") {
+            printer_opts.monochrome_context = true;
+        }
+
+        if let Some(gap) = matches.get_one::<String>("region-merge-gap") {
+            printer_opts.region_merge_gap = gap
+                .parse()
+                .context("Could not parse --region-merge-gap option value as unsigned integer")?;
+        }
+
+        if let Some(max) = matches.get_one::<String>("max-regions-per-line") {
+            printer_opts.max_regions_per_line = Some(max.parse().context(
+                "Could not parse --max-regions-per-line option value as unsigned integer",
+            )?);
+        }
+
+        if let Some(num) = matches.get_one::<String>("matches-per-line") {
+            let num: usize = num
+                .parse()
+                .context("Could not parse --matches-per-line option value as unsigned integer")?;
+            if num == 0 {
+                anyhow::bail!("--matches-per-line option value must be greater than 0");
+            }
+            printer_opts.matches_per_line = Some(num);
+        }
+
+        if let Some(glyph) = matches.get_one::<String>("gutter-separator") {
+            use unicode_width::UnicodeWidthChar;
+            let mut chars = glyph.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.width() == Some(1) => printer_opts.gutter_separator = Some(c),
+                _ => anyhow::bail!("--gutter-separator option value must be a single character which is 1 column wide but got {:?}", glyph),
+            }
+        }
+
+        if matches.contains_id("header-marker") {
+            let marker = match matches.get_one::<String>("header-marker") {
+                Some(marker) => marker.as_str(),
+                None if printer_opts.ascii_lines => "==>",
+                None => "⏺",
+            };
+            if marker.is_empty() || marker.chars().any(char::is_control) {
+                anyhow::bail!("--header-marker option value must be a non-empty string without control characters but got {:?}", marker);
+            }
+            printer_opts.header_marker = Some(marker);
+        }
+
+        if let Some(color) = matches.get_one::<String>("context-color") {
+            printer_opts.context_color = Some(
+                hgrep::syntect::parse_hex_color(color)
+                    .context("Could not parse --context-color option value")?,
+            );
+        }
+
+        if let Some(color) = matches.get_one::<String>("match-color") {
+            printer_opts.match_color = Some(
+                hgrep::syntect::parse_hex_color(color)
+                    .context("Could not parse --match-color option value")?,
+            );
+        }
+
+        if let Some(scale) = matches.get_one::<String>("color-scale") {
+            let scale: f32 = scale
+                .parse()
+                .context("Could not parse \"color-scale\" option value as number")?;
+            if !scale.is_finite() || scale < 0.0 {
+                anyhow::bail!(
+                    "--color-scale option value must be a non-negative number but got {}",
+                    scale
+                );
             }
+            printer_opts.color_scale = Some(scale);
         }
     }
 
@@ -690,10 +2680,43 @@ fn run(matches: ArgMatches) -> Result<bool> {
         }
     }
 
+    #[cfg(feature = "ripgrep")]
+    if matches.get_flag("stream") {
+        // Snippets are printed in the fixed window of lines around matches while searching a file
+        for opt in [
+            "context-max-bytes",
+            "context-overlap-policy",
+            "context-bias",
+        ] {
+            if matches!(matches.value_source(opt), Some(src) if src != ValueSource::DefaultValue) {
+                anyhow::bail!("--{} option is not available with --stream flag since snippets are printed while searching files", opt);
+            }
+        }
+    }
+
+    #[cfg(feature = "syntect-printer")]
+    let sample = match matches.get_one::<String>("sample-file") {
+        Some(arg) => {
+            let (path, line) = parse_cat_arg(arg, "sample-file")?;
+            Some(hgrep::syntect::sample_file(path, line)?)
+        }
+        None => None,
+    };
+
+    #[cfg(feature = "syntect-printer")]
+    if matches.get_flag("render-sample") {
+        hgrep::syntect::render_sample(io::stdout().lock(), &printer_opts, sample)?;
+        return Ok(true);
+    }
+
     if matches.get_flag("list-themes") {
         #[cfg(feature = "syntect-printer")]
         if printer_kind == PrinterKind::Syntect {
-            hgrep::syntect::list_themes(io::stdout().lock(), &printer_opts)?;
+            let names: Vec<&str> = matches
+                .get_many::<String>("themes")
+                .map(|names| names.map(String::as_str).collect())
+                .unwrap_or_default();
+            hgrep::syntect::list_themes(io::stdout().lock(), &printer_opts, &names, sample)?;
             return Ok(true);
         }
 
@@ -706,73 +2729,255 @@ fn run(matches: ArgMatches) -> Result<bool> {
         unreachable!();
     }
 
+    #[cfg(feature = "syntect-printer")]
+    if let Some(mut args) = matches.get_many::<String>("preview-theme") {
+        let (theme, path) = (args.next().unwrap(), args.next().unwrap()); // num_args(2) ensures two values
+        printer_opts.theme = Some(theme);
+        let printer = SyntectPrinter::with_stdout(printer_opts)?;
+        printer.preview_theme(std::path::Path::new(path))?;
+        return Ok(true);
+    }
+
+    #[cfg(feature = "syntect-printer")]
+    if let Some(arg) = matches.get_one::<String>("cat") {
+        let (path, line) = parse_cat_arg(arg, "cat")?;
+        let printer = SyntectPrinter::with_stdout(printer_opts)?;
+        printer.cat(path, line)?;
+        return Ok(true);
+    }
+
+    if matches.get_flag("self-test") {
+        // Long lines are not wrapped so that the printed lines can be checked as they are
+        printer_opts.text_wrap = TextWrapMode::Never;
+
+        #[cfg(feature = "syntect-printer")]
+        if printer_kind == PrinterKind::Syntect {
+            return self_test(|out| SyntectPrinter::new(out, printer_opts.clone()));
+        }
+
+        #[cfg(feature = "bat-printer")]
+        if printer_kind == PrinterKind::Bat {
+            return self_test(|out| {
+                Ok(CapturingBatPrinter {
+                    printer: Mutex::new(BatPrinter::new(printer_opts.clone())),
+                    out,
+                })
+            });
+        }
+
+        unreachable!();
+    }
+
     #[cfg(feature = "ripgrep")]
     if matches.get_flag("type-list") {
-        let config = build_ripgrep_config(min_context, max_context, &matches)?;
+        let config = build_ripgrep_config(min_context, max_context, None, &matches)?;
         config.print_types(io::stdout().lock())?;
         return Ok(true);
     }
 
+    let git_root = if matches.get_flag("with-git-root") {
+        Some(git_root(std::path::Path::new("."))?)
+    } else {
+        None
+    };
+
+    let per_page = snippets_per_page(&matches)?;
+    let dedup = matches
+        .get_flag("dedup-chunks")
+        .then_some(dedup::DEFAULT_CAPACITY);
+
     #[cfg(feature = "ripgrep")]
-    if let Some(pattern) = matches.get_one::<String>("PATTERN") {
-        use std::path::PathBuf;
+    let env_pattern = env_pattern(&matches)?;
 
-        let paths = matches
-            .get_many::<PathBuf>("PATH")
-            .map(|p| p.map(PathBuf::as_path));
-        let config = build_ripgrep_config(min_context, max_context, &matches)?;
+    if let Some(format) = matches.get_one::<String>("debug-config") {
+        let printer_source = match origins.source(&matches, "printer") {
+            Some(ArgSource::Default) if env::var(PRINTER_ENV_VAR).is_ok_and(|v| !v.is_empty()) => {
+                ArgSource::Env
+            }
+            source => source.unwrap_or(ArgSource::Default),
+        };
+        #[cfg(feature = "ripgrep")]
+        let config = pattern_and_paths(&matches, env_pattern.as_deref())
+            .map(|(pattern, _)| {
+                build_ripgrep_config(min_context, max_context, Some(pattern), &matches)
+            })
+            .transpose()?;
+        #[cfg(feature = "ripgrep")]
+        let config = config.as_ref().map(ripgrep::Config::debug_fields);
+        #[cfg(not(feature = "ripgrep"))]
+        let config = None;
+        let resolved = ResolvedConfig {
+            printer: (&printer_name(&matches)?, printer_source),
+            options: resolved_options(&matches, origins),
+            printer_opts: printer_opts.debug_fields(),
+            ripgrep_config: config,
+        };
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        if format == "json" {
+            resolved.write_json(&mut out)?;
+        } else {
+            resolved.write_text(&mut out)?;
+        }
+        return Ok(true);
+    }
+
+    #[cfg(feature = "ripgrep")]
+    if let Some((pattern, paths)) = pattern_and_paths(&matches, env_pattern.as_deref()) {
+        if stdin_is_piped() {
+            if env_pattern.is_some() {
+                // Do not leak the pattern which was passed via the environment variable
+                eprintln!("\x1b[1;93mwarning:\x1b[0m Input piped to stdin is ignored since --pattern-env is given. To read grep results from stdin, remove --pattern-env");
+            } else {
+                eprintln!("\x1b[1;93mwarning:\x1b[0m Input piped to stdin is ignored since PATTERN argument {:?} is given. To read grep results from stdin, remove PATTERN or pass --stdin", pattern);
+            }
+        }
+        let paths = (!paths.is_empty()).then(|| paths.into_iter());
+        let skipped = Mutex::default();
+        let mut config = build_ripgrep_config(min_context, max_context, Some(pattern), &matches)?;
+        if let Some(dir) = &git_root {
+            config.base_dir(dir);
+        }
+        if matches.contains_id("report-skipped") {
+            config.report_skipped(&skipped);
+        } else if matches.contains_id("max-filesize") && !matches.get_flag("no-hints") {
+            config.collect_skipped(&skipped); // For the hint after no match
+        }
+
+        if matches.get_flag("passthrough-matches") {
+            let found = ripgrep::print_matched_lines(pattern, paths, config, io::stdout())?;
+            report_skipped(&matches, skipped, found);
+            return Ok(found);
+        }
+
+        if matches.get_flag("count-only") {
+            use io::Write as _;
+            let total = ripgrep::count_matches(pattern, paths, config)?;
+            writeln!(io::stdout(), "{total}")?;
+            report_skipped(&matches, skipped, total > 0);
+            return Ok(total > 0);
+        }
+
+        if matches.get_flag("porcelain") {
+            let printer = CatchPanic::new(
+                PorcelainPrinter::with_stdout().path_separator(printer_opts.path_separator),
+                &PRINT_FAILURES,
+            );
+            let found = ripgrep::grep(printer, pattern, paths, config)?;
+            report_skipped(&matches, skipped, found);
+            return Ok(found);
+        }
 
         #[cfg(feature = "syntect-printer")]
         if printer_kind == PrinterKind::Syntect {
-            let printer = SyntectPrinter::with_stdout(printer_opts)?;
-            return ripgrep::grep(printer, pattern, paths, config);
+            let printer = DedupChunks::new(
+                Paginator::new(
+                    CatchPanic::new(SyntectPrinter::with_stdout(printer_opts)?, &PRINT_FAILURES),
+                    per_page,
+                ),
+                dedup,
+            );
+            let found = ripgrep::grep(printer, pattern, paths, config)?;
+            report_skipped(&matches, skipped, found);
+            return Ok(found);
         }
 
         #[cfg(feature = "bat-printer")]
         if printer_kind == PrinterKind::Bat {
-            let printer = std::sync::Mutex::new(BatPrinter::new(printer_opts));
-            return ripgrep::grep(printer, pattern, paths, config);
+            let printer = DedupChunks::new(
+                Paginator::new(
+                    CatchPanic::new(Mutex::new(BatPrinter::new(printer_opts)), &PRINT_FAILURES),
+                    per_page,
+                ),
+                dedup,
+            );
+            let found = ripgrep::grep(printer, pattern, paths, config)?;
+            report_skipped(&matches, skipped, found);
+            return Ok(found);
         }
 
-        unreachable!();
+        unreachable!();
+    }
+
+    #[cfg(feature = "ripgrep")]
+    for flag in ["count-only", "passthrough-matches"] {
+        if matches.get_flag(flag) {
+            anyhow::bail!("--{flag} is not available when reading grep results from stdin");
+        }
     }
 
-    let encoding = matches.get_one::<String>("encoding").map(String::as_str);
+    if matches.get_flag("porcelain") {
+        let printer = CatchPanic::new(
+            PorcelainPrinter::with_stdout().path_separator(printer_opts.path_separator),
+            &PRINT_FAILURES,
+        );
+        let files = stdin_files(&matches, min_context, max_context, git_root)?;
+        return print_files(&printer, files, &matches, false);
+    }
 
     #[cfg(feature = "syntect-printer")]
     if printer_kind == PrinterKind::Syntect {
-        use hgrep::printer::Printer;
-        use rayon::prelude::*;
-        let printer = SyntectPrinter::with_stdout(printer_opts)?;
-        return io::BufReader::new(io::stdin())
-            .grep_lines()
-            .chunks_per_file(min_context, max_context, encoding)?
-            .par_bridge()
-            .map(|file| {
-                printer.print(file?)?;
-                Ok(true)
-            })
-            .try_reduce(|| false, |a, b| Ok(a || b));
+        let printer = DedupChunks::new(
+            Paginator::new(
+                CatchPanic::new(SyntectPrinter::with_stdout(printer_opts)?, &PRINT_FAILURES),
+                per_page,
+            ),
+            dedup,
+        );
+        let files = stdin_files(&matches, min_context, max_context, git_root)?;
+        return print_files(&printer, files, &matches, true);
     }
 
     #[cfg(feature = "bat-printer")]
     if printer_kind == PrinterKind::Bat {
-        let mut found = false;
-        let printer = BatPrinter::new(printer_opts);
-        let stdin = io::stdin();
-        for f in io::BufReader::new(stdin.lock())
-            .grep_lines()
-            .chunks_per_file(min_context, max_context, encoding)?
-        {
-            printer.print(f?)?;
-            found = true;
-        }
-        return Ok(found);
+        let printer = DedupChunks::new(
+            Paginator::new(
+                CatchPanic::new(Mutex::new(BatPrinter::new(printer_opts)), &PRINT_FAILURES),
+                per_page,
+            ),
+            dedup,
+        );
+        let files = stdin_files(&matches, min_context, max_context, git_root)?;
+        return print_files(&printer, files, &matches, false);
     }
 
     unreachable!();
 }
 
+// Kind of the error for --report-errors-as-json. The first cause which is known in the chain decides the kind
+fn error_kind(err: &anyhow::Error) -> &'static str {
+    for cause in err.chain() {
+        #[cfg(feature = "ripgrep")]
+        if cause.is::<grep_regex::Error>() || cause.is::<grep_pcre2::Error>() {
+            return "InvalidPattern";
+        }
+        #[cfg(feature = "ripgrep")]
+        let io_err = match cause.downcast_ref::<ignore::Error>() {
+            Some(err) => err.io_error(),
+            None => cause.downcast_ref::<io::Error>(),
+        };
+        #[cfg(not(feature = "ripgrep"))]
+        let io_err = cause.downcast_ref::<io::Error>();
+        if let Some(err) = io_err {
+            return match err.kind() {
+                io::ErrorKind::NotFound => "FileNotFound",
+                io::ErrorKind::PermissionDenied => "PermissionDenied",
+                _ => "Io",
+            };
+        }
+    }
+    "Other"
+}
+
+// The message contains all causes of the error joined with ": "
+fn error_json(err: &anyhow::Error) -> String {
+    let json = serde_json::json!({
+        "error": format!("{:#}", err),
+        "kind": error_kind(err),
+    });
+    json.to_string()
+}
+
 fn main() {
     #[cfg(windows)]
     if let Err(code) = nu_ansi_term::enable_ansi_support() {
@@ -780,9 +2985,16 @@ fn main() {
         process::exit(2);
     }
 
-    let status = match Args::new().and_then(|a| run(command().get_matches_from(a))) {
-        Ok(true) => 0,
-        Ok(false) => 1,
+    let parsed = Args::new().and_then(parse_args);
+    let json = parsed
+        .as_ref()
+        .is_ok_and(|(m, _)| m.get_flag("report-errors-as-json"));
+    let status = match parsed.and_then(|(matches, origins)| exit_status(matches, origins)) {
+        Ok(status) => status,
+        Err(err) if json => {
+            eprintln!("{}", error_json(&err));
+            2
+        }
         Err(err) => {
             eprintln!("\x1b[1;91merror:\x1b[0m {}", err);
             for err in err.chain().skip(1) {
@@ -850,6 +3062,7 @@ mod tests {
         snapshot_test!(no_grid, ["--no-grid"]);
         snapshot_test!(theme, ["--theme", "Nord"]);
         snapshot_test!(tab, ["--tab", "8"]);
+        snapshot_test!(expand_tabs, ["--expand-tabs"]);
         snapshot_test!(bat_printer_long, ["--printer", "bat"]);
         snapshot_test!(bat_printer_short, ["-p", "bat"]);
         snapshot_test!(term_width, ["--term-width", "200"]);
@@ -857,19 +3070,91 @@ mod tests {
         snapshot_test!(first_only, ["--first-only"]);
         snapshot_test!(background, ["--background"]);
         snapshot_test!(ascii_lines, ["--ascii-lines"]);
+        snapshot_test!(no_ascii_lines, ["--no-ascii-lines"]);
+        snapshot_test!(squeeze_blank, ["--squeeze-blank"]);
+        snapshot_test!(strip_trailing_whitespace, ["--strip-trailing-whitespace"]);
+        snapshot_test!(with_character_position, ["--with-character-position"]);
+        snapshot_test!(ruler, ["--ruler"]);
+        snapshot_test!(chunk_range_header, ["--chunk-range-header"]);
+        snapshot_test!(with_diagnostics, ["--with-diagnostics"]);
+        snapshot_test!(explain_highlight, ["--explain-highlight"]);
+        snapshot_test!(compact, ["--compact"]);
+        snapshot_test!(layout, ["--layout", "unified"]);
+        snapshot_test!(path_separator, ["--path-separator", "slash"]);
+        snapshot_test!(path_separator_char, ["--path-separator", "|"]);
+        snapshot_test!(context_color, ["--context-color", "#1a1a2e"]);
+        snapshot_test!(self_test, ["--self-test"]);
+        snapshot_test!(self_test_alias, ["--test"]);
+        snapshot_test!(match_color, ["--match-color", "#3a3a5c"]);
+        snapshot_test!(color_scale, ["--color-scale", "0.8"]);
+        snapshot_test!(stdin_ext, ["--stdin-ext", "rs"]);
+        snapshot_test!(experimental_lsp, ["--experimental-lsp"]);
+        snapshot_test!(input_format, ["--input-format", "n", "--label", "foo.rs"]);
+        snapshot_test!(strip_ansi, ["--strip-ansi"]);
+        snapshot_test!(gutter_links, ["--gutter-links"]);
+        snapshot_test!(region_merge_gap, ["--region-merge-gap", "5"]);
+        snapshot_test!(max_regions_per_line, ["--max-regions-per-line", "3"]);
+        snapshot_test!(matches_per_line, ["--matches-per-line", "2"]);
+        snapshot_test!(gutter_separator, ["--gutter-separator", "|"]);
+        snapshot_test!(header_marker, ["--header-marker"]);
+        snapshot_test!(header_marker_with_value, ["--header-marker=>>"]);
+        snapshot_test!(only_matching_with_context, ["--oc", "20"]);
+        snapshot_test!(no_context_colors, ["--no-context-colors"]);
+        snapshot_test!(
+            context_and_match_color,
+            ["--context-color", "#1a1a2e", "--match-color", "3a3a5c"]
+        );
+        snapshot_test!(preview_theme, ["--preview-theme", "Nord", "src/main.rs"]);
+        snapshot_test!(cat, ["--cat", "src/main.rs"]);
+        snapshot_test!(paginate, ["--paginate", "10"]);
+        snapshot_test!(porcelain, ["--porcelain"]);
+        snapshot_test!(results_per_page, ["--results-per-page", "10"]);
+        snapshot_test!(cat_with_line, ["--cat", "src/main.rs:10"]);
+        snapshot_test!(report_skipped, ["--report-skipped", "pat"]);
+        snapshot_test!(group_by_match, ["--group-by-match"]);
+        snapshot_test!(dedup_chunks, ["--dedup-chunks"]);
+        snapshot_test!(report_skipped_list, ["--report-skipped=list", "pat"]);
         snapshot_test!(custom_assets, ["--printer", "bat", "--custom-assets"]);
         snapshot_test!(list_themes, ["--list-themes"]);
+        snapshot_test!(
+            list_themes_with_sample,
+            [
+                "--list-themes",
+                "--sample-file",
+                "src/main.rs:10",
+                "--themes",
+                "Nord,OneHalfDark"
+            ]
+        );
+        snapshot_test!(render_sample, ["--render-sample", "--theme", "Nord"]);
         snapshot_test!(type_list, ["--type-list"]);
+        snapshot_test!(stdin, ["--stdin"]);
         snapshot_test!(
             generate_completion_script,
             ["--generate-completion-script", "bash"]
         );
         snapshot_test!(generate_man_page, ["--generate-man-page"]);
+        snapshot_test!(print_default_config, ["--print-default-config"]);
+        snapshot_test!(debug_config, ["--debug-config"]);
+        snapshot_test!(debug_config_json, ["--debug-config=json"]);
         snapshot_test!(max_filesize, ["--max-filesize", "100M"]);
         snapshot_test!(unrestricted_once, ["-u"]);
+        snapshot_test!(no_ripgrep_defaults, ["--no-ripgrep-defaults"]);
+        snapshot_test!(count_only, ["--count-only"]);
+        snapshot_test!(use_ripgrep_config, ["--use-ripgrep-config"]);
+        snapshot_test!(passthrough_matches, ["--passthrough-matches"]);
         snapshot_test!(unrestricted_twice, ["-u", "-u"]);
         snapshot_test!(unrestricted_twice_in_single_flag, ["-uu"]);
         snapshot_test!(encoding, ["--encoding", "sjis"]);
+        snapshot_test!(with_git_root, ["--with-git-root"]);
+        snapshot_test!(sort_by_match_count, ["--sort-by-match-count", "desc"]);
+        snapshot_test!(context_max_bytes, ["--context-max-bytes", "1000"]);
+        snapshot_test!(no_context, ["--no-context"]);
+        snapshot_test!(context_overlap_policy, ["--context-overlap-policy", "clip"]);
+        snapshot_test!(context_bias, ["--context-bias", "after"]);
+        snapshot_test!(encoding_errors, ["--encoding-errors", "strict"]);
+        snapshot_test!(search_cwd, ["--search-cwd", "pat", "dir"]);
+        snapshot_test!(no_hints, ["--no-hints", "pat"]);
         snapshot_test!(
             all_printer_opts_before_args,
             [
@@ -943,7 +3228,7 @@ mod tests {
                     settings.bind(|| {
                         let cmd = command();
                         let mat = cmd.try_get_matches_from($args).unwrap();
-                        let err = run(mat).unwrap_err();
+                        let err = run(mat, ArgOrigins::default()).unwrap_err();
                         let mut msg = format!("{err}");
                         for err in err.chain().skip(1) {
                             write!(msg, " -> {err}").unwrap();
@@ -971,6 +3256,169 @@ mod tests {
             bat_doesnt_support_ascii_lines,
             ["--printer", "bat", "--ascii-lines"]
         );
+        snapshot_error_test!(
+            bat_doesnt_support_squeeze_blank,
+            ["--printer", "bat", "--squeeze-blank"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_strip_trailing_whitespace,
+            ["--printer", "bat", "--strip-trailing-whitespace"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_with_character_position,
+            ["--printer", "bat", "--with-character-position"]
+        );
+        snapshot_error_test!(bat_doesnt_support_ruler, ["--printer", "bat", "--ruler"]);
+        snapshot_error_test!(
+            bat_doesnt_support_context_color,
+            ["--printer", "bat", "--context-color", "#1a1a2e"]
+        );
+        snapshot_error_test!(invalid_context_color, ["--context-color", "#1a1a2"]);
+        snapshot_error_test!(
+            bat_doesnt_support_match_color,
+            ["--printer", "bat", "--match-color", "#3a3a5c"]
+        );
+        snapshot_error_test!(invalid_match_color, ["--match-color", "3a3a5x"]);
+        snapshot_error_test!(
+            bat_doesnt_support_color_scale,
+            ["--printer", "bat", "--color-scale", "0.8"]
+        );
+        snapshot_error_test!(invalid_color_scale, ["--color-scale", "dark"]);
+        snapshot_error_test!(unknown_stdin_ext, ["--stdin-ext", "foooooooo"]);
+        snapshot_error_test!(input_format_without_label, ["--input-format", "n"]);
+        snapshot_error_test!(label_without_input_format, ["--label", "foo.rs"]);
+        snapshot_error_test!(negative_color_scale, ["--color-scale=-0.5"]);
+        snapshot_error_test!(
+            bat_doesnt_support_explain_highlight,
+            ["--printer", "bat", "--explain-highlight"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_with_diagnostics,
+            ["--printer", "bat", "--with-diagnostics"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_compact,
+            ["--printer", "bat", "--compact"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_chunk_range_header,
+            ["--printer", "bat", "--chunk-range-header"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_gutter_links,
+            ["--printer", "bat", "--gutter-links"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_max_regions_per_line,
+            ["--printer", "bat", "--max-regions-per-line", "3"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_matches_per_line,
+            ["--printer", "bat", "--matches-per-line", "3"]
+        );
+        snapshot_error_test!(zero_matches_per_line, ["--matches-per-line", "0"]);
+        snapshot_error_test!(wide_gutter_separator, ["--gutter-separator", "日"]);
+        snapshot_error_test!(
+            bat_doesnt_support_gutter_separator,
+            ["--printer", "bat", "--gutter-separator", "|"]
+        );
+        snapshot_error_test!(empty_header_marker, ["--header-marker="]);
+        snapshot_error_test!(
+            bat_doesnt_support_header_marker,
+            ["--printer", "bat", "--header-marker"]
+        );
+        snapshot_error_test!(invalid_path_separator, ["--path-separator", "//"]);
+        snapshot_error_test!(invalid_region_merge_gap, ["--region-merge-gap", "two"]);
+        snapshot_error_test!(
+            bat_doesnt_support_only_matching_with_context,
+            ["--printer", "bat", "--oc", "20"]
+        );
+        snapshot_error_test!(
+            invalid_only_matching_with_context,
+            ["--only-matching-with-context=-1"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_no_context_colors,
+            ["--printer", "bat", "--no-context-colors"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_preview_theme,
+            ["--printer", "bat", "--preview-theme", "Nord", "src/main.rs"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_cat,
+            ["--printer", "bat", "--cat", "src/main.rs"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_render_sample,
+            ["--printer", "bat", "--render-sample"]
+        );
+        snapshot_error_test!(
+            bat_doesnt_support_themes,
+            ["--printer", "bat", "--list-themes", "--themes", "Nord"]
+        );
+        snapshot_error_test!(invalid_paginate, ["--paginate", "foo", "pat"]);
+        snapshot_error_test!(zero_paginate, ["--paginate", "0", "pat"]);
+        snapshot_error_test!(
+            cat_line_out_of_range,
+            ["--cat", "testdata/syntect/default.rs:100000"]
+        );
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(
+            bat_doesnt_support_highlight_surrounding_text,
+            ["--printer", "bat", "--highlight-surrounding-text"]
+        );
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(bat_doesnt_support_stream, ["--printer", "bat", "--stream"]);
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(count_only_with_stdin, ["--count-only"]);
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(passthrough_matches_with_stdin, ["--passthrough-matches"]);
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(
+            stream_with_context_max_bytes,
+            ["--stream", "--context-max-bytes", "1000"]
+        );
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(
+            stream_with_context_overlap_policy,
+            ["--stream", "--context-overlap-policy", "clip"]
+        );
+        #[cfg(feature = "ripgrep")]
+        snapshot_error_test!(
+            stream_with_context_bias,
+            ["--stream", "--context-bias", "after"]
+        );
+
+        #[cfg(feature = "bat-printer")]
+        #[test]
+        fn bat_doesnt_support_syntect_only_options() {
+            let cmd = command();
+            for &(id, reason) in SYNTECT_ONLY_OPTIONS {
+                let Some(arg) = cmd.get_arguments().find(|arg| arg.get_id() == id) else {
+                    continue;
+                };
+                let help = arg.get_help().unwrap().to_string();
+                assert!(help.ends_with(" is only for syntect printer"), "{help:?}");
+
+                let mut args = vec!["--printer".to_string(), "bat".to_string()];
+                args.push(format!("--{}", arg.get_long().unwrap()));
+                if arg.get_action().takes_values() && !arg.is_require_equals_set() {
+                    let num = arg.get_num_args().unwrap().min_values();
+                    args.extend(std::iter::repeat_n("1".to_string(), num));
+                }
+                let mat = cmd.clone().try_get_matches_from(&args).unwrap();
+                let err = run(mat, ArgOrigins::default()).unwrap_err();
+                let msg = format!("{err}");
+                assert!(
+                    msg.contains("only available for syntect printer"),
+                    "{args:?}: {msg:?}"
+                );
+                if let Some(reason) = reason {
+                    assert!(msg.ends_with(reason), "{args:?}: {msg:?}");
+                }
+            }
+        }
 
         #[test]
         fn arg_parser_debug_assert() {
@@ -984,6 +3432,9 @@ mod tests {
                 &["--printer", "foo"][..],
                 &["--wrap", "foo"][..],
                 &["--generate-completion-script", "unknown-shell"][..],
+                &["--stdin", "pat"][..],
+                &["--stream", "--group-by-match", "pat"][..],
+                &["--stream", "--sort-by-match-count", "desc", "pat"][..],
             ] {
                 let parsed = command().try_get_matches_from(args);
                 assert!(parsed.is_err(), "args: {:?}", args);
@@ -994,6 +3445,10 @@ mod tests {
     mod ripgrep_config {
         use super::*;
 
+        fn pattern(mat: &ArgMatches) -> Option<&str> {
+            mat.get_one::<String>("PATTERN").map(String::as_str)
+        }
+
         macro_rules! snapshot_test {
             ($name:ident, $args:expr) => {
                 #[test]
@@ -1013,7 +3468,8 @@ mod tests {
                             .parse()
                             .unwrap();
 
-                        let cfg = build_ripgrep_config(min_ctx, max_ctx, &mat).unwrap();
+                        let cfg =
+                            build_ripgrep_config(min_ctx, max_ctx, pattern(&mat), &mat).unwrap();
                         insta::assert_debug_snapshot!(cfg);
                     });
                 }
@@ -1030,14 +3486,34 @@ mod tests {
         );
         snapshot_test!(glob_before_opt, ["-g", "*.txt", "-i", "pat", "dir"]);
         snapshot_test!(glob_arg_with_hyphen, ["-g", "-foo_*.txt", "pat", "dir"]);
+        snapshot_test!(
+            prune,
+            ["--prune", "dist", "--prune", "node_modules", "pat", "dir"]
+        );
         snapshot_test!(ignore_case_smart_case, ["-i", "-S", "pat", "dir"]);
         snapshot_test!(smart_case_ignore_case, ["-S", "-i", "pat", "dir"]);
         snapshot_test!(max_count, ["--max-count", "100", "pat", "dir"]);
+        snapshot_test!(max_files, ["--max-files", "10", "pat", "dir"]);
         snapshot_test!(max_count_short, ["-m", "100", "pat", "dir"]);
         snapshot_test!(max_depth, ["--max-depth", "10", "pat", "dir"]);
         snapshot_test!(line_regexp_word_regexp, ["-x", "-w", "pat", "dir"]);
         snapshot_test!(word_regexp_line_regexp, ["-w", "-x", "pat", "dir"]);
         snapshot_test!(pcre2, ["-P", "pat", "dir"]);
+        snapshot_test!(
+            pcre2_jit_stack,
+            ["-P", "--pcre2-jit-stack", "64", "pat", "dir"]
+        );
+        snapshot_test!(no_pcre2_jit, ["-P", "--no-pcre2-jit", "pat", "dir"]);
+        snapshot_test!(
+            pcre2_ucp,
+            ["-P", "--no-unicode", "--pcre2-ucp", "pat", "dir"]
+        );
+        snapshot_test!(no_pcre2_ucp, ["-P", "--no-pcre2-ucp", "pat", "dir"]);
+        snapshot_test!(
+            highlight_surrounding_text,
+            ["--highlight-surrounding-text", "pat", "dir"]
+        );
+        snapshot_test!(stream, ["--stream", "pat", "dir"]);
         snapshot_test!(fixed_string_override_pcre2, ["-F", "-P", "pat", "dir"]);
         snapshot_test!(type_one, ["--type", "rust", "pat", "dir"]);
         snapshot_test!(type_many, ["-t", "rust", "-t", "go", "pat", "dir"]);
@@ -1080,9 +3556,44 @@ mod tests {
             ["-i", "-S", "-F", "-w", "-L", "-U", "-.", "-x", "-P", "pat", "dir"]
         );
         snapshot_test!(max_filesize, ["--max-filesize", "100M"]);
+        snapshot_test!(min_filesize, ["--min-filesize", "1K"]);
+        snapshot_test!(max_line_length, ["--max-line-length", "1000"]);
+        snapshot_test!(heap_limit, ["--heap-limit", "64M"]);
+        snapshot_test!(
+            case_normalize_display,
+            ["--case-normalize-display", "-i", "Pat", "dir"]
+        );
+
+        #[test]
+        fn multiline_dotall_aliases() {
+            for flag in ["--multiline-dotall", "--dot-matches-newline", "--dotall"] {
+                let mat = command()
+                    .try_get_matches_from(["hgrep", "-U", flag, "pat"])
+                    .unwrap();
+                let cfg = build_ripgrep_config(3, 6, pattern(&mat), &mat).unwrap();
+                let cfg = format!("{cfg:?}");
+                assert!(cfg.contains("multiline_dotall: true"), "flag: {flag}");
+            }
+        }
+        snapshot_test!(
+            changed_within_before,
+            ["--changed-within", "3d", "--changed-before", "2h"]
+        );
         snapshot_test!(unrestricted_once, ["-u"]);
+        snapshot_test!(no_ripgrep_defaults, ["--no-ripgrep-defaults"]);
         snapshot_test!(unrestricted_twice, ["-u", "-u"]);
         snapshot_test!(encoding, ["--encoding", "sjis"]);
+        snapshot_test!(sort_by_match_count_desc, ["--sort-by-match-count", "desc"]);
+        snapshot_test!(sort_by_match_count_asc, ["--sort-by-match-count", "ASC"]);
+        snapshot_test!(context_max_bytes, ["--context-max-bytes", "1000"]);
+        snapshot_test!(
+            context_overlap_policy,
+            ["--context-overlap-policy", "Separate"]
+        );
+        snapshot_test!(context_bias, ["--context-bias", "Before"]);
+        snapshot_test!(encoding_errors, ["--encoding-errors", "skip"]);
+        snapshot_test!(mmap_threshold, ["--mmap-threshold", "1M"]);
+        snapshot_test!(suppress_empty_context, ["--suppress-empty-context"]);
 
         macro_rules! snapshot_error_test {
             ($name:ident, $args:expr) => {
@@ -1093,7 +3604,7 @@ mod tests {
                     settings.set_snapshot_path(SNAPSHOT_DIR);
                     settings.bind(|| {
                         let mat = command().try_get_matches_from($args).unwrap();
-                        let err = build_ripgrep_config(3, 6, &mat).unwrap_err();
+                        let err = build_ripgrep_config(3, 6, pattern(&mat), &mat).unwrap_err();
                         let mut msg = format!("{err}");
                         for err in err.chain().skip(1) {
                             write!(msg, " -> {err}").unwrap();
@@ -1105,12 +3616,168 @@ mod tests {
         }
 
         snapshot_error_test!(max_count_parse_error, ["--max-count", "foo"]);
+        snapshot_error_test!(max_files_parse_error, ["--max-files", "foo"]);
         snapshot_error_test!(max_depth_parse_error, ["--max-depth", "foo"]);
         snapshot_error_test!(max_filesize_parse_error, ["--max-filesize", "foo"]);
+        snapshot_error_test!(min_filesize_parse_error, ["--min-filesize", "foo"]);
+        snapshot_error_test!(changed_within_parse_error, ["--changed-within", "foo"]);
+        snapshot_error_test!(changed_before_parse_error, ["--changed-before", "3x"]);
+        snapshot_error_test!(max_line_length_parse_error, ["--max-line-length", "foo"]);
+        snapshot_error_test!(heap_limit_parse_error, ["--heap-limit", "foo"]);
+        snapshot_error_test!(heap_limit_zero, ["--heap-limit", "0"]);
         snapshot_error_test!(regex_size_limit_parse_error, ["--regex-size-limit", "foo"]);
         snapshot_error_test!(dfa_size_limit_parse_error, ["--dfa-size-limit", "foo"]);
+        snapshot_error_test!(mmap_threshold_parse_error, ["--mmap-threshold", "foo"]);
+        snapshot_error_test!(pcre2_jit_stack_parse_error, ["--pcre2-jit-stack", "foo"]);
+        snapshot_error_test!(
+            pcre2_jit_stack_overflow,
+            ["--pcre2-jit-stack", "99999999999999", "pat"]
+        );
+        snapshot_error_test!(
+            context_max_bytes_parse_error,
+            ["--context-max-bytes", "foo"]
+        );
         snapshot_error_test!(too_many_u_flags_mutiple, ["-u", "-u", "-u"]);
         snapshot_error_test!(too_many_u_flags_single, ["-uuu"]);
+
+        #[test]
+        fn pattern_env() {
+            use std::path::Path;
+
+            // The variable names are specific to this test so that other tests running in parallel are not affected
+            const SET: &str = "HGREP_TEST_PATTERN_ENV_SET";
+            const EMPTY: &str = "HGREP_TEST_PATTERN_ENV_EMPTY";
+            const UNSET: &str = "HGREP_TEST_PATTERN_ENV_UNSET";
+            env::set_var(SET, "secret|token");
+            env::set_var(EMPTY, "");
+            env::remove_var(UNSET);
+
+            for (args, want) in [
+                (&["--pattern-env", SET][..], Some(("secret|token", &[][..]))),
+                (
+                    &["--pattern-env", SET, "dir1", "dir2"][..],
+                    Some(("secret|token", &["dir1", "dir2"][..])),
+                ),
+                (&["pat", "dir"][..], Some(("pat", &["dir"][..]))),
+                (&[][..], None),
+            ] {
+                let mat = command().try_get_matches_from(args).unwrap();
+                let env_pat = env_pattern(&mat).unwrap();
+                let have = pattern_and_paths(&mat, env_pat.as_deref());
+                let want = want.map(|(p, paths)| (p, paths.iter().map(Path::new).collect()));
+                assert_eq!(have, want, "args: {args:?}");
+            }
+
+            // --search-cwd appends the current directory to the paths
+            for (args, want) in [
+                (
+                    &["--search-cwd", "pat", "dir"][..],
+                    Some(("pat", &["dir", "."][..])),
+                ),
+                (
+                    &["--include-cwd", "pat", "dir"][..],
+                    Some(("pat", &["dir", "."][..])),
+                ),
+                (&["--search-cwd", "pat"][..], Some(("pat", &[][..]))),
+                (
+                    &["--search-cwd", "pat", ".", "dir"][..],
+                    Some(("pat", &[".", "dir"][..])),
+                ),
+                (
+                    &["--search-cwd", "--pattern-env", SET, "dir"][..],
+                    Some(("secret|token", &["dir", "."][..])),
+                ),
+            ] {
+                let mat = command().try_get_matches_from(args).unwrap();
+                let env_pat = env_pattern(&mat).unwrap();
+                let have = pattern_and_paths(&mat, env_pat.as_deref());
+                let want = want.map(|(p, paths)| (p, paths.iter().map(Path::new).collect()));
+                assert_eq!(have, want, "args: {args:?}");
+            }
+
+            // --case-normalize-display uses the pattern in the environment variable
+            let mat = command()
+                .try_get_matches_from(["--pattern-env", SET, "--case-normalize-display"])
+                .unwrap();
+            let env_pat = env_pattern(&mat).unwrap();
+            let (pat, _) = pattern_and_paths(&mat, env_pat.as_deref()).unwrap();
+            let cfg = format!("{:?}", build_ripgrep_config(3, 6, Some(pat), &mat).unwrap());
+            assert!(
+                cfg.contains(r#"case_normalize_display: Some("secret|token")"#),
+                "{cfg}"
+            );
+
+            for var in [EMPTY, UNSET] {
+                let mat = command()
+                    .try_get_matches_from(["--pattern-env", var])
+                    .unwrap();
+                let err = format!("{:#}", env_pattern(&mat).unwrap_err());
+                assert!(err.contains(var), "{err:?}");
+            }
+
+            let err = command()
+                .try_get_matches_from(["--pattern-env", SET, "--stdin"])
+                .unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+
+            env::remove_var(SET);
+            env::remove_var(EMPTY);
+        }
+    }
+
+    // Temporary directory removed even when the test fails
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("{name}-{}", process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn find_git_root() {
+        let git = |args: &[&str], cwd: &std::path::Path| {
+            process::Command::new("git")
+                .args(args)
+                .current_dir(cwd)
+                .output()
+        };
+        // Tests may run where git is not installed such as building the crate from crates.io
+        if !git(&["--version"], &env::temp_dir()).is_ok_and(|out| out.status.success()) {
+            eprintln!("find_git_root is skipped since `git` command is not available");
+            return;
+        }
+
+        let tmp = TempDir::new("hgrep-git-root-test");
+        let repo = tmp.0.join("repo");
+        let sub = repo.join("sub").join("dir");
+        std::fs::create_dir_all(&sub).unwrap();
+        let output = git(&["init", "-q"], &repo).unwrap();
+        assert!(output.status.success(), "{output:?}");
+
+        let root = git_root(&sub).unwrap();
+        assert_eq!(
+            root.canonicalize().unwrap(),
+            repo.canonicalize().unwrap(),
+            "root: {root:?}",
+        );
+
+        let outside = tmp.0.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        let err = git_root(&outside);
+        // The temporary directory may be inside some repository on the machine running the tests
+        if let Err(err) = err {
+            let msg = format!("{err}");
+            assert!(msg.contains("--with-git-root"), "{msg:?}");
+        }
     }
 
     #[test]
@@ -1122,6 +3789,216 @@ mod tests {
         }
     }
 
+    #[test]
+    fn no_match_behavior() {
+        let no_match = |args: &[&str]| {
+            let mut argv = vec!["hgrep"];
+            argv.extend_from_slice(args);
+            NoMatch::new(&command().try_get_matches_from(argv).unwrap())
+        };
+
+        assert_eq!(no_match(&["pat"]).unwrap(), NoMatch::default());
+        assert_eq!(no_match(&["pat"]).unwrap().exit_status, 1);
+        for (args, message, exit_status) in [
+            (&["--no-match-exit", "0"][..], None, 0),
+            (&["--no-match-exit", "255"][..], None, 255),
+            (&["--no-match-message", "nothing"][..], Some("nothing"), 1),
+            (
+                &["--no-match-message", "nothing", "--quiet-no-match"][..],
+                None,
+                1,
+            ),
+            (
+                &["--quiet-no-match", "--no-match-message", "nothing"][..],
+                Some("nothing"),
+                1,
+            ),
+            (
+                &["--no-match-message", "ok", "--no-match-exit", "0"][..],
+                Some("ok"),
+                0,
+            ),
+            (&["--self-test", "--no-match-exit", "0"][..], None, 1),
+        ] {
+            let want = NoMatch {
+                message: message.map(String::from),
+                exit_status,
+            };
+            assert_eq!(no_match(args).unwrap(), want, "{args:?}");
+        }
+
+        for code in ["256", "1.0", "foo"] {
+            let err = no_match(&["--no-match-exit", code]).unwrap_err();
+            let msg = format!("{}", err);
+            assert!(msg.contains("--no-match-exit"), "{msg:?}");
+        }
+    }
+
+    #[cfg(feature = "syntect-printer")]
+    #[test]
+    fn cat_arg() {
+        use std::path::Path;
+
+        for (arg, path, line) in [
+            ("src/main.rs", "src/main.rs", None),
+            ("src/main.rs:10", "src/main.rs", Some(10)),
+            ("src/main.rs:0010", "src/main.rs", Some(10)),
+            ("src/main.rs:", "src/main.rs:", None),
+            ("src/main.rs:x", "src/main.rs:x", None),
+            (":10", ":10", None),
+            ("a:b:10", "a:b", Some(10)),
+            ("/path/to/file:42", "/path/to/file", Some(42)),
+        ] {
+            let (p, l) = parse_cat_arg(arg, "cat").unwrap();
+            assert_eq!((p, l), (Path::new(path), line), "{arg:?}");
+        }
+
+        let err = parse_cat_arg("foo.rs:99999999999999999999", "cat").unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("Could not parse line number"), "{msg:?}");
+    }
+
+    #[test]
+    fn self_test_output() {
+        let output = format!(
+            "\x1b[1m sample.rs\x1b[0m\n\x1b[38;2;1;2;3m{}\x1b[0m\n",
+            SELF_TEST_EXPECTED_LINES.join("\n"),
+        );
+        assert_eq!(check_self_test_output(true, output.as_bytes()), Ok(()));
+
+        let err = check_self_test_output(false, output.as_bytes()).unwrap_err();
+        assert!(err.contains("No match"), "{err:?}");
+
+        let output = format!(" sample.rs\n{}\n", SELF_TEST_EXPECTED_LINES[1]);
+        let err = check_self_test_output(true, output.as_bytes()).unwrap_err();
+        assert!(err.contains("let message"), "{err:?}");
+
+        let output = SELF_TEST_EXPECTED_LINES.join("\n");
+        let err = check_self_test_output(true, output.as_bytes()).unwrap_err();
+        assert!(err.contains("File name"), "{err:?}");
+    }
+
+    #[test]
+    fn default_config() {
+        let mut v = vec![];
+        print_default_config(&mut v).unwrap();
+        let config = String::from_utf8(v).unwrap();
+
+        // All options are commented out by default
+        assert_eq!(shlex::split(&config), Some(vec![]));
+
+        for arg in command().get_arguments() {
+            let Some(long) = arg.get_long() else {
+                continue;
+            };
+            let line = format!("\n# --{long}");
+            let listed =
+                config.contains(&format!("{line}\n")) || config.contains(&format!("{line} "));
+            assert_eq!(
+                listed,
+                !INFORMATIONAL_OPTIONS.contains(&long),
+                "--{long} in config:\n{config}",
+            );
+        }
+
+        // Uncommented options at their default values are accepted
+        let cmd = command();
+        for line in config.lines() {
+            let Some(opt) = line.strip_prefix("# --") else {
+                continue;
+            };
+            let long = opt.split(' ').next().unwrap();
+            let arg = cmd
+                .get_arguments()
+                .find(|a| a.get_long() == Some(long))
+                .unwrap();
+            if arg.get_action().takes_values() && arg.get_default_values().is_empty() {
+                continue; // Placeholder of value
+            }
+            let args = shlex::split(&line[2..]).unwrap();
+            command()
+                .try_get_matches_from(&args)
+                .unwrap_or_else(|err| panic!("{line:?} is invalid: {err}"));
+        }
+    }
+
+    #[test]
+    fn arg_sources() {
+        let args = [
+            "--smart-case",
+            "--glob",
+            "a",
+            "--theme=Nord",
+            "-c",
+            "2",
+            "--glob",
+            "b",
+            "--no-grid",
+            "--debug-config",
+            "pat",
+        ];
+        let args = args.map(OsString::from);
+        let matches = command().try_get_matches_from(&args).unwrap();
+        // 3 arguments from ripgrep's config file, 3 arguments from HGREP_DEFAULT_OPTS, and the rest from command line
+        let origins = ArgOrigins::new(&matches, &args, 3, 3);
+        let tests = [
+            ("smart-case", Some(ArgSource::ConfigFile)),
+            ("theme", Some(ArgSource::Env)),
+            ("min-context", Some(ArgSource::Env)),
+            ("glob", Some(ArgSource::Flag)), // The last value decides the source
+            ("no-grid", Some(ArgSource::Flag)),
+            ("PATTERN", Some(ArgSource::Flag)),
+            ("max-context", Some(ArgSource::Default)),
+            ("ignore-case", Some(ArgSource::Default)),
+            ("max-count", None),
+        ];
+        for (id, want) in tests {
+            assert_eq!(origins.source(&matches, id), want, "{id}");
+        }
+
+        let options = resolved_options(&matches, origins);
+        let names: Vec<_> = options.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert!(names.contains(&"--max-context"), "{names:?}");
+        assert!(!names.contains(&"--ignore-case"), "{names:?}"); // Flags which are not set
+        let glob = options
+            .iter()
+            .find(|(name, _, _)| name == "--glob")
+            .unwrap();
+        assert_eq!(glob.1, ["a", "b"]);
+    }
+
+    #[test]
+    fn num_indices_of_args() {
+        let mut cmd = command();
+        cmd.build();
+        for args in [
+            &[][..],
+            &["--smart-case", "--glob", "a"][..],
+            &["--theme=Nord", "-c", "2"][..],
+            &["-c2", "-iS", "-C", "3"][..],
+            &["-c=2", "-t", "rust"][..],
+            &["-ic2", "--max-count", "3"][..],
+            &["--debug-config", "--debug-config=json", "-F"][..],
+            &["--preview-theme", "Nord", "path/to/file.rs"][..],
+            &["--themes", "Nord,Zenburn", "--list-themes"][..],
+            &["--themes=Nord,Zenburn,OneHalfDark", "--list-themes"][..],
+            &["pat", "dir1", "dir2"][..],
+            &["-i", "--", "-pat", "--dir"][..],
+        ] {
+            let args: Vec<_> = args.iter().map(OsString::from).collect();
+            let matches = cmd.clone().try_get_matches_from(&args).unwrap();
+            // Default values also have indices so only the values given as the arguments are checked
+            let want = cmd
+                .get_arguments()
+                .map(|arg| arg.get_id().as_str())
+                .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+                .filter_map(|id| matches.indices_of(id)?.max())
+                .max()
+                .unwrap_or(0);
+            assert_eq!(num_indices(&cmd, &args), want, "{args:?}");
+        }
+    }
+
     mod args {
         use super::*;
         use std::ffi::OsString;
@@ -1174,6 +4051,46 @@ mod tests {
             }
         }
 
+        #[test]
+        fn last_opposing_flag_takes_effect() {
+            let _lock = MU.lock().unwrap();
+            let _guard = Guard::new();
+
+            let cmd = command();
+            let defined = |id| cmd.get_arguments().any(|arg| arg.get_id() == id);
+            for &(a, b) in OPPOSING_FLAGS {
+                if !defined(a) || !defined(b) {
+                    continue;
+                }
+                let (flag_a, flag_b) = (format!("--{a}"), format!("--{b}"));
+
+                for (env, args, want, not) in [
+                    ("", vec![&flag_a, &flag_b], b, a),
+                    ("", vec![&flag_b, &flag_a], a, b),
+                    (flag_a.as_str(), vec![&flag_b], b, a),
+                    (flag_b.as_str(), vec![&flag_a], a, b),
+                    (&format!("{flag_a} {flag_b}"), vec![], b, a),
+                    (&format!("{flag_b} {flag_a}"), vec![], a, b),
+                ] {
+                    env::set_var(OPTS_ENV_VAR, env);
+                    let mut argv = vec![OsString::from("hgrep")];
+                    argv.extend(
+                        Args::new()
+                            .unwrap()
+                            .env
+                            .into_iter()
+                            .rev()
+                            .map(OsString::from),
+                    );
+                    argv.extend(args.iter().map(OsString::from));
+
+                    let matches = command().try_get_matches_from(&argv).unwrap();
+                    assert!(matches.get_flag(want), "{want:?} is not set: {argv:?}");
+                    assert!(!matches.get_flag(not), "{not:?} is set: {argv:?}");
+                }
+            }
+        }
+
         #[test]
         fn no_env_for_args() {
             let _lock = MU.lock().unwrap();
@@ -1215,4 +4132,130 @@ mod tests {
             assert!(msg.contains("is not a valid UTF-8 sequence"), "{msg:?}");
         }
     }
+
+    #[test]
+    fn errors_as_json() {
+        let err = anyhow::anyhow!("not found").context("foo.rs");
+        assert_eq!(
+            error_json(&err),
+            r#"{"error":"foo.rs: not found","kind":"Other"}"#,
+        );
+
+        let err = anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound)).context("foo.rs");
+        assert_eq!(error_kind(&err), "FileNotFound");
+        let err = anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(error_kind(&err), "PermissionDenied");
+        let err = anyhow::Error::new(io::Error::from(io::ErrorKind::InvalidData));
+        assert_eq!(error_kind(&err), "Io");
+
+        #[cfg(feature = "ripgrep")]
+        {
+            let err = grep_regex::RegexMatcher::new("(").unwrap_err();
+            assert_eq!(error_kind(&err.into()), "InvalidPattern");
+        }
+    }
+
+    #[test]
+    fn adjust_console_code_page() {
+        let never_called = |_| panic!("code page should not be changed");
+        assert_eq!(adjust_console(None, never_called), ConsoleAdjustment::Keep);
+        assert_eq!(
+            adjust_console(Some(UTF8_CODE_PAGE), never_called),
+            ConsoleAdjustment::Keep
+        );
+
+        let mut set = None;
+        let adjusted = adjust_console(Some(932), |cp| {
+            set = Some(cp);
+            true
+        });
+        assert_eq!(adjusted, ConsoleAdjustment::Switched { restore: 932 });
+        assert_eq!(set, Some(UTF8_CODE_PAGE));
+
+        assert_eq!(
+            adjust_console(Some(437), |_| false),
+            ConsoleAdjustment::AsciiOnly
+        );
+    }
+
+    #[cfg(feature = "ripgrep")]
+    mod use_ripgrep_config {
+        use super::*;
+        use std::fs;
+
+        fn parse(name: &str) -> (Vec<String>, Vec<String>) {
+            let path = format!("testdata/ripgrep_config/{name}.rgrc");
+            let content = fs::read_to_string(path).unwrap();
+            let mut warnings = vec![];
+            let args = ripgrep_config_args(&content, &mut warnings);
+            (args, warnings)
+        }
+
+        #[test]
+        fn supported_flags() {
+            let (args, warnings) = parse("supported");
+            assert_eq!(
+                args,
+                [
+                    "--smart-case",
+                    "--glob",
+                    "!*.lock",
+                    "--glob",
+                    "!target/",
+                    "--hidden",
+                    "--glob",
+                    "*.rs",
+                    "--type",
+                    "py",
+                    "--type-not",
+                    "js",
+                    "--min-context",
+                    "4",
+                    "--max-context",
+                    "4",
+                    "--max-count",
+                    "10",
+                    "--follow",
+                    "--fixed-strings",
+                ],
+            );
+            assert!(warnings.is_empty(), "{warnings:?}");
+
+            let matches = command().try_get_matches_from(&args).unwrap();
+            let cfg = build_ripgrep_config(3, 6, None, &matches);
+            assert!(cfg.is_ok(), "{cfg:?}");
+        }
+
+        #[test]
+        fn unsupported_flags() {
+            let (args, warnings) = parse("unsupported");
+            assert_eq!(args, ["--smart-case"]);
+            assert_eq!(
+                warnings,
+                [
+                    r#"Flag "--sort=path" is not supported by hgrep"#,
+                    r#"Flag "--max-columns" is not supported by hgrep"#,
+                    r#"Flag "--colors=line:none" is not supported by hgrep"#,
+                    r#"Flag "--no-heading" is not supported by hgrep"#,
+                    r#"Flag "-A" is not supported by hgrep"#,
+                    r#"Flag "--hidden=yes" does not take a value"#,
+                    r#"Argument "pattern" is not a flag"#,
+                    r#"Flag "--max-depth" requires a value"#,
+                ],
+            );
+        }
+
+        #[test]
+        fn hgrep_options_take_precedence() {
+            let (mut args, _) = parse("supported");
+            args.extend(["--max-count", "3", "--ignore-case"].map(String::from));
+            let matches = command().try_get_matches_from(&args).unwrap();
+            assert_eq!(
+                matches.get_one::<String>("max-count").map(String::as_str),
+                Some("3"),
+            );
+            assert!(matches.get_flag("ignore-case"));
+            assert!(!matches.get_flag("smart-case"));
+        }
+    }
 }
@@ -0,0 +1,232 @@
+use crate::chunk::File;
+use crate::printer::Printer;
+use anyhow::Result;
+use console::{Key, Term};
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+
+const PROMPT: &str = "\x1b[7m-- More -- (Enter or Space: next page, q: quit)\x1b[0m";
+
+// Error returned from `Paginator::print` when the user quit at the prompt. Searchers stop at the first printer error so
+// this error stops the search. The caller should regard this error as a successful exit
+#[derive(Debug)]
+pub struct Quit;
+
+impl fmt::Display for Quit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Quit at the prompt of --paginate")
+    }
+}
+
+impl std::error::Error for Quit {}
+
+// Source of the user's answer at the prompt between pages. This is separated from `Paginator` for unit tests
+pub trait PageInput {
+    // Return `true` to show the next page and `false` to quit
+    fn next_page(&mut self) -> io::Result<bool>;
+}
+
+// Prompt on the terminal which waits for a single key. The cursor is hidden while this is alive
+pub struct Terminal(Term);
+
+impl Terminal {
+    pub fn new() -> Self {
+        let term = Term::stdout();
+        let _ = term.hide_cursor(); // Failing to hide the cursor is not fatal
+        Self(term)
+    }
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.0.show_cursor();
+    }
+}
+
+impl PageInput for Terminal {
+    fn next_page(&mut self) -> io::Result<bool> {
+        self.0.write_str(PROMPT)?;
+        let next = loop {
+            // Raw key is read so that Ctrl-C quits at the prompt and the cursor is restored
+            match self.0.read_key_raw()? {
+                Key::Enter | Key::Char(' ') => break true,
+                Key::Char('q' | 'Q') | Key::Escape | Key::CtrlC => break false,
+                _ => {}
+            }
+        };
+        self.0.clear_line()?;
+        Ok(next)
+    }
+}
+
+struct Page<I> {
+    input: I,
+    printed: usize,
+    quit: bool,
+}
+
+// Printer which pauses after printing every N snippets and waits for the user's input. Snippets of one file are not
+// split into different pages. When the number of snippets per page is not given, this printer prints files as-is
+pub struct Paginator<P, I = Terminal> {
+    printer: P,
+    per_page: usize,
+    page: Option<Mutex<Page<I>>>,
+}
+
+impl<P> Paginator<P> {
+    pub fn new(printer: P, per_page: Option<usize>) -> Self {
+        Self::with_input(printer, per_page.map(|n| (n, Terminal::new())))
+    }
+}
+
+impl<P, I> Paginator<P, I> {
+    pub fn with_input(printer: P, pager: Option<(usize, I)>) -> Self {
+        let (per_page, page) = match pager {
+            Some((n, input)) => {
+                let page = Page {
+                    input,
+                    printed: 0,
+                    quit: false,
+                };
+                (n, Some(Mutex::new(page)))
+            }
+            None => (0, None),
+        };
+        Self {
+            printer,
+            per_page,
+            page,
+        }
+    }
+}
+
+impl<P: Printer, I: PageInput> Printer for Paginator<P, I> {
+    fn print(&self, file: File) -> Result<()> {
+        let Some(page) = &self.page else {
+            return self.printer.print(file);
+        };
+
+        // The lock is held while printing so that files printed in parallel don't go over the prompt
+        let mut page = page.lock().unwrap();
+        if page.quit {
+            return Err(Quit.into());
+        }
+        if page.printed >= self.per_page {
+            if !page.input.next_page()? {
+                page.quit = true;
+                return Err(Quit.into());
+            }
+            page.printed = 0;
+        }
+
+        let snippets = cmp::max(file.chunks.len(), 1);
+        self.printer.print(file)?;
+        page.printed += snippets;
+        Ok(())
+    }
+
+    fn print_header(&self, header: &str) -> Result<()> {
+        self.printer.print_header(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::LineMatch;
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+
+    #[derive(Default)]
+    struct DummyPrinter(Mutex<Vec<String>>);
+
+    impl Printer for &DummyPrinter {
+        fn print(&self, file: File) -> Result<()> {
+            let path = file.path.to_string_lossy().into_owned();
+            self.0.lock().unwrap().push(path);
+            Ok(())
+        }
+
+        fn print_header(&self, header: &str) -> Result<()> {
+            self.0.lock().unwrap().push(format!("header: {header}"));
+            Ok(())
+        }
+    }
+
+    // Answers at the prompts. "prompt" is recorded in the printer output when the prompt is shown
+    struct DummyInput<'a> {
+        answers: VecDeque<bool>,
+        printer: &'a DummyPrinter,
+    }
+
+    impl PageInput for DummyInput<'_> {
+        fn next_page(&mut self) -> io::Result<bool> {
+            self.printer.0.lock().unwrap().push("prompt".to_string());
+            Ok(self.answers.pop_front().unwrap())
+        }
+    }
+
+    fn file(path: &str, chunks: usize) -> File {
+        let lmats = (1..=chunks as u64).map(LineMatch::lnum).collect();
+        let chunks = (1..=chunks as u64).map(|l| (l, l)).collect();
+        File::new(PathBuf::from(path), lmats, chunks, "a\nb\nc\n".to_string())
+    }
+
+    #[test]
+    fn test_paginate_per_snippets() {
+        let printer = DummyPrinter::default();
+        let input = DummyInput {
+            answers: [true, true].into(),
+            printer: &printer,
+        };
+        let paginator = Paginator::with_input(&printer, Some((2, input)));
+        for (path, chunks) in [("a", 1), ("b", 1), ("c", 3), ("d", 1)] {
+            paginator.print(file(path, chunks)).unwrap();
+        }
+        drop(paginator);
+
+        // Snippets of 'c' are not split into multiple pages. The prompt is not shown after the last file
+        let printed = printer.0.into_inner().unwrap();
+        assert_eq!(printed, ["a", "b", "prompt", "c", "prompt", "d"]);
+    }
+
+    #[test]
+    fn test_paginate_quit() {
+        let printer = DummyPrinter::default();
+        let input = DummyInput {
+            answers: [false].into(),
+            printer: &printer,
+        };
+        let paginator = Paginator::with_input(&printer, Some((1, input)));
+        paginator.print(file("a", 1)).unwrap();
+        paginator.print_header("group").unwrap();
+        for path in ["b", "c"] {
+            let err = paginator.print(file(path, 1)).unwrap_err();
+            assert!(err.is::<Quit>(), "{err:?}");
+        }
+        drop(paginator);
+
+        // The prompt is shown only once and nothing is printed after quitting
+        let printed = printer.0.into_inner().unwrap();
+        assert_eq!(printed, ["a", "header: group", "prompt"]);
+    }
+
+    #[test]
+    fn test_no_pagination() {
+        let printer = DummyPrinter::default();
+        let paginator = Paginator::<_, DummyInput<'_>>::with_input(&printer, None);
+        for path in ["a", "b", "c"] {
+            paginator.print(file(path, 5)).unwrap();
+        }
+        drop(paginator);
+        assert_eq!(printer.0.into_inner().unwrap(), ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::broken_pipe::IgnoreBrokenPipe;
+use crate::chunk::File;
+use crate::printer::{PathSeparator, Printer};
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+// Version of the porcelain format. The format is a compatibility contract for scripts. Bump this version when the format
+// must be changed in an incompatible way
+pub const VERSION: u32 = 1;
+
+// Escape characters which would break the line-oriented records
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct Output<W> {
+    out: W,
+    started: bool,
+}
+
+// Printer for --porcelain. Output is stable and line-oriented so that scripts can parse it. The first line is the
+// version of the format like "porcelain=1" and records follow it. Fields of a record are separated by tabs.
+//
+// - `{path}\t{start}\t{end}`: A chunk of the file from the start line to the end line
+// - `match\t{line}`: A matched line in the preceding chunk
+// - `group\t{text}`: A header of the group with --group-by-match
+//
+// Backslashes, tabs, and newlines in paths and headers are escaped as `\\`, `\t`, and `\n`. No color and no wrapping.
+pub struct PorcelainPrinter<W: Write> {
+    output: Mutex<Output<W>>,
+    path_separator: PathSeparator,
+}
+
+impl PorcelainPrinter<io::Stdout> {
+    pub fn with_stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write> PorcelainPrinter<W> {
+    pub fn new(out: W) -> Self {
+        let output = Output {
+            out,
+            started: false,
+        };
+        Self {
+            output: Mutex::new(output),
+            path_separator: PathSeparator::Auto,
+        }
+    }
+
+    pub fn path_separator(mut self, sep: PathSeparator) -> Self {
+        self.path_separator = sep;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.into_inner().unwrap().out
+    }
+
+    fn write(&self, records: &str) -> io::Result<()> {
+        // Take lock here to print files in serial from multiple threads
+        let mut output = self.output.lock().unwrap();
+        if !output.started {
+            writeln!(output.out, "porcelain={}", VERSION)?;
+            output.started = true;
+        }
+        output.out.write_all(records.as_bytes())?;
+        output.out.flush()
+    }
+}
+
+impl<W: Write> Printer for PorcelainPrinter<W> {
+    fn print(&self, file: File) -> Result<()> {
+        if file.chunks.is_empty() || file.line_matches.is_empty() {
+            return Ok(());
+        }
+
+        let path = escape(&self.path_separator.display(&file.path));
+        let mut records = String::new();
+        let mut lmats = file.line_matches.iter().peekable();
+        for &(start, end) in file.chunks.iter() {
+            writeln!(records, "{}\t{}\t{}", path, start, end)?;
+            while lmats.next_if(|m| m.line_number < start).is_some() {}
+            while let Some(m) = lmats.next_if(|m| m.line_number <= end) {
+                writeln!(records, "match\t{}", m.line_number)?;
+            }
+        }
+
+        Ok(self.write(&records).ignore_broken_pipe()?)
+    }
+
+    fn print_header(&self, header: &str) -> Result<()> {
+        let record = format!("group\t{}\n", escape(header));
+        Ok(self.write(&record).ignore_broken_pipe()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Files, LineMatch};
+    use crate::test::read_matches;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn print(files: impl IntoIterator<Item = File>) -> String {
+        let printer = PorcelainPrinter::new(vec![]);
+        for file in files {
+            printer.print(file).unwrap();
+        }
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    // Lock the exact bytes of the output for all inputs in testdata/chunk. Any change to testdata/porcelain/chunk.out
+    // means a breaking change of the format
+    #[cfg(not(windows))]
+    #[test]
+    fn test_chunk_inputs() {
+        let dir = Path::new("testdata").join("chunk");
+        let mut inputs: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "in"))
+            .collect();
+        inputs.sort();
+
+        let mut files = vec![];
+        for input in inputs {
+            let name = input.file_stem().unwrap().to_str().unwrap();
+            let matches = read_matches(&dir, name);
+            for file in Files::new(matches.into_iter(), 3, 6, None).unwrap() {
+                files.push(file.unwrap());
+            }
+        }
+        let got = print(files);
+
+        let path = Path::new("testdata").join("porcelain").join("chunk.out");
+        let want = fs::read_to_string(path).unwrap();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_print_records() {
+        let file = File::new(
+            PathBuf::from("dir/a\tb.rs"),
+            vec![LineMatch::lnum(2), LineMatch::lnum(3), LineMatch::lnum(10)],
+            vec![(1, 5), (8, 12)],
+            "\n".repeat(12),
+        );
+        let printer = PorcelainPrinter::new(vec![]).path_separator(PathSeparator::Char('/'));
+        printer.print_header("foo\\bar").unwrap();
+        printer.print(file).unwrap();
+        let got = String::from_utf8(printer.into_inner()).unwrap();
+        let want = "porcelain=1\n\
+                    group\tfoo\\\\bar\n\
+                    dir/a\\tb.rs\t1\t5\n\
+                    match\t2\n\
+                    match\t3\n\
+                    dir/a\\tb.rs\t8\t12\n\
+                    match\t10\n";
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_print_path_separator() {
+        let file = File::new(
+            PathBuf::from("dir\\sub/a.rs"),
+            vec![LineMatch::lnum(1)],
+            vec![(1, 1)],
+            "\n".to_string(),
+        );
+        let printer = PorcelainPrinter::new(vec![]).path_separator(PathSeparator::Char('\\'));
+        printer.print(file).unwrap();
+        let got = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!(got, "porcelain=1\ndir\\\\sub\\\\a.rs\t1\t1\nmatch\t1\n");
+    }
+
+    #[test]
+    fn test_print_nothing() {
+        let file = File::new(PathBuf::from("a.rs"), vec![], vec![], String::new());
+        assert_eq!(print([file]), "");
+    }
+}
@@ -1,13 +1,120 @@
-use crate::chunk::File;
+use crate::chunk::{group_by_match, sort_by_match_count, File, SortOrder};
 use anyhow::Result;
+use std::any::Any;
+use std::borrow::Cow;
 use std::env;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TextWrapMode {
     Char,
     Never,
 }
 
+// Layout of headers and separators of snippets. `Native` keeps the layout which each printer has drawn historically.
+// `Unified` draws the same structure with all printers so that only highlighting differs
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layout {
+    Native,
+    Unified,
+}
+
+impl Layout {
+    // Minimum width of the line number column in the gutter including its left margin. bat always right-aligns line
+    // numbers in 4 columns so the unified layout makes syntect printer draw the gutter in the same width
+    pub fn min_line_number_width(self) -> u16 {
+        match self {
+            Self::Native => 0,
+            Self::Unified => 4,
+        }
+    }
+
+    // Empty lines put between files when no grid is drawn
+    pub fn files_separator(self) -> &'static str {
+        match self {
+            Self::Native => "\n\n",
+            Self::Unified => "\n",
+        }
+    }
+}
+
+// Separator of paths in output like ripgrep's --path-separator. `Auto` uses the separator of the platform. `Char`
+// replaces both '/' and '\\' in paths with the character so that paths mixing them are normalized. Paths are normalized
+// only when they are displayed so that files are still opened with the original paths
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PathSeparator {
+    #[default]
+    Auto,
+    Char(char),
+}
+
+impl PathSeparator {
+    pub fn normalize(self, path: &str) -> Cow<'_, str> {
+        let sep = match self {
+            Self::Char(c) => c,
+            Self::Auto if cfg!(windows) => '\\',
+            // On Unix, '/' is the only separator and '\\' is a valid character of file names
+            Self::Auto => return Cow::Borrowed(path),
+        };
+        if path.chars().any(|c| (c == '/' || c == '\\') && c != sep) {
+            Cow::Owned(path.replace(['/', '\\'], sep.encode_utf8(&mut [0; 4])))
+        } else {
+            Cow::Borrowed(path)
+        }
+    }
+
+    pub fn display(self, path: &Path) -> Cow<'_, str> {
+        match path.as_os_str().to_string_lossy() {
+            Cow::Borrowed(path) => self.normalize(path),
+            Cow::Owned(path) => Cow::Owned(self.normalize(&path).into_owned()),
+        }
+    }
+}
+
+// Title in the header of a file like "path/to/file.rs (annotation)"
+pub fn header_title(path: &Path, annotation: Option<&str>, sep: PathSeparator) -> String {
+    let path = sep.display(path);
+    match annotation {
+        Some(annotation) => format!("{} ({})", path, annotation),
+        None => path.into_owned(),
+    }
+}
+
+// Non-ASCII characters are escaped like "\u{e9}" for terminals which cannot print them
+pub fn escape_non_ascii(s: &str) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            escaped.extend(c.escape_unicode());
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+// Label of the range of lines in a snippet like "lines 10–24"
+pub fn chunk_range_label(start: u64, end: u64) -> String {
+    format!("lines {}–{}", start, end)
+}
+
+// Separator between snippets drawn after the gutter with the unified layout like "─ ─ ─ 8< ─ ─ ─". This is the same as
+// the snip line of bat so that bat printer can draw it as well. `gutter_width` is the number of characters before it
+pub fn snip_line(gutter_width: usize, term_width: usize, horizontal: &str) -> String {
+    const SNIP: &str = "8<";
+    let body_width = term_width.saturating_sub(gutter_width);
+    let left = format!("{} ", horizontal).repeat(body_width.saturating_sub(SNIP.len() / 2) / 4);
+    let rest = body_width.saturating_sub(left.chars().count() + SNIP.len());
+    let right = format!(" {}", horizontal).repeat(rest / 2);
+    format!("{}{}{}", left, SNIP, right)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TermColorSupport {
     True,
@@ -92,6 +199,7 @@ impl TermColorSupport {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct PrinterOptions<'main> {
     pub tab_width: usize,
     pub theme: Option<&'main str>,
@@ -103,11 +211,68 @@ pub struct PrinterOptions<'main> {
     pub text_wrap: TextWrapMode,
     pub first_only: bool,
     pub ascii_lines: bool,
+    pub squeeze_blank: bool,
+    pub strip_trailing_whitespace: bool,
+    pub character_position: bool,
+    pub expand_tabs: bool,
+    pub ruler: bool,
+    pub gutter_links: bool,
+    pub region_merge_gap: usize,
+    pub max_regions_per_line: Option<usize>,
+    pub matches_per_line: Option<usize>,
+    pub gutter_separator: Option<char>,
+    pub header_marker: Option<&'main str>,
+    pub monochrome_context: bool,
+    pub chunk_range_header: bool,
+    pub diagnostics: bool,
+    pub explain_highlight: bool,
+    pub compact: bool,
+    pub match_context_chars: Option<usize>,
+    pub layout: Layout,
+    pub color_scale: Option<f32>,
+    pub syntax_extension: Option<&'main str>,
+    pub path_separator: PathSeparator,
+    pub escape_non_ascii_paths: bool,
+    #[cfg(feature = "syntect-printer")]
+    pub context_color: Option<syntect::highlighting::Color>,
+    #[cfg(feature = "syntect-printer")]
+    pub match_color: Option<syntect::highlighting::Color>,
+}
+
+// $COLUMNS is preferred over the tty size as `tput cols` does
+fn detect_term_width() -> u16 {
+    use terminal_size::{terminal_size, Width};
+
+    let columns = env::var("COLUMNS").ok().and_then(|v| v.parse().ok());
+    if let Some(width) = columns.filter(|&w: &u16| w >= 10) {
+        return width;
+    }
+    terminal_size().map(|(Width(w), _)| w).unwrap_or(80) // Note: `tput` returns 80 when tty is not found
+}
+
+// Unicode box-drawing characters are not rendered correctly on some minimal terminals such as the Linux console or
+// when the locale does not use UTF-8. `LC_ALL` and `LC_CTYPE` override `LANG` as described in locale(7)
+fn detect_ascii_lines() -> bool {
+    if let Ok("dumb" | "linux") = env::var("TERM").as_deref() {
+        return true;
+    }
+
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|v| !v.is_empty());
+    let Some(locale) = locale else {
+        return false; // Locale is unknown (e.g. on Windows)
+    };
+    let Some((_, codeset)) = locale.split_once('.') else {
+        return true; // Locale without codeset such as "C" or "POSIX" uses ASCII
+    };
+    let codeset = codeset.split_once('@').map(|(c, _)| c).unwrap_or(codeset);
+    !codeset.eq_ignore_ascii_case("utf-8") && !codeset.eq_ignore_ascii_case("utf8")
 }
 
 impl<'main> Default for PrinterOptions<'main> {
     fn default() -> Self {
-        use terminal_size::{terminal_size, Width};
         Self {
             tab_width: 4,
             theme: None,
@@ -115,23 +280,343 @@ impl<'main> Default for PrinterOptions<'main> {
             background_color: false,
             color_support: TermColorSupport::detect(),
             custom_assets: false,
-            term_width: terminal_size().map(|(Width(w), _)| w).unwrap_or(80), // Note: `tput` returns 80 when tty is not found
+            term_width: detect_term_width(),
             text_wrap: TextWrapMode::Char,
             first_only: false,
-            ascii_lines: false,
+            ascii_lines: detect_ascii_lines(),
+            squeeze_blank: false,
+            strip_trailing_whitespace: false,
+            character_position: false,
+            expand_tabs: false,
+            ruler: false,
+            gutter_links: false,
+            region_merge_gap: 2,
+            max_regions_per_line: None,
+            matches_per_line: None,
+            gutter_separator: None,
+            header_marker: None,
+            monochrome_context: false,
+            chunk_range_header: false,
+            diagnostics: false,
+            explain_highlight: false,
+            compact: false,
+            match_context_chars: None,
+            layout: Layout::Native,
+            color_scale: None,
+            syntax_extension: None,
+            path_separator: PathSeparator::Auto,
+            escape_non_ascii_paths: false,
+            #[cfg(feature = "syntect-printer")]
+            context_color: None,
+            #[cfg(feature = "syntect-printer")]
+            match_color: None,
         }
     }
 }
 
+impl PrinterOptions<'_> {
+    // Names and `{:?}` values of all the options for --debug-config. The struct is destructured so that a new field
+    // cannot be forgotten here
+    pub fn debug_fields(&self) -> Vec<(&'static str, String)> {
+        let Self {
+            tab_width,
+            theme,
+            grid,
+            background_color,
+            color_support,
+            term_width,
+            custom_assets,
+            text_wrap,
+            first_only,
+            ascii_lines,
+            squeeze_blank,
+            strip_trailing_whitespace,
+            character_position,
+            expand_tabs,
+            ruler,
+            gutter_links,
+            region_merge_gap,
+            max_regions_per_line,
+            matches_per_line,
+            gutter_separator,
+            header_marker,
+            monochrome_context,
+            chunk_range_header,
+            diagnostics,
+            explain_highlight,
+            compact,
+            match_context_chars,
+            layout,
+            color_scale,
+            syntax_extension,
+            path_separator,
+            escape_non_ascii_paths,
+            #[cfg(feature = "syntect-printer")]
+            context_color,
+            #[cfg(feature = "syntect-printer")]
+            match_color,
+        } = self;
+        #[allow(unused_mut)]
+        let mut fields = vec![
+            ("tab_width", format!("{:?}", tab_width)),
+            ("theme", format!("{:?}", theme)),
+            ("grid", format!("{:?}", grid)),
+            ("background_color", format!("{:?}", background_color)),
+            ("color_support", format!("{:?}", color_support)),
+            ("term_width", format!("{:?}", term_width)),
+            ("custom_assets", format!("{:?}", custom_assets)),
+            ("text_wrap", format!("{:?}", text_wrap)),
+            ("first_only", format!("{:?}", first_only)),
+            ("ascii_lines", format!("{:?}", ascii_lines)),
+            ("squeeze_blank", format!("{:?}", squeeze_blank)),
+            (
+                "strip_trailing_whitespace",
+                format!("{:?}", strip_trailing_whitespace),
+            ),
+            ("character_position", format!("{:?}", character_position)),
+            ("expand_tabs", format!("{:?}", expand_tabs)),
+            ("ruler", format!("{:?}", ruler)),
+            ("gutter_links", format!("{:?}", gutter_links)),
+            ("region_merge_gap", format!("{:?}", region_merge_gap)),
+            (
+                "max_regions_per_line",
+                format!("{:?}", max_regions_per_line),
+            ),
+            ("matches_per_line", format!("{:?}", matches_per_line)),
+            ("gutter_separator", format!("{:?}", gutter_separator)),
+            ("header_marker", format!("{:?}", header_marker)),
+            ("monochrome_context", format!("{:?}", monochrome_context)),
+            ("chunk_range_header", format!("{:?}", chunk_range_header)),
+            ("diagnostics", format!("{:?}", diagnostics)),
+            ("explain_highlight", format!("{:?}", explain_highlight)),
+            ("compact", format!("{:?}", compact)),
+            ("match_context_chars", format!("{:?}", match_context_chars)),
+            ("layout", format!("{:?}", layout)),
+            ("color_scale", format!("{:?}", color_scale)),
+            ("syntax_extension", format!("{:?}", syntax_extension)),
+            ("path_separator", format!("{:?}", path_separator)),
+            (
+                "escape_non_ascii_paths",
+                format!("{:?}", escape_non_ascii_paths),
+            ),
+        ];
+        #[cfg(feature = "syntect-printer")]
+        {
+            fields.push(("context_color", format!("{:?}", context_color)));
+            fields.push(("match_color", format!("{:?}", match_color)));
+        }
+        fields
+    }
+}
+
 // Trait to replace printer implementation for unit tests
 pub trait Printer {
     fn print(&self, file: File) -> Result<()>;
+
+    // Print a header line before a group of files
+    fn print_header(&self, header: &str) -> Result<()> {
+        let mut out = io::stdout().lock();
+        writeln!(out, "\x1b[1m{}\x1b[0m", header)?;
+        Ok(out.flush()?)
+    }
+}
+
+// Printer which recovers from a panic while printing a file. The panic is reported to stderr as an error of the file and
+// the remaining files are still printed. The number of files which could not be printed is counted in `failed`. Since
+// the syntect printer renders a file into a buffer before writing it, nothing is written for the file which panicked
+pub struct CatchPanic<'a, P> {
+    printer: P,
+    failed: &'a AtomicUsize,
+}
+
+impl<'a, P> CatchPanic<'a, P> {
+    pub fn new(printer: P, failed: &'a AtomicUsize) -> Self {
+        Self { printer, failed }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
+
+impl<'a, P: Printer> Printer for CatchPanic<'a, P> {
+    fn print(&self, file: File) -> Result<()> {
+        let path = file.path.clone();
+        // The printer is not used after the panic except for printing other files. Printers don't share broken state
+        // between files since each file is rendered from scratch
+        match panic::catch_unwind(AssertUnwindSafe(|| self.printer.print(file))) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                eprintln!(
+                    "\x1b[1;91merror:\x1b[0m Could not print {:?} due to panic: {}. This is a bug of hgrep. Please report it at https://github.com/rhysd/hgrep/issues with the file",
+                    path,
+                    panic_message(payload.as_ref()),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn print_header(&self, header: &str) -> Result<()> {
+        self.printer.print_header(header)
+    }
+}
+
+// Print files grouped by their matched texts for --group-by-match. Files in each group are sorted by the order if given
+pub fn print_grouped_by_match<P: Printer>(
+    printer: &P,
+    files: Vec<File>,
+    order: Option<SortOrder>,
+) -> Result<bool> {
+    let groups = group_by_match(files);
+    let found = !groups.is_empty();
+    for (text, mut files) in groups {
+        if let Some(order) = order {
+            sort_by_match_count(&mut files, order);
+        }
+        let count: usize = files
+            .iter()
+            .flat_map(|f| f.line_matches.iter())
+            .map(|m| m.ranges.len())
+            .sum();
+        let header = format!("{:?}: {} matches in {} files", text, count, files.len());
+        printer.print_header(&header)?;
+        for file in files {
+            printer.print(file)?;
+        }
+    }
+    Ok(found)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chunk::LineMatch;
     use crate::test::EnvGuard;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct PanicPrinter(Mutex<Vec<PathBuf>>);
+
+    impl Printer for &PanicPrinter {
+        fn print(&self, file: File) -> Result<()> {
+            match file.path.to_str() {
+                Some("panic.rs") => panic!("oops"),
+                Some("panic_with_string.rs") => panic!("oops {}", 42),
+                Some("error.rs") => anyhow::bail!("error"),
+                _ => {}
+            }
+            self.0.lock().unwrap().push(file.path);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_catch_panic_and_print_rest() {
+        let file = |path: &str| {
+            let lmats = vec![LineMatch::lnum(1)];
+            File::new(PathBuf::from(path), lmats, vec![(1, 1)], "x\n".to_string())
+        };
+        let inner = PanicPrinter::default();
+        let failed = AtomicUsize::new(0);
+        let printer = CatchPanic::new(&inner, &failed);
+
+        for path in ["a.rs", "panic.rs", "b.rs", "panic_with_string.rs", "c.rs"] {
+            printer.print(file(path)).unwrap();
+        }
+        // Errors which are not panics are returned as-is
+        assert!(printer.print(file("error.rs")).is_err());
+
+        assert_eq!(failed.load(Ordering::Relaxed), 2);
+        let printed = inner.0.into_inner().unwrap();
+        let want: Vec<_> = ["a.rs", "b.rs", "c.rs"].iter().map(PathBuf::from).collect();
+        assert_eq!(printed, want);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static message");
+        let payload = panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 1");
+        let payload = panic::catch_unwind(|| panic::panic_any(42)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "unknown panic");
+    }
+
+    #[test]
+    fn test_layout_labels() {
+        let path = Path::new("src/main.rs");
+        let sep = PathSeparator::Char('/');
+        assert_eq!(header_title(path, None, sep), "src/main.rs");
+        assert_eq!(
+            header_title(path, Some("3 matches"), sep),
+            "src/main.rs (3 matches)"
+        );
+        assert_eq!(
+            header_title(path, None, PathSeparator::Char('\\')),
+            "src\\main.rs"
+        );
+        assert_eq!(chunk_range_label(10, 24), "lines 10–24");
+    }
+
+    #[test]
+    fn test_escape_non_ascii() {
+        assert_eq!(escape_non_ascii("src/main.rs"), "src/main.rs");
+        assert!(matches!(escape_non_ascii("src/main.rs"), Cow::Borrowed(_)));
+        assert_eq!(
+            escape_non_ascii("café/日本.rs"),
+            "caf\\u{e9}/\\u{65e5}\\u{672c}.rs"
+        );
+    }
+
+    #[test]
+    fn test_path_separator() {
+        // Paths from grep output on Windows often mix separators such as `src\foo/bar.rs`
+        let mixed = "C:\\src\\foo/bar.rs";
+        assert_eq!(
+            PathSeparator::Char('/').normalize(mixed),
+            "C:/src/foo/bar.rs"
+        );
+        assert_eq!(
+            PathSeparator::Char('\\').normalize(mixed),
+            "C:\\src\\foo\\bar.rs"
+        );
+        assert_eq!(
+            PathSeparator::Char('|').normalize(mixed),
+            "C:|src|foo|bar.rs"
+        );
+        let auto = PathSeparator::Auto.normalize(mixed);
+        if cfg!(windows) {
+            assert_eq!(auto, "C:\\src\\foo\\bar.rs");
+        } else {
+            assert_eq!(auto, mixed);
+        }
+
+        assert!(matches!(
+            PathSeparator::Char('/').normalize("src/foo.rs"),
+            Cow::Borrowed("src/foo.rs"),
+        ));
+        assert_eq!(
+            PathSeparator::Char('\\').display(Path::new("./src/foo.rs")),
+            ".\\src\\foo.rs"
+        );
+    }
+
+    #[test]
+    fn test_snip_line() {
+        // Same as bat's snip line with the gutter of 9 characters on a terminal of 30 columns
+        assert_eq!(snip_line(9, 30, "─"), "─ ─ ─ ─ ─ 8< ─ ─ ─ ─");
+        assert_eq!(snip_line(9, 30, "-"), "- - - - - 8< - - - -");
+        assert_eq!(snip_line(10, 10, "-"), "8<");
+    }
 
     #[test]
     fn test_detect_true_color_from_env() {
@@ -216,4 +701,48 @@ mod tests {
             assert_eq!(detected, want, "COLORTERM={colorterm:?} and TERM={term:?}",);
         }
     }
+
+    #[test]
+    fn test_detect_ascii_lines_from_env() {
+        for (term, lc_all, lc_ctype, lang, want) in [
+            (None, None, None, None, false),
+            (
+                Some("xterm-256color"),
+                None,
+                None,
+                Some("en_US.UTF-8"),
+                false,
+            ),
+            (Some("dumb"), None, None, Some("en_US.UTF-8"), true),
+            (Some("linux"), None, None, None, true),
+            (Some("xterm"), None, None, Some("C"), true),
+            (Some("xterm"), None, None, Some("POSIX"), true),
+            (Some("xterm"), None, None, Some("ja_JP.eucJP"), true),
+            (Some("xterm"), None, None, Some("en_US.utf8"), false),
+            (Some("xterm"), None, None, Some("de_DE.UTF-8@euro"), false),
+            (
+                Some("xterm"),
+                None,
+                None,
+                Some("de_DE.ISO-8859-15@euro"),
+                true,
+            ),
+            (Some("xterm"), Some("C"), None, Some("en_US.UTF-8"), true),
+            (Some("xterm"), Some("en_US.UTF-8"), None, Some("C"), false),
+            (Some("xterm"), None, Some("C.UTF-8"), Some("C"), false),
+            (Some("xterm"), Some(""), None, Some("C"), true),
+            (Some("xterm"), Some(""), Some(""), Some(""), false),
+        ] {
+            let mut guard = EnvGuard::default();
+            guard.set_env("TERM", term);
+            guard.set_env("LC_ALL", lc_all);
+            guard.set_env("LC_CTYPE", lc_ctype);
+            guard.set_env("LANG", lang);
+            assert_eq!(
+                detect_ascii_lines(),
+                want,
+                "TERM={term:?}, LC_ALL={lc_all:?}, LC_CTYPE={lc_ctype:?} and LANG={lang:?}",
+            );
+        }
+    }
 }
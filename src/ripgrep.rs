@@ -1,23 +1,33 @@
 use crate::broken_pipe::IgnoreBrokenPipe as _;
-use crate::chunk::Files;
+use crate::chunk::{
+    self, decode_text, encoding_for_label, relative_path, sort_by_match_count, ContextBias,
+    EncodingErrors, Files, LineMatch, LinesInclusive, OverlapPolicy, SortOrder,
+};
 use crate::grep::GrepMatch;
-use crate::printer::Printer;
+use crate::printer::{print_grouped_by_match, Printer};
 use anyhow::{Context, Result};
-use grep_matcher::{LineTerminator, Matcher};
+use grep_matcher::{LineTerminator, Match, Matcher, NoCaptures, NoError};
 use grep_pcre2::{RegexMatcher as Pcre2Matcher, RegexMatcherBuilder as Pcre2MatcherBuilder};
 use grep_regex::{RegexMatcher, RegexMatcherBuilder};
 use grep_searcher::{
-    BinaryDetection, Encoding, MmapChoice, Searcher, SearcherBuilder, Sink, SinkMatch,
+    BinaryDetection, Encoding, MmapChoice, Searcher, SearcherBuilder, Sink, SinkContext,
+    SinkFinish, SinkMatch,
 };
-use ignore::overrides::OverrideBuilder;
+use ignore::overrides::{Override, OverrideBuilder};
 use ignore::types::{Types, TypesBuilder};
-use ignore::{Walk, WalkBuilder};
+use ignore::{DirEntry, Walk, WalkBuilder};
+#[cfg(not(feature = "single-thread"))]
 use rayon::prelude::*;
+use std::collections::HashSet;
 use std::env;
-use std::fs::File;
-use std::io;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write as _};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // Note: 'main is a lifetime of scope of main() function
 
@@ -41,6 +51,54 @@ fn parse_size(input: &str) -> Result<u64> {
     Ok(u * mag)
 }
 
+// The error has no dedicated kind or type so it is distinguished by its whole message "configured allocation limit ({})
+// exceeded" where the number is the heap limit plus the initial capacity of the buffer. Errors from OS are never the one
+fn is_heap_limit_error(err: &io::Error, heap_limit: usize) -> bool {
+    if err.kind() != io::ErrorKind::Other || err.raw_os_error().is_some() {
+        return false;
+    }
+    let msg = err.to_string();
+    let Some(num) = msg
+        .strip_prefix("configured allocation limit (")
+        .and_then(|s| s.strip_suffix(") exceeded"))
+    else {
+        return false;
+    };
+    num.parse::<usize>().is_ok_and(|n| n >= heap_limit)
+}
+
+fn parse_duration(input: &str) -> Result<Duration> {
+    if input.is_empty() {
+        anyhow::bail!("Duration string must not be empty");
+    }
+
+    let i = input.len() - 1;
+    let (input, secs) = match input.as_bytes()[i] {
+        b's' => (&input[..i], 1),
+        b'm' => (&input[..i], 60),
+        b'h' => (&input[..i], 60 * 60),
+        b'd' => (&input[..i], 24 * 60 * 60),
+        b'w' => (&input[..i], 7 * 24 * 60 * 60),
+        _ => (input, 1),
+    };
+
+    let u: u64 = input
+        .parse()
+        .with_context(|| format!("Could not parse {:?} as unsigned integer", input))?;
+
+    Ok(Duration::from_secs(u.saturating_mul(secs)))
+}
+
+// Files which were not searched. They are collected only when `Config::report_skipped` or `Config::collect_skipped` is
+// set
+#[derive(Default, Debug)]
+pub struct SkippedFiles {
+    pub binary: Vec<PathBuf>,
+    pub too_large: Vec<PathBuf>,
+    pub filtered_by_type: Vec<PathBuf>,
+    pub long_lines: Vec<PathBuf>,
+}
+
 #[derive(Default, Debug)]
 pub struct Config<'main> {
     min_context: u64,
@@ -50,6 +108,7 @@ pub struct Config<'main> {
     case_insensitive: bool,
     smart_case: bool,
     globs: Box<[&'main str]>,
+    prune: Box<[&'main str]>,
     glob_case_insensitive: bool,
     fixed_strings: bool,
     word_regexp: bool,
@@ -58,11 +117,21 @@ pub struct Config<'main> {
     crlf: bool,
     multiline_dotall: bool,
     mmap: bool,
+    mmap_threshold: Option<u64>,
     max_count: Option<u64>,
     max_depth: Option<usize>,
+    max_files: Option<usize>,
     max_filesize: Option<u64>,
+    min_filesize: Option<u64>,
+    max_line_length: Option<usize>,
+    heap_limit: Option<usize>,
+    changed_within: Option<Duration>,
+    changed_before: Option<Duration>,
     line_regexp: bool,
     pcre2: bool,
+    pcre2_jit_stack: Option<usize>,
+    no_pcre2_jit: bool,
+    pcre2_ucp: Option<bool>,
     types: Vec<&'main str>,
     types_not: Vec<&'main str>,
     invert_match: bool,
@@ -71,6 +140,85 @@ pub struct Config<'main> {
     regex_size_limit: Option<usize>,
     dfa_size_limit: Option<usize>,
     encoding: Option<&'main str>,
+    encoding_errors: EncodingErrors,
+    base_dir: Option<&'main Path>,
+    sort_by_match_count: Option<SortOrder>,
+    context_max_bytes: Option<usize>,
+    overlap_policy: OverlapPolicy,
+    context_bias: ContextBias,
+    suppress_empty_context: bool,
+    highlight_surrounding_text: bool,
+    skipped: Option<&'main Mutex<SkippedFiles>>,
+    skipped_reported: bool,
+    group_by_match: bool,
+    stream: bool,
+    case_normalize_display: Option<&'main str>,
+}
+
+// Fields of `Config` which can be initialized with a struct literal at once:
+//
+//   ConfigBuilder { min_context: 3, max_context: 6, smart_case: true, ..Default::default() }.build()?
+//
+// Sizes are in bytes except for `pcre2_jit_stack` which is in MiB as `Config::pcre2_jit_stack`. Skipped files are not
+// collected by this struct. Use `Config::report_skipped` or `Config::collect_skipped` after building the config
+#[derive(Default, Debug, Clone)]
+pub struct ConfigBuilder<'main> {
+    pub min_context: u64,
+    pub max_context: u64,
+    pub no_ignore: bool,
+    pub hidden: bool,
+    pub case_insensitive: bool,
+    pub smart_case: bool,
+    pub globs: Vec<&'main str>,
+    pub prune: Vec<&'main str>,
+    pub glob_case_insensitive: bool,
+    pub fixed_strings: bool,
+    pub word_regexp: bool,
+    pub follow_symlink: bool,
+    pub multiline: bool,
+    pub crlf: bool,
+    pub multiline_dotall: bool,
+    pub mmap: bool,
+    pub mmap_threshold: Option<u64>,
+    pub max_count: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub max_files: Option<usize>,
+    pub max_filesize: Option<u64>,
+    pub min_filesize: Option<u64>,
+    pub max_line_length: Option<usize>,
+    pub heap_limit: Option<usize>,
+    pub changed_within: Option<Duration>,
+    pub changed_before: Option<Duration>,
+    pub line_regexp: bool,
+    pub pcre2: bool,
+    pub pcre2_jit_stack: Option<usize>,
+    pub no_pcre2_jit: bool,
+    pub pcre2_ucp: Option<bool>,
+    pub types: Vec<&'main str>,
+    pub types_not: Vec<&'main str>,
+    pub invert_match: bool,
+    pub one_file_system: bool,
+    pub no_unicode: bool,
+    pub regex_size_limit: Option<usize>,
+    pub dfa_size_limit: Option<usize>,
+    pub encoding: Option<&'main str>,
+    pub encoding_errors: EncodingErrors,
+    pub base_dir: Option<&'main Path>,
+    pub sort_by_match_count: Option<SortOrder>,
+    pub context_max_bytes: Option<usize>,
+    pub overlap_policy: OverlapPolicy,
+    pub context_bias: ContextBias,
+    pub suppress_empty_context: bool,
+    pub highlight_surrounding_text: bool,
+    pub group_by_match: bool,
+    pub stream: bool,
+    pub case_normalize_display: Option<&'main str>,
+}
+
+impl<'main> ConfigBuilder<'main> {
+    pub fn build(self) -> Result<Config<'main>> {
+        Config::with_fields(self)
+    }
 }
 
 impl<'main> Config<'main> {
@@ -80,6 +228,201 @@ impl<'main> Config<'main> {
         config
     }
 
+    // Conflicting flags are resolved as ripgrep does. -i takes precedence over -S, -x over -w, and -F over -P
+    pub fn with_fields(fields: ConfigBuilder<'main>) -> Result<Self> {
+        let mut config = Self {
+            min_context: fields.min_context,
+            max_context: fields.max_context,
+            no_ignore: fields.no_ignore,
+            hidden: fields.hidden,
+            case_insensitive: fields.case_insensitive,
+            smart_case: fields.smart_case,
+            globs: fields.globs.into_boxed_slice(),
+            prune: fields.prune.into_boxed_slice(),
+            glob_case_insensitive: fields.glob_case_insensitive,
+            fixed_strings: fields.fixed_strings,
+            word_regexp: fields.word_regexp,
+            follow_symlink: fields.follow_symlink,
+            multiline: fields.multiline,
+            crlf: fields.crlf,
+            multiline_dotall: fields.multiline_dotall,
+            mmap: fields.mmap,
+            mmap_threshold: fields.mmap_threshold,
+            max_count: fields.max_count,
+            max_depth: fields.max_depth,
+            max_files: fields.max_files,
+            max_filesize: fields.max_filesize,
+            min_filesize: fields.min_filesize,
+            max_line_length: fields.max_line_length,
+            heap_limit: fields.heap_limit,
+            changed_within: fields.changed_within,
+            changed_before: fields.changed_before,
+            line_regexp: fields.line_regexp,
+            pcre2: fields.pcre2,
+            pcre2_jit_stack: None,
+            no_pcre2_jit: fields.no_pcre2_jit,
+            pcre2_ucp: fields.pcre2_ucp,
+            types: fields.types,
+            types_not: fields.types_not,
+            invert_match: fields.invert_match,
+            one_file_system: fields.one_file_system,
+            no_unicode: fields.no_unicode,
+            regex_size_limit: fields.regex_size_limit,
+            dfa_size_limit: fields.dfa_size_limit,
+            encoding: fields.encoding,
+            encoding_errors: fields.encoding_errors,
+            base_dir: fields.base_dir,
+            sort_by_match_count: fields.sort_by_match_count,
+            context_max_bytes: fields.context_max_bytes,
+            overlap_policy: fields.overlap_policy,
+            context_bias: fields.context_bias,
+            suppress_empty_context: fields.suppress_empty_context,
+            highlight_surrounding_text: fields.highlight_surrounding_text,
+            skipped: None,
+            skipped_reported: false,
+            group_by_match: fields.group_by_match,
+            stream: fields.stream,
+            case_normalize_display: fields.case_normalize_display,
+        };
+        if let Some(mb) = fields.pcre2_jit_stack {
+            config.pcre2_jit_stack(mb)?;
+        }
+        if config.case_insensitive {
+            config.case_insensitive(true);
+        }
+        if config.line_regexp {
+            config.line_regexp(true);
+        }
+        if config.fixed_strings {
+            config.fixed_strings(true);
+        }
+        Ok(config)
+    }
+
+    // Names and `{:?}` values of the configuration for --debug-config. Skipped files are not configuration so they are
+    // omitted. The struct is destructured so that a new field cannot be forgotten here
+    pub fn debug_fields(&self) -> Vec<(&'static str, String)> {
+        let Self {
+            min_context,
+            max_context,
+            no_ignore,
+            hidden,
+            case_insensitive,
+            smart_case,
+            globs,
+            prune,
+            glob_case_insensitive,
+            fixed_strings,
+            word_regexp,
+            follow_symlink,
+            multiline,
+            crlf,
+            multiline_dotall,
+            mmap,
+            mmap_threshold,
+            max_count,
+            max_depth,
+            max_files,
+            max_filesize,
+            min_filesize,
+            max_line_length,
+            heap_limit,
+            changed_within,
+            changed_before,
+            line_regexp,
+            pcre2,
+            pcre2_jit_stack,
+            no_pcre2_jit,
+            pcre2_ucp,
+            types,
+            types_not,
+            invert_match,
+            one_file_system,
+            no_unicode,
+            regex_size_limit,
+            dfa_size_limit,
+            encoding,
+            encoding_errors,
+            base_dir,
+            sort_by_match_count,
+            context_max_bytes,
+            overlap_policy,
+            context_bias,
+            suppress_empty_context,
+            highlight_surrounding_text,
+            skipped_reported,
+            group_by_match,
+            stream,
+            case_normalize_display,
+            skipped: _,
+        } = self;
+        vec![
+            ("min_context", format!("{:?}", min_context)),
+            ("max_context", format!("{:?}", max_context)),
+            ("no_ignore", format!("{:?}", no_ignore)),
+            ("hidden", format!("{:?}", hidden)),
+            ("case_insensitive", format!("{:?}", case_insensitive)),
+            ("smart_case", format!("{:?}", smart_case)),
+            ("globs", format!("{:?}", globs)),
+            ("prune", format!("{:?}", prune)),
+            (
+                "glob_case_insensitive",
+                format!("{:?}", glob_case_insensitive),
+            ),
+            ("fixed_strings", format!("{:?}", fixed_strings)),
+            ("word_regexp", format!("{:?}", word_regexp)),
+            ("follow_symlink", format!("{:?}", follow_symlink)),
+            ("multiline", format!("{:?}", multiline)),
+            ("crlf", format!("{:?}", crlf)),
+            ("multiline_dotall", format!("{:?}", multiline_dotall)),
+            ("mmap", format!("{:?}", mmap)),
+            ("mmap_threshold", format!("{:?}", mmap_threshold)),
+            ("max_count", format!("{:?}", max_count)),
+            ("max_depth", format!("{:?}", max_depth)),
+            ("max_files", format!("{:?}", max_files)),
+            ("max_filesize", format!("{:?}", max_filesize)),
+            ("min_filesize", format!("{:?}", min_filesize)),
+            ("max_line_length", format!("{:?}", max_line_length)),
+            ("heap_limit", format!("{:?}", heap_limit)),
+            ("changed_within", format!("{:?}", changed_within)),
+            ("changed_before", format!("{:?}", changed_before)),
+            ("line_regexp", format!("{:?}", line_regexp)),
+            ("pcre2", format!("{:?}", pcre2)),
+            ("pcre2_jit_stack", format!("{:?}", pcre2_jit_stack)),
+            ("no_pcre2_jit", format!("{:?}", no_pcre2_jit)),
+            ("pcre2_ucp", format!("{:?}", pcre2_ucp)),
+            ("types", format!("{:?}", types)),
+            ("types_not", format!("{:?}", types_not)),
+            ("invert_match", format!("{:?}", invert_match)),
+            ("one_file_system", format!("{:?}", one_file_system)),
+            ("no_unicode", format!("{:?}", no_unicode)),
+            ("regex_size_limit", format!("{:?}", regex_size_limit)),
+            ("dfa_size_limit", format!("{:?}", dfa_size_limit)),
+            ("encoding", format!("{:?}", encoding)),
+            ("encoding_errors", format!("{:?}", encoding_errors)),
+            ("base_dir", format!("{:?}", base_dir)),
+            ("sort_by_match_count", format!("{:?}", sort_by_match_count)),
+            ("context_max_bytes", format!("{:?}", context_max_bytes)),
+            ("overlap_policy", format!("{:?}", overlap_policy)),
+            ("context_bias", format!("{:?}", context_bias)),
+            (
+                "suppress_empty_context",
+                format!("{:?}", suppress_empty_context),
+            ),
+            (
+                "highlight_surrounding_text",
+                format!("{:?}", highlight_surrounding_text),
+            ),
+            ("skipped_reported", format!("{:?}", skipped_reported)),
+            ("group_by_match", format!("{:?}", group_by_match)),
+            ("stream", format!("{:?}", stream)),
+            (
+                "case_normalize_display",
+                format!("{:?}", case_normalize_display),
+            ),
+        ]
+    }
+
     pub fn min_context(&mut self, num: u64) -> &mut Self {
         self.min_context = num;
         self
@@ -116,11 +459,19 @@ impl<'main> Config<'main> {
         self
     }
 
+    // Globs are matched like gitignore. When multiple globs match a path, the last one wins as ripgrep does. For example,
+    // `*.rs` followed by `!main.rs` excludes main.rs and `!main.rs` followed by `*.rs` includes it
     pub fn globs(&mut self, globs: impl Iterator<Item = &'main str>) -> &mut Self {
         self.globs = globs.collect();
         self
     }
 
+    // Directories whose names are equal to one of these are not traversed at any depth
+    pub fn prune(&mut self, names: impl Iterator<Item = &'main str>) -> &mut Self {
+        self.prune = names.collect();
+        self
+    }
+
     pub fn glob_case_insensitive(&mut self, yes: bool) -> &mut Self {
         self.glob_case_insensitive = yes;
         self
@@ -175,6 +526,11 @@ impl<'main> Config<'main> {
         self
     }
 
+    pub fn mmap_threshold(&mut self, input: &str) -> Result<&mut Self> {
+        self.mmap_threshold = Some(parse_size(input)?);
+        Ok(self)
+    }
+
     pub fn max_count(&mut self, num: u64) -> &mut Self {
         self.max_count = Some(num);
         self
@@ -185,11 +541,38 @@ impl<'main> Config<'main> {
         self
     }
 
+    // Stop walking directories after the number of files are searched. With `sort_by_match_count`, all files are searched
+    // and only the first files in the sorted order are printed
+    pub fn max_files(&mut self, num: usize) -> &mut Self {
+        self.max_files = Some(num);
+        self
+    }
+
     pub fn pcre2(&mut self, yes: bool) -> &mut Self {
         self.pcre2 = yes;
         self
     }
 
+    pub fn pcre2_jit_stack(&mut self, mb: usize) -> Result<&mut Self> {
+        let Some(size) = mb.checked_mul(1 << 20) else {
+            anyhow::bail!("PCRE2 JIT stack size {} MiB is too large", mb);
+        };
+        self.pcre2_jit_stack = Some(size);
+        Ok(self)
+    }
+
+    pub fn no_pcre2_jit(&mut self, yes: bool) -> &mut Self {
+        self.no_pcre2_jit = yes;
+        self
+    }
+
+    // Whether \b, \w, \d, \s, and POSIX classes in PCRE2 regex are Unicode-aware. When this is not set, they are
+    // Unicode-aware unless --no-unicode is given
+    pub fn pcre2_ucp(&mut self, yes: bool) -> &mut Self {
+        self.pcre2_ucp = Some(yes);
+        self
+    }
+
     pub fn types(&mut self, types: impl Iterator<Item = &'main str>) -> &mut Self {
         self.types = types.collect();
         self
@@ -205,6 +588,40 @@ impl<'main> Config<'main> {
         Ok(self)
     }
 
+    // Files smaller than the size are not searched. Unlike `max_filesize`, this is checked after opening each file
+    pub fn min_filesize(&mut self, input: &str) -> Result<&mut Self> {
+        self.min_filesize = Some(parse_size(input)?);
+        Ok(self)
+    }
+
+    pub fn max_line_length(&mut self, len: usize) -> &mut Self {
+        self.max_line_length = Some(len);
+        self
+    }
+
+    // Files which have lines exceeding the limit are skipped as long lines instead of failing the search
+    pub fn heap_limit(&mut self, input: &str) -> Result<&mut Self> {
+        let limit = usize::try_from(parse_size(input)?)
+            .with_context(|| format!("Heap limit {:?} is too large", input))?;
+        if limit == 0 {
+            anyhow::bail!(
+                "Heap limit must be greater than 0 since no line can be read with zero heap"
+            );
+        }
+        self.heap_limit = Some(limit);
+        Ok(self)
+    }
+
+    pub fn changed_within(&mut self, input: &str) -> Result<&mut Self> {
+        self.changed_within = Some(parse_duration(input)?);
+        Ok(self)
+    }
+
+    pub fn changed_before(&mut self, input: &str) -> Result<&mut Self> {
+        self.changed_before = Some(parse_duration(input)?);
+        Ok(self)
+    }
+
     pub fn invert_match(&mut self, yes: bool) -> &mut Self {
         self.invert_match = yes;
         self
@@ -235,7 +652,84 @@ impl<'main> Config<'main> {
         self
     }
 
-    fn build_walker(&self, mut paths: impl Iterator<Item = &'main Path>) -> Result<Walk> {
+    pub fn encoding_errors(&mut self, errors: EncodingErrors) -> &mut Self {
+        self.encoding_errors = errors;
+        self
+    }
+
+    pub fn base_dir(&mut self, dir: &'main Path) -> &mut Self {
+        self.base_dir = Some(dir);
+        self
+    }
+
+    pub fn sort_by_match_count(&mut self, order: SortOrder) -> &mut Self {
+        self.sort_by_match_count = Some(order);
+        self
+    }
+
+    pub fn context_max_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.context_max_bytes = Some(bytes);
+        self
+    }
+
+    pub fn overlap_policy(&mut self, policy: OverlapPolicy) -> &mut Self {
+        self.overlap_policy = policy;
+        self
+    }
+
+    pub fn context_bias(&mut self, bias: ContextBias) -> &mut Self {
+        self.context_bias = bias;
+        self
+    }
+
+    pub fn suppress_empty_context(&mut self, yes: bool) -> &mut Self {
+        self.suppress_empty_context = yes;
+        self
+    }
+
+    pub fn highlight_surrounding_text(&mut self, yes: bool) -> &mut Self {
+        self.highlight_surrounding_text = yes;
+        self
+    }
+
+    // Show matched text in the case of the given text when they are equal ignoring case. This is useful with
+    // case-insensitive search to see how the matches relate to the pattern
+    pub fn case_normalize_display(&mut self, text: &'main str) -> &mut Self {
+        self.case_normalize_display = Some(text);
+        self
+    }
+
+    pub fn report_skipped(&mut self, skipped: &'main Mutex<SkippedFiles>) -> &mut Self {
+        self.skipped = Some(skipped);
+        self.skipped_reported = true;
+        self
+    }
+
+    // Collect the skipped files as `report_skipped` but the caller does not report all of them. Warnings for skipped
+    // files are still printed
+    pub fn collect_skipped(&mut self, skipped: &'main Mutex<SkippedFiles>) -> &mut Self {
+        self.skipped = Some(skipped);
+        self.skipped_reported = false;
+        self
+    }
+
+    pub fn group_by_match(&mut self, yes: bool) -> &mut Self {
+        self.group_by_match = yes;
+        self
+    }
+
+    // Print matches in each file while searching it instead of collecting all of them. Memory usage is bounded by the
+    // window of lines around matches rather than by the number of matches. Sorting and grouping files are not done in
+    // this mode since they need all the results
+    pub fn stream(&mut self, yes: bool) -> &mut Self {
+        self.stream = yes;
+        self
+    }
+
+    fn build_walker<'a>(
+        &self,
+        mut paths: impl Iterator<Item = &'a Path>,
+    ) -> Result<(Walk, Option<SkipFilter<'main>>)> {
         let target = paths.next().unwrap();
 
         let mut builder = OverrideBuilder::new(target);
@@ -261,16 +755,41 @@ impl<'main> Config<'main> {
             .require_git(false)
             .follow_links(self.follow_symlink)
             .max_depth(self.max_depth)
-            .max_filesize(self.max_filesize)
-            .overrides(overrides)
-            .types(self.build_types()?)
             .same_file_system(self.one_file_system);
 
         if !self.no_ignore {
             builder.add_custom_ignore_filename(".rgignore");
         }
 
-        Ok(builder.build())
+        if !self.prune.is_empty() {
+            // The filter must be 'static. Paths given explicitly (depth 0) are always traversed
+            let names: Box<[OsString]> = self.prune.iter().map(OsString::from).collect();
+            builder.filter_entry(move |entry| {
+                entry.depth() == 0
+                    || !entry.file_type().is_some_and(|t| t.is_dir())
+                    || !names.iter().any(|n| n == entry.file_name())
+            });
+        }
+
+        // The walker skips files silently. To report the skipped files, filter them by ourselves instead
+        let types = self.build_types()?;
+        let filter = if let Some(skipped) = self.skipped {
+            builder.overrides(overrides.clone());
+            Some(SkipFilter {
+                types,
+                overrides,
+                max_filesize: self.max_filesize,
+                skipped,
+            })
+        } else {
+            builder
+                .max_filesize(self.max_filesize)
+                .overrides(overrides)
+                .types(types);
+            None
+        };
+
+        Ok((builder.build(), filter))
     }
 
     fn build_regex_matcher(&self, pat: &str) -> Result<RegexMatcher> {
@@ -314,6 +833,20 @@ impl<'main> Config<'main> {
         })
     }
 
+    // Returns the max JIT stack size in bytes, or `None` when JIT should not be used
+    fn pcre2_jit_stack_size(&self) -> Option<usize> {
+        if self.no_pcre2_jit {
+            return None;
+        }
+
+        #[cfg(target_pointer_width = "64")]
+        let default = Some(10 * (1 << 20));
+        #[cfg(not(target_pointer_width = "64"))]
+        let default = None;
+
+        self.pcre2_jit_stack.or(default)
+    }
+
     fn build_pcre2_matcher(&self, pat: &str) -> Result<Pcre2Matcher> {
         let mut builder = Pcre2MatcherBuilder::new();
         builder
@@ -323,15 +856,15 @@ impl<'main> Config<'main> {
             .multi_line(true)
             .crlf(self.crlf);
 
-        #[cfg(target_pointer_width = "64")]
-        {
+        if let Some(size) = self.pcre2_jit_stack_size() {
             builder
                 .jit_if_available(true)
-                .max_jit_stack_size(Some(10 * (1 << 20)));
+                .max_jit_stack_size(Some(size));
         }
 
-        if !self.no_unicode {
-            builder.utf(true).ucp(true);
+        let ucp = self.pcre2_ucp.unwrap_or(!self.no_unicode);
+        if ucp || !self.no_unicode {
+            builder.utf(true).ucp(ucp);
         }
 
         if self.multiline {
@@ -345,9 +878,41 @@ impl<'main> Config<'main> {
         }
     }
 
-    fn build_searcher(&self) -> Result<Searcher> {
+    // Fast path for --fixed-strings with case-insensitive search. `None` is returned when the pattern needs the regex
+    // engine. Non-ASCII patterns need Unicode case folding, and 'k' and 's' also match 'K' (Kelvin sign) and 'ſ' (long
+    // s) with Unicode case folding
+    fn build_literal_matcher(&self, pat: &str) -> Option<AsciiCaseInsensitiveMatcher> {
+        let case_insensitive = self.case_insensitive
+            || self.smart_case && !pat.bytes().any(|b| b.is_ascii_uppercase());
+        if !self.fixed_strings
+            || !case_insensitive
+            || self.word_regexp
+            || self.line_regexp
+            || self.multiline
+            || pat.is_empty()
+            || !pat.is_ascii()
+            || pat.contains('\n')
+        {
+            return None;
+        }
+        if !self.no_unicode && pat.bytes().any(|b| matches!(b, b'k' | b'K' | b's' | b'S')) {
+            return None;
+        }
+        Some(AsciiCaseInsensitiveMatcher::new(pat.as_bytes()))
+    }
+
+    // When the threshold is set, only files larger than it are memory-mapped since reading small files is faster than
+    // setting up memory maps for them
+    fn use_mmap(&self, file: &File) -> bool {
+        match self.mmap_threshold {
+            Some(threshold) => file.metadata().is_ok_and(|m| m.len() > threshold),
+            None => self.mmap,
+        }
+    }
+
+    fn build_searcher(&self, mmap: bool) -> Result<Searcher> {
         let mut builder = SearcherBuilder::new();
-        let mmap = if self.mmap {
+        let mmap = if mmap {
             unsafe { MmapChoice::auto() }
         } else {
             MmapChoice::never()
@@ -357,13 +922,23 @@ impl<'main> Config<'main> {
             .line_number(true)
             .multi_line(self.multiline)
             .memory_map(mmap)
+            .heap_limit(self.heap_limit)
             .invert_match(self.invert_match);
+        if self.stream {
+            // Context lines are reported by the searcher since the file is not read again to make chunks
+            let context = self.max_context as usize;
+            builder.before_context(context).after_context(context);
+        }
         if self.crlf {
             builder.line_terminator(LineTerminator::crlf());
         }
         if let Some(label) = self.encoding {
             builder.encoding(Some(Encoding::new(label)?));
         }
+        if self.encoding_errors != EncodingErrors::Replace {
+            // Text decoded by `Ripgrep::decode` is searched instead of the file
+            builder.encoding(None).bom_sniffing(false);
+        }
         Ok(builder.build())
     }
 
@@ -400,38 +975,369 @@ impl<'main> Config<'main> {
     }
 }
 
-pub fn grep<'main, P: Printer + Sync>(
-    printer: P,
-    pat: &str,
+fn walk<'main>(
     paths: Option<impl Iterator<Item = &'main Path>>,
-    config: Config<'main>,
-) -> Result<bool> {
-    let entries = if let Some(paths) = paths {
-        config.build_walker(paths)?
+    config: &Config<'main>,
+) -> Result<impl Iterator<Item = Result<PathBuf>> + 'main> {
+    let (entries, filter, roots) = if let Some(paths) = paths {
+        let paths: Vec<_> = paths.collect();
+        let (entries, filter) = config.build_walker(paths.iter().copied())?;
+        (entries, filter, paths.len())
     } else {
         let cwd = env::current_dir()?;
         let paths = std::iter::once(cwd.as_path());
-        config.build_walker(paths)?
+        let (entries, filter) = config.build_walker(paths)?;
+        (entries, filter, 1)
     };
+    let mtime = MtimeFilter::new(config.changed_within, config.changed_before);
+    // Checking identities of files needs their metadata. Only one path is walked for each file unless multiple roots
+    // are given or symlinks are followed
+    let dedup = roots > 1 || config.follow_symlink;
+    let files = files(entries, dedup, filter);
+    let files = files.filter(move |path| match path {
+        Ok(path) => mtime.matches(path),
+        Err(_) => true,
+    });
+
+    // Files are capped after sorting them when they are sorted by match count
+    let max_files = config
+        .max_files
+        .filter(|_| config.sort_by_match_count.is_none() || config.group_by_match);
+    let mut searched = 0;
+    Ok(files.take_while(move |path| {
+        if path.is_err() {
+            return true; // Errors are not counted as searched files
+        }
+        searched += 1;
+        max_files.is_none_or(|max| searched <= max)
+    }))
+}
+
+// Filter files by their last modification times for --changed-within and --changed-before
+struct MtimeFilter {
+    newer_than: Option<SystemTime>,
+    older_than: Option<SystemTime>,
+}
+
+impl MtimeFilter {
+    fn new(within: Option<Duration>, before: Option<Duration>) -> Self {
+        let now = SystemTime::now();
+        let ago = |d: Duration| now.checked_sub(d).unwrap_or(UNIX_EPOCH);
+        Self {
+            newer_than: within.map(ago),
+            older_than: before.map(ago),
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        if self.newer_than.is_none() && self.older_than.is_none() {
+            return true;
+        }
+        let Ok(mtime) = fs::metadata(path).and_then(|m| m.modified()) else {
+            return false;
+        };
+        self.newer_than.is_none_or(|t| t <= mtime) && self.older_than.is_none_or(|t| mtime < t)
+    }
+}
+
+// Filter files by types and sizes as the walker does while recording the skipped files
+struct SkipFilter<'main> {
+    types: Types,
+    overrides: Override,
+    max_filesize: Option<u64>,
+    skipped: &'main Mutex<SkippedFiles>,
+}
+
+impl<'main> SkipFilter<'main> {
+    fn skip(&self, entry: &DirEntry) -> bool {
+        // Paths given explicitly are not filtered
+        if entry.depth() == 0 {
+            return false;
+        }
 
-    let paths = entries.filter_map(|entry| match entry {
+        let path = entry.path();
+        // Like the walker, type filters are not applied when the path matches to globs
+        if !self.types.is_empty()
+            && self.overrides.matched(path, false).is_none()
+            && self.types.matched(path, false).is_ignore()
+        {
+            let mut skipped = self.skipped.lock().unwrap();
+            skipped.filtered_by_type.push(path.to_path_buf());
+            return true;
+        }
+
+        if let Some(max) = self.max_filesize {
+            if entry.metadata().is_ok_and(|m| m.len() > max) {
+                let mut skipped = self.skipped.lock().unwrap();
+                skipped.too_large.push(path.to_path_buf());
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+fn is_loop_error(err: &ignore::Error) -> bool {
+    match err {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_loop_error(err),
+        _ => false,
+    }
+}
+
+// Identity of the physical file. Device and inode numbers are used on Unix. On other platforms, the canonical path is
+// used instead since the file index is not available on stable Rust
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path) -> Option<PathBuf> {
+    path.canonicalize().ok()
+}
+
+fn files<'main>(
+    entries: Walk,
+    dedup: bool,
+    filter: Option<SkipFilter<'main>>,
+) -> impl Iterator<Item = Result<PathBuf>> + 'main {
+    // Identities of searched files. The same file can be reached via multiple symlinks with --follow or via multiple
+    // spellings of the same directory like `./src` and `src`. The first path is used
+    let mut visited = HashSet::new();
+    entries.filter_map(move |entry| match entry {
         Ok(entry) => {
-            if entry.file_type().is_some_and(|t| t.is_file()) {
-                Some(Ok(entry.into_path()))
-            } else {
-                None
+            // Paths given explicitly (depth 0) are searched even if they are symlinks to files
+            let is_file = entry.file_type().is_some_and(|t| t.is_file())
+                || (entry.depth() == 0 && entry.path().is_file());
+            if !is_file || filter.as_ref().is_some_and(|f| f.skip(&entry)) {
+                return None;
+            }
+            if dedup {
+                if let Some(id) = file_id(entry.path()) {
+                    if !visited.insert(id) {
+                        return None;
+                    }
+                }
             }
+            Some(Ok(entry.into_path()))
+        }
+        // The walker detects symlink cycles with --follow. Skip the link instead of stopping the entire search
+        Err(err) if is_loop_error(&err) => {
+            eprintln!("\x1b[1;93mwarning:\x1b[0m {}. Skipped", err);
+            None
         }
         Err(err) => Some(Err(anyhow::Error::new(err))),
-    });
+    })
+}
 
+pub fn grep<'main, P: Printer + Sync>(
+    printer: P,
+    pat: &str,
+    paths: Option<impl Iterator<Item = &'main Path>>,
+    config: Config<'main>,
+) -> Result<bool> {
+    let paths = walk(paths, &config)?;
     if config.pcre2 {
         Ripgrep::with_pcre2(pat, config, printer)?.grep(paths)
+    } else if let Some(matcher) = config.build_literal_matcher(pat) {
+        Ripgrep::new(matcher, config, printer).grep(paths)
     } else {
         Ripgrep::with_regex(pat, config, printer)?.grep(paths)
     }
 }
 
+// Count matched lines in files without building chunks or printing anything. This is the fastest path of the search and
+// useful to compare the search throughput with ripgrep. Options only for printing results such as sorting files have no
+// effect
+pub fn count_matches<'main>(
+    pat: &str,
+    paths: Option<impl Iterator<Item = &'main Path>>,
+    mut config: Config<'main>,
+) -> Result<u64> {
+    config.sort_by_match_count = None; // --max-files is applied while walking directories
+    let paths = walk(paths, &config)?;
+    if config.pcre2 {
+        let matcher = config.build_pcre2_matcher(pat)?;
+        Ripgrep::new(matcher, config, ()).count_all(paths)
+    } else if let Some(matcher) = config.build_literal_matcher(pat) {
+        Ripgrep::new(matcher, config, ()).count_all(paths)
+    } else {
+        let matcher = config.build_regex_matcher(pat)?;
+        Ripgrep::new(matcher, config, ()).count_all(paths)
+    }
+}
+
+// Search files and write the matched lines to `out` in grep's `path:line:text` format without building chunks. The text
+// of lines is taken from the searcher so files are not read again. Lines in the same file are written at once so that
+// lines of different files are not interleaved. Options only for printing results such as sorting files have no effect
+pub fn print_matched_lines<'main, W: io::Write + Send>(
+    pat: &str,
+    paths: Option<impl Iterator<Item = &'main Path>>,
+    mut config: Config<'main>,
+    out: W,
+) -> Result<bool> {
+    config.sort_by_match_count = None; // --max-files is applied while walking directories
+    let paths = walk(paths, &config)?;
+    let out = Mutex::new(out);
+    if config.pcre2 {
+        let matcher = config.build_pcre2_matcher(pat)?;
+        Ripgrep::new(matcher, config, ()).passthrough_all(paths, &out)
+    } else if let Some(matcher) = config.build_literal_matcher(pat) {
+        Ripgrep::new(matcher, config, ()).passthrough_all(paths, &out)
+    } else {
+        let matcher = config.build_regex_matcher(pat)?;
+        Ripgrep::new(matcher, config, ()).passthrough_all(paths, &out)
+    }
+}
+
+// Search files with the matcher built by the caller instead of compiling a pattern. The options to build a regex
+// (fixed_strings, line_regexp, word_regexp, case_insensitive, smart_case, pcre2, no_unicode, ...) have no effect
+// since the matcher is not built by hgrep.
+pub fn grep_with_matcher<'main, M, P>(
+    printer: P,
+    matcher: M,
+    paths: Option<impl Iterator<Item = &'main Path>>,
+    config: Config<'main>,
+) -> Result<bool>
+where
+    M: Matcher + Sync,
+    P: Printer + Sync,
+{
+    let paths = walk(paths, &config)?;
+    Ripgrep::new(matcher, config, printer).grep(paths)
+}
+
+// Lower-level API of `grep_with_matcher`. Instead of printing the results with a printer, this function returns an
+// iterator of the results so that the caller can process them by itself. Files are searched sequentially.
+pub fn search_paths<'main, M: Matcher + 'main>(
+    matcher: M,
+    paths: Option<impl Iterator<Item = &'main Path>>,
+    config: Config<'main>,
+) -> Result<impl Iterator<Item = Result<chunk::File>> + 'main> {
+    let paths = walk(paths, &config)?;
+    let ripgrep = Ripgrep::new(matcher, config, ());
+    Ok(paths.flat_map(move |path| {
+        let matches = match path.and_then(|path| ripgrep.search(path)) {
+            Ok(Some(matches)) => matches,
+            Ok(None) => return vec![],
+            Err(err) => return vec![Err(err)],
+        };
+        match ripgrep.chunks(matches) {
+            Ok(files) => files.collect(),
+            Err(err) => vec![Err(err)],
+        }
+    }))
+}
+
+/// Searches the pattern in the string in memory instead of files and returns the matched chunks. `filename` is attached
+/// to the results so that printers can detect the syntax from it. The file does not need to exist.
+///
+/// ```
+/// use hgrep::ripgrep::Config;
+///
+/// let code = "fn main() {\n    println!(\"hello\");\n}\n";
+/// let files = hgrep::search_str(code, "main.rs", "hello", Config::new(1, 1)).unwrap();
+///
+/// assert_eq!(files.len(), 1);
+/// let file = &files[0];
+/// assert_eq!(file.path.to_str(), Some("main.rs"));
+/// assert_eq!(file.line_matches[0].line_number, 2);
+/// assert_eq!(file.line_matches[0].ranges, [(14, 19)]);
+/// assert_eq!(file.chunks.as_ref(), [(1, 3)]);
+/// ```
+pub fn search_str(
+    contents: &str,
+    filename: &str,
+    pat: &str,
+    config: Config<'_>,
+) -> Result<Vec<chunk::File>> {
+    let path = PathBuf::from(filename);
+    if config.pcre2 {
+        let matcher = config.build_pcre2_matcher(pat)?;
+        Ripgrep::new(matcher, config, ()).search_str(contents, path)
+    } else if let Some(matcher) = config.build_literal_matcher(pat) {
+        Ripgrep::new(matcher, config, ()).search_str(contents, path)
+    } else {
+        let matcher = config.build_regex_matcher(pat)?;
+        Ripgrep::new(matcher, config, ()).search_str(contents, path)
+    }
+}
+
+// Matcher of an ASCII literal with ASCII case folding. The regex engine cannot use its literal optimizations for a
+// case-insensitive literal since the number of its case variants grows exponentially. Instead, candidates are found by
+// memchr with both cases of the rarest byte in the needle and each candidate is verified
+#[derive(Clone, Debug)]
+struct AsciiCaseInsensitiveMatcher {
+    needle: Vec<u8>,
+    rare: usize, // Index of the byte to find candidates
+}
+
+impl AsciiCaseInsensitiveMatcher {
+    fn new(needle: &[u8]) -> Self {
+        // Rough frequency of bytes in source code. Lower is rarer
+        fn rank(b: u8) -> u8 {
+            match b.to_ascii_lowercase() {
+                b' ' | b'\t' => 4,
+                b'e' | b't' | b'a' | b'o' | b'i' | b'n' | b's' | b'r' | b'l' => 3,
+                b'a'..=b'z' => 2,
+                b'0'..=b'9' | b'_' | b'.' | b',' | b'(' | b')' | b';' | b'=' => 1,
+                _ => 0,
+            }
+        }
+        let needle = needle.to_ascii_lowercase();
+        let rare = (0..needle.len())
+            .min_by_key(|&i| rank(needle[i]))
+            .unwrap_or(0);
+        Self { needle, rare }
+    }
+}
+
+impl Matcher for AsciiCaseInsensitiveMatcher {
+    type Captures = NoCaptures;
+    type Error = NoError;
+
+    fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, NoError> {
+        let len = self.needle.len();
+        let (lower, upper) = (
+            self.needle[self.rare],
+            self.needle[self.rare].to_ascii_uppercase(),
+        );
+        let mut start = at;
+        while start + len <= haystack.len() {
+            let hay = &haystack[start + self.rare..haystack.len() - (len - self.rare - 1)];
+            let found = if lower == upper {
+                memchr::memchr(lower, hay)
+            } else {
+                memchr::memchr2(lower, upper, hay)
+            };
+            let Some(idx) = found else {
+                break;
+            };
+            start += idx;
+            if haystack[start..start + len].eq_ignore_ascii_case(&self.needle) {
+                return Ok(Some(Match::new(start, start + len)));
+            }
+            start += 1;
+        }
+        Ok(None)
+    }
+
+    fn new_captures(&self) -> Result<NoCaptures, NoError> {
+        Ok(NoCaptures::new())
+    }
+
+    // The needle never contains a newline so a match does not span multiple lines
+    fn line_terminator(&self) -> Option<LineTerminator> {
+        Some(LineTerminator::byte(b'\n'))
+    }
+}
+
 #[derive(Default)]
 struct LineRegions<'a> {
     ranges: &'a [(usize, usize)],
@@ -495,52 +1401,254 @@ struct Matches<'a, M: Matcher> {
     path: PathBuf,
     matcher: &'a M,
     buf: Vec<GrepMatch>,
+    binary: bool,
+}
+
+// Decrement the remaining count for --max-count. Returns false when no more match can be reported
+fn take_count(count: &Option<Mutex<u64>>) -> bool {
+    if let Some(count) = count {
+        // Note: AtomicU64 is not available since it does not provide fetch_saturating_sub
+        let mut c = count.lock().unwrap();
+        if *c == 0 {
+            return false;
+        }
+        *c -= 1;
+    }
+    true
+}
+
+fn match_ranges<M: Matcher>(matcher: &M, bytes: &[u8]) -> io::Result<Vec<(usize, usize)>> {
+    let mut ranges = vec![];
+    matcher
+        .find_iter(bytes, |m| {
+            ranges.push((m.start(), m.end()));
+            true
+        })
+        .map_err(|e| io::Error::other(format!("{}", e)))?;
+    Ok(ranges)
 }
 
 impl<'a, M: Matcher> Sink for Matches<'a, M> {
     type Error = io::Error;
 
     fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
-        if let Some(count) = &self.count {
-            // Note: AtomicU64 is not available since it does not provide fetch_saturating_sub
-            let mut c = count.lock().unwrap();
-            if *c == 0 {
-                return Ok(false);
-            }
-            *c -= 1;
+        if !take_count(self.count) {
+            return Ok(false);
         }
 
         let line_number = mat.line_number().unwrap();
         let path = &self.path;
 
-        let mut ranges = vec![];
-        self.matcher
-            .find_iter(mat.bytes(), |m| {
-                ranges.push((m.start(), m.end()));
-                true
-            })
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}", e)))?;
+        let ranges = match_ranges(self.matcher, mat.bytes())?;
         let mut regions = LineRegions::new(&ranges);
 
-        let mut line_number = line_number;
-        for line in mat.lines() {
+        for (line_number, line) in (line_number..).zip(mat.lines()) {
             self.buf.push(GrepMatch {
                 path: path.to_owned(),
                 line_number,
                 ranges: regions.line_ranges(line.len()),
             });
-            line_number += 1;
         }
 
         Ok(true)
     }
+
+    fn finish(&mut self, _searcher: &Searcher, finish: &SinkFinish) -> Result<(), Self::Error> {
+        self.binary = finish.binary_byte_offset().is_some();
+        Ok(())
+    }
+}
+
+// Sink to only count matched lines for `count_matches`. Matched lines are not collected
+struct MatchCount<'a> {
+    count: &'a Option<Mutex<u64>>,
+    total: &'a AtomicU64,
+    binary: bool,
+}
+
+impl<'a> Sink for MatchCount<'a> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        if !take_count(self.count) {
+            return Ok(false);
+        }
+        let lines = mat.lines().count() as u64;
+        self.total.fetch_add(lines, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    fn finish(&mut self, _searcher: &Searcher, finish: &SinkFinish) -> Result<(), Self::Error> {
+        self.binary = finish.binary_byte_offset().is_some();
+        Ok(())
+    }
+}
+
+// Sink for `print_matched_lines`. Matched lines are formatted in the buffer as `path:line:text`
+struct MatchedLines<'a> {
+    count: &'a Option<Mutex<u64>>,
+    path: String,
+    buf: Vec<u8>,
+    binary: bool,
+}
+
+impl<'a> Sink for MatchedLines<'a> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        if !take_count(self.count) {
+            return Ok(false);
+        }
+        let line_number = mat.line_number().unwrap();
+        for (line_number, line) in (line_number..).zip(mat.lines()) {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            write!(self.buf, "{}:{}:", self.path, line_number)?;
+            self.buf.extend_from_slice(line);
+            self.buf.push(b'\n');
+        }
+        Ok(true)
+    }
+
+    fn finish(&mut self, _searcher: &Searcher, finish: &SinkFinish) -> Result<(), Self::Error> {
+        self.binary = finish.binary_byte_offset().is_some();
+        Ok(())
+    }
+}
+
+// Maximum number of lines buffered by the streaming search before they are printed. A window may slightly exceed this
+// with the context lines after the last match. Densely matched regions are split into multiple windows
+const STREAM_WINDOW_LINES: usize = 1024;
+
+// Sink for the streaming search. Matched lines and context lines reported by the searcher are buffered in the window
+// and printed as a chunk when the searcher reports the end of the contiguous lines or the window is full
+struct Window<'a, 'main, M: Matcher, P> {
+    ripgrep: &'a Ripgrep<'main, M, P>,
+    path: PathBuf,
+    contents: String,
+    first_lnum: u64,
+    num_lines: usize,
+    lmats: Vec<LineMatch>,
+    found: bool,
+    binary: bool,
+    error: Option<anyhow::Error>,
+}
+
+impl<'a, 'main, M: Matcher, P: Printer> Window<'a, 'main, M, P> {
+    fn push_line(&mut self, line: &[u8], lnum: u64) {
+        if self.num_lines == 0 {
+            self.first_lnum = lnum;
+        }
+        self.contents.push_str(&String::from_utf8_lossy(line));
+        self.num_lines += 1;
+    }
+
+    // Context lines before the first match and after the last match are cut at empty lines within the range between
+    // --min-context and --max-context as `Files` does
+    fn chunk_range(&self) -> (u64, u64) {
+        let first_match = self.lmats.first().unwrap().line_number; // OK since the window has at least one match
+        let last_match = self.lmats.last().unwrap().line_number;
+        let min_context = self.ripgrep.config.min_context;
+        let before_end = first_match.saturating_sub(min_context);
+        let after_start = last_match + min_context;
+
+        let mut start = self.first_lnum;
+        let mut end = self.first_lnum + self.num_lines as u64 - 1;
+        for (line, lnum) in LinesInclusive::starting_at(&self.contents, self.first_lnum) {
+            if !line.trim_end_matches(['\n', '\r']).is_empty() {
+                continue;
+            }
+            if lnum < before_end {
+                start = lnum + 1;
+            } else if after_start < lnum {
+                end = lnum - 1;
+                break;
+            }
+        }
+        (start, end)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.lmats.is_empty() {
+            // Only context lines remain after the window was flushed
+            self.contents.clear();
+            self.num_lines = 0;
+            return Ok(());
+        }
+
+        let chunks = vec![self.chunk_range()];
+        let lmats = mem::take(&mut self.lmats);
+        let contents = mem::take(&mut self.contents);
+        self.num_lines = 0;
+        let mut file = chunk::File::new(self.path.clone(), lmats, chunks, contents);
+        file.line_offset = self.first_lnum - 1;
+        let file = self.ripgrep.postprocess(file)?;
+        self.ripgrep.printer.print(file)?;
+        self.found = true;
+        Ok(())
+    }
+
+    // Printer errors are not I/O errors of the searcher. Keep the error and stop the search
+    fn try_flush(&mut self) -> bool {
+        match self.flush() {
+            Ok(()) => true,
+            Err(err) => {
+                self.error = Some(err);
+                false
+            }
+        }
+    }
+}
+
+impl<'a, 'main, M: Matcher, P: Printer> Sink for Window<'a, 'main, M, P> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        if !take_count(&self.ripgrep.count) {
+            return Ok(false);
+        }
+        if self.num_lines >= STREAM_WINDOW_LINES && !self.try_flush() {
+            return Ok(false);
+        }
+
+        let ranges = match_ranges(&self.ripgrep.matcher, mat.bytes())?;
+        let mut regions = LineRegions::new(&ranges);
+        let line_number = mat.line_number().unwrap();
+        for (lnum, line) in (line_number..).zip(mat.lines()) {
+            self.push_line(line, lnum);
+            let ranges = regions.line_ranges(line.len());
+            self.lmats.push(LineMatch::new(lnum, ranges));
+        }
+
+        Ok(true)
+    }
+
+    fn context(
+        &mut self,
+        _searcher: &Searcher,
+        ctx: &SinkContext<'_>,
+    ) -> Result<bool, Self::Error> {
+        self.push_line(ctx.bytes(), ctx.line_number().unwrap());
+        Ok(true)
+    }
+
+    fn context_break(&mut self, _searcher: &Searcher) -> Result<bool, Self::Error> {
+        Ok(self.try_flush())
+    }
+
+    fn finish(&mut self, _searcher: &Searcher, finish: &SinkFinish) -> Result<(), Self::Error> {
+        self.binary = finish.binary_byte_offset().is_some();
+        self.try_flush();
+        Ok(())
+    }
 }
 
-struct Ripgrep<'main, M: Matcher, P: Printer> {
+struct Ripgrep<'main, M: Matcher, P> {
     config: Config<'main>,
     matcher: M,
     count: Option<Mutex<u64>>,
     printer: P,
+    cwd: Option<PathBuf>,
 }
 
 impl<'main, P: Printer + Sync> Ripgrep<'main, RegexMatcher, P> {
@@ -555,39 +1663,127 @@ impl<'main, P: Printer + Sync> Ripgrep<'main, Pcre2Matcher, P> {
     }
 }
 
-impl<'main, M, P> Ripgrep<'main, M, P>
-where
-    M: Matcher + Sync,
-    P: Printer + Sync,
-{
+// Only the head of each file is checked for --max-line-length to avoid reading the whole file twice
+const LINE_LENGTH_CHECK_BYTES: u64 = 8 * 1024;
+
+fn has_long_line(mut file: &File, max_len: usize) -> io::Result<bool> {
+    let mut head = vec![];
+    file.take(LINE_LENGTH_CHECK_BYTES).read_to_end(&mut head)?;
+    file.rewind()?; // The searcher reads the file from the current position
+    Ok(head.split(|&b| b == b'\n').any(|line| line.len() > max_len))
+}
+
+impl<'main, M: Matcher, P> Ripgrep<'main, M, P> {
     fn new(matcher: M, config: Config<'main>, printer: P) -> Self {
         Self {
             count: config.max_count.map(Mutex::new),
             matcher,
             printer,
             config,
+            cwd: env::current_dir().ok(),
         }
     }
 
-    // Return Result<Option<Vec<_>>> instead of Result<Vec<_>> to make the `filter_map` predicate easy
-    // in `grep()` method
-    fn search(&self, path: PathBuf) -> Result<Option<Vec<GrepMatch>>> {
+    // Open the file to search. `None` is returned when the file should not be searched
+    fn open(&self, path: &Path) -> Result<Option<File>> {
         if let Some(count) = &self.count {
             if *count.lock().unwrap() == 0 {
                 return Ok(None);
             }
         }
 
-        let file = File::open(&path)?;
-        let mut searcher = self.config.build_searcher()?;
+        let file = File::open(path)?;
+        if let Some(min) = self.config.min_filesize {
+            if file.metadata().is_ok_and(|m| m.len() < min) {
+                return Ok(None);
+            }
+        }
+        if let Some(max_len) = self.config.max_line_length {
+            if has_long_line(&file, max_len)? {
+                self.report_long_lines(path);
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(file))
+    }
+
+    fn report_long_lines(&self, path: &Path) {
+        if let Some(skipped) = self.config.skipped {
+            skipped.lock().unwrap().long_lines.push(path.to_path_buf());
+        }
+    }
+
+    // The searcher fails when a line does not fit in the buffer limited by --heap-limit. Such file is skipped as a file
+    // with long lines and this method returns true
+    fn skip_heap_limit_error(&self, path: &Path, err: &io::Error) -> bool {
+        let exceeded = self
+            .config
+            .heap_limit
+            .is_some_and(|limit| is_heap_limit_error(err, limit));
+        if exceeded {
+            self.report_long_lines(path);
+            if !self.config.skipped_reported {
+                eprintln!(
+                    "\x1b[1;93mwarning:\x1b[0m {:?} has a line exceeding --heap-limit. Skipped",
+                    path,
+                );
+            }
+        }
+        exceeded
+    }
+
+    // The transcoder of the searcher always replaces malformed byte sequences. With other --encoding-errors modes, the
+    // file is decoded in the same way as `Files` decodes it for printing so that the searched text is the same as the
+    // printed text. `None` is returned when the file should be searched directly
+    fn decode(&self, mut file: &File, path: &Path) -> Result<Option<String>> {
+        if self.config.encoding_errors == EncodingErrors::Replace {
+            return Ok(None);
+        }
+        let encoding = encoding_for_label(self.config.encoding)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        let text = decode_text(bytes, encoding, self.config.encoding_errors)
+            .with_context(|| format!("Could not decode the file {:?}", path))?;
+        Ok(Some(text))
+    }
+
+    fn report_binary(&self, path: &Path) {
+        if let Some(skipped) = self.config.skipped {
+            skipped.lock().unwrap().binary.push(path.to_path_buf());
+        }
+    }
+
+    // Return Result<Option<Vec<_>>> instead of Result<Vec<_>> to make the `filter_map` predicate easy
+    // in `grep()` method
+    fn search(&self, path: PathBuf) -> Result<Option<Vec<GrepMatch>>> {
+        let Some(file) = self.open(&path)? else {
+            return Ok(None);
+        };
+
+        let decoded = self.decode(&file, &path)?;
+        let mut searcher = self.config.build_searcher(self.config.use_mmap(&file))?;
         let mut matches = Matches {
             count: &self.count,
             path,
             matcher: &self.matcher,
             buf: vec![],
+            binary: false,
         };
 
-        searcher.search_file(&self.matcher, &file, &mut matches)?;
+        let searched = match &decoded {
+            Some(text) => searcher.search_slice(&self.matcher, text.as_bytes(), &mut matches),
+            None => searcher.search_file(&self.matcher, &file, &mut matches),
+        };
+        if let Err(err) = searched {
+            if self.skip_heap_limit_error(&matches.path, &err) {
+                return Ok(None);
+            }
+            return Err(err.into());
+        }
+        if matches.binary {
+            self.report_binary(&matches.path);
+        }
         if matches.buf.is_empty() {
             return Ok(None);
         }
@@ -595,10 +1791,251 @@ where
         Ok(Some(matches.buf))
     }
 
-    fn print_matches(&self, matches: Vec<GrepMatch>) -> Result<bool> {
+    // Search the file with the sink. Returns false when the file was not searched
+    fn search_with<S: Sink<Error = io::Error>>(&self, path: &Path, sink: &mut S) -> Result<bool> {
+        let Some(file) = self.open(path)? else {
+            return Ok(false);
+        };
+
+        let decoded = self.decode(&file, path)?;
+        let mut searcher = self.config.build_searcher(self.config.use_mmap(&file))?;
+        let searched = match &decoded {
+            Some(text) => searcher.search_slice(&self.matcher, text.as_bytes(), sink),
+            None => searcher.search_file(&self.matcher, &file, sink),
+        };
+        if let Err(err) = searched {
+            if self.skip_heap_limit_error(path, &err) {
+                return Ok(false);
+            }
+            return Err(err.into());
+        }
+        Ok(true)
+    }
+
+    // Count matched lines in the file and add them to the total
+    fn count(&self, path: PathBuf, total: &AtomicU64) -> Result<()> {
+        let mut sink = MatchCount {
+            count: &self.count,
+            total,
+            binary: false,
+        };
+        if self.search_with(&path, &mut sink)? && sink.binary {
+            self.report_binary(&path);
+        }
+        Ok(())
+    }
+
+    // Write the matched lines in the file to `out`. Returns whether some line was matched
+    fn passthrough<W: io::Write>(&self, path: PathBuf, out: &Mutex<W>) -> Result<bool> {
+        let display = relative_path(path.clone(), self.config.base_dir, self.cwd.as_deref());
+        let mut sink = MatchedLines {
+            count: &self.count,
+            path: display.display().to_string(),
+            buf: vec![],
+            binary: false,
+        };
+        if !self.search_with(&path, &mut sink)? {
+            return Ok(false);
+        }
+        if sink.binary {
+            self.report_binary(&path);
+        }
+        if sink.buf.is_empty() {
+            return Ok(false);
+        }
+        out.lock()
+            .unwrap()
+            .write_all(&sink.buf)
+            .ignore_broken_pipe()?;
+        Ok(true)
+    }
+
+    fn search_str(&self, contents: &str, path: PathBuf) -> Result<Vec<chunk::File>> {
+        let mut searcher = self.config.build_searcher(false)?;
+        let mut matches = Matches {
+            count: &self.count,
+            path,
+            matcher: &self.matcher,
+            buf: vec![],
+            binary: false,
+        };
+        searcher.search_slice(&self.matcher, contents.as_bytes(), &mut matches)?;
+        if matches.buf.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut files = self.files(matches.buf)?;
+        files.contents(contents.to_string());
+        files.map(|file| self.postprocess(file?)).collect()
+    }
+
+    fn files(
+        &self,
+        matches: Vec<GrepMatch>,
+    ) -> Result<Files<impl Iterator<Item = Result<GrepMatch>>>> {
         let (min, max) = (self.config.min_context, self.config.max_context);
+        let mut files = Files::new(matches.into_iter().map(Ok), min, max, self.config.encoding)?;
+        if let Some(dir) = self.config.base_dir {
+            files.base_dir(dir.to_path_buf());
+        }
+        if let Some(bytes) = self.config.context_max_bytes {
+            files.context_max_bytes(bytes);
+        }
+        files
+            .encoding_errors(self.config.encoding_errors)
+            .overlap_policy(self.config.overlap_policy)
+            .context_bias(self.config.context_bias);
+        Ok(files)
+    }
+
+    fn chunks(
+        &self,
+        matches: Vec<GrepMatch>,
+    ) -> Result<impl Iterator<Item = Result<chunk::File>> + '_> {
+        let files = self.files(matches)?;
+        Ok(files.map(|file| self.postprocess(file?)))
+    }
+
+    fn postprocess(&self, mut file: chunk::File) -> Result<chunk::File> {
+        // Context lines are removed before searching them for --highlight-surrounding-text
+        if self.config.suppress_empty_context {
+            file.suppress_empty_context();
+        }
+        if self.config.highlight_surrounding_text {
+            file = self.find_context_matches(file)?;
+        }
+        if let Some(text) = self.config.case_normalize_display {
+            file.normalize_match_case(text);
+        }
+        Ok(file)
+    }
+
+    // Search the pattern in context lines of the chunks again since the searcher only reports matched lines
+    fn find_context_matches(&self, mut file: chunk::File) -> Result<chunk::File> {
+        let mut context_matches = vec![];
+        let mut matched = file.line_matches.iter().map(|m| m.line_number).peekable();
+        let mut chunks = file.chunks.iter();
+        let mut chunk = chunks.next();
+
+        for (line, lnum) in file.lines() {
+            while chunk.is_some_and(|&(_, end)| end < lnum) {
+                chunk = chunks.next();
+            }
+            let Some(&(start, _)) = chunk else {
+                break;
+            };
+            if lnum < start {
+                continue;
+            }
+            while matched.next_if(|&n| n < lnum).is_some() {}
+            if matched.peek() == Some(&lnum) {
+                continue;
+            }
+
+            let mut ranges = vec![];
+            self.matcher
+                .find_iter(line.as_bytes(), |m| {
+                    if !m.is_empty() {
+                        ranges.push((m.start(), m.end()));
+                    }
+                    true
+                })
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            if !ranges.is_empty() {
+                context_matches.push(LineMatch::new(lnum, ranges));
+            }
+        }
+
+        file.context_matches = context_matches.into_boxed_slice();
+        Ok(file)
+    }
+}
+
+impl<'main, M: Matcher + Sync, P: Sync> Ripgrep<'main, M, P> {
+    fn count_all<I>(&self, paths: I) -> Result<u64>
+    where
+        I: Iterator<Item = Result<PathBuf>> + Send,
+    {
+        let total = AtomicU64::new(0);
+        #[cfg(not(feature = "single-thread"))]
+        paths
+            .par_bridge()
+            .try_for_each(|path| self.count(path?, &total))?;
+        #[cfg(feature = "single-thread")]
+        for path in paths {
+            self.count(path?, &total)?;
+        }
+        Ok(total.into_inner())
+    }
+
+    fn passthrough_all<I, W>(&self, paths: I, out: &Mutex<W>) -> Result<bool>
+    where
+        I: Iterator<Item = Result<PathBuf>> + Send,
+        W: io::Write + Send,
+    {
+        #[cfg(not(feature = "single-thread"))]
+        return paths
+            .par_bridge()
+            .map(|path| self.passthrough(path?, out))
+            .try_reduce(|| false, |a, b| Ok(a || b));
+        #[cfg(feature = "single-thread")]
+        {
+            let mut found = false;
+            for path in paths {
+                found = self.passthrough(path?, out)? || found;
+            }
+            Ok(found)
+        }
+    }
+}
+
+impl<'main, M, P> Ripgrep<'main, M, P>
+where
+    M: Matcher + Sync,
+    P: Printer + Sync,
+{
+    // Search the file and print the matches while searching it. Returns whether some match was printed
+    fn stream(&self, path: PathBuf) -> Result<bool> {
+        let Some(file) = self.open(&path)? else {
+            return Ok(false);
+        };
+
+        let decoded = self.decode(&file, &path)?;
+        let mut searcher = self.config.build_searcher(self.config.use_mmap(&file))?;
+        let mut window = Window {
+            ripgrep: self,
+            path: relative_path(path.clone(), self.config.base_dir, self.cwd.as_deref()),
+            contents: String::new(),
+            first_lnum: 1,
+            num_lines: 0,
+            lmats: vec![],
+            found: false,
+            binary: false,
+            error: None,
+        };
+
+        let searched = match &decoded {
+            Some(text) => searcher.search_slice(&self.matcher, text.as_bytes(), &mut window),
+            None => searcher.search_file(&self.matcher, &file, &mut window),
+        };
+        if let Err(err) = searched {
+            if self.skip_heap_limit_error(&path, &err) {
+                return Ok(window.found);
+            }
+            return Err(err.into());
+        }
+        if let Some(err) = window.error {
+            return Err(err);
+        }
+        if window.binary {
+            self.report_binary(&path);
+        }
+        Ok(window.found)
+    }
+
+    fn print_matches(&self, matches: Vec<GrepMatch>) -> Result<bool> {
         let mut found = false;
-        for file in Files::new(matches.into_iter().map(Ok), min, max, self.config.encoding)? {
+        for file in self.chunks(matches)? {
             self.printer.print(file?)?;
             found = true;
         }
@@ -609,14 +2046,57 @@ where
     where
         I: Iterator<Item = Result<PathBuf>> + Send,
     {
-        paths
-            .par_bridge()
-            .filter_map(|path| match path {
-                Ok(path) => self.search(path).transpose(),
-                Err(err) => Some(Err(err)),
-            })
-            .map(|matches| self.print_matches(matches?))
-            .try_reduce(|| false, |a, b| Ok(a || b))
+        if self.config.stream {
+            // Files are searched one by one so that windows of different files are not interleaved in the output
+            let mut found = false;
+            for path in paths {
+                found = self.stream(path?)? || found;
+            }
+            return Ok(found);
+        }
+
+        // Files are searched and printed sequentially in the order of walking directories with single-thread feature
+        #[cfg(not(feature = "single-thread"))]
+        let paths = paths.par_bridge();
+        let matches = paths.filter_map(|path| match path {
+            Ok(path) => self.search(path).transpose(),
+            Err(err) => Some(Err(err)),
+        });
+
+        let order = self.config.sort_by_match_count;
+        if order.is_none() && !self.config.group_by_match {
+            let printed = matches.map(|matches| self.print_matches(matches?));
+            #[cfg(not(feature = "single-thread"))]
+            return printed.try_reduce(|| false, |a, b| Ok(a || b));
+            #[cfg(feature = "single-thread")]
+            {
+                let mut found = false;
+                for printed in printed {
+                    found = printed? || found;
+                }
+                return Ok(found);
+            }
+        }
+
+        // All files need to be buffered to sort or group them before printing
+        let files = matches
+            .map(|matches| self.chunks(matches?)?.collect::<Result<Vec<_>>>())
+            .collect::<Result<Vec<_>>>()?;
+        let mut files: Vec<_> = files.into_iter().flatten().collect();
+        if self.config.group_by_match {
+            return print_grouped_by_match(&self.printer, files, order);
+        }
+        if let Some(order) = order {
+            sort_by_match_count(&mut files, order);
+            if let Some(max) = self.config.max_files {
+                files.truncate(max);
+            }
+        }
+        let found = !files.is_empty();
+        for file in files {
+            self.printer.print(file)?;
+        }
+        Ok(found)
     }
 }
 
@@ -624,7 +2104,8 @@ where
 mod tests {
     use super::*;
     use crate::chunk::{File, LineMatch};
-    use crate::test::{read_all_expected_chunks, read_expected_chunks};
+    use crate::test::{read_all_expected_chunks, read_expected_chunks, TempDir};
+    use grep_matcher::{Match, NoCaptures, NoError};
     use pretty_assertions::assert_eq;
     use regex::Regex;
     use std::ffi::OsStr;
@@ -678,6 +2159,63 @@ mod tests {
         inputs
     }
 
+    #[test]
+    fn test_config_with_fields() {
+        let built = ConfigBuilder {
+            min_context: 3,
+            max_context: 6,
+            smart_case: true,
+            globs: vec!["*.rs", "!*.md"],
+            max_filesize: Some(1024),
+            pcre2_jit_stack: Some(2),
+            overlap_policy: OverlapPolicy::Separate,
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+
+        let mut config = Config::new(3, 6);
+        config
+            .smart_case(true)
+            .globs(["*.rs", "!*.md"].into_iter())
+            .max_filesize("1K")
+            .unwrap()
+            .pcre2_jit_stack(2)
+            .unwrap()
+            .overlap_policy(OverlapPolicy::Separate);
+        assert_eq!(format!("{:?}", built), format!("{:?}", config));
+
+        let err = ConfigBuilder {
+            pcre2_jit_stack: Some(usize::MAX),
+            ..Default::default()
+        }
+        .build()
+        .unwrap_err();
+        assert!(format!("{err}").contains("too large"), "{err}");
+
+        // Conflicting flags are resolved in the same way as setters
+        let built = ConfigBuilder {
+            case_insensitive: true,
+            smart_case: true,
+            word_regexp: true,
+            line_regexp: true,
+            fixed_strings: true,
+            pcre2: true,
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut config = Config::default();
+        config
+            .smart_case(true)
+            .case_insensitive(true)
+            .pcre2(true)
+            .fixed_strings(true)
+            .word_regexp(true)
+            .line_regexp(true);
+        assert_eq!(format!("{:?}", built), format!("{:?}", config));
+    }
+
     #[test]
     fn test_grep_each_file() {
         let dir = Path::new("testdata").join("chunk");
@@ -728,10 +2266,236 @@ mod tests {
         assert_eq!(expected, got);
     }
 
-    #[test]
-    fn test_grep_no_match_found() {
-        let path = Path::new("testdata").join("chunk").join("single_max.in");
-        let paths = iter::once(path.as_path());
+    // Matcher to find '*' at end of line, which is equivalent to regex `\*$`
+    struct StarAtEndMatcher;
+    impl Matcher for StarAtEndMatcher {
+        type Captures = NoCaptures;
+        type Error = NoError;
+
+        fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, NoError> {
+            let line = haystack.strip_suffix(b"\n").unwrap_or(haystack);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            Ok(match line.len().checked_sub(1) {
+                Some(idx) if at <= idx && line[idx] == b'*' => Some(Match::new(idx, idx + 1)),
+                _ => None,
+            })
+        }
+
+        fn new_captures(&self) -> Result<NoCaptures, NoError> {
+            Ok(NoCaptures::new())
+        }
+    }
+
+    #[test]
+    fn test_grep_sort_by_match_count() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)))
+            .collect::<Vec<_>>();
+
+        for order in [SortOrder::Descending, SortOrder::Ascending] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.sort_by_match_count(order);
+            let found = grep(
+                &printer,
+                r"\*$",
+                Some(paths.iter().map(PathBuf::as_path)),
+                config,
+            )
+            .unwrap();
+            assert!(found);
+
+            let got = printer.0.into_inner().unwrap();
+            let mut expected = got.clone();
+            sort_by_match_count(&mut expected, order);
+            assert_eq!(expected, got, "order: {:?}", order);
+
+            let counts: Vec<_> = got.iter().map(|f| f.line_matches.len()).collect();
+            assert!(counts.first() != counts.last(), "counts: {:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_grep_max_files() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)))
+            .collect::<Vec<_>>();
+        let grep_files = |configure: &dyn Fn(&mut Config<'_>)| {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            configure(&mut config);
+            grep(
+                &printer,
+                r"\*$",
+                Some(paths.iter().map(PathBuf::as_path)),
+                config,
+            )
+            .unwrap();
+            printer.0.into_inner().unwrap()
+        };
+
+        let all = grep_files(&|_| {});
+        assert!(all.len() > 2, "{:?}", all.len());
+        for max in [0, 1, 2] {
+            let got = grep_files(&|c| {
+                c.max_files(max);
+            });
+            assert!(got.len() <= max, "max={} got={}", max, got.len());
+        }
+
+        // Files are capped after sorting them
+        let mut expected = all;
+        sort_by_match_count(&mut expected, SortOrder::Descending);
+        expected.truncate(2);
+        let got = grep_files(&|c| {
+            c.max_files(2).sort_by_match_count(SortOrder::Descending);
+        });
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_count_matches() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)))
+            .collect::<Vec<_>>();
+        let re = Regex::new(r"\*$").unwrap();
+        let expected = paths
+            .iter()
+            .map(|p| fs::read_to_string(p).unwrap())
+            .map(|s| s.lines().filter(|l| re.is_match(l)).count() as u64)
+            .sum::<u64>();
+        assert!(expected > 0);
+
+        let count = |config: Config<'_>| {
+            let paths = paths.iter().map(PathBuf::as_path);
+            count_matches(r"\*$", Some(paths), config).unwrap()
+        };
+        assert_eq!(count(Config::new(3, 6)), expected);
+
+        let mut config = Config::new(3, 6);
+        config.max_count(3);
+        assert_eq!(count(config), 3);
+
+        let mut config = Config::new(3, 6);
+        config.invert_match(true);
+        let num_lines = paths
+            .iter()
+            .map(|p| fs::read_to_string(p).unwrap().lines().count() as u64)
+            .sum::<u64>();
+        assert_eq!(count(config), num_lines - expected);
+    }
+
+    #[test]
+    fn test_print_matched_lines() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)))
+            .collect::<Vec<_>>();
+        let re = Regex::new(r"\*$").unwrap();
+        let mut expected = vec![];
+        for path in paths.iter() {
+            let contents = fs::read_to_string(path).unwrap();
+            for (idx, line) in contents.lines().enumerate() {
+                if re.is_match(line) {
+                    expected.push(format!("{}:{}:{}", path.display(), idx + 1, line));
+                }
+            }
+        }
+        assert!(!expected.is_empty());
+
+        let mut out = vec![];
+        let found = print_matched_lines(
+            r"\*$",
+            Some(paths.iter().map(PathBuf::as_path)),
+            Config::new(3, 6),
+            &mut out,
+        )
+        .unwrap();
+        assert!(found);
+
+        // Files are searched in parallel so the order of files is not stable
+        let out = String::from_utf8(out).unwrap();
+        let mut actual: Vec<_> = out.lines().map(str::to_string).collect();
+        actual.sort();
+        expected.sort();
+        assert_eq!(actual, expected);
+
+        let mut out = vec![];
+        let found = print_matched_lines(
+            "this pattern never matches",
+            Some(paths.iter().map(PathBuf::as_path)),
+            Config::new(3, 6),
+            &mut out,
+        )
+        .unwrap();
+        assert!(!found);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_grep_with_custom_matcher() {
+        let dir = Path::new("testdata").join("chunk");
+        let inputs = read_all_inputs(&dir);
+
+        let mut printer = DummyPrinter::default();
+        let paths = inputs
+            .iter()
+            .map(|s| dir.join(format!("{}.in", s)))
+            .collect::<Vec<_>>();
+        let paths = paths.iter().map(PathBuf::as_path);
+
+        let config = Config::new(3, 6);
+        let found = grep_with_matcher(&printer, StarAtEndMatcher, Some(paths), config).unwrap();
+
+        printer.validate_and_remove_region_ranges();
+
+        let mut got = printer.0.into_inner().unwrap();
+        got.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut expected = read_all_expected_chunks(&dir, &inputs);
+        expected.sort_by(|a, b| a.path.cmp(&b.path));
+
+        assert!(found);
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn test_search_paths_with_custom_matcher() {
+        let dir = Path::new("testdata").join("chunk");
+        for input in read_all_inputs(&dir) {
+            let file = dir.join(format!("{}.in", input));
+            let paths = iter::once(file.as_path());
+            let files = search_paths(StarAtEndMatcher, Some(paths), Config::new(3, 6))
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+
+            let mut printer = DummyPrinter(Mutex::new(files));
+            printer.validate_and_remove_region_ranges();
+            let got = printer.0.into_inner().unwrap();
+
+            let expected = read_expected_chunks(&dir, &input)
+                .map(|f| vec![f])
+                .unwrap_or_default();
+            assert_eq!(expected, got, "test file: {:?}", file);
+        }
+    }
+
+    #[test]
+    fn test_grep_no_match_found() {
+        let path = Path::new("testdata").join("chunk").join("single_max.in");
+        let paths = iter::once(path.as_path());
         let printer = DummyPrinter::default();
         let pat = "^this does not match to any line!!!!!!$";
         let found = grep(&printer, pat, Some(paths), Config::new(3, 6)).unwrap();
@@ -851,6 +2615,759 @@ mod tests {
         assert_eq!(files.pop().unwrap(), expected, "file={}", file);
     }
 
+    #[test]
+    fn test_max_depth() {
+        let dir = Path::new("testdata").join("ripgrep").join("max_depth");
+        let top = dir.join("top.txt");
+        let one = dir.join("one").join("one.txt");
+        let two = dir.join("one").join("two").join("two.txt");
+
+        for (depth, paths, expected) in [
+            (0, &dir, vec![]),
+            (1, &dir, vec![&top]),
+            (2, &dir, vec![&one, &top]),
+            (3, &dir, vec![&two, &one, &top]),
+            (0, &two, vec![&two]), // Explicitly given file is searched
+        ] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(1, 2);
+            config.max_depth(depth);
+
+            let paths = iter::once(paths.as_path());
+            let found = grep(&printer, "max depth test", Some(paths), config).unwrap();
+            assert_eq!(found, !expected.is_empty(), "depth={}", depth);
+
+            let mut got: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect();
+            got.sort();
+            let mut expected: Vec<_> = expected.into_iter().cloned().collect();
+            expected.sort();
+            assert_eq!(got, expected, "depth={}", depth);
+        }
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = Path::new("testdata").join("ripgrep").join("prune");
+        let top = dir.join("top.txt");
+        let a = dir.join("dist").join("a.txt");
+        let b = dir.join("src").join("dist").join("b.txt");
+        let c = dir.join("src").join("c.txt");
+        let file = dir.join("other").join("dist");
+        let dist = dir.join("dist");
+
+        for (prune, path, expected) in [
+            (vec![], &dir, vec![&top, &a, &b, &c, &file]),
+            // Directories are pruned at any depth, but files with the same name are still searched
+            (vec!["dist"], &dir, vec![&top, &c, &file]),
+            (vec!["dist", "src"], &dir, vec![&top, &file]),
+            (vec!["dis"], &dir, vec![&top, &a, &b, &c, &file]),
+            // Explicitly given directory is searched
+            (vec!["dist"], &dist, vec![&a]),
+        ] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(1, 2);
+            config.prune(prune.iter().copied());
+
+            let paths = iter::once(path.as_path());
+            grep(&printer, "prune test", Some(paths), config).unwrap();
+
+            let mut got: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect();
+            got.sort();
+            let mut expected: Vec<_> = expected.into_iter().cloned().collect();
+            expected.sort();
+            assert_eq!(got, expected, "prune={:?}", prune);
+        }
+    }
+
+    #[test]
+    fn test_min_filesize() {
+        let dir = Path::new("testdata").join("ripgrep").join("min_filesize");
+        let small = dir.join("small.txt");
+        let large = dir.join("large.txt");
+        let small_size = fs::metadata(&small).unwrap().len();
+
+        for (min, path, expected) in [
+            (None, &dir, vec![&small, &large]),
+            (Some(small_size.to_string()), &dir, vec![&small, &large]),
+            (Some((small_size + 1).to_string()), &dir, vec![&large]),
+            (Some("1K".to_string()), &dir, vec![]),
+            // Files given explicitly are also skipped
+            (Some((small_size + 1).to_string()), &small, vec![]),
+        ] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(1, 2);
+            if let Some(min) = &min {
+                config.min_filesize(min).unwrap();
+            }
+
+            let paths = iter::once(path.as_path());
+            grep(&printer, "min filesize test", Some(paths), config).unwrap();
+
+            let mut got: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect();
+            got.sort();
+            let mut expected: Vec<_> = expected.into_iter().cloned().collect();
+            expected.sort();
+            assert_eq!(got, expected, "min={min:?}, path={path:?}");
+        }
+    }
+
+    #[test]
+    fn test_last_glob_wins() {
+        let dir = Path::new("testdata").join("ripgrep").join("prune");
+        let top = dir.join("top.txt");
+        let a = dir.join("dist").join("a.txt");
+        let b = dir.join("src").join("dist").join("b.txt");
+        let c = dir.join("src").join("c.txt");
+        let file = dir.join("other").join("dist");
+
+        for (globs, expected) in [
+            (vec!["*.txt"], vec![&top, &a, &b, &c]),
+            (vec!["!c.txt"], vec![&top, &a, &b, &file]),
+            (vec!["*.txt", "!c.txt"], vec![&top, &a, &b]),
+            (vec!["!c.txt", "*.txt"], vec![&top, &a, &b, &c]),
+            (vec!["!*.txt", "c.txt"], vec![&c]),
+            (vec!["c.txt", "!*.txt"], vec![]),
+            (vec!["!src/**", "src/c.txt"], vec![&c]),
+            (vec!["src/c.txt", "!src/**"], vec![]),
+            // Only paths matching to the include glob are searched
+            (vec!["!dist", "dist"], vec![&file]),
+            (vec!["dist", "!dist"], vec![]),
+        ] {
+            // Globs are also applied by the filter to collect skipped files
+            for collect_skipped in [false, true] {
+                let printer = DummyPrinter::default();
+                let skipped = Mutex::default();
+                let mut config = Config::new(1, 2);
+                config.globs(globs.iter().copied());
+                if collect_skipped {
+                    config.collect_skipped(&skipped);
+                }
+
+                let paths = iter::once(dir.as_path());
+                grep(&printer, "prune test", Some(paths), config).unwrap();
+
+                let mut got: Vec<_> = printer
+                    .0
+                    .into_inner()
+                    .unwrap()
+                    .into_iter()
+                    .map(|f| f.path)
+                    .collect();
+                got.sort();
+                let mut expected: Vec<_> = expected.iter().copied().cloned().collect();
+                expected.sort();
+                assert_eq!(got, expected, "globs={globs:?}, skipped={collect_skipped}");
+            }
+        }
+    }
+
+    #[cfg(feature = "single-thread")]
+    #[test]
+    fn test_single_thread() {
+        use std::thread::{self, ThreadId};
+
+        #[derive(Default)]
+        struct ThreadPrinter(Mutex<Vec<(PathBuf, ThreadId)>>);
+        impl Printer for &ThreadPrinter {
+            fn print(&self, file: File) -> Result<()> {
+                let id = thread::current().id();
+                self.0.lock().unwrap().push((file.path, id));
+                Ok(())
+            }
+        }
+
+        let dir = Path::new("testdata").join("ripgrep").join("prune");
+        let search = || {
+            let printer = ThreadPrinter::default();
+            let paths = iter::once(dir.as_path());
+            grep(&printer, "prune test", Some(paths), Config::new(1, 2)).unwrap();
+            printer.0.into_inner().unwrap()
+        };
+
+        // All files are printed on the current thread in the same order
+        let printed = search();
+        assert_eq!(printed.len(), 5, "{printed:?}");
+        let current = thread::current().id();
+        assert!(printed.iter().all(|(_, id)| *id == current), "{printed:?}");
+        for _ in 0..3 {
+            assert_eq!(search(), printed);
+        }
+    }
+
+    #[test]
+    fn test_suppress_empty_context() {
+        let path = Path::new("testdata")
+            .join("ripgrep")
+            .join("blank_context.txt");
+        for stream in [false, true] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(1, 1);
+            config.suppress_empty_context(true).stream(stream);
+            let paths = iter::once(path.as_path());
+            grep(&printer, "suppress empty context", Some(paths), config).unwrap();
+
+            // Blank lines around the first match are removed. The context lines of the second match are not blank
+            let files = printer.0.into_inner().unwrap();
+            let chunks: Vec<_> = files
+                .iter()
+                .flat_map(|f| f.chunks.iter().copied())
+                .collect();
+            assert_eq!(chunks, [(3, 3), (6, 8)], "stream={stream}");
+        }
+    }
+
+    #[test]
+    fn test_highlight_surrounding_text() {
+        let path = Path::new("testdata")
+            .join("ripgrep")
+            .join("surrounding_text.txt");
+
+        for enabled in [true, false] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(3, 6);
+            config.max_count(1).highlight_surrounding_text(enabled);
+            let paths = iter::once(path.as_path());
+            let found = grep(&printer, "TODO", Some(paths), config).unwrap();
+            assert!(found);
+
+            let files = printer.0.into_inner().unwrap();
+            assert_eq!(files.len(), 1);
+            let file = &files[0];
+            assert_eq!(
+                file.line_matches.as_ref(),
+                &[LineMatch::new(1, vec![(0, 4)])]
+            );
+
+            let expected = if enabled {
+                vec![
+                    LineMatch::new(3, vec![(8, 12), (22, 26)]),
+                    LineMatch::new(4, vec![(0, 4)]),
+                ]
+            } else {
+                vec![]
+            };
+            assert_eq!(file.context_matches.as_ref(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_report_skipped() {
+        let dir = Path::new("testdata").join("ripgrep").join("skipped");
+        let printer = DummyPrinter::default();
+        let skipped = Mutex::default();
+        let mut config = Config::new(1, 2);
+        config
+            .max_filesize("100")
+            .unwrap()
+            .types_not(["rust"].into_iter())
+            .report_skipped(&skipped);
+
+        let paths = iter::once(dir.as_path());
+        let found = grep(&printer, "skipped file test", Some(paths), config).unwrap();
+        assert!(found);
+
+        let got: Vec<_> = printer
+            .0
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(got, vec![dir.join("searched.txt")]);
+
+        let skipped = skipped.into_inner().unwrap();
+        assert_eq!(skipped.binary, vec![dir.join("binary.dat")]);
+        assert_eq!(skipped.too_large, vec![dir.join("large.txt")]);
+        assert_eq!(skipped.filtered_by_type, vec![dir.join("filtered.rs")]);
+    }
+
+    #[test]
+    fn test_collect_skipped() {
+        let dir = Path::new("testdata").join("ripgrep").join("skipped");
+        let printer = DummyPrinter::default();
+        let skipped = Mutex::default();
+        let mut config = Config::new(1, 2);
+        config
+            .max_filesize("100")
+            .unwrap()
+            .collect_skipped(&skipped);
+        assert!(!config.skipped_reported);
+
+        let paths = iter::once(dir.as_path());
+        let found = grep(&printer, "padding line", Some(paths), config).unwrap();
+        assert!(!found);
+        let skipped = skipped.into_inner().unwrap();
+        assert_eq!(skipped.too_large, vec![dir.join("large.txt")]);
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let dir = Path::new("testdata").join("ripgrep").join("long_line");
+        let printer = DummyPrinter::default();
+        let skipped = Mutex::default();
+        let mut config = Config::new(1, 2);
+        config.max_line_length(100).report_skipped(&skipped);
+
+        let paths = iter::once(dir.as_path());
+        let found = grep(&printer, "max line length test", Some(paths), config).unwrap();
+        assert!(found);
+
+        // The match at the first line is found after checking the head of the file
+        let files = printer.0.into_inner().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, dir.join("short.txt"));
+        assert_eq!(files[0].line_matches[0].line_number, 1);
+
+        let skipped = skipped.into_inner().unwrap();
+        assert_eq!(skipped.long_lines, vec![dir.join("long.txt")]);
+    }
+
+    #[test]
+    fn test_heap_limit() {
+        // Generate the file with a 10MB line at runtime instead of putting it in testdata
+        let tmp = TempDir::new("heap-limit-test");
+        let dir = &tmp.0;
+        let long = dir.join("long.txt");
+        let mut contents = "x".repeat(10 * 1024 * 1024);
+        contents.push_str(" heap limit test\n");
+        fs::write(&long, &contents).unwrap();
+        fs::write(dir.join("short.txt"), "heap limit test\n").unwrap();
+
+        let search = |heap_limit: Option<&str>| {
+            let printer = DummyPrinter::default();
+            let skipped = Mutex::default();
+            let mut config = Config::new(1, 2);
+            config.report_skipped(&skipped);
+            if let Some(limit) = heap_limit {
+                config.heap_limit(limit).unwrap();
+            }
+            let paths = iter::once(dir.as_path());
+            let found = grep(&printer, "heap limit test", Some(paths), config).unwrap();
+            assert!(found);
+            let mut paths: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path.file_name().unwrap().to_owned())
+                .collect();
+            paths.sort();
+            (paths, skipped.into_inner().unwrap().long_lines)
+        };
+
+        let with_limit = search(Some("1M"));
+        let without_limit = search(None);
+
+        // The file with the line exceeding the limit is skipped instead of stopping the search
+        assert_eq!(with_limit, (vec!["short.txt".into()], vec![long]));
+        assert_eq!(
+            without_limit,
+            (vec!["long.txt".into(), "short.txt".into()], vec![])
+        );
+    }
+
+    #[test]
+    fn test_heap_limit_value() {
+        let mut config = Config::new(1, 2);
+        config.heap_limit("64K").unwrap();
+        assert_eq!(config.heap_limit, Some(64 * 1024));
+        let err = config.heap_limit("0").unwrap_err();
+        assert!(format!("{err}").contains("must be greater than 0"), "{err}");
+        assert_eq!(config.heap_limit, Some(64 * 1024));
+    }
+
+    #[test]
+    fn test_is_heap_limit_error() {
+        let limit = 1024 * 1024;
+        let err = |msg: &str| io::Error::other(msg.to_string());
+        let exceeded = format!(
+            "configured allocation limit ({}) exceeded",
+            limit + 64 * 1024
+        );
+        assert!(is_heap_limit_error(&err(&exceeded), limit));
+        assert!(!is_heap_limit_error(&err(&exceeded), limit * 2));
+        assert!(!is_heap_limit_error(
+            &err("configured allocation limit"),
+            limit
+        ));
+        assert!(!is_heap_limit_error(
+            &err("configured allocation limit (foo) exceeded"),
+            limit
+        ));
+        let other_kind = io::Error::new(io::ErrorKind::InvalidData, exceeded);
+        assert!(!is_heap_limit_error(&other_kind, limit));
+        assert!(!is_heap_limit_error(
+            &io::Error::from_raw_os_error(12),
+            limit
+        ));
+    }
+
+    #[test]
+    fn test_mmap_threshold() {
+        let dir = Path::new("testdata").join("ripgrep").join("skipped");
+        let small = fs::File::open(dir.join("searched.txt")).unwrap();
+        let large = fs::File::open(dir.join("large.txt")).unwrap();
+
+        let mut config = Config::new(1, 2);
+        assert!(!config.use_mmap(&small));
+        assert!(!config.use_mmap(&large));
+        config.mmap(true);
+        assert!(config.use_mmap(&small));
+        assert!(config.use_mmap(&large));
+        config.mmap(false).mmap_threshold("100").unwrap();
+        assert!(!config.use_mmap(&small));
+        assert!(config.use_mmap(&large));
+
+        // Both files are searched though they are read in the different ways
+        let printer = DummyPrinter::default();
+        let paths = [dir.join("searched.txt"), dir.join("large.txt")];
+        let paths = paths.iter().map(PathBuf::as_path);
+        let found = grep(&printer, "skipped file test", Some(paths), config).unwrap();
+        assert!(found);
+        let mut got: Vec<_> = printer
+            .0
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        got.sort();
+        assert_eq!(got, vec![dir.join("large.txt"), dir.join("searched.txt")]);
+    }
+
+    #[test]
+    fn test_changed_within_and_before() {
+        let dir = Path::new("testdata").join("ripgrep").join("mtime");
+        let now = SystemTime::now();
+        let set_mtime = |name: &str, ago: Duration| {
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .open(dir.join(name))
+                .unwrap();
+            file.set_modified(now - ago).unwrap();
+        };
+        set_mtime("recent.txt", Duration::from_secs(60));
+        set_mtime("old.txt", Duration::from_secs(10 * 24 * 60 * 60));
+
+        for (within, before, expected) in [
+            (None, None, &["old.txt", "recent.txt"][..]),
+            (Some("1h"), None, &["recent.txt"][..]),
+            (None, Some("1d"), &["old.txt"][..]),
+            (Some("30d"), Some("1d"), &["old.txt"][..]),
+            (Some("1d"), Some("1d"), &[][..]),
+        ] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(1, 2);
+            if let Some(d) = within {
+                config.changed_within(d).unwrap();
+            }
+            if let Some(d) = before {
+                config.changed_before(d).unwrap();
+            }
+
+            let paths = iter::once(dir.as_path());
+            let found = grep(&printer, "mtime test", Some(paths), config).unwrap();
+            assert_eq!(found, !expected.is_empty(), "{within:?} {before:?}");
+
+            let mut got: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect();
+            got.sort();
+            let expected: Vec<_> = expected.iter().map(|n| dir.join(n)).collect();
+            assert_eq!(got, expected, "{within:?} {before:?}");
+        }
+    }
+
+    #[test]
+    fn test_stream_bounded_window() {
+        // Generate the large input at runtime instead of putting it in testdata
+        let path = env::temp_dir().join(format!("hgrep-stream-test-{}.txt", std::process::id()));
+        let num_lines = STREAM_WINDOW_LINES * 10;
+        let mut contents = String::new();
+        for i in 1..=num_lines {
+            if i % 3 == 0 {
+                contents.push_str("context\n");
+            } else {
+                contents.push_str("this is *match*\n");
+            }
+        }
+        fs::write(&path, &contents).unwrap();
+
+        let printer = DummyPrinter::default();
+        let mut config = Config::new(1, 2);
+        config.stream(true);
+        let found = grep(
+            &printer,
+            r"\*match\*",
+            Some(iter::once(path.as_path())),
+            config,
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(found.unwrap());
+
+        let files = printer.0.into_inner().unwrap();
+        assert!(files.len() >= 10, "{}", files.len());
+
+        let mut matched = vec![];
+        for file in &files {
+            let lines: Vec<_> = file.lines().collect();
+            // Each window buffers lines up to the limit and context lines after the last match
+            assert!(lines.len() <= STREAM_WINDOW_LINES + 2, "{}", lines.len());
+            assert_eq!(file.line_offset + 1, lines[0].1);
+            assert_eq!(file.chunks.len(), 1);
+            let (start, end) = file.chunks[0];
+            assert!(
+                lines[0].1 <= start && end <= lines.last().unwrap().1,
+                "{start}-{end}"
+            );
+
+            for lmat in file.line_matches.iter() {
+                let (line, _) = lines[(lmat.line_number - lines[0].1) as usize];
+                assert_eq!(line, "this is *match*\n");
+                assert_eq!(lmat.ranges, vec![(8, 15)]);
+                matched.push(lmat.line_number);
+            }
+        }
+
+        let expected: Vec<_> = (1..=num_lines as u64).filter(|i| i % 3 != 0).collect();
+        assert_eq!(matched, expected);
+    }
+
+    #[test]
+    fn test_stream_same_matches() {
+        let dir = Path::new("testdata").join("chunk");
+        for input in read_all_inputs(&dir) {
+            let path = dir.join(format!("{}.in", input));
+
+            let mut matched = vec![];
+            for stream in [false, true] {
+                let printer = DummyPrinter::default();
+                let mut config = Config::new(3, 6);
+                config.stream(stream);
+                let found = grep(&printer, r"\*$", Some(iter::once(path.as_path())), config);
+                let found = found.unwrap();
+
+                let mut lnums = vec![];
+                for file in printer.0.into_inner().unwrap() {
+                    assert_eq!(file.path, path, "{:?}", input);
+                    for lmat in file.line_matches.iter() {
+                        let (start, end) = *file
+                            .chunks
+                            .iter()
+                            .find(|(s, e)| *s <= lmat.line_number && lmat.line_number <= *e)
+                            .unwrap();
+                        assert!(start <= end, "{:?}", input);
+                        lnums.push(lmat.line_number);
+                    }
+                }
+                assert_eq!(found, !lnums.is_empty(), "{:?}", input);
+                matched.push(lnums);
+            }
+            assert_eq!(matched[0], matched[1], "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_search_str_same_as_files() {
+        let dir = Path::new("testdata").join("chunk");
+        for input in read_all_inputs(&dir) {
+            let path = dir.join(format!("{}.in", input));
+            let contents = fs::read_to_string(&path).unwrap();
+
+            let printer = DummyPrinter::default();
+            grep(
+                &printer,
+                r"\*$",
+                Some(iter::once(path.as_path())),
+                Config::new(3, 6),
+            )
+            .unwrap();
+            let want = printer.0.into_inner().unwrap();
+
+            let filename = path.to_str().unwrap();
+            let got = search_str(&contents, filename, r"\*$", Config::new(3, 6)).unwrap();
+            assert_eq!(got, want, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_search_str() {
+        let contents = "foo\nbar\n\nFOO bar foo\n";
+        for pcre2 in [false, true] {
+            let mut config = Config::new(0, 0);
+            config.pcre2(pcre2).case_insensitive(true);
+            let files = search_str(contents, "buf.txt", "foo", config).unwrap();
+            assert_eq!(files.len(), 1, "{pcre2}");
+            let file = &files[0];
+            assert_eq!(file.path, Path::new("buf.txt"), "{pcre2}");
+            assert_eq!(file.contents.as_ref(), contents, "{pcre2}");
+            assert_eq!(
+                file.line_matches.as_ref(),
+                [
+                    LineMatch::new(1, vec![(0, 3)]),
+                    LineMatch::new(4, vec![(0, 3), (8, 11)]),
+                ],
+                "{pcre2}",
+            );
+            assert_eq!(file.chunks.as_ref(), [(1, 1), (4, 4)], "{pcre2}");
+        }
+
+        // Options to post-process the results are applied
+        let mut config = Config::new(1, 1);
+        config.max_count(1).highlight_surrounding_text(true);
+        let files = search_str(contents, "buf.txt", "bar", config).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].line_matches.as_ref(),
+            [LineMatch::new(2, vec![(0, 3)])]
+        );
+        assert_eq!(files[0].chunks.as_ref(), [(1, 3)]);
+
+        let files = search_str(contents, "buf.txt", "baz", Config::new(3, 6)).unwrap();
+        assert!(files.is_empty());
+
+        let err = search_str(contents, "buf.txt", "(", Config::new(3, 6)).unwrap_err();
+        assert!(format!("{}", err).contains("regex"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let tests = &[
+            ("30", Ok(30)),
+            ("30s", Ok(30)),
+            ("30m", Ok(30 * 60)),
+            ("2h", Ok(2 * 60 * 60)),
+            ("3d", Ok(3 * 24 * 60 * 60)),
+            ("1w", Ok(7 * 24 * 60 * 60)),
+            ("", Err("Duration string must not be empty")),
+            ("abc", Err("Could not parse \"abc\" as unsigned integer")),
+            ("3dd", Err("Could not parse \"3d\" as unsigned integer")),
+            ("-3d", Err("Could not parse \"-3\" as unsigned integer")),
+        ];
+
+        for (input, want) in tests.iter().copied() {
+            match (parse_duration(input), want) {
+                (Ok(got), Ok(want)) => {
+                    assert_eq!(got, Duration::from_secs(want), "input={input:?}")
+                }
+                (Err(err), Err(want)) => {
+                    let msg = format!("{}", err);
+                    assert!(msg.contains(want), "wanted {want:?} but got {msg:?}");
+                }
+                (got, want) => panic!("wanted {want:?} but got {got:?} for {input:?}"),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlink_cycle() {
+        // sub/parent -> .. makes a cycle and alias -> sub makes the same files reachable twice
+        let dir = Path::new("testdata").join("ripgrep").join("symlink_cycle");
+        let printer = DummyPrinter::default();
+        let mut config = Config::new(1, 2);
+        config.follow_symlink(true);
+
+        let paths = iter::once(dir.as_path());
+        let found = grep(&printer, "symlink cycle test", Some(paths), config).unwrap();
+        assert!(found);
+
+        let mut got: Vec<_> = printer
+            .0
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path.canonicalize().unwrap())
+            .collect();
+        got.sort();
+        let mut expected = vec![
+            dir.join("top.txt").canonicalize().unwrap(),
+            dir.join("sub").join("sub.txt").canonicalize().unwrap(),
+        ];
+        expected.sort();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_same_file_in_multiple_roots() {
+        let dir = Path::new("testdata").join("ripgrep").join("min_filesize");
+        let dotted = Path::new(".").join(&dir);
+        let printer = DummyPrinter::default();
+
+        // The same directory is given with different spellings. The first spelling is used
+        let paths = [dotted.as_path(), dir.as_path(), &dir.join("small.txt")];
+        let config = Config::new(1, 2);
+        grep(
+            &printer,
+            "min filesize test",
+            Some(paths.into_iter()),
+            config,
+        )
+        .unwrap();
+
+        let mut got: Vec<_> = printer
+            .0
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        got.sort();
+        let expected = vec![dotted.join("large.txt"), dotted.join("small.txt")];
+        assert_eq!(got, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_same_file_via_symlinked_root() {
+        // alias -> sub
+        let dir = Path::new("testdata").join("ripgrep").join("symlink_cycle");
+        let sub = dir.join("sub");
+        let alias = dir.join("alias");
+        let printer = DummyPrinter::default();
+
+        let paths = [alias.as_path(), sub.as_path()];
+        let config = Config::new(1, 2);
+        grep(
+            &printer,
+            "symlink cycle test",
+            Some(paths.into_iter()),
+            config,
+        )
+        .unwrap();
+
+        let got: Vec<_> = printer
+            .0
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(got, [alias.join("sub.txt")]);
+    }
+
     #[test]
     fn test_multiline() {
         test_ripgrep_config("multiline.txt", r"this\r?\nis the\r?\ntest string", |c| {
@@ -877,6 +3394,71 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_ascii_case_insensitive_matcher() {
+        let matcher = AsciiCaseInsensitiveMatcher::new(b"Hello(");
+        let find = |hay: &str, at| {
+            matcher
+                .find_at(hay.as_bytes(), at)
+                .unwrap()
+                .map(|m| (m.start(), m.end()))
+        };
+        assert_eq!(find("hello(", 0), Some((0, 6)));
+        assert_eq!(find("say HELLO( hElLo(", 0), Some((4, 10)));
+        assert_eq!(find("say HELLO( hElLo(", 5), Some((11, 17)));
+        assert_eq!(find("hello hello(", 0), Some((6, 12)));
+        assert_eq!(find("hello", 0), None);
+        assert_eq!(find("hello(", 1), None);
+        assert_eq!(find("", 0), None);
+
+        let mut config = Config::new(0, 0);
+        assert!(config.build_literal_matcher("hello(").is_none());
+        config.fixed_strings(true);
+        assert!(config.build_literal_matcher("hello(").is_none());
+        config.case_insensitive(true);
+        assert!(config.build_literal_matcher("hello(").is_some());
+        assert!(config.build_literal_matcher("").is_none());
+        assert!(config.build_literal_matcher("héllo").is_none());
+        assert!(config.build_literal_matcher("test").is_none()); // 's' matches 'ſ' with Unicode
+        config.no_unicode(true);
+        assert!(config.build_literal_matcher("test").is_some());
+        config.smart_case(true);
+        assert!(config.build_literal_matcher("hello").is_some());
+        assert!(config.build_literal_matcher("Hello").is_none());
+        config
+            .smart_case(false)
+            .case_insensitive(true)
+            .word_regexp(true);
+        assert!(config.build_literal_matcher("hello").is_none());
+    }
+
+    #[test]
+    fn test_literal_matcher_same_as_regex() {
+        let config = || {
+            let mut config = Config::new(1, 2);
+            config.fixed_strings(true).case_insensitive(true);
+            config
+        };
+        let dir = Path::new("testdata").join("chunk");
+        for input in read_all_inputs(&dir) {
+            let path = dir.join(format!("{}.in", input));
+            let contents = fs::read_to_string(&path).unwrap();
+            for pat in ["fn", "Main", "println!(", "*", "= 0;", "impl<", "x"] {
+                assert!(config().build_literal_matcher(pat).is_some(), "{pat:?}");
+                let regex = config().build_regex_matcher(pat).unwrap();
+                let want = Ripgrep::new(regex, config(), ())
+                    .search_str(&contents, path.clone())
+                    .unwrap();
+                let got = search_str(&contents, "", pat, config()).unwrap();
+                assert_eq!(got.len(), want.len(), "{input:?} {pat:?}");
+                for (got, want) in got.iter().zip(want.iter()) {
+                    assert_eq!(got.line_matches, want.line_matches, "{input:?} {pat:?}");
+                    assert_eq!(got.chunks, want.chunks, "{input:?} {pat:?}");
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_fixed_strings() {
         test_ripgrep_config("fixed_string.txt", r"this\sis\stest", |c| {
@@ -891,14 +3473,180 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_case_normalize_display() {
+        let path = Path::new("testdata")
+            .join("ripgrep")
+            .join("case_normalize.txt");
+        for (stream, normalize, expected) in [
+            (
+                false,
+                true,
+                "hello hello hello\nsay hello world\nhelloWorld\n",
+            ),
+            (
+                true,
+                true,
+                "hello hello hello\nsay hello world\nhelloWorld\n",
+            ),
+            (
+                false,
+                false,
+                "Hello HELLO hello\nsay hElLo world\nHelloWorld\n",
+            ),
+        ] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(0, 0);
+            config.case_insensitive(true).stream(stream);
+            if normalize {
+                config.case_normalize_display("hello");
+            }
+
+            let found = grep(&printer, "hello", Some(iter::once(path.as_path())), config).unwrap();
+            assert!(found);
+
+            let files = printer.0.into_inner().unwrap();
+            assert_eq!(files.len(), 1, "{files:?}");
+            assert_eq!(files[0].contents.as_ref(), expected, "{stream} {normalize}");
+        }
+    }
+
+    #[test]
+    fn test_pcre2_ucp() {
+        let path = Path::new("testdata").join("ripgrep").join("pcre2_ucp.txt");
+        // Lines: "Le café est prêt", "naïve approach", "Ärger über Öl", "plain ascii words"
+        for (ucp, no_unicode, pat, expected) in [
+            // \b and \w are Unicode-aware by default
+            (None, false, r"\bcafé\b", vec![(1, vec![(3, 8)])]),
+            (None, false, r"\bcaf\b", vec![]),
+            (None, false, r"\w+ber", vec![(3, vec![(7, 12)])]),
+            (None, false, r"\bna\b", vec![]),
+            (Some(true), false, r"\bcafé\b", vec![(1, vec![(3, 8)])]),
+            // Accented letters are not word characters in ASCII mode
+            (Some(false), false, r"\bcafé\b", vec![]),
+            (Some(false), false, r"\bcaf\b", vec![(1, vec![(3, 6)])]),
+            (Some(false), false, r"\w+ber", vec![]),
+            (Some(false), false, r"\bna\b", vec![(2, vec![(0, 2)])]),
+            (Some(false), false, r"\bwords\b", vec![(4, vec![(12, 17)])]),
+            // The text is still handled as UTF-8 in ASCII mode
+            (Some(false), false, r"caf.\s", vec![(1, vec![(3, 9)])]),
+            // --no-unicode disables Unicode-aware classes unless UCP is explicitly enabled
+            (None, true, r"\bcaf\b", vec![(1, vec![(3, 6)])]),
+            (Some(true), true, r"\bcafé\b", vec![(1, vec![(3, 8)])]),
+            (Some(true), true, r"\bcaf\b", vec![]),
+        ] {
+            let printer = DummyPrinter::default();
+            let mut config = Config::new(0, 0);
+            config.pcre2(true).no_unicode(no_unicode);
+            if let Some(ucp) = ucp {
+                config.pcre2_ucp(ucp);
+            }
+
+            let found = grep(&printer, pat, Some(iter::once(path.as_path())), config).unwrap();
+            assert_eq!(found, !expected.is_empty(), "{pat:?} {ucp:?} {no_unicode}");
+
+            let got: Vec<_> = printer
+                .0
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .flat_map(|f| f.line_matches.into_vec())
+                .map(|m| (m.line_number, m.ranges))
+                .collect();
+            assert_eq!(got, expected, "{pat:?} {ucp:?} {no_unicode}");
+        }
+    }
+
+    #[test]
+    fn test_pcre2_jit_stack() {
+        test_ripgrep_config("pcre2.txt", r"this\sis\stest", |c| {
+            c.pcre2(true).pcre2_jit_stack(64).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_pcre2_no_jit() {
+        test_ripgrep_config("pcre2.txt", r"this\sis\stest", |c| {
+            c.pcre2(true).no_pcre2_jit(true);
+        });
+    }
+
+    #[test]
+    fn test_regex_size_limit() {
+        let pat = (0..1200)
+            .map(|i| format!(r"word{}(?-u:\w{{1000}})", i))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        let config = Config::new(3, 6);
+        let err = config.build_regex_matcher(&pat).unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("exceeds size limit"), "message={:?}", msg);
+
+        let mut config = Config::new(3, 6);
+        config.regex_size_limit("200M").unwrap();
+        config.build_regex_matcher(&pat).unwrap();
+    }
+
+    #[test]
+    fn test_pcre2_jit_stack_size() {
+        let mut config = Config::new(3, 6);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(config.pcre2_jit_stack_size(), Some(10 * 1024 * 1024));
+        config.pcre2_jit_stack(64).unwrap();
+        assert_eq!(config.pcre2_jit_stack_size(), Some(64 * 1024 * 1024));
+        assert!(config.pcre2_jit_stack(usize::MAX).is_err());
+        assert_eq!(config.pcre2_jit_stack_size(), Some(64 * 1024 * 1024));
+        config.no_pcre2_jit(true);
+        assert_eq!(config.pcre2_jit_stack_size(), None);
+    }
+
     #[test]
     fn test_invalid_encoding_error() {
         Config::new(1, 2)
             .encoding("foooooooooooo")
-            .build_searcher()
+            .build_searcher(false)
             .unwrap_err();
     }
 
+    #[test]
+    fn test_encoding_errors() {
+        let path = Path::new("testdata")
+            .join("chunk")
+            .join("encoding")
+            .join("invalid_sjis.txt");
+        let search = |pat: &str, errors: EncodingErrors, stream: bool| {
+            let mut config = Config::new(0, 0);
+            config
+                .encoding("sjis")
+                .encoding_errors(errors)
+                .stream(stream);
+            let printer = DummyPrinter::default();
+            grep(&printer, pat, Some(iter::once(path.as_path())), config)?;
+            Ok::<_, anyhow::Error>(printer.0.into_inner().unwrap())
+        };
+
+        for stream in [false, true] {
+            let files = search("は", EncodingErrors::Replace, stream).unwrap();
+            assert_eq!(files.len(), 1, "{stream}");
+            assert_eq!(files[0].contents.lines().next(), Some("こんにちは\u{fffd}"));
+            let files = search("\u{fffd}", EncodingErrors::Replace, stream).unwrap();
+            assert_eq!(files.len(), 1, "{stream}");
+
+            // The malformed byte is removed on both searching and printing
+            let files = search("は$", EncodingErrors::Skip, stream).unwrap();
+            assert_eq!(files.len(), 1, "{stream}");
+            assert_eq!(files[0].contents.lines().next(), Some("こんにちは"));
+            assert_eq!(files[0].line_matches[0].ranges, [(12, 15)], "{stream}");
+            let files = search("\u{fffd}", EncodingErrors::Skip, stream).unwrap();
+            assert!(files.is_empty(), "{stream}");
+
+            let err = search("world", EncodingErrors::Strict, stream).unwrap_err();
+            let msg = format!("{err:#}");
+            assert!(msg.contains("Could not decode the file"), "{msg:?}");
+        }
+    }
+
     macro_rules! line_regions_tests {
         {$(
             $name:ident(
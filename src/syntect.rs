@@ -1,20 +1,30 @@
 use crate::broken_pipe::IgnoreBrokenPipe as _;
-use crate::chunk::{File, LinesInclusive};
-use crate::printer::{Printer, PrinterOptions, TermColorSupport, TextWrapMode};
+use crate::chunk::{File, LineMatch, LinesInclusive};
+use crate::printer::{
+    chunk_range_label, escape_non_ascii, header_title, snip_line, Layout, PathSeparator, Printer,
+    PrinterOptions, TermColorSupport, TextWrapMode,
+};
 use ansi_colours::ansi256_from_rgb;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use flate2::read::ZlibDecoder;
+use std::borrow::Cow;
 use std::cmp;
+use std::env;
+use std::fs;
 use std::io::{self, Stdout, StdoutLock, Write};
 use std::ops::{Deref, DerefMut};
 use std::path::Path;
+use std::slice;
 use std::str::Chars;
 use syntect::highlighting::{
-    Color, FontStyle, HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
+    Color, FontStyle, HighlightIterator, HighlightState, Highlighter, Theme, ThemeSet,
 };
 use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+// Re-exported for `SyntectPrinter::highlighted_lines`
+pub use syntect::highlighting::Style;
+
 // Note for lifetimes:
 // - 'file is a lifetime for File instance which is passed to print() method
 // - 'main is a lifetime for the scope of main function (the caller of printer)
@@ -44,52 +54,299 @@ impl WriteOnLocked for Stdout {
     }
 }
 
-pub fn list_themes<W: Write>(out: W, opts: &PrinterOptions<'_>) -> Result<()> {
+// Lists the themes with their samples. When `names` is empty, all themes are listed. When `sample` is `None`, the
+// built-in sample is used
+pub fn list_themes<W: Write>(
+    out: W,
+    opts: &PrinterOptions<'_>,
+    names: &[&str],
+    sample: Option<File>,
+) -> Result<()> {
+    let syntaxes = load_syntax_set()?;
+    list_themes_with_syntaxes(out, opts, &syntaxes, names, sample, true)
+}
+
+// Renders only the sample of the theme specified by `opts.theme` without the title so that the output can be converted
+// into a screenshot by tools like `aha`
+pub fn render_sample<W: Write>(
+    out: W,
+    opts: &PrinterOptions<'_>,
+    sample: Option<File>,
+) -> Result<()> {
     let syntaxes = load_syntax_set()?;
-    list_themes_with_syntaxes(out, opts, &syntaxes)
+    let name = opts
+        .theme
+        .unwrap_or_else(|| default_theme_name(opts.color_support));
+    list_themes_with_syntaxes(out, opts, &syntaxes, &[name], sample, false)
+}
+
+/// Reads the file as a sample of themes. When `line` is given, the line is highlighted as matched.
+pub fn sample_file(path: &Path, line: Option<u64>) -> Result<File> {
+    let contents = fs::read(path).with_context(|| format!("Could not read file {:?}", path))?;
+    whole_file(path, contents, line)
+}
+
+fn whole_file(path: &Path, contents: Vec<u8>, line: Option<u64>) -> Result<File> {
+    let contents = String::from_utf8_lossy(&contents).into_owned();
+    let last = cmp::max(LinesInclusive::new(&contents).count(), 1) as u64;
+    let lmats = match line {
+        Some(lnum) if lnum == 0 || last < lnum => anyhow::bail!(
+            "Line {} is out of range of file {:?} which has {} line(s)",
+            lnum,
+            path,
+            last,
+        ),
+        Some(lnum) => vec![LineMatch::lnum(lnum)],
+        None => vec![],
+    };
+    Ok(File::new(
+        path.to_path_buf(),
+        lmats,
+        vec![(1, last)],
+        contents,
+    ))
+}
+
+// Only the file extension and the first line are considered to find the syntax of a custom sample
+fn sample_syntax<'a>(syntaxes: &'a SyntaxSet, file: &File) -> &'a SyntaxReference {
+    let ext = file.path.extension().and_then(|e| e.to_str());
+    ext.and_then(syntax_name_for_extension)
+        .and_then(|name| syntaxes.find_syntax_by_name(name))
+        .or_else(|| ext.and_then(|e| syntaxes.find_syntax_by_extension(e)))
+        .or_else(|| syntaxes.find_syntax_by_first_line(file.first_line()))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
 }
 
 fn list_themes_with_syntaxes<W: Write>(
     mut out: W,
     opts: &PrinterOptions<'_>,
     syntaxes: &SyntaxSet,
+    names: &[&str],
+    sample: Option<File>,
+    title: bool,
 ) -> Result<()> {
     let themes = {
         let mut m = load_bat_themes()?.themes;
         m.extend(ThemeSet::load_defaults().themes);
+        for name in names {
+            if !m.contains_key(*name) {
+                anyhow::bail!(
+                    "Unknown theme {:?}. See --list-themes for the available themes",
+                    name
+                );
+            }
+        }
+        if !names.is_empty() {
+            m.retain(|name, _| names.contains(&name.as_str()));
+        }
         let mut v: Vec<_> = m.into_iter().collect();
         v.sort_by(|l, r| l.0.cmp(&r.0));
+        if let Some(scale) = opts.color_scale {
+            for (_, theme) in v.iter_mut() {
+                scale_theme_colors(theme, scale);
+            }
+        }
         v
     };
 
-    let syntax = syntaxes.find_syntax_by_name("Rust").unwrap();
-    let sample_file = File::sample_file();
+    let (sample_file, syntax) = match sample {
+        Some(mut file) => {
+            if opts.expand_tabs {
+                file.expand_tabs(opts.tab_width);
+            }
+            let syntax = sample_syntax(syntaxes, &file);
+            (file, syntax)
+        }
+        None => (
+            File::sample_file(),
+            syntaxes.find_syntax_by_name("Rust").unwrap(),
+        ),
+    };
 
     themes
         .iter()
         .try_for_each(|(name, theme)| -> Result<()> {
-            let mut drawer = Drawer::new(&mut out, opts, theme, &sample_file.chunks);
-            drawer.canvas.set_bold()?;
-            write!(drawer.canvas, "{:?}", name)?;
-            drawer.canvas.draw_newline()?;
-            drawer.canvas.draw_sample()?;
-            writeln!(drawer.canvas)?;
+            let mut drawer = Drawer::new(&mut out, opts, theme, &sample_file);
+            if title {
+                drawer.canvas.set_bold()?;
+                drawer.draw_theme_name(name)?;
+                drawer.canvas.draw_newline()?;
+                drawer.canvas.draw_sample(drawer.term_width)?;
+                writeln!(drawer.canvas)?;
+            }
 
             let hl = LineHighlighter::new(syntax, theme, syntaxes);
             drawer.draw_file(&sample_file, hl)?;
-            Ok(writeln!(drawer.canvas)?)
+            if title {
+                writeln!(drawer.canvas)?;
+            }
+            Ok(())
         })
         .ignore_broken_pipe()?;
 
     Ok(())
 }
 
+fn default_theme_name(color_support: TermColorSupport) -> &'static str {
+    if color_support == TermColorSupport::Ansi16 {
+        "ansi"
+    } else {
+        "Monokai Extended" // 25bit -> 8bit color conversion works really well with this colorscheme
+    }
+}
+
+// Syntaxes for the file extensions which are not (correctly) mapped by syntect
+fn syntax_name_for_extension(ext: &str) -> Option<&'static str> {
+    match ext {
+        "fs" => Some("F#"),
+        "h" => Some("C++"),
+        "pac" => Some("JavaScript (Babel)"),
+        "nse" => Some("Lua"),
+        "automount" | "device" | "dnssd" | "link" | "mount" | "netdev" | "network" | "nspawn"
+        | "path" | "service" | "scope" | "slice" | "socket" | "swap" | "target" | "timer" => {
+            Some("INI")
+        }
+        "sarif" | "jsonl" => Some("JSON"),
+        "ron" => Some("Rust"),
+        _ => None,
+    }
+}
+
+// Blank lines are squeezed unless they are matched lines or their neighbors
+fn is_squeezable(file: &File, line: &str, lnum: u64) -> bool {
+    if !line.trim_end_matches(['\n', '\r']).is_empty() {
+        return false;
+    }
+    let near = |n: u64| {
+        file.line_matches
+            .binary_search_by_key(&n, |m| m.line_number)
+            .is_ok()
+    };
+    !near(lnum.saturating_sub(1)) && !near(lnum) && !near(lnum + 1)
+}
+
+// Matched regions in a line which are separated by fewer than `merge_gap` characters are merged into one region. When
+// the line still has more than `max_regions` regions, the entire line is highlighted as one region since many small
+// regions are noisy. `regions` must be sorted
+fn coalesce_regions(
+    line: &str,
+    regions: Vec<(usize, usize)>,
+    merge_gap: usize,
+    max_regions: Option<usize>,
+) -> Vec<(usize, usize)> {
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(regions.len());
+    for (start, end) in regions {
+        if let Some(last) = merged.last_mut() {
+            let close = start <= last.1
+                || line
+                    .get(last.1..start)
+                    .is_some_and(|gap| gap.chars().count() < merge_gap);
+            if close {
+                last.1 = cmp::max(last.1, end);
+                continue;
+            }
+        }
+        merged.push((start, end));
+    }
+
+    if max_regions.is_some_and(|max| merged.len() > max) {
+        return vec![(0, line.trim_end_matches(['\n', '\r']).len())];
+    }
+    merged
+}
+
 // Use u64::log10 once it is stabilized: https://github.com/rust-lang/rust/issues/70887
 #[inline]
 fn num_digits(n: u64) -> u16 {
     (n as f64).log10() as u16 + 1
 }
 
+// 1-based position of the character at the byte offset. This differs from the byte offset when multi-byte characters
+// precede the offset
+fn char_position(line: &str, offset: usize) -> usize {
+    line.char_indices().take_while(|&(i, _)| i < offset).count() + 1
+}
+
+fn max_char_position(file: &File) -> usize {
+    let mut max = 0;
+    let mut matched = file.line_matches.iter().peekable();
+    for (line, lnum) in file.lines() {
+        let Some(m) = matched.next_if(|m| m.line_number == lnum) else {
+            if matched.peek().is_none() {
+                break;
+            }
+            continue;
+        };
+        if let Some(&(start, _)) = m.ranges.first() {
+            max = cmp::max(max, char_position(line, start));
+        }
+    }
+    max
+}
+
+// Byte range of the slice containing the matched span and `chars` characters on each side of it. The slice never goes
+// across the line and is always on char boundaries
+fn match_context_window(line: &str, (start, end): (usize, usize), chars: usize) -> (usize, usize) {
+    let end = cmp::min(end, line.len());
+    let start = cmp::min(start, end);
+    let start = line[..start]
+        .char_indices()
+        .rev()
+        .take(chars)
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(start);
+    let end = line[end..]
+        .char_indices()
+        .nth(chars)
+        .map(|(i, _)| end + i)
+        .unwrap_or(line.len());
+    (start, end)
+}
+
+// Tokens cut into the byte range of the line
+fn slice_tokens<'line>(tokens: &[Token<'line>], start: usize, end: usize) -> Vec<Token<'line>> {
+    let mut sliced = vec![];
+    let mut offset = 0;
+    for tok in tokens {
+        let (s, e) = (offset, offset + tok.text.len());
+        offset = e;
+        if e <= start || end <= s {
+            continue;
+        }
+        let text = &tok.text[start.saturating_sub(s)..cmp::min(end, e) - s];
+        sliced.push(Token {
+            style: tok.style,
+            text,
+        });
+    }
+    sliced
+}
+
+// How the syntax of a file was detected. This is reported by --explain-highlight
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SyntaxSource {
+    StdinExt,
+    Mapping,
+    Extension,
+    Shebang,
+    FirstLine,
+    Fallback,
+}
+
+impl SyntaxSource {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::StdinExt => "--stdin-ext",
+            Self::Mapping => "hgrep's mapping for the file extension, name, or path",
+            Self::Extension => "file extension",
+            Self::Shebang => "shebang",
+            Self::FirstLine => "first line like modeline",
+            Self::Fallback => "no match, fallback",
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Token<'line> {
     style: Style,
@@ -243,6 +500,40 @@ fn weak_blend_fg_color(mut fg: Color, bg: Color) -> Color {
     blend_fg_color(fg, bg)
 }
 
+// Multiply each RGB component of the color by `scale` for --color-scale. Special colors for 16 colors and 256 colors
+// themes are not changed since they are not RGB values
+fn scale_color(c: Color, scale: f32) -> Color {
+    if c.a == 0 || c.a == 1 {
+        return c;
+    }
+    let mul = |v: u8| (v as f32 * scale).round().clamp(0.0, 255.0) as u8;
+    Color {
+        r: mul(c.r),
+        g: mul(c.g),
+        b: mul(c.b),
+        a: c.a,
+    }
+}
+
+// Scale all colors in the theme which are used for drawing output
+fn scale_theme_colors(theme: &mut Theme, scale: f32) {
+    let settings = &mut theme.settings;
+    for color in [
+        &mut settings.foreground,
+        &mut settings.background,
+        &mut settings.line_highlight,
+        &mut settings.find_highlight,
+        &mut settings.find_highlight_foreground,
+    ] {
+        *color = color.map(|c| scale_color(c, scale));
+    }
+    for item in theme.scopes.iter_mut() {
+        let style = &mut item.style;
+        style.foreground = style.foreground.map(|c| scale_color(c, scale));
+        style.background = style.background.map(|c| scale_color(c, scale));
+    }
+}
+
 #[derive(Debug)]
 struct Palette {
     foreground: Color,
@@ -339,6 +630,7 @@ struct Canvas<W: Write> {
     out: W,
     true_color: bool,
     has_background: bool,
+    context_bg: Option<Color>,
     palette: Palette,
     current_fg: Option<Color>,
     current_bg: Option<Color>,
@@ -358,16 +650,21 @@ impl<W: Write> DerefMut for Canvas<W> {
 
 impl<W: Write> Canvas<W> {
     fn new(out: W, opts: &PrinterOptions<'_>, theme: &Theme) -> Self {
-        let palette = if opts.color_support == TermColorSupport::Ansi16 {
+        let mut palette = if opts.color_support == TermColorSupport::Ansi16 {
             Palette::ANSI16
         } else {
             Palette::new(theme)
         };
+        // --match-color takes precedence over the theme's line highlight color
+        if let Some(c) = opts.match_color {
+            palette.match_bg = c;
+        }
 
         Self {
             out,
             true_color: opts.color_support == TermColorSupport::True,
             has_background: !palette.is_ansi16() && opts.background_color,
+            context_bg: opts.context_color,
             palette,
             current_fg: None,
             current_bg: None,
@@ -433,13 +730,24 @@ impl<W: Write> Canvas<W> {
         self.set_fg(self.palette.foreground)
     }
 
+    // Background color of tokens in context lines. --context-color takes precedence over the theme's colors
     fn set_background(&mut self, c: Color) -> io::Result<()> {
-        if self.has_background {
+        if let Some(bg) = self.context_bg {
+            self.set_bg(bg)?;
+        } else if self.has_background {
             self.set_bg(c)?;
         }
         Ok(())
     }
 
+    fn set_context_bg(&mut self) -> io::Result<()> {
+        if let Some(bg) = self.context_bg {
+            self.set_bg(bg)
+        } else {
+            self.set_default_bg()
+        }
+    }
+
     fn set_bold(&mut self) -> io::Result<()> {
         self.out.write_all(b"\x1b[1m")?;
         Ok(())
@@ -502,6 +810,12 @@ impl<W: Write> Canvas<W> {
         self.set_bg(self.palette.region_bg)
     }
 
+    // Softer style than matched regions for occurrences of the pattern in context lines
+    fn set_context_region_style(&mut self) -> io::Result<()> {
+        self.set_fg(self.palette.region_bg)?;
+        self.set_underline()
+    }
+
     fn set_gutter_color(&mut self) -> io::Result<()> {
         self.set_fg(self.palette.gutter_fg)?;
         self.set_default_bg()
@@ -531,12 +845,24 @@ impl<W: Write> Canvas<W> {
         Ok(())
     }
 
-    #[rustfmt::skip]
-    fn draw_sample(&mut self) -> io::Result<()> {
-        self.draw_sample_row(&[("Foreground:   ", self.palette.foreground), ("Background:   ", self.palette.background)])?;
-        self.draw_sample_row(&[("MatchLineBG:  ", self.palette.match_bg),   ("MatchLineNum: ", self.palette.match_lnum_fg)])?;
-        self.draw_sample_row(&[("MatchRegionFG:", self.palette.region_fg),  ("MatchRegionBG:", self.palette.region_bg)])?;
-        self.draw_sample_row(&[("GutterFG:     ", self.palette.gutter_fg)])
+    // Draw the palette colors in aligned columns. Fewer columns are used when the terminal is too narrow to put
+    // two colors in one row
+    fn draw_sample(&mut self, term_width: u16) -> io::Result<()> {
+        const COLUMN_WIDTH: u16 = 4 + 14 + 1 + 4; // indent + label + space + color
+        let colors = [
+            ("Foreground:   ", self.palette.foreground),
+            ("Background:   ", self.palette.background),
+            ("MatchLineBG:  ", self.palette.match_bg),
+            ("MatchLineNum: ", self.palette.match_lnum_fg),
+            ("MatchRegionFG:", self.palette.region_fg),
+            ("MatchRegionBG:", self.palette.region_bg),
+            ("GutterFG:     ", self.palette.gutter_fg),
+        ];
+        let per_row = (term_width / COLUMN_WIDTH).clamp(1, 2) as usize;
+        for row in colors.chunks(per_row) {
+            self.draw_sample_row(row)?;
+        }
+        Ok(())
     }
 }
 
@@ -546,6 +872,7 @@ struct LineChars<'a> {
     vertical_and_right: &'a str,
     down_and_horizontal: &'a str,
     up_and_horizontal: &'a str,
+    vertical_and_horizontal: &'a str,
     dashed_horizontal: &'a str,
 }
 
@@ -555,6 +882,7 @@ const UNICODE_LINE_CHARS: LineChars<'static> = LineChars {
     vertical_and_right: "├",
     down_and_horizontal: "┬",
     up_and_horizontal: "┴",
+    vertical_and_horizontal: "┼",
     dashed_horizontal: "╶",
 };
 
@@ -564,6 +892,7 @@ const ASCII_LINE_CHARS: LineChars<'static> = LineChars {
     vertical_and_right: "|",
     down_and_horizontal: "-",
     up_and_horizontal: "-",
+    vertical_and_horizontal: "-",
     dashed_horizontal: "-",
 };
 
@@ -606,25 +935,130 @@ impl<'a> LineHighlighter<'a> {
     }
 }
 
+// Iterator returned from `SyntectPrinter::highlighted_lines`
+pub struct HighlightedLines<'a, 'file> {
+    hl: LineHighlighter<'a>,
+    lines: LinesInclusive<'file>,
+    chunks: slice::Iter<'file, (u64, u64)>,
+    chunk: Option<(u64, u64)>,
+    first_only: bool,
+}
+
+impl<'a, 'file> Iterator for HighlightedLines<'a, 'file> {
+    type Item = Result<(u64, Vec<(Style, &'file str)>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (start, end) = self.chunk?;
+            let (line, lnum) = self.lines.next()?;
+            if lnum < start {
+                if let Err(err) = self.hl.skip_line(line) {
+                    self.chunk = None;
+                    return Some(Err(err));
+                }
+                continue;
+            }
+
+            if lnum == end {
+                self.chunk = if self.first_only {
+                    None
+                } else {
+                    self.chunks.next().copied()
+                };
+            }
+
+            let tokens = match self.hl.highlight(line) {
+                Ok(tokens) => tokens,
+                Err(err) => {
+                    self.chunk = None;
+                    return Some(Err(err));
+                }
+            };
+            let tokens = tokens
+                .into_iter()
+                .filter_map(|mut tok| {
+                    tok.chomp();
+                    (!tok.text.is_empty()).then_some((tok.style, tok.text))
+                })
+                .collect();
+            return Some(Ok((lnum, tokens)));
+        }
+    }
+}
+
+// URL of the file for OSC 8 hyperlinks. Bytes which are not allowed in a URL path are percent-encoded
+fn file_url(path: &Path) -> String {
+    let path = fs::canonicalize(path)
+        .or_else(|_| env::current_dir().map(|dir| dir.join(path)))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let path = path.to_string_lossy();
+    // Convert `\\?\C:\path\to\file` to `/C:/path/to/file`
+    #[cfg(windows)]
+    let path = format!("/{}", path.trim_start_matches(r"\\?\").replace('\\', "/"));
+
+    let mut url = "file://".to_string();
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~:".contains(&b) {
+            url.push(b as char);
+        } else {
+            url.push_str(&format!("%{:02X}", b));
+        }
+    }
+    url
+}
+
 // Drawer is responsible for one-time screen drawing
 struct Drawer<'file, W: Write> {
     grid: bool,
     term_width: u16,
     lnum_width: u16,
+    char_pos_width: u16,
     first_only: bool,
+    squeeze_blank: bool,
+    ruler: bool,
+    chunk_range_header: bool,
     wrap: bool,
     tab_width: u16,
+    file_url: Option<String>,
+    region_merge_gap: usize,
+    max_regions_per_line: Option<usize>,
+    matches_per_line: Option<usize>,
+    monochrome_context: bool,
+    compact: bool,
+    match_context_chars: Option<usize>,
+    layout: Layout,
+    path_separator: PathSeparator,
+    escape_non_ascii_paths: bool,
+    gutter_separator: String,
+    header_marker: Option<String>,
     chars: LineChars<'file>,
     canvas: Canvas<W>,
 }
 
 impl<'file, W: Write> Drawer<'file, W> {
-    fn new(out: W, opts: &PrinterOptions<'_>, theme: &'file Theme, chunks: &[(u64, u64)]) -> Self {
+    fn new(out: W, opts: &PrinterOptions<'_>, theme: &'file Theme, file: &File) -> Self {
+        let chunks = &file.chunks;
         let last_lnum = chunks.last().map(|(_, e)| *e).unwrap_or(0);
         let mut lnum_width = num_digits(last_lnum);
-        if chunks.len() > 1 {
+
+        // Gutter has ':{pos}' after line numbers with --with-character-position
+        let mut char_pos_width = 0;
+        if opts.character_position {
+            let max = max_char_position(file);
+            if max > 0 {
+                char_pos_width = num_digits(max as u64) + 1;
+                lnum_width += char_pos_width;
+            }
+        }
+
+        if chunks.len() > 1 || opts.squeeze_blank {
             lnum_width = cmp::max(lnum_width, 3); // Consider '...' in gutter
         }
+        // - 1 for the left margin of line numbers
+        lnum_width = cmp::max(
+            lnum_width,
+            opts.layout.min_line_number_width().saturating_sub(1),
+        );
 
         let chars = if opts.ascii_lines {
             ASCII_LINE_CHARS
@@ -636,14 +1070,46 @@ impl<'file, W: Write> Drawer<'file, W> {
             grid: opts.grid,
             term_width: opts.term_width,
             lnum_width,
+            char_pos_width,
             wrap: opts.text_wrap == TextWrapMode::Char,
             tab_width: opts.tab_width as u16,
             first_only: opts.first_only,
+            squeeze_blank: opts.squeeze_blank,
+            ruler: opts.ruler,
+            chunk_range_header: opts.chunk_range_header,
+            file_url: opts.gutter_links.then(|| file_url(&file.path)),
+            region_merge_gap: opts.region_merge_gap,
+            max_regions_per_line: opts.max_regions_per_line,
+            matches_per_line: opts.matches_per_line,
+            monochrome_context: opts.monochrome_context,
+            compact: opts.compact,
+            match_context_chars: opts.match_context_chars,
+            layout: opts.layout,
+            path_separator: opts.path_separator,
+            escape_non_ascii_paths: opts.escape_non_ascii_paths,
+            gutter_separator: opts
+                .gutter_separator
+                .map(String::from)
+                .unwrap_or_else(|| chars.vertical.to_string()),
+            header_marker: opts.header_marker.map(String::from),
             chars,
             canvas: Canvas::new(out, opts, theme),
         }
     }
 
+    // Regions to highlight in the matched line. Only the first matches are highlighted with --matches-per-line
+    fn line_regions(&self, line: &str, mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        if let Some(max) = self.matches_per_line {
+            ranges.truncate(max);
+        }
+        coalesce_regions(
+            line,
+            ranges,
+            self.region_merge_gap,
+            self.max_regions_per_line,
+        )
+    }
+
     #[inline]
     fn gutter_width(&self) -> u16 {
         if self.grid {
@@ -653,6 +1119,12 @@ impl<'file, W: Write> Drawer<'file, W> {
         }
     }
 
+    // Width of the text area. This is zero when the gutter does not fit in the terminal due to huge line numbers
+    #[inline]
+    fn body_width(&self) -> usize {
+        self.term_width.saturating_sub(self.gutter_width()) as usize
+    }
+
     fn draw_horizontal_line(&mut self, sep: &str) -> io::Result<()> {
         self.canvas.set_gutter_color()?;
         let gutter_width = self.gutter_width();
@@ -660,27 +1132,63 @@ impl<'file, W: Write> Drawer<'file, W> {
             self.canvas.write_all(self.chars.horizontal.as_bytes())?;
         }
         self.canvas.write_all(sep.as_bytes())?;
-        for _ in 0..self.term_width - gutter_width + 1 {
+        for _ in 0..(self.term_width as usize + 1).saturating_sub(gutter_width as usize) {
             self.canvas.write_all(self.chars.horizontal.as_bytes())?;
         }
         self.canvas.draw_newline()
     }
 
-    fn draw_line_number(&mut self, lnum: u64, matched: bool) -> io::Result<()> {
+    // Theme name is truncated with '…' when it does not fit in the terminal
+    fn draw_theme_name(&mut self, name: &str) -> io::Result<()> {
+        let quoted = format!("{:?}", name);
+        let max_width = self.term_width as usize;
+        if quoted.width_cjk() <= max_width {
+            return self.canvas.write_all(quoted.as_bytes());
+        }
+        let mut width = 1; // for '…'
+        for c in quoted.chars() {
+            width += c.width_cjk().unwrap_or(0);
+            if width > max_width {
+                break;
+            }
+            write!(self.canvas, "{}", c)?;
+        }
+        self.canvas.write_all("…".as_bytes())
+    }
+
+    fn draw_line_number(
+        &mut self,
+        lnum: u64,
+        matched: bool,
+        char_pos: Option<usize>,
+    ) -> io::Result<()> {
         if matched {
             self.canvas.set_match_lnum_color()?;
         } else {
             self.canvas.set_gutter_color()?;
         }
-        let width = num_digits(lnum);
+        let width = num_digits(lnum) + self.char_pos_width;
         self.canvas
             .draw_spaces((self.lnum_width - width) as usize)?;
-        write!(self.canvas, " {}", lnum)?;
+        if let Some(url) = &self.file_url {
+            // OSC 8 hyperlink which opens the file at the line
+            write!(
+                self.canvas,
+                " \x1b]8;;{url}#L{lnum}\x1b\\{lnum}\x1b]8;;\x1b\\"
+            )?;
+        } else {
+            write!(self.canvas, " {}", lnum)?;
+        }
+        if self.char_pos_width > 0 {
+            let pos = char_pos.map(|p| format!(":{}", p)).unwrap_or_default();
+            let width = self.char_pos_width as usize;
+            write!(self.canvas, "{:<width$}", pos, width = width)?;
+        }
         if self.grid {
             if matched {
                 self.canvas.set_gutter_color()?;
             }
-            write!(self.canvas, " {}", self.chars.vertical)?;
+            write!(self.canvas, " {}", self.gutter_separator)?;
         }
         self.canvas.set_default_bg()?;
         self.canvas.write_all(b" ")?;
@@ -691,25 +1199,62 @@ impl<'file, W: Write> Drawer<'file, W> {
         self.canvas.set_gutter_color()?;
         self.canvas.draw_spaces(self.lnum_width as usize + 2)?;
         if self.grid {
-            write!(self.canvas, "{} ", self.chars.vertical)?;
+            write!(self.canvas, "{} ", self.gutter_separator)?;
         }
         Ok(())
     }
 
+    // Column ruler for --ruler aligned with the text area. Every 5th column is marked with '+' and every 10th column is
+    // marked with the last digit of its tens place like "----+----1----+----2"
+    fn draw_ruler(&mut self) -> io::Result<()> {
+        self.draw_wrapping_gutter()?;
+        let width = self.term_width.saturating_sub(self.gutter_width());
+        let ruler: String = (1..=width)
+            .map(|col| match col {
+                c if c % 10 == 0 => char::from_digit(u32::from(c / 10 % 10), 10).unwrap(),
+                c if c % 5 == 0 => '+',
+                _ => '-',
+            })
+            .collect();
+        self.canvas.write_all(ruler.as_bytes())?;
+        self.canvas.draw_newline()
+    }
+
+    // Line range of the chunk for --chunk-range-header like "lines 10–24" aligned with the text area
+    fn draw_chunk_range(&mut self, start: u64, end: u64) -> io::Result<()> {
+        self.draw_wrapping_gutter()?;
+        let range = chunk_range_label(start, end);
+        write!(self.canvas, "{}", range)?;
+        if self.canvas.has_background {
+            let body_width = self.body_width();
+            self.canvas.fill_spaces(range.width_cjk(), body_width)?;
+        }
+        self.canvas.draw_newline()
+    }
+
     fn draw_separator_line(&mut self) -> io::Result<()> {
+        if self.layout == Layout::Unified {
+            return self.draw_snip_line();
+        }
         self.canvas.set_gutter_color()?;
         // + 1 for left margin and - 3 for length of "..."
         let left_margin = self.lnum_width + 1 - 3;
         self.canvas.draw_spaces(left_margin as usize)?;
         let w = if self.grid {
-            write!(self.canvas, "... {}", self.chars.vertical_and_right)?;
+            // The junction is not drawn when the separator is customized by --gutter-separator
+            let junction = if self.gutter_separator == self.chars.vertical {
+                self.chars.vertical_and_right
+            } else {
+                &self.gutter_separator
+            };
+            write!(self.canvas, "... {}", junction)?;
             5
         } else {
             write!(self.canvas, "...")?;
             3
         };
         self.canvas.set_default_bg()?;
-        let body_width = self.term_width - left_margin - w; // This crashes when terminal width is smaller than gutter
+        let body_width = self.term_width.saturating_sub(left_margin + w);
         for _ in 0..body_width {
             self.canvas
                 .write_all(self.chars.dashed_horizontal.as_bytes())?;
@@ -717,15 +1262,71 @@ impl<'file, W: Write> Drawer<'file, W> {
         self.canvas.draw_newline()
     }
 
+    // Separator between snippets with the unified layout. The gutter has '...' and the body is the same as bat's
+    fn draw_snip_line(&mut self) -> io::Result<()> {
+        self.canvas.set_gutter_color()?;
+        // + 1 for left margin and - 3 for length of "..."
+        self.canvas
+            .draw_spaces((self.lnum_width + 1 - 3) as usize)?;
+        write!(self.canvas, "...")?;
+        // bat puts the snip line just after '...' without the grid
+        let mut gutter_width = self.gutter_width() as usize;
+        if self.grid {
+            write!(self.canvas, " {} ", self.gutter_separator)?;
+        } else {
+            gutter_width -= 1;
+        }
+        let snip = snip_line(
+            gutter_width,
+            self.term_width as usize,
+            self.chars.horizontal,
+        );
+        self.canvas.write_all(snip.as_bytes())?;
+        self.canvas.draw_newline()
+    }
+
+    // Draw a run of blank lines squeezed by --squeeze-blank as one blank line with '...' in gutter
+    fn draw_squeezed_line(&mut self) -> io::Result<()> {
+        self.canvas.set_gutter_color()?;
+        // + 1 for left margin and - 3 for length of "..."
+        self.canvas
+            .draw_spaces((self.lnum_width + 1 - 3) as usize)?;
+        write!(self.canvas, "...")?;
+        if self.grid {
+            write!(self.canvas, " {}", self.gutter_separator)?;
+        }
+        self.canvas.set_default_bg()?;
+        self.canvas.write_all(b" ")?;
+        if self.canvas.has_background {
+            let body_width = self.body_width();
+            self.canvas.draw_spaces(body_width)?;
+        }
+        self.canvas.draw_newline()
+    }
+
+    fn draw_blank_lines(
+        &mut self,
+        blanks: &mut Option<(u64, Vec<Token<'_>>, usize)>,
+    ) -> io::Result<()> {
+        match blanks.take() {
+            Some((lnum, tokens, 1)) => self.draw_line(tokens, lnum, None, &[], None),
+            Some(_) => self.draw_squeezed_line(),
+            None => Ok(()),
+        }
+    }
+
     fn draw_text_wrappping(
         &mut self,
         matched: bool,
         style: Style,
         in_region: bool,
+        in_context: bool,
     ) -> io::Result<()> {
         self.canvas.draw_newline()?;
         self.draw_wrapping_gutter()?;
-        if in_region {
+        if in_region && in_context {
+            self.canvas.set_context_region_style()
+        } else if in_region {
             self.canvas.set_region_color()
         } else if matched {
             self.canvas.set_match_style(style)
@@ -739,6 +1340,8 @@ impl<'file, W: Write> Drawer<'file, W> {
         mut tokens: Vec<Token<'_>>,
         lnum: u64,
         regions: Option<Vec<(usize, usize)>>,
+        context_regions: &[(usize, usize)],
+        char_pos: Option<usize>,
     ) -> io::Result<()> {
         // The highlighter requires newline at the end. But we don't want it since
         // - we sometimes need to fill the rest of line with spaces
@@ -750,14 +1353,32 @@ impl<'file, W: Write> Drawer<'file, W> {
             }
         }
 
-        let body_width = (self.term_width - self.gutter_width()) as usize;
+        let body_width = self.body_width();
         let matched = regions.is_some();
 
+        // Tokens are still highlighted for context lines with --no-context-colors since the parse state must be
+        // carried to the following lines
+        if !matched && self.monochrome_context {
+            let plain = Style {
+                foreground: self.canvas.palette.foreground,
+                background: self.canvas.palette.background,
+                font_style: FontStyle::empty(),
+            };
+            for tok in tokens.iter_mut() {
+                tok.style = plain;
+            }
+        }
+
         let tokens = tokens.as_slice();
-        let regions = regions.as_ref().map(AsRef::as_ref).unwrap_or(&[][..]);
+        // Occurrences of the pattern in context lines are drawn with softer style than matched regions
+        let in_context = !matched && !context_regions.is_empty();
+        let regions = regions
+            .as_ref()
+            .map(AsRef::as_ref)
+            .unwrap_or(context_regions);
         let mut events = DrawEvents::new(tokens, regions);
 
-        self.draw_line_number(lnum, matched)?;
+        self.draw_line_number(lnum, matched, char_pos)?;
         if matched {
             self.canvas.set_match_style(events.current_style)?;
         } else if !tokens.is_empty() {
@@ -769,10 +1390,16 @@ impl<'file, W: Write> Drawer<'file, W> {
         loop {
             match events.next_event() {
                 DrawEvent::Char('\t') if self.tab_width > 0 => {
+                    saw_zwj = false; // Tab is not joined with the previous character
                     let w = self.tab_width as usize;
                     if width + w > body_width && self.wrap {
-                        self.canvas.draw_spaces(body_width - width)?;
-                        self.draw_text_wrappping(matched, events.current_style, events.in_region)?;
+                        self.canvas.draw_spaces(body_width.saturating_sub(width))?;
+                        self.draw_text_wrappping(
+                            matched,
+                            events.current_style,
+                            events.in_region,
+                            in_context,
+                        )?;
                         width = 0;
                     } else {
                         self.canvas.draw_spaces(w)?;
@@ -790,9 +1417,15 @@ impl<'file, W: Write> Drawer<'file, W> {
                     } else {
                         c.width_cjk().unwrap_or(0)
                     };
-                    if width + w > body_width && self.wrap {
-                        self.canvas.draw_spaces(body_width - width)?;
-                        self.draw_text_wrappping(matched, events.current_style, events.in_region)?;
+                    // Wrapping an empty row does not help when the character is wider than the text area
+                    if width + w > body_width && width > 0 && self.wrap {
+                        self.canvas.draw_spaces(body_width.saturating_sub(width))?;
+                        self.draw_text_wrappping(
+                            matched,
+                            events.current_style,
+                            events.in_region,
+                            in_context,
+                        )?;
                         width = 0;
                     }
                     write!(self.canvas, "{}", c)?;
@@ -810,9 +1443,16 @@ impl<'file, W: Write> Drawer<'file, W> {
                             .set_font_style(events.current_style.font_style)?;
                     }
                 }
+                DrawEvent::RegionStart if in_context => {
+                    self.canvas.set_context_region_style()?;
+                }
                 DrawEvent::RegionStart => {
                     self.canvas.set_region_color()?;
                 }
+                DrawEvent::RegionEnd if in_context => {
+                    self.canvas.unset_underline()?;
+                    self.canvas.set_style(events.current_style)?;
+                }
                 DrawEvent::RegionEnd => {
                     self.canvas.set_match_style(events.current_style)?;
                 }
@@ -823,9 +1463,9 @@ impl<'file, W: Write> Drawer<'file, W> {
         if matched {
             self.canvas.set_match_bg_color()?;
         } else if width == 0 {
-            self.canvas.set_default_bg()?;
+            self.canvas.set_context_bg()?;
         }
-        if self.canvas.has_background || matched {
+        if self.canvas.has_background || self.canvas.context_bg.is_some() || matched {
             self.canvas.fill_spaces(width, body_width)?;
         }
 
@@ -839,25 +1479,59 @@ impl<'file, W: Write> Drawer<'file, W> {
         let mut chunks = file.chunks.iter();
         let mut chunk = chunks.next().unwrap(); // OK since chunks is not empty
 
-        for (line, lnum) in LinesInclusive::new(&file.contents) {
+        // Consecutive blank lines which are not drawn yet for --squeeze-blank: (first line number, tokens, count)
+        let mut blanks = None;
+
+        for (line, lnum) in file.lines() {
             let (start, end) = *chunk;
             if lnum < start {
                 hl.skip_line(line)?; // Discard parsed result
                 continue;
             }
             if start <= lnum && lnum <= end {
-                let regions = match matched.split_first() {
-                    Some((m, ms)) if m.line_number == lnum => {
-                        matched = ms;
-                        Some(m.ranges.clone()) // XXX: Cannot move out ranges in line match
+                if self.chunk_range_header && lnum == start {
+                    self.draw_chunk_range(start, end)?;
+                }
+                if self.ruler && lnum == start {
+                    self.draw_ruler()?;
+                }
+                if self.squeeze_blank && is_squeezable(file, line, lnum) {
+                    match &mut blanks {
+                        Some((_, _, count)) => {
+                            hl.skip_line(line)?;
+                            *count += 1;
+                        }
+                        None => blanks = Some((lnum, hl.highlight(line)?, 1)),
                     }
-                    _ => None,
-                };
-                // Collect to `Vec` rather than handing HighlightIterator as-is. HighlightIterator takes ownership of Highlighter
-                // while the iteration. When the highlighter is stored in `self`, it means the iterator takes ownership of `self`.
-                self.draw_line(hl.highlight(line)?, lnum, regions)?;
+                } else {
+                    self.draw_blank_lines(&mut blanks)?;
+                    let regions = match matched.split_first() {
+                        Some((m, ms)) if m.line_number == lnum => {
+                            matched = ms;
+                            Some(m.ranges.clone()) // XXX: Cannot move out ranges in line match
+                        }
+                        _ => None,
+                    };
+                    let context_regions = file
+                        .context_matches
+                        .binary_search_by_key(&lnum, |m| m.line_number)
+                        .map(|i| file.context_matches[i].ranges.as_slice())
+                        .unwrap_or(&[]);
+                    let char_pos = match &regions {
+                        Some(r) if self.char_pos_width > 0 => {
+                            r.first().map(|&(start, _)| char_position(line, start))
+                        }
+                        _ => None,
+                    };
+                    let regions = regions.map(|r| self.line_regions(line, r));
+                    // Collect to `Vec` rather than handing HighlightIterator as-is. HighlightIterator takes ownership of Highlighter
+                    // while the iteration. When the highlighter is stored in `self`, it means the iterator takes ownership of `self`.
+                    let tokens = hl.highlight(line)?;
+                    self.draw_line(tokens, lnum, regions, context_regions, char_pos)?;
+                }
 
                 if lnum == end {
+                    self.draw_blank_lines(&mut blanks)?;
                     if self.first_only {
                         break;
                     }
@@ -874,38 +1548,232 @@ impl<'file, W: Write> Drawer<'file, W> {
         Ok(())
     }
 
-    fn draw_header(&mut self, path: &Path) -> io::Result<()> {
-        self.draw_horizontal_line(self.chars.horizontal)?;
-        self.canvas.set_default_bg()?;
-        let path = path.as_os_str().to_string_lossy();
-        self.canvas.set_default_fg()?;
-        self.canvas.set_bold()?;
-        write!(self.canvas, " {}", path)?;
-        if self.canvas.has_background {
-            self.canvas
-                .fill_spaces(path.width_cjk() + 1, self.term_width as usize)?;
+    // Matched line for --compact prefixed with "{path}:{lnum}: ". The gutter and borders are not drawn and long lines
+    // are not wrapped
+    fn draw_compact_line(
+        &mut self,
+        path: &str,
+        mut tokens: Vec<Token<'_>>,
+        lnum: u64,
+        regions: &[(usize, usize)],
+        (leading, trailing): (bool, bool),
+    ) -> io::Result<()> {
+        if let Some(tok) = tokens.last_mut() {
+            tok.chomp();
         }
-        self.canvas.draw_newline()?;
-        if self.grid {
-            self.draw_horizontal_line(self.chars.down_and_horizontal)?;
+
+        self.canvas.set_gutter_color()?;
+        write!(self.canvas, "{}:", path)?;
+        self.canvas.set_match_lnum_color()?;
+        write!(self.canvas, "{}", lnum)?;
+        self.canvas.set_gutter_color()?;
+        self.canvas.write_all(b": ")?;
+        if leading {
+            self.canvas.write_all(b"...")?;
         }
-        Ok(())
+
+        let mut events = DrawEvents::new(&tokens, regions);
+        self.canvas.set_match_style(events.current_style)?;
+        loop {
+            match events.next_event() {
+                DrawEvent::Char('\t') if self.tab_width > 0 => {
+                    self.canvas.draw_spaces(self.tab_width as usize)?;
+                }
+                DrawEvent::Char(c) => write!(self.canvas, "{}", c)?,
+                DrawEvent::TokenBoundary(prev_style) => {
+                    if !events.in_region {
+                        self.canvas.unset_font_style(prev_style.font_style)?;
+                        self.canvas.set_fg(events.current_style.foreground)?;
+                        self.canvas
+                            .set_font_style(events.current_style.font_style)?;
+                    }
+                }
+                DrawEvent::RegionStart => self.canvas.set_region_color()?,
+                DrawEvent::RegionEnd => self.canvas.set_match_style(events.current_style)?,
+                DrawEvent::Done => break,
+            }
+        }
+
+        if trailing {
+            self.canvas.set_gutter_color()?;
+            self.canvas.write_all(b"...")?;
+        }
+        self.canvas.draw_newline()
     }
 
-    fn draw_footer(&mut self) -> io::Result<()> {
-        if self.grid {
-            self.draw_horizontal_line(self.chars.up_and_horizontal)?;
+    // Slices for --only-matching-with-context. Each matched region is drawn with `chars` characters around it on the
+    // same line. Other regions in the slice are also highlighted
+    fn draw_match_contexts(
+        &mut self,
+        path: &str,
+        line: &str,
+        tokens: Vec<Token<'_>>,
+        lnum: u64,
+        regions: &[(usize, usize)],
+        chars: usize,
+    ) -> io::Result<()> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let whole = [(0, line.len())];
+        let spans = if regions.is_empty() {
+            &whole[..]
+        } else {
+            regions
+        };
+        for &span in spans {
+            let (start, end) = match_context_window(line, span, chars);
+            let tokens = slice_tokens(&tokens, start, end);
+            let regions: Vec<_> = regions
+                .iter()
+                .filter(|&&(s, e)| s < end && start < e)
+                .map(|&(s, e)| (cmp::max(s, start) - start, cmp::min(e, end) - start))
+                .collect();
+            let ellipsis = (start > 0, end < line.len());
+            self.draw_compact_line(path, tokens, lnum, &regions, ellipsis)?;
         }
         Ok(())
     }
 
-    fn draw_file(&mut self, file: &File, hl: LineHighlighter) -> Result<()> {
-        self.draw_header(&file.path)?;
-        self.draw_body(file, hl)?;
-        self.draw_footer()?;
-        Ok(())
+    fn display_path<'p>(&self, path: &'p Path) -> Cow<'p, str> {
+        let path = self.path_separator.display(path);
+        if !self.escape_non_ascii_paths {
+            return path;
+        }
+        match path {
+            Cow::Borrowed(path) => escape_non_ascii(path),
+            Cow::Owned(path) => Cow::Owned(escape_non_ascii(&path).into_owned()),
+        }
     }
-}
+
+    // Body for --compact which draws only matched lines. Other lines are still parsed to keep the highlighting state
+    fn draw_compact_body(&mut self, file: &File, mut hl: LineHighlighter<'_>) -> Result<()> {
+        let path = self.display_path(&file.path);
+        let mut matched = file.line_matches.iter().peekable();
+        for (line, lnum) in file.lines() {
+            let Some(m) = matched.next_if(|m| m.line_number == lnum) else {
+                if matched.peek().is_none() {
+                    break;
+                }
+                hl.skip_line(line)?;
+                continue;
+            };
+            let regions = self.line_regions(line, m.ranges.clone());
+            let tokens = hl.highlight(line)?;
+            if let Some(chars) = self.match_context_chars {
+                self.draw_match_contexts(&path, line, tokens, lnum, &regions, chars)?;
+            } else {
+                self.draw_compact_line(&path, tokens, lnum, &regions, (false, false))?;
+            }
+            if self.first_only {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Header with the unified layout. The title is aligned with the text of lines as bat does
+    fn draw_unified_header(&mut self, path: &Path, annotation: Option<&str>) -> io::Result<()> {
+        if self.grid {
+            self.draw_horizontal_line(self.chars.down_and_horizontal)?;
+        } else {
+            self.canvas.draw_newline()?;
+        }
+        self.draw_wrapping_gutter()?;
+        let mut title = header_title(path, annotation, self.path_separator);
+        if self.escape_non_ascii_paths {
+            title = escape_non_ascii(&title).into_owned();
+        }
+        if let Some(marker) = &self.header_marker {
+            title = format!("{} {}", marker, title);
+        }
+        self.canvas.set_default_fg()?;
+        self.canvas.set_bold()?;
+        self.canvas.write_all(title.as_bytes())?;
+        if self.canvas.has_background {
+            let body_width = self.body_width();
+            self.canvas.fill_spaces(title.width_cjk(), body_width)?;
+        }
+        self.canvas.draw_newline()?;
+        if self.grid {
+            self.draw_horizontal_line(self.chars.vertical_and_horizontal)?;
+        }
+        Ok(())
+    }
+
+    fn draw_header(&mut self, path: &Path, annotation: Option<&str>) -> io::Result<()> {
+        if self.layout == Layout::Unified {
+            return self.draw_unified_header(path, annotation);
+        }
+        self.draw_horizontal_line(self.chars.horizontal)?;
+        self.canvas.set_default_bg()?;
+        let path = self.display_path(path);
+        self.canvas.set_default_fg()?;
+        self.canvas.set_bold()?;
+        let mut width = 0;
+        if let Some(marker) = &self.header_marker {
+            write!(self.canvas, " {}", marker)?;
+            width += marker.width_cjk() + 1;
+        }
+        write!(self.canvas, " {}", path)?;
+        width += path.width_cjk() + 1;
+        if let Some(annotation) = annotation {
+            self.canvas.unset_bold()?;
+            self.canvas.set_gutter_color()?;
+            write!(self.canvas, " ({})", annotation)?;
+            width += annotation.width_cjk() + 3;
+        }
+        if self.canvas.has_background {
+            self.canvas.fill_spaces(width, self.term_width as usize)?;
+        }
+        self.canvas.draw_newline()?;
+        if self.grid {
+            self.draw_horizontal_line(self.chars.down_and_horizontal)?;
+        }
+        Ok(())
+    }
+
+    fn draw_footer(&mut self) -> io::Result<()> {
+        if self.grid {
+            self.draw_horizontal_line(self.chars.up_and_horizontal)?;
+        }
+        Ok(())
+    }
+
+    fn draw_file(&mut self, file: &File, hl: LineHighlighter) -> Result<()> {
+        if self.compact || self.match_context_chars.is_some() {
+            return self.draw_compact_body(file, hl);
+        }
+        self.draw_header(&file.path, file.annotation.as_deref())?;
+        self.draw_body(file, hl)?;
+        self.draw_footer()?;
+        Ok(())
+    }
+}
+
+/// Parses a hex RGB color string like "#1a1a2e". The leading '#' is optional.
+///
+/// ```
+/// use hgrep::syntect::parse_hex_color;
+///
+/// let c = parse_hex_color("#1a1a2e").unwrap();
+/// assert_eq!((c.r, c.g, c.b, c.a), (0x1a, 0x1a, 0x2e, 0xff));
+/// assert!(parse_hex_color("#1a1a2").is_err());
+/// ```
+pub fn parse_hex_color(input: &str) -> Result<Color> {
+    let hex = input.strip_prefix('#').unwrap_or(input);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        anyhow::bail!(
+            "Invalid color {:?}. Color must be 6 hex digits of RGB like \"#1a1a2e\"",
+            input,
+        );
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap(); // OK since all digits are hex
+    Ok(Color {
+        r: channel(0),
+        g: channel(2),
+        b: channel(4),
+        a: 0xff,
+    })
+}
 
 fn load_themes(name: Option<&str>) -> Result<ThemeSet> {
     let bat_defaults: ThemeSet = load_bat_themes()?;
@@ -964,19 +1832,30 @@ impl<'main> SyntectPrinter<'main, Stdout> {
 
 impl<'main, W> SyntectPrinter<'main, W> {
     pub fn new(writer: W, opts: PrinterOptions<'main>) -> Result<Self> {
-        Ok(Self {
-            writer,
-            syntaxes: load_syntax_set()?,
-            themes: load_themes(opts.theme)?,
-            opts,
-        })
+        let assets = SyntectAssets {
+            syntax_set: load_syntax_set()?,
+            theme_set: load_themes(opts.theme)?,
+        };
+        let printer = Self::with_assets(assets, writer, opts);
+        if let Some(ext) = printer.opts.syntax_extension {
+            if printer.find_syntax_by_extension(ext).is_none() {
+                anyhow::bail!("Unknown file extension {:?} for --stdin-ext. No syntax is found for the extension", ext);
+            }
+        }
+        Ok(printer)
     }
 
     pub fn with_assets(assets: SyntectAssets, writer: W, opts: PrinterOptions<'main>) -> Self {
+        let mut themes = assets.theme_set;
+        if let Some(scale) = opts.color_scale {
+            for theme in themes.themes.values_mut() {
+                scale_theme_colors(theme, scale);
+            }
+        }
         Self {
             writer,
             syntaxes: assets.syntax_set,
-            themes: assets.theme_set,
+            themes,
             opts,
         }
     }
@@ -985,35 +1864,80 @@ impl<'main, W> SyntectPrinter<'main, W> {
         &mut self.writer
     }
 
+    fn theme_name(&self) -> &'main str {
+        self.opts
+            .theme
+            .unwrap_or_else(|| default_theme_name(self.opts.color_support))
+    }
+
     fn theme(&self) -> &Theme {
-        let name = self.opts.theme.unwrap_or_else(|| {
-            if self.opts.color_support == TermColorSupport::Ansi16 {
-                "ansi"
-            } else {
-                "Monokai Extended" // 25bit -> 8bit color conversion works really well with this colorscheme
-            }
-        });
-        &self.themes.themes[name]
+        &self.themes.themes[self.theme_name()]
+    }
+
+    /// Highlights lines in the chunks of the file without drawing them to the writer. Each item is a pair of line
+    /// number and styled tokens of the line. Trailing newline is not included in the tokens.
+    ///
+    /// ```
+    /// use hgrep::chunk::File;
+    /// use hgrep::printer::PrinterOptions;
+    /// use hgrep::syntect::SyntectPrinter;
+    ///
+    /// let printer = SyntectPrinter::with_stdout(PrinterOptions::default()).unwrap();
+    /// let file = File::sample_file();
+    ///
+    /// let mut lnums = vec![];
+    /// for line in printer.highlighted_lines(&file) {
+    ///     let (lnum, tokens) = line.unwrap();
+    ///     let text: String = tokens.iter().map(|(_, text)| *text).collect();
+    ///     assert!(!text.ends_with('\n'));
+    ///     lnums.push(lnum);
+    /// }
+    /// assert_eq!(lnums, [1, 2, 3, 4, 5, 6, 7]);
+    /// ```
+    pub fn highlighted_lines<'file>(&self, file: &'file File) -> HighlightedLines<'_, 'file> {
+        let theme = self.theme();
+        let syntax = self.find_syntax(file);
+        let mut chunks = file.chunks.iter();
+        let chunk = chunks.next().copied();
+        HighlightedLines {
+            hl: LineHighlighter::new(syntax, theme, &self.syntaxes),
+            lines: file.lines(),
+            chunks,
+            chunk,
+            first_only: self.opts.first_only,
+        }
     }
 
     fn find_syntax(&self, file: &File) -> &SyntaxReference {
+        self.detect_syntax(file)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+
+    // `None` means that no syntax matched to the file and Plain Text should be used as fallback
+    fn find_syntax_by_extension(&self, ext: &str) -> Option<&SyntaxReference> {
+        syntax_name_for_extension(ext)
+            .and_then(|n| self.syntaxes.find_syntax_by_name(n))
+            .or_else(|| self.syntaxes.find_syntax_by_extension(ext))
+    }
+
+    fn detect_syntax(&self, file: &File) -> Option<&SyntaxReference> {
+        self.detect_syntax_with_source(file)
+            .map(|(syntax, _)| syntax)
+    }
+
+    fn detect_syntax_with_source(&self, file: &File) -> Option<(&SyntaxReference, SyntaxSource)> {
+        // --stdin-ext takes precedence over the file path
+        if let Some(ext) = self.opts.syntax_extension {
+            let syntax = self.find_syntax_by_extension(ext)?;
+            return Some((syntax, SyntaxSource::StdinExt));
+        }
+
         let extension = file.path.extension();
         let file_name = file.path.file_name();
 
         let name =
             // Find from file extension
-            extension.and_then(|e| match e.to_str()? {
-                "fs" => Some("F#"),
-                "h" => Some("C++"),
-                "pac" => Some("JavaScript (Babel)"),
-                "nse" => Some("Lua"),
-                "automount" | "device" | "dnssd" | "link" | "mount" | "netdev" | "network"
-                | "nspawn" | "path" | "service" | "scope" | "slice" | "socket" | "swap"
-                | "target" | "timer" => Some("INI"),
-                "sarif" | "jsonl" => Some("JSON"),
-                "ron" => Some("Rust"),
-                _ => None,
-            })
+            extension.and_then(|e| syntax_name_for_extension(e.to_str()?))
             // Find from file name
             .or_else(|| match file_name?.to_str()? {
                 ".clang-format" | "fish_history" => Some("YAML"),
@@ -1068,32 +1992,186 @@ impl<'main, W> SyntectPrinter<'main, W> {
         // Avoid `SyntaxSet::find_syntax_for_file` since it opens the file and reads the first line.
         // (That's why `SyntaxSet::find_syntax_for_file` returns `io::Result`).
         // It is redundant since we already read the file content into `File` struct.
-        name.and_then(|n| self.syntaxes.find_syntax_by_name(n))
-            .or_else(|| self.syntaxes.find_syntax_by_extension(extension?.to_str()?))
-            .or_else(|| self.syntaxes.find_syntax_by_extension(file_name?.to_str()?))
-            .or_else(|| self.syntaxes.find_syntax_by_first_line(file.first_line()))
-            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+        if let Some(syntax) = name.and_then(|n| self.syntaxes.find_syntax_by_name(n)) {
+            return Some((syntax, SyntaxSource::Mapping));
+        }
+        let by_extension = extension
+            .and_then(|e| self.syntaxes.find_syntax_by_extension(e.to_str()?))
+            .or_else(|| self.syntaxes.find_syntax_by_extension(file_name?.to_str()?));
+        if let Some(syntax) = by_extension {
+            return Some((syntax, SyntaxSource::Extension));
+        }
+        let first_line = file.first_line();
+        let syntax = self.syntaxes.find_syntax_by_first_line(first_line)?;
+        let source = if first_line.starts_with("#!") {
+            SyntaxSource::Shebang
+        } else {
+            SyntaxSource::FirstLine
+        };
+        Some((syntax, source))
+    }
+
+    // One line report for --explain-highlight which tells the syntax and the theme used for highlighting the file
+    fn explain_highlight(&self, file: &File) -> String {
+        let (syntax, source) = self.detect_syntax_with_source(file).unwrap_or_else(|| {
+            (
+                self.syntaxes.find_syntax_plain_text(),
+                SyntaxSource::Fallback,
+            )
+        });
+        let theme_source = if self.opts.theme.is_some() {
+            "--theme"
+        } else {
+            "default"
+        };
+        format!(
+            "{}: syntax {:?} (detected by {}), theme {:?} ({})",
+            self.opts.path_separator.display(&file.path),
+            syntax.name,
+            source.describe(),
+            self.theme_name(),
+            theme_source,
+        )
+    }
+
+    // Warnings for --with-diagnostics about fallbacks which happened while printing the file
+    fn diagnostics(&self, file: &File, syntax_found: bool) -> Vec<String> {
+        let path = self.opts.path_separator.display(&file.path);
+        let mut warnings = vec![];
+        if !syntax_found {
+            warnings.push(format!("[WARN] {path}: unknown syntax, using Plain Text"));
+        }
+        // Invalid byte sequences were replaced with U+FFFD on decoding the file
+        if file.contents.contains(char::REPLACEMENT_CHARACTER) {
+            warnings.push(format!(
+                "[WARN] {path}: invalid byte sequences were replaced with U+FFFD, try --encoding"
+            ));
+        }
+        warnings
+    }
+}
+
+// Remove spaces and tabs at the end of each line of the printed output. Escape sequences after them such as resetting
+// colors are kept
+fn strip_trailing_whitespace(buf: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(buf.len());
+    for line in buf.split_inclusive(|&b| b == b'\n') {
+        let (line, newline) = match line.strip_suffix(b"\n") {
+            Some(line) => (line, &b"\n"[..]),
+            None => (line, &b""[..]),
+        };
+        // Escape sequences (ESC '[' ... final byte) and whitespaces after the last visible character
+        let mut escapes = vec![];
+        let mut end = 0;
+        let mut i = 0;
+        while i < line.len() {
+            if line[i] == 0x1b && line.get(i + 1) == Some(&b'[') {
+                let len = line[i + 2..]
+                    .iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map(|p| p + 3)
+                    .unwrap_or(line.len() - i);
+                escapes.push(&line[i..i + len]);
+                i += len;
+            } else if line[i] == b' ' || line[i] == b'\t' {
+                i += 1;
+            } else {
+                i += 1;
+                end = i;
+                escapes.clear();
+            }
+        }
+        stripped.extend_from_slice(&line[..end]);
+        for escape in escapes {
+            stripped.extend_from_slice(escape);
+        }
+        stripped.extend_from_slice(newline);
+    }
+    stripped
+}
+
+impl<'main, W: WriteOnLocked> SyntectPrinter<'main, W> {
+    /// Prints the entire content of the file highlighted with the theme in the options. This is useful to compare how
+    /// themes render real code. No line is highlighted as matched.
+    pub fn preview_theme(&self, path: &Path) -> Result<()> {
+        let contents = fs::read(path)
+            .with_context(|| format!("Could not read file {:?} to preview theme", path))?;
+        self.print_whole_file(path, contents, None)
+    }
+
+    /// Prints the entire content of the file like `cat` command with the same layout as search results. When `line` is
+    /// given, the line is highlighted as matched.
+    pub fn cat(&self, path: &Path, line: Option<u64>) -> Result<()> {
+        let contents = fs::read(path).with_context(|| format!("Could not read file {:?}", path))?;
+        self.print_whole_file(path, contents, line)
+    }
+
+    fn print_whole_file(&self, path: &Path, contents: Vec<u8>, line: Option<u64>) -> Result<()> {
+        let mut file = whole_file(path, contents, line)?;
+        if self.opts.expand_tabs {
+            file.expand_tabs(self.opts.tab_width);
+        }
+
+        let mut buf = vec![];
+        let theme = self.theme();
+        let syntax = self.find_syntax(&file);
+        let hl = LineHighlighter::new(syntax, theme, &self.syntaxes);
+        Drawer::new(&mut buf, &self.opts, theme, &file).draw_file(&file, hl)?;
+        if self.opts.strip_trailing_whitespace {
+            buf = strip_trailing_whitespace(&buf);
+        }
+
+        let mut output = self.writer.lock();
+        output.write_all(&buf).ignore_broken_pipe()?;
+        Ok(output.flush()?)
     }
 }
 
 impl<'main, W: WriteOnLocked> Printer for SyntectPrinter<'main, W> {
-    fn print(&self, file: File) -> Result<()> {
+    fn print(&self, mut file: File) -> Result<()> {
         if file.chunks.is_empty() || file.line_matches.is_empty() {
             return Ok(());
         }
+        if self.opts.explain_highlight {
+            let mut output = self.writer.lock();
+            writeln!(output, "{}", self.explain_highlight(&file)).ignore_broken_pipe()?;
+            return Ok(output.flush()?);
+        }
+        if self.opts.expand_tabs {
+            file.expand_tabs(self.opts.tab_width);
+        }
 
         let mut buf = vec![];
         let theme = self.theme();
-        let syntax = self.find_syntax(&file);
+        let detected = self.detect_syntax(&file);
+        let syntax = detected.unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let warnings = if self.opts.diagnostics {
+            self.diagnostics(&file, detected.is_some())
+        } else {
+            vec![]
+        };
 
         let hl = LineHighlighter::new(syntax, theme, &self.syntaxes);
-        Drawer::new(&mut buf, &self.opts, theme, &file.chunks).draw_file(&file, hl)?;
+        Drawer::new(&mut buf, &self.opts, theme, &file).draw_file(&file, hl)?;
+        if self.opts.strip_trailing_whitespace {
+            buf = strip_trailing_whitespace(&buf);
+        }
 
         // Take lock here to print files in serial from multiple threads
         let mut output = self.writer.lock();
+        // Warnings are printed to stderr while the lock is held so that they are followed by the snippets of the file
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
         output.write_all(&buf).ignore_broken_pipe()?;
         Ok(output.flush()?)
     }
+
+    fn print_header(&self, header: &str) -> Result<()> {
+        let mut output = self.writer.lock();
+        writeln!(output, "\x1b[1m{}\x1b[0m", header).ignore_broken_pipe()?;
+        Ok(output.flush()?)
+    }
 }
 
 #[cfg(test)]
@@ -1233,6 +2311,7 @@ mod tests {
             let mut opts = PrinterOptions {
                 term_width: 80,
                 color_support: TermColorSupport::True,
+                ascii_lines: false,
                 ..Default::default()
             };
             f(&mut opts);
@@ -1406,7 +2485,77 @@ mod tests {
             }),
             test_wrap_between_regions(|_| {}),
             test_wrap_regions_japanese(|_| {}),
+            test_squeeze_blank(|o| {
+                o.squeeze_blank = true;
+            }),
+            test_squeeze_blank_bg(|o| {
+                o.squeeze_blank = true;
+                o.background_color = true;
+            }),
+            test_character_position(|o| {
+                o.character_position = true;
+            }),
+            test_ruler(|o| {
+                o.ruler = true;
+            }),
+            test_context_color(|o| {
+                o.context_color = Some(Color {
+                    r: 0x1a,
+                    g: 0x1a,
+                    b: 0x2e,
+                    a: 0xff,
+                });
+            }),
+            test_match_color(|o| {
+                o.match_color = Some(Color {
+                    r: 0x3a,
+                    g: 0x3a,
+                    b: 0x5c,
+                    a: 0xff,
+                });
+            }),
         );
+
+        #[test]
+        fn test_no_context() {
+            use crate::chunk::Files;
+            use crate::grep::GrepMatch;
+
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let infile = dir.join("no_context.rs");
+            let outfile = dir.join("no_context.out");
+            let contents = fs::read_to_string(&infile).unwrap();
+            let matches: Vec<_> = contents
+                .lines()
+                .enumerate()
+                .filter_map(|(idx, line)| {
+                    let start = line.find("*match to ")?;
+                    let end = line.find(" line*")? + " line*".len();
+                    Some(Ok(GrepMatch {
+                        path: infile.clone(),
+                        line_number: idx as u64 + 1,
+                        ranges: vec![(start, end)],
+                    }))
+                })
+                .collect();
+            let mut files = Files::new(matches.into_iter(), 0, 0, None).unwrap();
+            let file = files.next().unwrap().unwrap();
+            assert_eq!(file.chunks.as_ref(), &[(3, 4), (8, 8), (12, 12)]);
+            run_uitest(file, outfile, |_| {});
+        }
+
+        #[test]
+        fn test_highlight_surrounding_text() {
+            let dir = Path::new(".").join("testdata").join("syntect");
+            let infile = dir.join("highlight_surrounding_text.rs");
+            let outfile = dir.join("highlight_surrounding_text.out");
+            let mut file = read_chunks(infile);
+            // Only the first match is reported as matched line like `--max-count 1`
+            let mut lmats = file.line_matches.into_vec();
+            file.context_matches = lmats.split_off(1).into_boxed_slice();
+            file.line_matches = lmats.into_boxed_slice();
+            run_uitest(file, outfile, |_| {});
+        }
     }
 
     // Separate module from `ui` since pretty_assertions is too slow for showing diff between byte sequences.
@@ -1427,12 +2576,14 @@ mod tests {
             let mut opts = PrinterOptions {
                 term_width: 80,
                 color_support: TermColorSupport::True,
+                ascii_lines: false,
                 ..Default::default()
             };
             f(&mut opts);
 
             let mut got = vec![];
-            list_themes_with_syntaxes(&mut got, &opts, &ASSETS.syntax_set).unwrap();
+            list_themes_with_syntaxes(&mut got, &opts, &ASSETS.syntax_set, &[], None, true)
+                .unwrap();
 
             assert_eq!(
                 expected,
@@ -1462,6 +2613,14 @@ mod tests {
             test_background(|o| {
                 o.background_color = true;
             }),
+            test_narrow(|o| {
+                o.term_width = 40;
+            }),
+            test_narrow_ascii_no_wrap(|o| {
+                o.term_width = 40;
+                o.ascii_lines = true;
+                o.text_wrap = TextWrapMode::Never;
+            }),
         }
     }
 
@@ -1536,6 +2695,875 @@ mod tests {
         assert!(msg.contains("Unknown theme"), "message={:?}", msg);
     }
 
+    #[test]
+    fn test_parse_hex_color() {
+        for (input, want) in [
+            ("#1a1a2e", (0x1a, 0x1a, 0x2e)),
+            ("1a1a2e", (0x1a, 0x1a, 0x2e)),
+            ("#FFffFF", (0xff, 0xff, 0xff)),
+            ("#000000", (0, 0, 0)),
+        ] {
+            let c = parse_hex_color(input).unwrap();
+            assert_eq!(
+                (c.r, c.g, c.b, c.a),
+                (want.0, want.1, want.2, 0xff),
+                "{input:?}"
+            );
+        }
+
+        for input in [
+            "",
+            "#",
+            "#1a1a2",
+            "#1a1a2e0",
+            "#1a1a2g",
+            "##1a1a2e",
+            "#ｱa1a2e",
+        ] {
+            let err = parse_hex_color(input).unwrap_err();
+            let msg = format!("{}", err);
+            assert!(msg.contains("Invalid color"), "{input:?}: {msg:?}");
+        }
+    }
+
+    #[test]
+    fn test_preview_theme() {
+        let path = Path::new("testdata").join("syntect").join("default.rs");
+        let preview = |theme| {
+            let opts = PrinterOptions {
+                theme: Some(theme),
+                color_support: TermColorSupport::True,
+                ascii_lines: false,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.preview_theme(&path).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        for theme in ["Nord", "Dracula"] {
+            let printed = preview(theme);
+
+            // The entire file is printed with line numbers
+            let contents = fs::read_to_string(&path).unwrap();
+            let lines = contents.lines().count();
+            assert!(printed.contains("default.rs"), "{printed:?}");
+            assert!(printed.contains(&format!("{lines} │")), "{printed:?}");
+
+            // The foreground color of the theme is used for the text
+            let fg = ASSETS.theme_set.themes[theme].settings.foreground.unwrap();
+            let seq = format!("\x1b[38;2;{};{};{}m", fg.r, fg.g, fg.b);
+            assert!(printed.contains(&seq), "{seq:?} is not in {printed:?}");
+        }
+        assert_ne!(preview("Nord"), preview("Dracula"));
+    }
+
+    #[test]
+    fn test_preview_theme_file_not_found() {
+        let stdout = DummyStdout::default();
+        let printer =
+            SyntectPrinter::with_assets(ASSETS.clone(), stdout, PrinterOptions::default());
+        let err = printer
+            .preview_theme(Path::new("this-file-does-not-exist.rs"))
+            .unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("Could not read file"), "message={:?}", msg);
+    }
+
+    #[test]
+    fn test_cat() {
+        let path = Path::new("testdata").join("syntect").join("default.rs");
+        let cat = |line| {
+            let opts = PrinterOptions {
+                color_support: TermColorSupport::True,
+                ascii_lines: false,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.cat(&path, line).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().count();
+        let plain = cat(None);
+        assert!(plain.contains("default.rs"), "{plain:?}");
+        assert!(plain.contains(&format!("{lines} │")), "{plain:?}");
+
+        // Only the given line is painted as matched
+        let highlighted = cat(Some(3));
+        assert_ne!(plain, highlighted);
+        assert_eq!(plain.lines().count(), highlighted.lines().count());
+        let diff: Vec<_> = plain
+            .lines()
+            .zip(highlighted.lines())
+            .filter(|(l, r)| l != r)
+            .collect();
+        assert_eq!(diff.len(), 1, "{diff:?}");
+    }
+
+    #[test]
+    fn test_cat_line_out_of_range() {
+        let path = Path::new("testdata").join("syntect").join("default.rs");
+        let lines = fs::read_to_string(&path).unwrap().lines().count() as u64;
+        for line in [0, lines + 1] {
+            let stdout = DummyStdout::default();
+            let printer =
+                SyntectPrinter::with_assets(ASSETS.clone(), stdout, PrinterOptions::default());
+            let err = printer.cat(&path, Some(line)).unwrap_err();
+            let msg = format!("{}", err);
+            assert!(msg.contains("is out of range of file"), "message={:?}", msg);
+        }
+    }
+
+    #[test]
+    fn test_list_themes_with_names_and_sample() {
+        let opts = PrinterOptions {
+            color_support: TermColorSupport::True,
+            ..Default::default()
+        };
+        let list = |names: &[&str], sample| {
+            let mut out = vec![];
+            list_themes_with_syntaxes(&mut out, &opts, &ASSETS.syntax_set, names, sample, true)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let all = list(&[], None);
+        let some = list(&["Nord", "OneHalfDark"], None);
+        assert!(some.contains("Nord"), "{some:?}");
+        assert!(some.contains("OneHalfDark"), "{some:?}");
+        assert!(!some.contains("Monokai Extended"), "{some:?}");
+        assert!(some.len() < all.len());
+
+        let path = Path::new("testdata").join("syntect").join("default.rs");
+        let sample = sample_file(&path, Some(3)).unwrap();
+        let custom = list(&["Nord"], Some(sample));
+        assert!(custom.contains("default.rs"), "{custom:?}");
+        assert_ne!(custom, list(&["Nord"], None));
+
+        let mut out = vec![];
+        let err = list_themes_with_syntaxes(
+            &mut out,
+            &opts,
+            &ASSETS.syntax_set,
+            &["Unknown Theme"],
+            None,
+            true,
+        )
+        .unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("Unknown theme"), "message={:?}", msg);
+    }
+
+    #[test]
+    fn test_render_sample_without_title() {
+        let opts = PrinterOptions {
+            color_support: TermColorSupport::True,
+            ..Default::default()
+        };
+        let mut listed = vec![];
+        list_themes_with_syntaxes(
+            &mut listed,
+            &opts,
+            &ASSETS.syntax_set,
+            &["Monokai Extended"],
+            None,
+            true,
+        )
+        .unwrap();
+        let mut rendered = vec![];
+        list_themes_with_syntaxes(
+            &mut rendered,
+            &opts,
+            &ASSETS.syntax_set,
+            &["Monokai Extended"],
+            None,
+            false,
+        )
+        .unwrap();
+        let (listed, rendered) = (
+            String::from_utf8(listed).unwrap(),
+            String::from_utf8(rendered).unwrap(),
+        );
+        assert!(!rendered.is_empty());
+        assert!(!rendered.contains("Monokai Extended"), "{rendered:?}");
+        assert!(
+            listed.contains(rendered.trim_end()),
+            "{listed:?} vs {rendered:?}"
+        );
+    }
+
+    #[test]
+    fn test_print_annotation_in_header() {
+        let mut file = File::sample_file();
+        file.annotation = Some("modified".to_string());
+        let opts = PrinterOptions {
+            color_support: TermColorSupport::True,
+            ascii_lines: false,
+            ..Default::default()
+        };
+        let stdout = DummyStdout::default();
+        let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+        printer.print(file).unwrap();
+        let printed = mem::take(printer.writer_mut()).0.into_inner();
+        let printed = String::from_utf8(printed).unwrap();
+        let header = printed.lines().nth(1).unwrap();
+        let path = header.find(" sample.rs").unwrap();
+        let annotation = header.find(" (modified)").unwrap();
+        assert!(path < annotation, "header: {header:?}");
+    }
+
+    #[test]
+    fn test_escape_non_ascii_paths_in_header() {
+        for layout in [Layout::Native, Layout::Unified] {
+            let mut file = File::sample_file();
+            file.path = Path::new("café").join("sample.rs");
+            let opts = PrinterOptions {
+                color_support: TermColorSupport::True,
+                escape_non_ascii_paths: true,
+                layout,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.print(file).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            let printed = String::from_utf8(printed).unwrap();
+            assert!(printed.contains("caf\\u{e9}"), "{layout:?}: {printed:?}");
+            assert!(!printed.contains("café"), "{layout:?}: {printed:?}");
+        }
+    }
+
+    #[test]
+    fn test_coalesce_regions() {
+        let line = "a, b, c,d  e ああ い\n";
+        for (regions, gap, max, want) in [
+            (vec![], 2, None, vec![]),
+            (vec![(0, 1)], 2, None, vec![(0, 1)]),
+            // Separated by ", " (2 characters) is not merged with the default gap
+            (
+                vec![(0, 1), (3, 4), (6, 7)],
+                2,
+                None,
+                vec![(0, 1), (3, 4), (6, 7)],
+            ),
+            (vec![(0, 1), (3, 4), (6, 7)], 3, None, vec![(0, 7)]),
+            // Separated by "," (1 character)
+            (vec![(6, 7), (8, 9)], 2, None, vec![(6, 9)]),
+            // Adjacent and overlapping regions are always merged
+            (vec![(0, 2), (2, 3), (3, 5)], 0, None, vec![(0, 5)]),
+            (vec![(0, 4), (1, 2), (3, 6)], 0, None, vec![(0, 6)]),
+            (vec![(6, 7), (8, 9)], 0, None, vec![(6, 7), (8, 9)]),
+            (vec![(6, 7), (8, 9)], 1, None, vec![(6, 7), (8, 9)]),
+            // Gap is counted in characters, not in bytes. " ああ " is 4 characters and 8 bytes
+            (vec![(11, 12), (20, 23)], 5, None, vec![(11, 23)]),
+            (vec![(11, 12), (20, 23)], 4, None, vec![(11, 12), (20, 23)]),
+            (vec![(13, 16), (16, 19), (20, 23)], 2, None, vec![(13, 23)]),
+            // Fall back to the entire line (without the newline) when too many regions remain after merging
+            (
+                vec![(0, 1), (3, 4), (6, 7)],
+                2,
+                Some(3),
+                vec![(0, 1), (3, 4), (6, 7)],
+            ),
+            (vec![(0, 1), (3, 4), (6, 7)], 2, Some(2), vec![(0, 23)]),
+            (vec![(0, 1), (3, 4), (6, 7)], 3, Some(2), vec![(0, 7)]),
+            (vec![(0, 1)], 2, Some(0), vec![(0, 23)]),
+        ] {
+            let got = coalesce_regions(line, regions.clone(), gap, max);
+            assert_eq!(got, want, "regions={regions:?} gap={gap} max={max:?}");
+        }
+    }
+
+    #[test]
+    fn test_gutter_links_for_grep_output() {
+        use crate::grep::BufReadExt;
+
+        // Links are made from paths and line numbers parsed from grep output read from stdin
+        let input = "testdata/cli/input/calc.rs:7:        _ => None,\n\
+                     testdata/cli/input/parse.rs:4:    let op = it.next()?;\n";
+        let opts = PrinterOptions {
+            gutter_links: true,
+            color_support: TermColorSupport::True,
+            ..Default::default()
+        };
+        let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+        for file in input
+            .as_bytes()
+            .grep_lines()
+            .chunks_per_file(1, 1, None)
+            .unwrap()
+        {
+            printer.print(file.unwrap()).unwrap();
+        }
+        let printed = mem::take(printer.writer_mut()).0.into_inner();
+        let printed = String::from_utf8(printed).unwrap();
+
+        for (path, lnum) in [
+            ("testdata/cli/input/calc.rs", 7),
+            ("testdata/cli/input/parse.rs", 4),
+        ] {
+            let url = file_url(Path::new(path));
+            assert!(url.starts_with("file:///"), "{url:?}");
+            assert!(url.ends_with(path), "{url:?}");
+            let link = format!(" \x1b]8;;{url}#L{lnum}\x1b\\{lnum}\x1b]8;;\x1b\\");
+            assert!(printed.contains(&link), "{link:?} is not in {printed:?}");
+        }
+    }
+
+    #[test]
+    fn test_gutter_links() {
+        let print = |gutter_links| {
+            let opts = PrinterOptions {
+                gutter_links,
+                color_support: TermColorSupport::True,
+                ascii_lines: false,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.print(File::sample_file()).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        let printed = print(true);
+        let url = file_url(Path::new("sample.rs"));
+        assert!(url.starts_with("file:///"), "{url:?}");
+        assert!(url.ends_with("/sample.rs"), "{url:?}");
+        for lnum in 1..=7 {
+            let link = format!(" \x1b]8;;{url}#L{lnum}\x1b\\{lnum}\x1b]8;;\x1b\\");
+            assert!(printed.contains(&link), "{link:?} is not in {printed:?}");
+        }
+        assert_eq!(printed.matches("\x1b]8;;").count(), 7 * 2, "{printed:?}");
+
+        assert!(!print(false).contains("\x1b]8;"));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let opts = PrinterOptions::default();
+        let printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+        for (path, contents, want) in [
+            ("foo.rs", "fn main() {}\n", &[][..]),
+            ("foo.txt", "hello\n", &[][..]),
+            // Detected from the first line
+            ("foo", "#!/bin/bash\n", &[][..]),
+            (
+                "foo.xyz",
+                "hello\n",
+                &["[WARN] foo.xyz: unknown syntax, using Plain Text"][..],
+            ),
+            (
+                "foo.rs",
+                "// \u{fffd}\n",
+                &["[WARN] foo.rs: invalid byte sequences were replaced with U+FFFD, try --encoding"][..],
+            ),
+        ] {
+            let lmats = vec![LineMatch::lnum(1)];
+            let file = File::new(PathBuf::from(path), lmats, vec![(1, 1)], contents.to_string());
+            let found = printer.detect_syntax(&file).is_some();
+            assert_eq!(printer.diagnostics(&file, found), want, "{path:?} {contents:?}");
+        }
+    }
+
+    #[test]
+    fn test_monochrome_context() {
+        let print = |monochrome_context| {
+            let opts = PrinterOptions {
+                monochrome_context,
+                color_support: TermColorSupport::True,
+                ascii_lines: true,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.print(File::sample_file()).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+        // Number of distinct foreground colors in the body of the line which contains the text
+        let num_colors = |printed: &str, text: &str| {
+            let line = printed.lines().find(|l| l.contains(text)).unwrap();
+            let (_, body) = line.split_once('|').unwrap();
+            let mut colors: Vec<_> = body
+                .split("\x1b[")
+                .filter(|s| s.starts_with("38;2;"))
+                .map(|s| s.split_once('m').unwrap().0)
+                .collect();
+            colors.sort_unstable();
+            colors.dedup();
+            colors.len()
+        };
+
+        let context = "print_sqrt";
+        let matched = "result";
+        let printed = print(false);
+        assert!(num_colors(&printed, context) > 1, "{printed:?}");
+        assert!(num_colors(&printed, matched) > 1, "{printed:?}");
+
+        let printed = print(true);
+        assert_eq!(num_colors(&printed, context), 1, "{printed:?}");
+        assert!(num_colors(&printed, matched) > 1, "{printed:?}");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_file_url() {
+        for (path, want) in [
+            ("/path/to/file.rs", "file:///path/to/file.rs"),
+            (
+                "/path/with space/#hash%.rs",
+                "file:///path/with%20space/%23hash%25.rs",
+            ),
+            (
+                "/path/to/ファイル.rs",
+                "file:///path/to/%E3%83%95%E3%82%A1%E3%82%A4%E3%83%AB.rs",
+            ),
+        ] {
+            assert_eq!(file_url(Path::new(path)), want, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn test_ruler_aligns_with_text() {
+        let contents = "fn main() {\n\tlet x = 1;\n}\n".to_string();
+        let lmats = vec![LineMatch::new(2, vec![(5, 6)])];
+        let file = File::new(PathBuf::from("test.rs"), lmats, vec![(1, 3)], contents);
+        let opts = PrinterOptions {
+            ruler: true,
+            tab_width: 4,
+            color_support: TermColorSupport::True,
+            term_width: 40,
+            ascii_lines: false,
+            ..Default::default()
+        };
+        let stdout = DummyStdout::default();
+        let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+        printer.print(file).unwrap();
+        let printed = mem::take(printer.writer_mut()).0.into_inner();
+        let printed = String::from_utf8(printed).unwrap();
+
+        // Remove escape sequences to compare the columns of the visible characters
+        let mut stripped = String::new();
+        let mut in_escape = false;
+        for c in printed.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if in_escape => {}
+                c => stripped.push(c),
+            }
+        }
+        let lines: Vec<Vec<char>> = stripped.lines().map(|l| l.chars().collect()).collect();
+
+        let ruler = lines.iter().find(|l| l.contains(&'+')).unwrap();
+        assert_eq!(ruler.len(), 40, "ruler: {ruler:?}");
+        let ruler_start = ruler.iter().position(|&c| c == '-').unwrap();
+        let first = lines.iter().find(|l| l.contains(&'{')).unwrap();
+        assert_eq!(first.iter().position(|&c| c == 'f'), Some(ruler_start));
+
+        // The tab is expanded to 4 spaces so 'l' of 'let' is put at the 5th column, which is marked with '+'
+        let second = lines.iter().find(|l| l.contains(&'=')).unwrap();
+        let let_col = second.iter().position(|&c| c == 'l').unwrap();
+        assert_eq!(let_col - ruler_start, 4);
+        assert_eq!(ruler[let_col], '+');
+    }
+
+    #[test]
+    fn test_chunk_range_header() {
+        let contents: String = (1..=20).map(|i| format!("let x{i} = {i};\n")).collect();
+        let lmats = vec![
+            LineMatch::new(3, vec![(4, 6)]),
+            LineMatch::new(14, vec![(4, 7)]),
+        ];
+        let chunks = vec![(1, 5), (12, 16)];
+        let file = File::new(PathBuf::from("test.rs"), lmats, chunks, contents);
+        let print = |chunk_range_header| {
+            let opts = PrinterOptions {
+                chunk_range_header,
+                color_support: TermColorSupport::True,
+                background_color: true,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.print(file.clone()).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        let printed = print(true);
+        let lines: Vec<_> = printed.lines().collect();
+        for (start, end) in &file.chunks {
+            let header = format!("lines {start}–{end}");
+            let i = lines.iter().position(|l| l.contains(&header)).unwrap();
+            // The header is put just before the first line of the chunk
+            let first = format!(" {start} ");
+            assert!(lines[i + 1].contains(&first), "{:?}", lines[i + 1]);
+        }
+        assert_eq!(printed.matches("lines ").count(), 2, "{printed:?}");
+
+        assert!(!print(false).contains("lines "));
+    }
+
+    #[test]
+    fn test_color_scale() {
+        let c = |r, g, b, a| Color { r, g, b, a };
+        assert_eq!(scale_color(c(100, 200, 50, 255), 0.5), c(50, 100, 25, 255));
+        assert_eq!(scale_color(c(100, 200, 50, 128), 1.5), c(150, 255, 75, 128));
+        assert_eq!(scale_color(c(100, 200, 50, 255), 0.0), c(0, 0, 0, 255));
+        // Special colors of 16 colors and 256 colors themes are not RGB values
+        assert_eq!(scale_color(c(3, 0, 0, 0), 0.5), c(3, 0, 0, 0));
+        assert_eq!(scale_color(c(0, 0, 0, 1), 2.0), c(0, 0, 0, 1));
+
+        let print = |color_scale| {
+            let file = File::sample_file();
+            let opts = PrinterOptions {
+                color_scale,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            let fg = printer.theme().settings.foreground.unwrap();
+            printer.print(file).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            (fg, String::from_utf8(printed).unwrap())
+        };
+
+        let (fg, printed) = print(None);
+        let (scaled_fg, scaled) = print(Some(0.5));
+        assert_eq!(scaled_fg, scale_color(fg, 0.5));
+        let escape = |c: Color| format!("\x1b[38;2;{};{};{}m", c.r, c.g, c.b);
+        assert!(printed.contains(&escape(fg)), "{printed:?}");
+        assert!(!scaled.contains(&escape(fg)), "{scaled:?}");
+        assert!(scaled.contains(&escape(scaled_fg)), "{scaled:?}");
+    }
+
+    #[test]
+    fn test_compact() {
+        let contents: String = (1..=20).map(|i| format!("let x{i} = {i};\n")).collect();
+        let lmats = vec![
+            LineMatch::new(3, vec![(4, 6)]),
+            LineMatch::new(4, vec![(4, 6)]),
+            LineMatch::new(14, vec![(4, 7)]),
+        ];
+        let chunks = vec![(1, 7), (11, 17)];
+        let file = File::new(PathBuf::from("test.rs"), lmats, chunks, contents);
+        let opts = PrinterOptions {
+            compact: true,
+            color_support: TermColorSupport::True,
+            ..Default::default()
+        };
+        let stdout = DummyStdout::default();
+        let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+        printer.print(file).unwrap();
+        let palette = Palette::new(printer.theme());
+        let printed = mem::take(printer.writer_mut()).0.into_inner();
+        let printed = String::from_utf8(printed).unwrap();
+
+        // Remove escape sequences like "\x1b[38;2;1;2;3m" to check the text
+        let mut text = String::new();
+        let mut in_escape = false;
+        for c in printed.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if in_escape => {}
+                c => text.push(c),
+            }
+        }
+        let want = "test.rs:3: let x3 = 3;\ntest.rs:4: let x4 = 4;\ntest.rs:14: let x14 = 14;\n";
+        assert_eq!(text, want);
+
+        // Each matched line is highlighted and its matched region is colored
+        let Color { r, g, b, .. } = palette.region_bg;
+        let region = format!("\x1b[48;2;{r};{g};{b}m");
+        for line in printed.lines() {
+            let (_, body) = line.split_once(": ").unwrap();
+            assert!(body.contains("\x1b[38;2;"), "{line:?}");
+            assert!(body.contains(&region), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn test_match_context_window() {
+        let line = "pub fn foo(bar: i32) -> i32 { bar }";
+        let (s, e) = match_context_window(line, (11, 14), 3);
+        assert_eq!(&line[s..e], "oo(bar: i");
+        // The window is cut at the start and end of the line
+        let (s, e) = match_context_window(line, (0, 3), 5);
+        assert_eq!(&line[s..e], "pub fn f");
+        let (s, e) = match_context_window(line, (30, 33), 10);
+        assert_eq!(&line[s..e], " -> i32 { bar }");
+        // No context
+        let (s, e) = match_context_window(line, (11, 14), 0);
+        assert_eq!(&line[s..e], "bar");
+        // Multi-byte characters are counted as one character
+        let line = "あいうえおMATCHかきくけこ";
+        let (s, e) = match_context_window(line, (15, 20), 2);
+        assert_eq!(&line[s..e], "えおMATCHかき");
+    }
+
+    #[test]
+    fn test_only_matching_with_context() {
+        let contents = "fn main() {\n    let answer = compute(42) + compute(43);\n}\n";
+        let lmats = vec![LineMatch::new(2, vec![(17, 24), (31, 38)])];
+        let file = File::new(
+            PathBuf::from("test.rs"),
+            lmats,
+            vec![(1, 3)],
+            contents.into(),
+        );
+        let opts = PrinterOptions {
+            match_context_chars: Some(4),
+            color_support: TermColorSupport::True,
+            ..Default::default()
+        };
+        let stdout = DummyStdout::default();
+        let mut printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+        printer.print(file).unwrap();
+        let printed = mem::take(printer.writer_mut()).0.into_inner();
+        let printed = String::from_utf8(printed).unwrap();
+
+        let mut text = String::new();
+        let mut in_escape = false;
+        for c in printed.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if in_escape => {}
+                c => text.push(c),
+            }
+        }
+        let want = "test.rs:2: ...r = compute(42)...\ntest.rs:2: ...) + compute(43)...\n";
+        assert_eq!(text, want);
+    }
+
+    #[test]
+    fn test_strip_trailing_whitespace() {
+        for (input, want) in [
+            ("", ""),
+            ("abc\n", "abc\n"),
+            ("abc   \n", "abc\n"),
+            ("abc \t\x1b[0m\n", "abc\x1b[0m\n"),
+            (
+                "\x1b[48;5;1mabc  \x1b[48;5;2m  \x1b[0m\n",
+                "\x1b[48;5;1mabc\x1b[48;5;2m\x1b[0m\n",
+            ),
+            ("a b\x1b[0m c  \n  \n", "a b\x1b[0m c\n\n"),
+            ("  indent\nno newline  ", "  indent\nno newline"),
+        ] {
+            let have = strip_trailing_whitespace(input.as_bytes());
+            assert_eq!(String::from_utf8(have).unwrap(), want, "input={input:?}");
+        }
+    }
+
+    #[test]
+    fn test_strip_trailing_whitespace_in_output() {
+        let print = |strip_trailing_whitespace| {
+            let file = File::new(
+                PathBuf::from("test.rs"),
+                vec![LineMatch::lnum(2)],
+                vec![(1, 3)],
+                "fn f() {\n    let x = 1;  \n}\n".into(),
+            );
+            let opts = PrinterOptions {
+                strip_trailing_whitespace,
+                background_color: true,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let mut printer =
+                SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            printer.print(file).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        // Remove SGR sequences to see the text on the terminal
+        let plain = |line: &str| {
+            let mut plain = String::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                if c == '\x1b' {
+                    chars.by_ref().find(|&c| c == 'm');
+                } else {
+                    plain.push(c);
+                }
+            }
+            plain
+        };
+
+        let padded = print(false);
+        assert!(
+            padded.lines().any(|l| plain(l).ends_with(' ')),
+            "{padded:?}"
+        );
+        let stripped = print(true);
+        for line in stripped.lines() {
+            assert!(!plain(line).ends_with([' ', '\t']), "{line:?}");
+        }
+        assert!(
+            stripped.lines().any(|l| plain(l).ends_with("let x = 1;")),
+            "{stripped:?}"
+        );
+        assert_eq!(stripped.lines().count(), padded.lines().count());
+    }
+
+    #[test]
+    fn test_gutter_separator() {
+        let print = |gutter_separator| {
+            let file = File::new(
+                PathBuf::from("test.rs"),
+                vec![LineMatch::lnum(2), LineMatch::lnum(9)],
+                vec![(1, 3), (8, 10)],
+                "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n".into(),
+            );
+            let opts = PrinterOptions {
+                gutter_separator,
+                ascii_lines: false,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let mut printer =
+                SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            printer.print(file).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        let default = print(None);
+        let custom = print(Some('┃'));
+        assert!(!default.contains('┃'), "{default:?}");
+
+        // Each line of the snippets and the separator between them has the glyph after the gutter
+        let mut plain = String::new();
+        let mut chars = custom.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm'); // Skip SGR sequence
+            } else {
+                plain.push(c);
+            }
+        }
+        let lines: Vec<_> = plain.lines().filter(|l| l.contains('┃')).collect();
+        assert_eq!(lines.len(), 7, "{custom:?}");
+        for lnum in [1, 2, 3, 8, 9, 10] {
+            let gutter = format!("{lnum} ┃ ");
+            assert!(
+                lines.iter().any(|l| l.contains(&gutter)),
+                "{gutter:?} in {custom:?}"
+            );
+        }
+        assert!(lines.iter().any(|l| l.contains("... ┃")), "{plain:?}");
+        assert_eq!(custom.replace('┃', "│").replace("... │", "... ├"), default);
+    }
+
+    #[test]
+    fn test_header_marker() {
+        let print = |header_marker, layout, compact| {
+            let file = File::new(
+                PathBuf::from("test.rs"),
+                vec![LineMatch::lnum(2)],
+                vec![(1, 3)],
+                "a\nb\nc\n".into(),
+            );
+            let opts = PrinterOptions {
+                header_marker,
+                layout,
+                compact,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let mut printer =
+                SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            printer.print(file).unwrap();
+            let printed = mem::take(printer.writer_mut()).0.into_inner();
+            String::from_utf8(printed).unwrap()
+        };
+
+        for layout in [Layout::Native, Layout::Unified] {
+            let default = print(None, layout, false);
+            assert!(!default.contains('⏺'), "{default:?}");
+            let marked = print(Some("⏺"), layout, false);
+            assert!(marked.contains("⏺ test.rs"), "{marked:?}");
+            assert_eq!(marked.matches('⏺').count(), 1, "{marked:?}");
+            assert_eq!(marked.replace("⏺ ", ""), default);
+            let ascii = print(Some("==>"), layout, false);
+            assert!(ascii.contains("==> test.rs"), "{ascii:?}");
+        }
+
+        // Compact layout has no header
+        let compact = print(Some("⏺"), Layout::Native, true);
+        assert!(!compact.contains('⏺'), "{compact:?}");
+        assert_eq!(compact, print(None, Layout::Native, true));
+    }
+
+    #[test]
+    fn test_matches_per_line() {
+        let print = |ranges: Vec<(usize, usize)>, matches_per_line| {
+            let contents = "let a = b + c + d;\n";
+            let lmats = vec![LineMatch::new(1, ranges)];
+            let file = File::new(
+                PathBuf::from("test.rs"),
+                lmats,
+                vec![(1, 1)],
+                contents.into(),
+            );
+            let opts = PrinterOptions {
+                matches_per_line,
+                region_merge_gap: 0,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let mut printer =
+                SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            printer.print(file).unwrap();
+            mem::take(printer.writer_mut()).0.into_inner()
+        };
+
+        let all = vec![(4, 5), (8, 9), (12, 13), (16, 17)];
+        assert_eq!(
+            print(all.clone(), Some(2)),
+            print(vec![(4, 5), (8, 9)], None)
+        );
+        assert_eq!(print(all.clone(), Some(10)), print(all.clone(), None));
+        assert_ne!(print(all.clone(), Some(1)), print(all, None));
+    }
+
+    #[test]
+    fn test_gutter_wider_than_terminal() {
+        // Found by fuzzing. Drawing lines panicked when the gutter left no room for wide characters
+        for (lnum, want_rows) in [(10000, 2), (1000000, 2)] {
+            let mut file = File::new(
+                PathBuf::from("test.txt"),
+                vec![LineMatch::lnum(lnum)],
+                vec![(lnum, lnum)],
+                "ああ\n".to_string(),
+            );
+            file.line_offset = lnum - 1;
+            let opts = PrinterOptions {
+                term_width: 10,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let stdout = DummyStdout::default();
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), stdout, opts);
+            printer.print(file).unwrap();
+            let printed = printer.writer.0.into_inner();
+            let printed = String::from_utf8(printed).unwrap();
+            let rows = printed.lines().filter(|l| l.contains('あ')).count();
+            assert_eq!(rows, want_rows, "{printed:?}");
+        }
+    }
+
     #[test]
     fn test_print_nothing() {
         let file = File::new(PathBuf::from("x.txt"), vec![], vec![], String::new());
@@ -1609,6 +3637,9 @@ mod tests {
             ErrorStdoutLock(io::ErrorKind::Other),
             &opts,
             &ASSETS.syntax_set,
+            &[],
+            None,
+            true,
         )
         .unwrap_err();
         assert_eq!(&format!("{}", err), "dummy error!", "message={}", err);
@@ -1621,6 +3652,9 @@ mod tests {
             ErrorStdoutLock(io::ErrorKind::BrokenPipe),
             &opts,
             &ASSETS.syntax_set,
+            &[],
+            None,
+            true,
         )
         .unwrap();
     }
@@ -1682,6 +3716,86 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_find_syntax_from_stdin_ext() {
+        let tests = [
+            ("rs", "foo.txt", "Rust"),
+            ("ron", "foo", "Rust"),
+            ("c", "#!/bin/bash", "C"),
+            ("fs", "Makefile", "F#"),
+        ];
+
+        for (ext, path, name) in tests {
+            let opts = PrinterOptions {
+                syntax_extension: Some(ext),
+                ..Default::default()
+            };
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            let file = File::new(PathBuf::from(path), vec![], vec![], path.to_string());
+            let syntax = printer.find_syntax(&file);
+            assert_eq!(syntax.name, name, "ext={ext:?} path={path:?}");
+        }
+
+        let opts = PrinterOptions {
+            syntax_extension: Some("foooooooo"),
+            ..Default::default()
+        };
+        let err = SyntectPrinter::new(DummyStdout::default(), opts)
+            .err()
+            .unwrap();
+        assert!(format!("{err}").contains("\"foooooooo\""), "{err}");
+    }
+
+    #[test]
+    fn test_explain_highlight() {
+        let tests = [
+            (
+                "testdata/syntect/default.rs",
+                None,
+                r#"syntax "Rust" (detected by file extension), theme "Monokai Extended" (default)"#,
+            ),
+            (
+                "config.ron",
+                None,
+                r#"syntax "Rust" (detected by hgrep's mapping for the file extension, name, or path), theme "Monokai Extended" (default)"#,
+            ),
+            (
+                "script",
+                Some("Nord"),
+                r#"syntax "Bourne Again Shell (bash)" (detected by shebang), theme "Nord" (--theme)"#,
+            ),
+            (
+                "foo.unknown",
+                None,
+                r#"syntax "Plain Text" (detected by no match, fallback), theme "Monokai Extended" (default)"#,
+            ),
+        ];
+
+        for (path, theme, want) in tests {
+            let opts = PrinterOptions {
+                explain_highlight: true,
+                theme,
+                color_support: TermColorSupport::True,
+                ..Default::default()
+            };
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            // Files other than the fixture don't exist. Only the script has a shebang
+            let contents = fs::read_to_string(path).unwrap_or_else(|_| match path {
+                "script" => "#!/bin/bash\necho hi\n".into(),
+                _ => "foo\nbar\n".into(),
+            });
+            let file = File::new(
+                PathBuf::from(path),
+                vec![LineMatch::lnum(1)],
+                vec![(1, 2)],
+                contents,
+            );
+            printer.print(file).unwrap();
+            let printed = String::from_utf8(printer.writer.0.into_inner()).unwrap();
+            assert_eq!(printed, format!("{path}: {want}\n"));
+        }
+    }
+
     #[test]
     fn test_find_syntax_from_first_line() {
         let tests = [
@@ -1705,4 +3819,40 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_highlighted_lines() {
+        let contents = (1..=10)
+            .map(|i| format!("let x{i} = {i};\n"))
+            .collect::<String>();
+        let file = File::new(
+            PathBuf::from("test.rs"),
+            vec![LineMatch::lnum(3), LineMatch::lnum(8)],
+            vec![(2, 4), (7, 9)],
+            contents,
+        );
+
+        for (first_only, want) in [(false, &[2, 3, 4, 7, 8, 9][..]), (true, &[2, 3, 4][..])] {
+            let opts = PrinterOptions {
+                first_only,
+                ..Default::default()
+            };
+            let printer = SyntectPrinter::with_assets(ASSETS.clone(), DummyStdout::default(), opts);
+            let lines = printer
+                .highlighted_lines(&file)
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+
+            let lnums: Vec<_> = lines.iter().map(|(lnum, _)| *lnum).collect();
+            assert_eq!(lnums, want, "first_only={first_only}");
+            for (lnum, tokens) in lines {
+                let text: String = tokens.iter().map(|(_, text)| *text).collect();
+                assert_eq!(text, format!("let x{lnum} = {lnum};"));
+                assert!(
+                    tokens.len() > 1,
+                    "line {lnum} is not highlighted: {tokens:?}"
+                );
+            }
+        }
+    }
 }
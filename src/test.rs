@@ -3,7 +3,8 @@ use crate::grep::GrepMatch;
 use anyhow::Result;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
 
 pub(crate) fn read_matches<S: AsRef<str>>(dir: &Path, input: S) -> Vec<Result<GrepMatch>> {
     let path = dir.join(format!("{}.in", input.as_ref()));
@@ -76,9 +77,21 @@ pub(crate) fn read_all_expected_chunks<S: AsRef<str>>(dir: &Path, inputs: &[S])
         .collect()
 }
 
-#[derive(Default)]
+// Environment variables are shared by all tests running in parallel. The lock serializes tests which modify them
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
 pub(crate) struct EnvGuard {
     saved: Vec<(String, Option<String>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Default for EnvGuard {
+    fn default() -> Self {
+        Self {
+            saved: vec![],
+            _lock: ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner()),
+        }
+    }
 }
 
 impl EnvGuard {
@@ -109,3 +122,20 @@ impl Drop for EnvGuard {
         }
     }
 }
+
+// Temporary directory which is removed even when the test fails
+pub(crate) struct TempDir(pub(crate) PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("hgrep-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,13 @@
+// TODO: Support more operators
+fn calc(op: char, lhs: i64, rhs: i64) -> Option<i64> {
+    match op {
+        '+' => Some(lhs + rhs),
+        '-' => Some(lhs - rhs),
+        '*' => Some(lhs * rhs),
+        _ => None, // TODO: Report an error instead of returning None when an unknown operator is given by users
+    }
+}
+
+fn main() {
+    println!("{:?}", calc('+', 1, 2));
+}
@@ -0,0 +1,7 @@
+fn parse(input: &str) -> Option<(i64, char, i64)> {
+    let mut it = input.split_whitespace();
+    let lhs = it.next()?.parse().ok()?;
+    let op = it.next()?.chars().next()?; // TODO: Reject multiple characters
+    let rhs = it.next()?.parse().ok()?;
+    Some((lhs, op, rhs))
+}
@@ -0,0 +1 @@
+// skipped file test
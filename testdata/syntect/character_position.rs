@@ -0,0 +1,10 @@
+fn main() {
+    println!("*match to ascii line*");
+    // 日本語のコメント *match to japanese line*
+    let s = "ok";
+    let emoji = "🐶🐱 *match to emoji line*";
+    for _ in 0..10 {
+        // loop body
+    }
+    let wide = "ＡＢＣ ＤＥＦ ＧＨＩ ＪＫＬ ＭＮＯ ＰＱＲ ＳＴＵ ＶＷＸ ＹＺ *match to wide wrapped line*";
+}
@@ -0,0 +1,12 @@
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn main() {
+	let p = Point { x: 1, y: 2 };
+    println!("{} {}", p.x, "*match to this line*");
+
+    // This is a long context line which should be wrapped and whose background should be painted with the given color
+    println!("{}", p.y);
+}
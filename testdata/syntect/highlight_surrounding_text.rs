@@ -0,0 +1,6 @@
+fn main() {
+    println!("*match to this line*");
+    let s = "*match to context line*";
+    //                         (width considering line number) 80 cols -> |
+    let t = ["*match to context line*", "*match to oooooo line*", "*match to wrapped line*"];
+}
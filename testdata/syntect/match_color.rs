@@ -0,0 +1,12 @@
+fn sum(values: &[i32]) -> i32 {
+    let mut total = 0;
+    for v in values {
+        total += v; // *match to this line*
+    }
+    total
+}
+
+fn main() {
+    // This is a long line whose whole background is painted with the given color: "*match to this line*"
+    println!("{}", sum(&[1, 2, 3]));
+}
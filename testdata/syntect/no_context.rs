@@ -0,0 +1,13 @@
+fn main() {
+    let x = 1;
+    println!("*match to this line*");
+    println!("*match to this line*");
+    let y = 2;
+    foo();
+
+    let z = "*match to this line*";
+    bar();
+    let w = 3;
+    baz();
+    // *match to this line*
+}
@@ -0,0 +1,13 @@
+// Fixed-width records
+const RECORDS: &[&str] = &[
+    "0001ALICE     TOKYO     ",
+    "0002BOB       OSAKA     ",
+    "0003CAROL     *match to this line*",
+    "0004DAVE      KYOTO     ",
+];
+
+fn main() {
+    for r in RECORDS {
+        println!("{}", r);
+    }
+}
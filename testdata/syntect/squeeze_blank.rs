@@ -0,0 +1,13 @@
+fn main() {
+    let x = 1;
+
+
+    let y = 2;
+    println!("*match to this line*");
+
+    let z = 3;
+
+
+
+    foo();
+}
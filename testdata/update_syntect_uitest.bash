@@ -81,11 +81,21 @@ env -u COLORTERM "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-wi
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_between_regions.rs         > ./testdata/syntect/wrap_between_regions.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_accross_regions.rs         > ./testdata/syntect/wrap_accross_regions.out
 "$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80                           ./testdata/syntect/wrap_regions_japanese.rs        > ./testdata/syntect/wrap_regions_japanese.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --squeeze-blank           ./testdata/syntect/squeeze_blank.rs                > ./testdata/syntect/squeeze_blank.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --squeeze-blank --background ./testdata/syntect/squeeze_blank_bg.rs          > ./testdata/syntect/squeeze_blank_bg.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 -m 1 --highlight-surrounding-text ./testdata/syntect/highlight_surrounding_text.rs          > ./testdata/syntect/highlight_surrounding_text.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --with-character-position ./testdata/syntect/character_position.rs          > ./testdata/syntect/character_position.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --no-context ./testdata/syntect/no_context.rs          > ./testdata/syntect/no_context.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --ruler ./testdata/syntect/ruler.rs          > ./testdata/syntect/ruler.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --context-color '#1a1a2e' ./testdata/syntect/context_color.rs          > ./testdata/syntect/context_color.out
+"$HGREP" '\*match to .+? line\*' -c 6 -C 6 -p syntect --term-width 80 --match-color '#3a3a5c' ./testdata/syntect/match_color.rs          > ./testdata/syntect/match_color.out
 
 # Test for --list-themes
 "$HGREP" --list-themes -p syntect --term-width 80              > ./testdata/syntect/list_themes_default.out
 "$HGREP" --list-themes -p syntect --term-width 80 --no-grid    > ./testdata/syntect/list_themes_no_grid.out
 "$HGREP" --list-themes -p syntect --term-width 80 --background > ./testdata/syntect/list_themes_background.out
+"$HGREP" --list-themes -p syntect --term-width 40              > ./testdata/syntect/list_themes_narrow.out
+"$HGREP" --list-themes -p syntect --term-width 40 --ascii-lines --wrap never > ./testdata/syntect/list_themes_narrow_ascii_no_wrap.out
 
 # Previews
 cat ./testdata/syntect/ansi16_colors.out
@@ -152,7 +162,17 @@ cat ./testdata/syntect/multi_regions_bg.out
 cat ./testdata/syntect/wrap_between_regions.out
 cat ./testdata/syntect/wrap_accross_regions.out
 cat ./testdata/syntect/wrap_regions_japanese.out
+cat ./testdata/syntect/squeeze_blank.out
+cat ./testdata/syntect/squeeze_blank_bg.out
+cat ./testdata/syntect/highlight_surrounding_text.out
+cat ./testdata/syntect/character_position.out
+cat ./testdata/syntect/no_context.out
+cat ./testdata/syntect/ruler.out
+cat ./testdata/syntect/context_color.out
+cat ./testdata/syntect/match_color.out
 
 cat ./testdata/syntect/list_themes_default.out
 cat ./testdata/syntect/list_themes_no_grid.out
 cat ./testdata/syntect/list_themes_background.out
+cat ./testdata/syntect/list_themes_narrow.out
+cat ./testdata/syntect/list_themes_narrow_ascii_no_wrap.out
@@ -0,0 +1,1006 @@
+// Integration tests which run the built hgrep binary and compare its outputs with the golden files in testdata/cli/golden.
+// Run the tests with UPDATE_GOLDEN=1 to regenerate the golden files.
+
+// Some helpers are used only by the tests for searching files with the builtin ripgrep
+#![cfg_attr(not(feature = "ripgrep"), allow(dead_code))]
+
+use assert_cmd::cargo::CommandCargoExt as _;
+use std::env;
+use std::fs;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+const INPUT_DIR: &str = "testdata/cli/input";
+const CALC_RS: &str = "testdata/cli/input/calc.rs";
+const STDIN_INPUT: &str = "testdata/cli/stdin.txt";
+
+// Environment variables and options which affect the output are pinned so that the outputs don't depend on the terminal
+// running the tests
+fn hgrep_default_printer() -> Command {
+    let mut cmd = Command::cargo_bin("hgrep").unwrap();
+    cmd.env_remove("HGREP_DEFAULT_OPTS")
+        .env_remove("HGREP_PRINTER")
+        .env_remove("LC_ALL")
+        .env_remove("LC_CTYPE")
+        .env("LANG", "en_US.UTF-8")
+        .env("TERM", "xterm-256color")
+        .env("COLORTERM", "truecolor")
+        .args(["--term-width", "80", "--theme", "Nord"])
+        .stdin(Stdio::null());
+    cmd
+}
+
+fn hgrep(printer: &str) -> Command {
+    let mut cmd = hgrep_default_printer();
+    cmd.args(["--printer", printer]);
+    cmd
+}
+
+// Run the command with piping the input to its stdin
+fn run_with_stdin(cmd: &mut Command, input: &[u8]) -> Output {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn run_with_stdin_file(cmd: &mut Command, path: &str) -> Output {
+    let input = fs::read(path).unwrap();
+    run_with_stdin(cmd, &input)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// Paths in outputs are separated with '\' on Windows. Fixtures must not contain '\' so that the golden files can be
+// shared across platforms
+fn normalize(output: &[u8]) -> Vec<u8> {
+    if cfg!(windows) {
+        output
+            .iter()
+            .map(|&b| if b == b'\\' { b'/' } else { b })
+            .collect()
+    } else {
+        output.to_vec()
+    }
+}
+
+fn check_golden(name: &str, actual: &[u8]) {
+    let path = Path::new("testdata").join("cli").join("golden").join(name);
+    let actual = normalize(actual);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read(&path).unwrap_or_else(|err| {
+        panic!("Could not read golden file {path:?}: {err}. Run tests with UPDATE_GOLDEN=1 to create it")
+    });
+    assert!(
+        expected == actual,
+        "Output does not match to golden file {:?}. Run tests with UPDATE_GOLDEN=1 to update it\n\
+         --- expected ---\n{}\n--- actual ---\n{}",
+        path,
+        String::from_utf8_lossy(&expected),
+        String::from_utf8_lossy(&actual),
+    );
+}
+
+fn check_success(name: &str, output: &Output) {
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "stderr: {:?}",
+        stderr(output)
+    );
+    assert_eq!(stderr(output), "");
+    check_golden(name, &output.stdout);
+}
+
+fn check_error(name: &str, output: &Output) {
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "stdout: {:?}",
+        stdout(output)
+    );
+    assert_eq!(stdout(output), "");
+    check_golden(name, &output.stderr);
+}
+
+#[cfg(all(feature = "ripgrep", feature = "syntect-printer"))]
+mod syntect_printer {
+    use super::*;
+
+    #[test]
+    fn search_dir_sorted() {
+        let output = hgrep("syntect")
+            .args(["-c", "2", "-C", "4", "--sort-by-match-count", "desc"])
+            .args(["TODO", INPUT_DIR])
+            .output()
+            .unwrap();
+        check_success("syntect_search_dir_sorted.out", &output);
+    }
+
+    #[test]
+    fn wrap_never() {
+        let output = hgrep("syntect")
+            .args(["--wrap", "never", "TODO", CALC_RS])
+            .output()
+            .unwrap();
+        check_success("syntect_wrap_never.out", &output);
+    }
+
+    #[test]
+    fn narrow_term_width() {
+        let output = hgrep("syntect")
+            .args(["--term-width", "40", "TODO", CALC_RS])
+            .output()
+            .unwrap();
+        check_success("syntect_narrow_term_width.out", &output);
+    }
+
+    #[test]
+    fn no_match() {
+        let output = hgrep("syntect")
+            .args(["this pattern does not match", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(1),
+            "stderr: {:?}",
+            stderr(&output)
+        );
+        assert_eq!(stdout(&output), "");
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let output = hgrep("syntect").args(["(", CALC_RS]).output().unwrap();
+        check_error("error_invalid_regex.err", &output);
+    }
+
+    #[test]
+    fn invalid_regex_as_json() {
+        let output = hgrep("syntect")
+            .args(["--report-errors-as-json", "(", CALC_RS])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(stdout(&output), "");
+        let stderr = stderr(&output);
+        assert_eq!(stderr.lines().count(), 1, "{stderr:?}");
+        let json: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+        let msg = json["error"].as_str().unwrap();
+        assert!(msg.starts_with("regex parse error:"), "{msg:?}");
+        assert_eq!(json["kind"], "InvalidPattern");
+    }
+}
+
+mod self_test {
+    use super::*;
+
+    fn check_self_test(printer: &str) {
+        let output = hgrep(printer).arg("--self-test").output().unwrap();
+        assert!(output.status.success(), "stdout: {:?}", stdout(&output));
+        let out = stdout(&output);
+        assert!(out.contains("read grep results from stdin ... "), "{out:?}");
+        #[cfg(feature = "ripgrep")]
+        assert!(
+            out.contains("search files with builtin ripgrep ... "),
+            "{out:?}"
+        );
+        assert!(!out.contains("FAILED"), "{out:?}");
+    }
+
+    #[cfg(feature = "syntect-printer")]
+    #[test]
+    fn syntect_printer() {
+        check_self_test("syntect");
+    }
+
+    #[cfg(feature = "bat-printer")]
+    #[test]
+    fn bat_printer() {
+        check_self_test("bat");
+    }
+}
+
+#[cfg(feature = "syntect-printer")]
+mod no_match {
+    use super::*;
+
+    // Empty grep results piped to stdin
+    const NO_MATCH_INPUT: &[u8] = b"";
+
+    #[test]
+    fn default_exit_status_without_message() {
+        let output = run_with_stdin(hgrep("syntect").arg("--stdin"), NO_MATCH_INPUT);
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(stdout(&output), "");
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[test]
+    fn custom_exit_status_and_message() {
+        let output = run_with_stdin(
+            hgrep("syntect").args([
+                "--stdin",
+                "--no-match-exit",
+                "0",
+                "--no-match-message",
+                "No TODO found",
+            ]),
+            NO_MATCH_INPUT,
+        );
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), "");
+        assert_eq!(stderr(&output), "No TODO found\n");
+    }
+
+    #[test]
+    fn quiet_no_match_cancels_message() {
+        let output = run_with_stdin(
+            hgrep("syntect")
+                .env("HGREP_DEFAULT_OPTS", "--no-match-message 'nothing matched'")
+                .args(["--stdin", "--quiet-no-match", "--no-match-exit", "3"]),
+            NO_MATCH_INPUT,
+        );
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn search_files() {
+        let output = hgrep("syntect")
+            .args(["--no-match-exit", "0", "--no-match-message", "none"])
+            .args(["this pattern does not match", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), "");
+        assert_eq!(stderr(&output), "none\n");
+
+        // The options don't affect the exit status when something matches
+        let output = hgrep("syntect")
+            .args(["--no-match-exit", "3", "--no-match-message", "none"])
+            .args(["TODO", CALC_RS])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn hint_files_exceeding_max_filesize() {
+        // Only the file skipped by --max-filesize contains the pattern
+        let args = [
+            "--max-filesize",
+            "100",
+            "padding line",
+            "testdata/ripgrep/skipped",
+        ];
+        let output = hgrep("syntect").args(args).output().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(stdout(&output), "");
+        assert_eq!(
+            stderr(&output),
+            "\x1b[1;96mhint:\x1b[0m 1 file(s) exceeded --max-filesize; re-run with a larger limit?\n",
+        );
+
+        let output = hgrep("syntect")
+            .arg("--no-hints")
+            .args(args)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[test]
+    fn invalid_exit_status() {
+        let output = run_with_stdin(
+            hgrep("syntect").args(["--stdin", "--no-match-exit", "256"]),
+            NO_MATCH_INPUT,
+        );
+        assert_eq!(output.status.code(), Some(2));
+        let err = stderr(&output);
+        assert!(err.contains("--no-match-exit"), "{err:?}");
+    }
+}
+
+#[cfg(feature = "syntect-printer")]
+mod paginate {
+    use super::*;
+
+    // The prompt is never shown since stdout is not a terminal while running tests
+    #[test]
+    fn ignored_when_stdout_is_not_terminal() {
+        let want = run_with_stdin_file(hgrep("syntect").arg("--stdin"), STDIN_INPUT);
+        let output = run_with_stdin_file(
+            hgrep("syntect").args(["--stdin", "--paginate", "1"]),
+            STDIN_INPUT,
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+        assert_eq!(stderr(&output), "");
+    }
+}
+
+#[cfg(feature = "syntect-printer")]
+mod syntect_stdin {
+    use super::*;
+
+    #[test]
+    fn read_grep_results() {
+        let output =
+            run_with_stdin_file(hgrep("syntect").args(["-c", "1", "-C", "2"]), STDIN_INPUT);
+        check_success("syntect_stdin.out", &output);
+    }
+
+    // Windows paths are not converted into file:// URIs here
+    #[cfg(not(windows))]
+    #[test]
+    fn read_lsp_references() {
+        let input: Vec<_> = fs::read_to_string(STDIN_INPUT)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with(CALC_RS))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert!(!input.is_empty());
+        let uri = format!(
+            "file://{}",
+            env::current_dir().unwrap().join(CALC_RS).display()
+        );
+        let locations: Vec<_> = input
+            .iter()
+            .map(|line| {
+                let lnum: u64 = line.split(':').nth(1).unwrap().parse().unwrap();
+                let line = lnum - 1;
+                format!(r#"{{"uri":"{uri}","range":{{"start":{{"line":{line},"character":0}},"end":{{"line":{line},"character":1}}}}}}"#)
+            })
+            .collect();
+        let response = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"result":[{}]}}"#,
+            locations.join(",")
+        );
+
+        let want = run_with_stdin(&mut hgrep("syntect"), input.concat().as_bytes());
+        assert!(want.status.success(), "stderr: {:?}", stderr(&want));
+        let output = run_with_stdin(
+            hgrep("syntect").arg("--experimental-lsp"),
+            response.as_bytes(),
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+    }
+
+    #[test]
+    fn read_grep_results_without_filename() {
+        // Input like `grep -nH` and `grep -n` for the same file
+        let input: Vec<_> = fs::read_to_string(STDIN_INPUT)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with(CALC_RS))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert!(!input.is_empty());
+        let with_filename = input.concat();
+        let without_filename: String = input
+            .iter()
+            .map(|line| {
+                line.strip_prefix(CALC_RS)
+                    .unwrap()
+                    .strip_prefix(':')
+                    .unwrap()
+            })
+            .collect();
+
+        let want = run_with_stdin(&mut hgrep("syntect"), with_filename.as_bytes());
+        assert!(want.status.success(), "stderr: {:?}", stderr(&want));
+        let output = run_with_stdin(
+            hgrep("syntect").args(["--input-format", "n", "--label", CALC_RS]),
+            without_filename.as_bytes(),
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+    }
+
+    #[test]
+    fn read_colored_grep_results() {
+        // Color the input like `grep --color=always -nH`
+        let input = fs::read_to_string(STDIN_INPUT).unwrap();
+        let colored: String = input
+            .lines()
+            .map(|line| {
+                let mut split = line.splitn(3, ':');
+                let (path, lnum, text) = (split.next(), split.next(), split.next());
+                format!(
+                    "\x1b[35m\x1b[K{}\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\x1b[32m\x1b[K{}\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K{}\n",
+                    path.unwrap(),
+                    lnum.unwrap(),
+                    text.unwrap(),
+                )
+            })
+            .collect();
+        let output = run_with_stdin(
+            hgrep("syntect").args(["-c", "1", "-C", "2", "--strip-ansi"]),
+            colored.as_bytes(),
+        );
+        check_success("syntect_stdin.out", &output);
+    }
+
+    #[test]
+    fn empty_input() {
+        let output = run_with_stdin(&mut hgrep("syntect"), b"");
+        assert_eq!(
+            output.status.code(),
+            Some(1),
+            "stderr: {:?}",
+            stderr(&output)
+        );
+        assert_eq!(stdout(&output), "");
+    }
+
+    #[test]
+    fn invalid_option_value() {
+        let output = hgrep("syntect")
+            .args(["--term-width", "foo"])
+            .output()
+            .unwrap();
+        check_error("error_invalid_term_width.err", &output);
+    }
+}
+
+#[cfg(all(feature = "syntect-printer", feature = "bat-printer"))]
+mod layout {
+    use super::*;
+
+    fn strip_ansi_escapes(s: &str) -> String {
+        let mut stripped = String::new();
+        let mut in_escape = false;
+        for c in s.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if in_escape => {}
+                c => stripped.push(c),
+            }
+        }
+        stripped
+    }
+
+    // Lines of the output without colors. Trailing spaces are removed since they are filled only by syntect printer for
+    // highlighted lines
+    fn structure(output: &Output) -> Vec<String> {
+        strip_ansi_escapes(&stdout(output))
+            .lines()
+            .map(|l| l.trim_end().to_string())
+            .collect()
+    }
+
+    fn search(printer: &str, args: &[&str]) -> Output {
+        let mut cmd = hgrep(printer);
+        cmd.args(["-c", "0", "-C", "0", "--sort-by-match-count", "desc"])
+            .args(["--layout", "unified"])
+            .args(args);
+        run_with_stdin_file(&mut cmd, STDIN_INPUT)
+    }
+
+    #[test]
+    fn unified_grid() {
+        let bat = search("bat", &[]);
+        check_success("bat_layout_unified.out", &bat);
+        let syntect = search("syntect", &[]);
+        check_success("syntect_layout_unified.out", &syntect);
+        assert_eq!(structure(&syntect), structure(&bat));
+    }
+
+    #[test]
+    fn unified_no_grid() {
+        let bat = search("bat", &["--no-grid"]);
+        check_success("bat_layout_unified_no_grid.out", &bat);
+        let syntect = search("syntect", &["--no-grid"]);
+        check_success("syntect_layout_unified_no_grid.out", &syntect);
+        assert_eq!(structure(&syntect), structure(&bat));
+    }
+}
+
+mod porcelain {
+    use super::*;
+
+    #[test]
+    fn read_grep_results() {
+        let output = run_with_stdin_file(
+            hgrep_default_printer().args(["-c", "1", "-C", "2", "--porcelain"]),
+            STDIN_INPUT,
+        );
+        check_success("porcelain_stdin.out", &output);
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn search_dir_sorted() {
+        let output = hgrep_default_printer()
+            .args(["-c", "2", "-C", "4", "--sort-by-match-count", "desc"])
+            .args(["--porcelain", "TODO", INPUT_DIR])
+            .output()
+            .unwrap();
+        check_success("porcelain_search_dir_sorted.out", &output);
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn count_only() {
+        let count = |args: &[&str]| {
+            let output = hgrep_default_printer()
+                .args(["--porcelain", "TODO", INPUT_DIR])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+            stdout(&output)
+                .lines()
+                .filter(|l| l.starts_with("match\t"))
+                .count()
+        };
+        let expected = count(&[]);
+        assert!(expected > 0);
+
+        let output = hgrep_default_printer()
+            .args(["--count-only", "TODO", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        // Only the total is printed without any snippet
+        assert_eq!(stdout(&output), format!("{expected}\n"));
+
+        let output = hgrep_default_printer()
+            .args(["--count-only", "this pattern never matches", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(stdout(&output), "0\n");
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn passthrough_matches() {
+        let output = hgrep_default_printer()
+            .args(["--passthrough-matches", "TODO", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let matched = stdout(&output);
+        let mut lines: Vec<_> = matched.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "testdata/cli/input/calc.rs:1:// TODO: Support more operators",
+                "testdata/cli/input/calc.rs:7:        _ => None, // TODO: Report an error instead of returning None when an unknown operator is given by users",
+                "testdata/cli/input/parse.rs:4:    let op = it.next()?.chars().next()?; // TODO: Reject multiple characters",
+            ],
+        );
+
+        // The output can be read by hgrep as grep results
+        let output = run_with_stdin(
+            hgrep_default_printer().args(["--porcelain", "-c", "1", "-C", "1"]),
+            matched.as_bytes(),
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let num_matches = stdout(&output)
+            .lines()
+            .filter(|l| l.starts_with("match\t"))
+            .count();
+        assert_eq!(num_matches, 3);
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn empty_pattern_matches_all_lines() {
+        let path = "testdata/ripgrep/fixed_string.txt";
+        let output = hgrep_default_printer()
+            .args(["--porcelain", "", path])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let num_lines = fs::read_to_string(path).unwrap().lines().count();
+        let stdout = stdout(&output);
+        let lines: Vec<_> = stdout.lines().skip(1).collect();
+        assert_eq!(lines[0], format!("{path}\t1\t{num_lines}"));
+        let lnums: Vec<_> = lines[1..].iter().map(|l| l.to_string()).collect();
+        let expected: Vec<_> = (1..=num_lines).map(|n| format!("match\t{n}")).collect();
+        assert_eq!(lnums, expected);
+    }
+
+    #[test]
+    fn header_marker_is_not_printed() {
+        let output = hgrep_default_printer()
+            .args(["--porcelain", "--header-marker=MARKER", "TODO", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let stdout = stdout(&output);
+        assert!(stdout.contains("match\t"), "{stdout:?}");
+        assert!(!stdout.contains("MARKER"), "{stdout:?}");
+    }
+
+    #[cfg(feature = "ripgrep")]
+    #[test]
+    fn max_files() {
+        let files = |args: &[&str]| {
+            let output = hgrep_default_printer()
+                .args(["--porcelain", "TODO", INPUT_DIR])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+            stdout(&output)
+                .lines()
+                .filter(|l| l.starts_with(INPUT_DIR))
+                .map(|l| l.split('\t').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(files(&[]).len() > 1);
+        assert_eq!(files(&["--max-files", "1"]).len(), 1);
+
+        let sorted = files(&["--sort-by-match-count", "desc"]);
+        let capped = files(&["--sort-by-match-count", "desc", "--max-files", "1"]);
+        assert_eq!(capped, sorted[..1]);
+    }
+
+    #[test]
+    fn path_separator() {
+        let want = run_with_stdin_file(hgrep_default_printer().arg("--porcelain"), STDIN_INPUT);
+        let output = run_with_stdin_file(
+            hgrep_default_printer().args(["--porcelain", "--path-separator", "|"]),
+            STDIN_INPUT,
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert!(stdout(&output).contains("testdata|cli|input|calc.rs\t"));
+        assert_eq!(stdout(&output), stdout(&want).replace('/', "|"));
+    }
+
+    #[cfg(feature = "syntect-printer")]
+    #[test]
+    fn path_separator_in_header() {
+        let output = run_with_stdin_file(
+            hgrep("syntect").args(["--path-separator", "backslash", "--ascii-lines"]),
+            STDIN_INPUT,
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let stdout = stdout(&output);
+        assert!(stdout.contains(r"testdata\cli\input\calc.rs"), "{stdout:?}");
+        assert!(!stdout.contains("testdata/cli"), "{stdout:?}");
+    }
+
+    // The output does not depend on the printer
+    #[cfg(feature = "bat-printer")]
+    #[test]
+    fn printer_option_is_ignored() {
+        let want = run_with_stdin_file(hgrep("syntect").arg("--porcelain"), STDIN_INPUT);
+        let output = run_with_stdin_file(hgrep("bat").arg("--porcelain"), STDIN_INPUT);
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+    }
+}
+
+#[cfg(all(feature = "ripgrep", feature = "bat-printer"))]
+mod bat_printer {
+    use super::*;
+
+    #[test]
+    fn search_dir_sorted() {
+        let output = hgrep("bat")
+            .args(["-c", "2", "-C", "4", "--sort-by-match-count", "desc"])
+            .args(["TODO", INPUT_DIR])
+            .output()
+            .unwrap();
+        check_success("bat_search_dir_sorted.out", &output);
+    }
+
+    #[test]
+    fn wrap_never() {
+        let output = hgrep("bat")
+            .args(["--wrap", "never", "TODO", CALC_RS])
+            .output()
+            .unwrap();
+        check_success("bat_wrap_never.out", &output);
+    }
+
+    #[test]
+    fn narrow_term_width() {
+        let output = hgrep("bat")
+            .args(["--term-width", "40", "TODO", CALC_RS])
+            .output()
+            .unwrap();
+        check_success("bat_narrow_term_width.out", &output);
+    }
+
+    #[test]
+    fn unsupported_flag() {
+        let output = hgrep("bat")
+            .args(["--background", "TODO", CALC_RS])
+            .output()
+            .unwrap();
+        check_error("error_bat_unsupported_flag.err", &output);
+    }
+}
+
+#[cfg(feature = "bat-printer")]
+mod bat_stdin {
+    use super::*;
+
+    #[test]
+    fn read_grep_results() {
+        let output = run_with_stdin_file(hgrep("bat").args(["-c", "1", "-C", "2"]), STDIN_INPUT);
+        check_success("bat_stdin.out", &output);
+    }
+}
+
+#[cfg(all(feature = "ripgrep", feature = "syntect-printer"))]
+mod piped_stdin_with_pattern {
+    use super::*;
+
+    // grep result of the file which is different from the file searched with PATTERN
+    const PIPED_INPUT: &[u8] = b"testdata/cli/input/parse.rs:4:TODO\n";
+
+    #[test]
+    fn pattern_with_piped_stdin_warns() {
+        let output = run_with_stdin(hgrep("syntect").args(["TODO", CALC_RS]), PIPED_INPUT);
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+
+        let err = stderr(&output);
+        assert!(err.contains("Input piped to stdin is ignored"), "{err:?}");
+        assert!(err.contains("--stdin"), "{err:?}");
+
+        // The pattern is searched and the piped grep result is not used
+        let out = stdout(&output);
+        assert!(out.contains("calc.rs"), "{out:?}");
+        assert!(!out.contains("parse.rs"), "{out:?}");
+    }
+
+    #[test]
+    fn pattern_without_piped_stdin_does_not_warn() {
+        let output = hgrep("syntect").args(["TODO", CALC_RS]).output().unwrap();
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[test]
+    fn stdin_flag_reads_grep_results() {
+        let output = run_with_stdin(hgrep("syntect").arg("--stdin"), PIPED_INPUT);
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stderr(&output), "");
+        let out = stdout(&output);
+        assert!(out.contains("parse.rs"), "{out:?}");
+    }
+
+    #[test]
+    fn stdin_flag_conflicts_with_pattern() {
+        let output = run_with_stdin(
+            hgrep("syntect").args(["--stdin", "TODO", CALC_RS]),
+            PIPED_INPUT,
+        );
+        assert_eq!(output.status.code(), Some(2));
+        let err = stderr(&output);
+        assert!(err.contains("--stdin"), "{err:?}");
+        assert!(err.contains("PATTERN"), "{err:?}");
+        assert_eq!(stdout(&output), "");
+    }
+}
+
+#[cfg(all(feature = "ripgrep", feature = "syntect-printer"))]
+mod pattern_env {
+    use super::*;
+
+    const VAR: &str = "HGREP_TEST_PATTERN";
+
+    #[test]
+    fn same_as_pattern_argument() {
+        let with_arg = hgrep("syntect").args(["TODO", CALC_RS]).output().unwrap();
+        let with_env = hgrep("syntect")
+            .env(VAR, "TODO")
+            .args(["--pattern-env", VAR, CALC_RS])
+            .output()
+            .unwrap();
+        assert!(with_env.status.success(), "stderr: {:?}", stderr(&with_env));
+        assert_eq!(stderr(&with_env), "");
+        assert_eq!(stdout(&with_env), stdout(&with_arg));
+    }
+
+    #[test]
+    fn unset_or_empty_variable() {
+        for value in [None, Some("")] {
+            let mut cmd = hgrep("syntect");
+            match value {
+                Some(v) => cmd.env(VAR, v),
+                None => cmd.env_remove(VAR),
+            };
+            let output = cmd.args(["--pattern-env", VAR, CALC_RS]).output().unwrap();
+            assert_eq!(output.status.code(), Some(2), "value: {value:?}");
+            assert!(stderr(&output).contains(VAR), "{:?}", stderr(&output));
+            assert_eq!(stdout(&output), "");
+        }
+    }
+
+    #[test]
+    fn pattern_is_not_leaked_in_warning() {
+        let output = run_with_stdin(
+            hgrep("syntect")
+                .env(VAR, "TODO")
+                .args(["--pattern-env", VAR, CALC_RS]),
+            b"testdata/cli/input/parse.rs:4:TODO\n",
+        );
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let err = stderr(&output);
+        assert!(err.contains("--pattern-env"), "{err:?}");
+        assert!(!err.contains("TODO"), "{err:?}");
+    }
+}
+
+#[cfg(feature = "ripgrep")]
+mod use_ripgrep_config {
+    use super::*;
+
+    const CONFIG: &str = "testdata/ripgrep_config/cli_test.rgrc";
+
+    fn search(args: &[&str]) -> Output {
+        hgrep_default_printer()
+            .env("RIPGREP_CONFIG_PATH", CONFIG)
+            .args(["--porcelain", "todo", INPUT_DIR])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    fn matched_lines(output: &Output) -> Vec<String> {
+        assert!(output.status.success(), "stderr: {:?}", stderr(output));
+        let mut path = "";
+        let stdout = stdout(output);
+        let mut lines = vec![];
+        for line in stdout.lines().skip(1) {
+            match line.strip_prefix("match\t") {
+                Some(lnum) => lines.push(format!("{path}:{lnum}")),
+                None => path = line.split('\t').next().unwrap(),
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn apply_flags_in_config_file() {
+        // --ignore-case, --glob, and --max-count are applied and --sort is ignored
+        let output = search(&["--use-ripgrep-config"]);
+        assert_eq!(matched_lines(&output), [format!("{CALC_RS}:1")]);
+        assert_eq!(
+            stderr(&output),
+            format!("\x1b[1;93mwarning:\x1b[0m Flag \"--sort=path\" is not supported by hgrep in ripgrep config file {CONFIG:?}. Ignored\n"),
+        );
+    }
+
+    #[test]
+    fn hgrep_options_take_precedence() {
+        let output = search(&["--use-ripgrep-config", "--max-count", "2"]);
+        assert_eq!(
+            matched_lines(&output),
+            [format!("{CALC_RS}:1"), format!("{CALC_RS}:7")],
+        );
+    }
+
+    #[test]
+    fn config_file_is_ignored_without_flag() {
+        let output = search(&[]);
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(stderr(&output), "");
+    }
+
+    #[test]
+    fn debug_config_shows_sources() {
+        let output = hgrep_default_printer()
+            .env("RIPGREP_CONFIG_PATH", CONFIG)
+            .env("HGREP_DEFAULT_OPTS", "--max-files=10 --max-count 3")
+            .args(["--use-ripgrep-config", "--debug-config=json", "todo"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let options = json["options"].as_array().unwrap();
+        let source = |name: &str| {
+            let opt = options.iter().find(|o| o["name"] == name);
+            opt.map(|o| o["source"].as_str().unwrap().to_string())
+        };
+        assert_eq!(source("--ignore-case").as_deref(), Some("config-file"));
+        assert_eq!(source("--glob").as_deref(), Some("config-file"));
+        assert_eq!(source("--max-count").as_deref(), Some("env")); // Overrides the config file
+        assert_eq!(source("--max-files").as_deref(), Some("env"));
+        assert_eq!(source("--theme").as_deref(), Some("flag"));
+        assert_eq!(source("--max-context").as_deref(), Some("default"));
+        assert_eq!(source("--smart-case"), None);
+
+        assert_eq!(json["printer_options"]["theme"], r#"Some("Nord")"#);
+        assert_eq!(json["ripgrep_config"]["case_insensitive"], "true");
+        assert_eq!(json["ripgrep_config"]["max_count"], "Some(3)");
+        assert_eq!(json["ripgrep_config"]["max_files"], "Some(10)");
+    }
+
+    #[test]
+    fn config_file_not_found() {
+        let output = hgrep_default_printer()
+            .env(
+                "RIPGREP_CONFIG_PATH",
+                "testdata/ripgrep_config/not_found.rgrc",
+            )
+            .args(["--use-ripgrep-config", "todo", INPUT_DIR])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert!(
+            stderr(&output).contains("Could not read ripgrep config file"),
+            "{:?}",
+            stderr(&output),
+        );
+    }
+}
+
+#[cfg(all(
+    feature = "ripgrep",
+    feature = "syntect-printer",
+    feature = "bat-printer"
+))]
+mod printer_env {
+    use super::*;
+
+    fn search(cmd: &mut Command) -> Output {
+        cmd.args(["TODO", CALC_RS]).output().unwrap()
+    }
+
+    #[test]
+    fn select_default_printer() {
+        let want = search(&mut hgrep("bat"));
+        let output = search(hgrep_default_printer().env("HGREP_PRINTER", "bat"));
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+
+        // Empty value is the same as the variable is not set
+        let want = search(&mut hgrep("syntect"));
+        let output = search(hgrep_default_printer().env("HGREP_PRINTER", ""));
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+    }
+
+    #[test]
+    fn printer_option_takes_precedence() {
+        let want = search(&mut hgrep_default_printer());
+        let output = search(hgrep("syntect").env("HGREP_PRINTER", "bat"));
+        assert!(output.status.success(), "stderr: {:?}", stderr(&output));
+        assert_eq!(stdout(&output), stdout(&want));
+    }
+
+    #[test]
+    fn unknown_printer() {
+        let output = search(hgrep_default_printer().env("HGREP_PRINTER", "less"));
+        assert_eq!(output.status.code(), Some(2));
+        let err = stderr(&output);
+        assert!(err.contains("HGREP_PRINTER"), "{err:?}");
+        assert!(err.contains("\"less\""), "{err:?}");
+        assert_eq!(stdout(&output), "");
+    }
+}